@@ -84,20 +84,20 @@ mod diesel_impl {
     }
 
     pub fn generate_patchset(session: &mut Session) -> Vec<u8> {
-        session.patchset().unwrap()
+        session.patchset().unwrap().into_bytes()
     }
 
     pub fn generate_changeset(session: &mut Session) -> Vec<u8> {
-        session.changeset().unwrap()
+        session.changeset().unwrap().into_bytes()
     }
 
     pub fn apply_patchset_to_conn(conn: &mut SqliteConnection, patchset: &[u8]) {
-        conn.apply_patchset(patchset, |_| ConflictAction::Abort)
+        conn.apply_bytes(patchset, |_| ConflictAction::Abort)
             .unwrap();
     }
 
     pub fn apply_changeset_to_conn(conn: &mut SqliteConnection, changeset: &[u8]) {
-        conn.apply_changeset(changeset, |_| ConflictAction::Abort)
+        conn.apply_bytes(changeset, |_| ConflictAction::Abort)
             .unwrap();
     }
 
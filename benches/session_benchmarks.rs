@@ -5,7 +5,7 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use diesel::prelude::*;
 use diesel::sql_query;
-use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+use diesel_sqlite_session::{invert_changeset, ApplyOptions, ConflictAction, SqliteSessionExt};
 use std::hint::black_box;
 use std::time::Duration;
 
@@ -174,6 +174,42 @@ fn bench_apply_patchset(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark applying a single-row changeset with and without
+/// `ApplyOptions::optimize_small`.
+fn bench_apply_small(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply_small");
+
+    let changeset = {
+        let mut conn = setup_connection();
+        let mut session = conn.create_session().unwrap();
+        session.attach::<items::table>().unwrap();
+
+        insert_rows(&mut conn, 0, 1);
+
+        session.changeset().unwrap()
+    };
+    // Applying the insert and then its inverse leaves the replica as it was.
+    let undo = invert_changeset(&changeset).unwrap();
+
+    for (name, options) in [
+        ("default", ApplyOptions::new()),
+        ("optimize_small", ApplyOptions::new().optimize_small(true)),
+    ] {
+        let mut conn = setup_connection();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                conn.apply_changeset_with(black_box(&changeset), &options, |_| {
+                    ConflictAction::Abort
+                })
+                .unwrap();
+                conn.apply_changeset_with(black_box(&undo), &options, |_| ConflictAction::Abort)
+                    .unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
 /// Benchmark mixed operations (INSERT, UPDATE, DELETE).
 fn bench_mixed_operations(c: &mut Criterion) {
     c.bench_function("mixed_operations_75", |b| {
@@ -239,6 +275,7 @@ criterion_group! {
               bench_patchset_generation,
               bench_changeset_generation,
               bench_apply_patchset,
+              bench_apply_small,
               bench_mixed_operations,
               bench_full_replication
 }
@@ -0,0 +1,19 @@
+//! Build check for the `no_std` subset of `diesel-sqlite-session`.
+//!
+//! The main crate needs `std` through Diesel, so it cannot be built for a
+//! `no_std` target itself. This crate compiles the source file holding
+//! `SqliteErrorCode`, `ConflictType`, `ConflictAction` and `SqliteValue`
+//! under `#![no_std]`, so any `std` item creeping into it fails the build:
+//!
+//! ```text
+//! cargo build -p diesel-sqlite-session-no-std-check --target thumbv7em-none-eabihf
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+#[path = "../../src/core_types.rs"]
+mod core_types;
+
+pub use core_types::{ConflictAction, ConflictType, SqliteErrorCode, SqliteValue};
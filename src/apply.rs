@@ -1,22 +1,832 @@
 //! Apply changesets and patchsets to Diesel connections.
 
-use std::ffi::c_int;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void, CStr, CString};
+use std::fmt;
+use std::iter::Sum;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr;
+use std::rc::Rc;
+use std::time::Duration;
 
 use diesel::SqliteConnection;
 
-use crate::errors::{ApplyError, ConflictAction, ConflictType, SqliteErrorCode};
+use crate::buffer::take_sqlite_buffer;
+use crate::changeset::{invert_changeset, reorder_changeset_columns, Changeset};
+use crate::conflict::{ByKind, Conflict, ConflictRecord, ConflictResolver};
+use crate::encode::{Encoder, Format};
+use crate::errors::{ApplyError, ChangesetError, ConflictAction, ConflictType, SqliteErrorCode};
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+use crate::events::{ConflictEvents, EventResolver};
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+use crate::ffi::sqlite3changeset_apply_v2_strm;
 use crate::ffi::{
-    sqlite3_changeset_iter, sqlite3changeset_apply, SQLITE_CHANGESET_ABORT, SQLITE_OK,
-    SQLITE_TOOBIG,
+    sqlite3, sqlite3_changeset_iter, sqlite3_close, sqlite3_db_filename, sqlite3_db_status,
+    sqlite3_get_autocommit, sqlite3_open_v2, sqlite3_total_changes64, sqlite3changeset_apply_v2,
+    sqlite3changeset_fk_conflicts, SQLITE_BUSY, SQLITE_CHANGESETAPPLY_NOSAVEPOINT,
+    SQLITE_DBSTATUS_DEFERRED_FKS, SQLITE_LOCKED, SQLITE_OK, SQLITE_OPEN_READWRITE,
 };
+use crate::iter::{read_changeset, read_op, read_op_kind, ChangeOp, OpKind};
+use crate::query::{column_names, quote_identifier, Statement};
+use crate::rebase::RebaseData;
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+use crate::stream::{input_callback, InputContext};
+use crate::telemetry::{Timer, APPLY_DURATION};
+use crate::validate::validate_changeset;
+use crate::value::SqliteValue;
+
+/// Options controlling how a changeset or patchset is applied.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, ConflictAction, SqliteSessionExt};
+///
+/// let mut replica = SqliteConnection::establish(":memory:").unwrap();
+/// # let changeset = diesel_sqlite_session::Changeset::default();
+/// let options = ApplyOptions::new().max_conflicts(100);
+/// replica
+///     .apply_changeset_with(&changeset, &options, |_| ConflictAction::Omit)
+///     .unwrap();
+/// ```
+// Each flag is an independent builder switch, so an enum or bitflags would
+// only obscure the one-to-one mapping to the public setters.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyOptions {
+    max_conflicts: Option<usize>,
+    collect_omitted: bool,
+    no_savepoint: bool,
+    insert_on_not_found: bool,
+    append_only: bool,
+    defer_foreign_keys: bool,
+    on_applied: Option<AppliedHook>,
+    busy_retry: Option<BusyRetry>,
+    target_db: Option<String>,
+    per_operation_isolation: bool,
+    collect_affected_keys: bool,
+    savepoint_name: Option<String>,
+    optimize_small: bool,
+    /// Receives the code returned by the last `sqlite3changeset_apply_v2` call.
+    result_code: Option<Rc<Cell<c_int>>>,
+}
+
+impl ApplyOptions {
+    /// Create options matching the behavior of the plain apply methods.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort once more than `limit` conflicts have been reported.
+    ///
+    /// The conflict handler is invoked at most `limit` times; the next conflict
+    /// rolls the whole apply back and returns
+    /// [`ApplyError::ConflictLimitExceeded`]. This guards against replicas that
+    /// are badly out of sync.
+    #[inline]
+    #[must_use]
+    pub fn max_conflicts(mut self, limit: usize) -> Self {
+        self.max_conflicts = Some(limit);
+        self
+    }
+
+    /// Record every change the conflict handler omits.
+    ///
+    /// When enabled, [`ApplyStats::omitted`] lists the table and primary key
+    /// of each change skipped with [`ConflictAction::Omit`], giving a precise
+    /// worklist for later reconciliation.
+    #[inline]
+    #[must_use]
+    pub fn collect_omitted(mut self, enabled: bool) -> Self {
+        self.collect_omitted = enabled;
+        self
+    }
+
+    /// Record the primary key of every applied change, grouped by table.
+    ///
+    /// When enabled, [`ApplyStats::affected_keys`] maps each table to the
+    /// primary keys of the rows the apply inserted, updated or deleted, in
+    /// changeset order, which is what targeted cache invalidation needs.
+    /// Changes omitted by the conflict handler are left out.
+    #[inline]
+    #[must_use]
+    pub fn collect_affected_keys(mut self, enabled: bool) -> Self {
+        self.collect_affected_keys = enabled;
+        self
+    }
+
+    /// Apply without wrapping the changes in a savepoint.
+    ///
+    /// By default `SQLite` applies each changeset inside its own savepoint and
+    /// rolls it back if the apply fails or is aborted. With this enabled the
+    /// caller owns transaction management: open a transaction before applying,
+    /// and roll it back yourself on error, since changes applied before a
+    /// failure are left in place. Skipping the savepoint is noticeably faster
+    /// when applying many changesets inside one outer transaction.
+    #[inline]
+    #[must_use]
+    pub fn no_savepoint(mut self, enabled: bool) -> Self {
+        self.no_savepoint = enabled;
+        self
+    }
+
+    /// Turn updates of rows missing from the target into inserts.
+    ///
+    /// By default an update whose row does not exist raises
+    /// [`ConflictType::NotFound`], and even [`ConflictAction::Replace`] cannot
+    /// apply it. With this enabled the row is inserted instead, built from the
+    /// update's new values and its primary key, and the conflict handler is not
+    /// invoked for it. Columns the update did not modify are not recorded in
+    /// the changeset, so they take their default values. The conflict still
+    /// counts towards [`ApplyStats::conflicts`] and
+    /// [`max_conflicts`](Self::max_conflicts).
+    #[inline]
+    #[must_use]
+    pub fn insert_on_not_found(mut self, enabled: bool) -> Self {
+        self.insert_on_not_found = enabled;
+        self
+    }
+
+    /// Apply to append-only tables, where a primary key collision is a bug.
+    ///
+    /// Meant for CRDT-style append logs whose rows carry globally unique keys
+    /// such as UUIDs: inserts from independent peers then merge without
+    /// conflict. An insert that collides with an existing row is not handed to
+    /// the conflict handler; the apply is rolled back and
+    /// [`ApplyError::DuplicatePrimaryKey`] identifies the row. Other conflicts
+    /// are resolved by the handler as usual.
+    #[inline]
+    #[must_use]
+    pub fn append_only(mut self, enabled: bool) -> Self {
+        self.append_only = enabled;
+        self
+    }
+
+    /// Leave foreign key violations to be checked when the transaction commits.
+    ///
+    /// `SQLite` already defers foreign key checks until the end of each apply,
+    /// so the order of rows within one changeset never matters. Violations
+    /// still outstanding at that point are reported as a single
+    /// [`ConflictType::ForeignKey`] conflict. With this enabled that conflict is
+    /// not handed to the conflict handler. Within an enclosing transaction, a
+    /// child row may then arrive in one changeset and its parent in a later
+    /// one; the constraint is still enforced, and the commit fails if a
+    /// violation remains.
+    ///
+    /// `SQLite` switches `PRAGMA defer_foreign_keys` off when the apply
+    /// returns, which forgets the outstanding violations. They are counted
+    /// again through rows of a temporary table that reference missing rows,
+    /// and the pragma is left on for the rest of the transaction. Outside a
+    /// transaction there is no later commit, and the conflict goes to the
+    /// handler as usual.
+    #[inline]
+    #[must_use]
+    pub fn defer_foreign_keys(mut self, enabled: bool) -> Self {
+        self.defer_foreign_keys = enabled;
+        self
+    }
+
+    /// Retry the whole apply while the database is busy or locked.
+    ///
+    /// An apply that fails with `SQLITE_BUSY` or `SQLITE_LOCKED` is retried up
+    /// to `max_attempts` attempts in total, sleeping `backoff` before the
+    /// second attempt and doubling the delay before each later one. Once the
+    /// attempts are used up the last error is returned. Retrying is safe
+    /// because a failed apply is rolled back through its savepoint; with
+    /// [`no_savepoint`](Self::no_savepoint) the caller must make sure nothing
+    /// was written before the failure. The conflict handler may be invoked
+    /// again for the same changes on every attempt. On `wasm32-unknown-unknown`
+    /// attempts are not delayed, as threads cannot sleep there.
+    #[inline]
+    #[must_use]
+    pub fn busy_retry(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.busy_retry = Some(BusyRetry {
+            max_attempts,
+            backoff,
+        });
+        self
+    }
+
+    /// Apply into the attached database `schema` instead of `main`.
+    ///
+    /// `SQLite` always applies changes to tables in `main`, so the changes are
+    /// applied through a separate connection opened on the file backing
+    /// `schema`. That connection commits on its own: the apply is not part of
+    /// any transaction open on the calling connection, which must not hold a
+    /// write lock on `schema` at the time. Databases without a file, such as
+    /// `temp` or in-memory attachments, cannot be targeted and fail with
+    /// [`ApplyError::TargetDatabaseUnavailable`]. Passing `"main"` applies
+    /// to the calling connection as usual.
+    #[inline]
+    #[must_use]
+    pub fn target_db(mut self, schema: &str) -> Self {
+        self.target_db = Some(schema.to_owned());
+        self
+    }
+
+    /// Apply each change on its own, skipping the changes that fail.
+    ///
+    /// By default one failing change, such as a conflict the handler aborts,
+    /// rolls back the whole apply. With this enabled every change is applied
+    /// separately inside its own savepoint, even when
+    /// [`no_savepoint`](Self::no_savepoint) is set: a change that fails is
+    /// rolled back alone, recorded in [`ApplyStats::failed`], and the apply
+    /// moves on to the next one. This is much slower than applying the changes
+    /// together, and foreign keys are checked after each change rather than
+    /// once at the end, so a child row must not arrive before its parent.
+    ///
+    /// Some failures still stop the apply, leaving the changes applied before
+    /// them in place: a panicking conflict handler, more conflicts than
+    /// [`max_conflicts`](Self::max_conflicts) allows, a database that stays
+    /// busy and input that cannot be read. [`busy_retry`](Self::busy_retry)
+    /// retries the change that hit the busy database.
+    #[inline]
+    #[must_use]
+    pub fn per_operation_isolation(mut self, enabled: bool) -> Self {
+        self.per_operation_isolation = enabled;
+        self
+    }
+
+    /// Skip the savepoint when the input holds a single change.
+    ///
+    /// Meant for chatty real-time sync, where most changesets carry one row
+    /// and opening and releasing a savepoint is a noticeable share of the
+    /// apply. When the input holds exactly one change it is applied as if
+    /// [`no_savepoint`](Self::no_savepoint) were enabled; larger inputs are
+    /// applied as usual. Applying one row is mostly atomic on its own, but
+    /// some failures still leave part of it in place, for example a
+    /// [`ConflictAction::Replace`] that deletes the existing row before the
+    /// insert fails, or a foreign key violation the handler aborts inside an
+    /// enclosing transaction. Roll the enclosing transaction back on error.
+    /// Ignored when [`per_operation_isolation`](Self::per_operation_isolation)
+    /// is enabled or a [`savepoint_name`](Self::savepoint_name) is set.
+    #[inline]
+    #[must_use]
+    pub fn optimize_small(mut self, enabled: bool) -> Self {
+        self.optimize_small = enabled;
+        self
+    }
+
+    /// Name the savepoint the changes are applied in.
+    ///
+    /// `SQLite` always names its savepoint `changeset_apply`, which can clash
+    /// with savepoints of the same name opened by the caller. With a name set,
+    /// the apply opens `SAVEPOINT name` itself, applies without `SQLite`'s
+    /// savepoint, and releases it on success or rolls back to it on failure,
+    /// leaving the caller's enclosing savepoints untouched. Ignored when
+    /// [`no_savepoint`](Self::no_savepoint) is enabled.
+    #[inline]
+    #[must_use]
+    pub fn savepoint_name(mut self, name: &str) -> Self {
+        self.savepoint_name = Some(name.to_owned());
+        self
+    }
+
+    /// Call `callback` for every change that was applied.
+    ///
+    /// Useful to keep a derived view, such as an in-memory cache, in sync with
+    /// the replica. The callback runs once the apply has succeeded, in
+    /// changeset order, and is skipped for changes the conflict handler
+    /// omitted; it is never called for an apply that fails and is rolled back.
+    /// Changes are matched to omissions by table, kind and primary key, so if
+    /// the input holds several changes of the same kind to one row, omitting
+    /// one of them skips them all.
+    #[inline]
+    #[must_use]
+    pub fn on_applied<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&AppliedChange) + 'static,
+    {
+        self.on_applied = Some(AppliedHook(Rc::new(RefCell::new(callback))));
+        self
+    }
+
+    /// Whether the changes are applied without any savepoint.
+    fn skips_savepoint(&self) -> bool {
+        self.no_savepoint && !self.per_operation_isolation
+    }
+
+    /// The savepoint to open around the apply instead of `SQLite`'s own.
+    fn named_savepoint(&self) -> Option<&str> {
+        if self.skips_savepoint() {
+            None
+        } else {
+            self.savepoint_name.as_deref()
+        }
+    }
+
+    /// Flags passed to `sqlite3changeset_apply_v2`.
+    fn flags(&self) -> c_int {
+        if self.skips_savepoint() || self.named_savepoint().is_some() {
+            SQLITE_CHANGESETAPPLY_NOSAVEPOINT
+        } else {
+            0
+        }
+    }
+}
+
+/// Settings registered with [`ApplyOptions::busy_retry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BusyRetry {
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl BusyRetry {
+    /// Delay before the attempt following attempt number `attempt` (1-based).
+    fn delay_after(self, attempt: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff.saturating_mul(factor)
+    }
+}
+
+/// Whether a failed apply may succeed when retried.
+fn is_busy(err: &ApplyError) -> bool {
+    // Extended result codes keep the primary code in their low byte.
+    matches!(err, ApplyError::ApplyFailed(code)
+        if matches!(code.to_raw() & 0xff, SQLITE_BUSY | SQLITE_LOCKED))
+}
+
+/// A connection opened on the file backing an attached database, closed on drop.
+struct TargetConnection(*mut sqlite3);
+
+impl TargetConnection {
+    /// Open a connection on the file backing database `schema` of `db`.
+    ///
+    /// # Safety
+    ///
+    /// `db` must be a valid connection handle.
+    unsafe fn open(db: *mut sqlite3, schema: &str) -> Result<Self, ApplyError> {
+        let unavailable = || ApplyError::TargetDatabaseUnavailable {
+            schema: schema.to_owned(),
+        };
+        let c_schema = CString::new(schema).map_err(|_| unavailable())?;
+
+        // SAFETY: the caller guarantees `db` is valid and `c_schema` is a
+        // NUL-terminated string; the returned name is copied before `db` is
+        // used again.
+        let filename = unsafe {
+            let name = sqlite3_db_filename(db, c_schema.as_ptr());
+            if name.is_null() {
+                return Err(unavailable());
+            }
+            CStr::from_ptr(name).to_owned()
+        };
+        if filename.is_empty() {
+            return Err(unavailable());
+        }
+
+        let mut target = ptr::null_mut();
+        // SAFETY: `filename` is NUL-terminated and `target` is a valid
+        // out-pointer.
+        let rc = unsafe {
+            sqlite3_open_v2(
+                filename.as_ptr(),
+                &mut target,
+                SQLITE_OPEN_READWRITE,
+                ptr::null(),
+            )
+        };
+        // Wrap the handle first so it is closed even if opening failed.
+        let target = Self(target);
+        if rc == SQLITE_OK {
+            Ok(target)
+        } else {
+            Err(apply_failed(rc))
+        }
+    }
+}
+
+impl Drop for TargetConnection {
+    fn drop(&mut self) {
+        // SAFETY: the handle was returned by `sqlite3_open_v2`, is owned by this
+        // type and is closed exactly once; closing a null handle is a no-op.
+        unsafe {
+            sqlite3_close(self.0);
+        }
+    }
+}
+
+type AppliedFn = dyn FnMut(&AppliedChange);
+
+/// Callback registered with [`ApplyOptions::on_applied`].
+#[derive(Clone)]
+struct AppliedHook(Rc<RefCell<AppliedFn>>);
+
+impl fmt::Debug for AppliedHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AppliedHook(..)")
+    }
+}
+
+impl PartialEq for AppliedHook {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for AppliedHook {}
+
+/// A change reported to an [`ApplyOptions::on_applied`] callback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedChange {
+    change: ChangeOp,
+}
+
+impl AppliedChange {
+    /// Name of the table the change was applied to.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> &str {
+        self.change.table()
+    }
+
+    /// Kind of operation that was applied.
+    #[inline]
+    #[must_use]
+    pub const fn op(&self) -> OpKind {
+        self.change.op()
+    }
+
+    /// Values the row holds after the change, empty for deletes.
+    ///
+    /// A `None` entry marks a column the change does not record, such as an
+    /// unmodified column of an update.
+    #[inline]
+    #[must_use]
+    pub fn new_values(&self) -> &[Option<SqliteValue>] {
+        self.change.new_values()
+    }
+
+    /// Primary key values of the affected row.
+    #[inline]
+    #[must_use]
+    pub fn primary_key(&self) -> Vec<SqliteValue> {
+        self.change.primary_key_values()
+    }
+
+    /// The full operation, including old values.
+    #[inline]
+    #[must_use]
+    pub fn change(&self) -> &ChangeOp {
+        &self.change
+    }
+}
+
+/// A change that was skipped because the conflict handler returned
+/// [`ConflictAction::Omit`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OmittedChange {
+    table: String,
+    op: OpKind,
+    conflict: ConflictType,
+    primary_key: Vec<SqliteValue>,
+}
+
+impl OmittedChange {
+    fn new(op: &ChangeOp, conflict: ConflictType) -> Self {
+        Self {
+            table: op.table().to_owned(),
+            op: op.op(),
+            conflict,
+            primary_key: op.primary_key_values(),
+        }
+    }
+
+    /// Name of the table the change targeted.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Kind of operation that was omitted.
+    #[inline]
+    #[must_use]
+    pub const fn op(&self) -> OpKind {
+        self.op
+    }
+
+    /// The conflict that caused the change to be omitted.
+    #[inline]
+    #[must_use]
+    pub const fn conflict(&self) -> ConflictType {
+        self.conflict
+    }
+
+    /// Primary key values of the affected row.
+    #[inline]
+    #[must_use]
+    pub fn primary_key(&self) -> &[SqliteValue] {
+        &self.primary_key
+    }
+}
+
+/// A change that failed while applying with
+/// [`ApplyOptions::per_operation_isolation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedChange {
+    table: String,
+    op: OpKind,
+    primary_key: Vec<SqliteValue>,
+    error: String,
+}
+
+impl FailedChange {
+    fn new(op: &ChangeOp, error: &ApplyError) -> Self {
+        Self {
+            table: op.table().to_owned(),
+            op: op.op(),
+            primary_key: op.primary_key_values(),
+            error: error.to_string(),
+        }
+    }
+
+    /// Name of the table the change targeted.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Kind of operation that failed.
+    #[inline]
+    #[must_use]
+    pub const fn op(&self) -> OpKind {
+        self.op
+    }
+
+    /// Primary key values of the affected row.
+    #[inline]
+    #[must_use]
+    pub fn primary_key(&self) -> &[SqliteValue] {
+        &self.primary_key
+    }
+
+    /// Description of the error that stopped the change.
+    #[inline]
+    #[must_use]
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+}
+
+/// Number of operations of each kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct OpCounts {
+    inserts: usize,
+    updates: usize,
+    deletes: usize,
+}
+
+impl OpCounts {
+    fn of_mut(&mut self, op: OpKind) -> &mut usize {
+        match op {
+            OpKind::Insert => &mut self.inserts,
+            OpKind::Update => &mut self.updates,
+            OpKind::Delete => &mut self.deletes,
+        }
+    }
+
+    /// Count the operations of an encoded changeset or patchset.
+    fn of_changeset(data: &[u8]) -> Result<Self, ChangesetError> {
+        let mut counts = Self::default();
+        let mut iter = read_changeset(data)?;
+        while let Some(op) = iter.next_kind() {
+            *counts.of_mut(op?) += 1;
+        }
+        Ok(counts)
+    }
+}
+
+/// Statistics about a successful apply.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApplyStats {
+    conflicts: usize,
+    applied: OpCounts,
+    omitted: Vec<OmittedChange>,
+    failed: Vec<FailedChange>,
+    affected_keys: HashMap<String, Vec<Vec<SqliteValue>>>,
+    rows_changed: u64,
+    empty_input: bool,
+}
+
+impl ApplyStats {
+    /// Statistics of applying an empty changeset or patchset.
+    ///
+    /// Every count is zero and [`is_empty_input`](Self::is_empty_input) is
+    /// set. Applies return this for empty input without calling into `SQLite`.
+    #[inline]
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            empty_input: true,
+            ..Self::default()
+        }
+    }
+
+    /// Number of rows inserted.
+    ///
+    /// Includes rows inserted by [`ApplyOptions::insert_on_not_found`].
+    #[inline]
+    #[must_use]
+    pub const fn inserted(&self) -> usize {
+        self.applied.inserts
+    }
+
+    /// Number of rows updated.
+    #[inline]
+    #[must_use]
+    pub const fn updated(&self) -> usize {
+        self.applied.updates
+    }
+
+    /// Number of rows deleted.
+    #[inline]
+    #[must_use]
+    pub const fn deleted(&self) -> usize {
+        self.applied.deletes
+    }
+
+    /// Whether the apply left the target unchanged.
+    ///
+    /// True when no row was inserted, updated or deleted: the input was empty
+    /// or every change in it was omitted. Useful to log idempotent resyncs.
+    #[inline]
+    #[must_use]
+    pub const fn is_noop(&self) -> bool {
+        self.applied.inserts == 0 && self.applied.updates == 0 && self.applied.deletes == 0
+    }
+
+    /// Whether the applied changeset or patchset was empty.
+    ///
+    /// Tells an empty input apart from one whose changes were all omitted,
+    /// which [`is_noop`](Self::is_noop) reports alike.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty_input(&self) -> bool {
+        self.empty_input
+    }
+
+    /// Number of rows the apply inserted, updated or deleted.
+    ///
+    /// Read from `sqlite3_total_changes64` before and after the apply, so it
+    /// needs no per-change bookkeeping. Unlike the per-kind counts it also
+    /// includes rows changed by triggers fired during the apply.
+    #[inline]
+    #[must_use]
+    pub const fn total_rows_changed(&self) -> u64 {
+        self.rows_changed
+    }
+
+    /// Number of times the conflict handler was invoked.
+    #[inline]
+    #[must_use]
+    pub const fn conflicts(&self) -> usize {
+        self.conflicts
+    }
+
+    /// Changes omitted by the conflict handler.
+    ///
+    /// Always empty unless [`ApplyOptions::collect_omitted`] is enabled.
+    #[inline]
+    #[must_use]
+    pub fn omitted(&self) -> &[OmittedChange] {
+        &self.omitted
+    }
+
+    /// Changes that failed and were rolled back.
+    ///
+    /// Always empty unless [`ApplyOptions::per_operation_isolation`] is enabled.
+    #[inline]
+    #[must_use]
+    pub fn failed(&self) -> &[FailedChange] {
+        &self.failed
+    }
+
+    /// Primary keys of the applied changes, grouped by table.
+    ///
+    /// Each key holds the values of the primary key columns, in column order.
+    /// Always empty unless [`ApplyOptions::collect_affected_keys`] is enabled.
+    #[inline]
+    #[must_use]
+    pub fn affected_keys(&self) -> &HashMap<String, Vec<Vec<SqliteValue>>> {
+        &self.affected_keys
+    }
+
+    /// Add the statistics of another apply to these.
+    ///
+    /// Counts are summed and the collected changes and keys are appended, so
+    /// the result describes the whole batch of applies.
+    /// [`is_empty_input`](Self::is_empty_input) stays set only if every input
+    /// was empty. Summing an iterator of statistics does the same.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ApplyOptions, ApplyStats, ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// # let batch: Vec<Vec<u8>> = Vec::new();
+    /// let total: ApplyStats = batch
+    ///     .iter()
+    ///     .map(|changeset| {
+    ///         replica
+    ///             .apply_changeset_with(changeset, &ApplyOptions::new(), |_| ConflictAction::Abort)
+    ///             .unwrap()
+    ///     })
+    ///     .sum();
+    /// println!("{} rows inserted", total.inserted());
+    /// ```
+    pub fn merge(&mut self, other: &ApplyStats) {
+        self.absorb(other.clone());
+    }
+
+    /// Add the statistics of a separate apply of more changes.
+    fn absorb(&mut self, other: Self) {
+        self.empty_input &= other.empty_input;
+        self.conflicts += other.conflicts;
+        self.applied.inserts += other.applied.inserts;
+        self.applied.updates += other.applied.updates;
+        self.applied.deletes += other.applied.deletes;
+        self.rows_changed += other.rows_changed;
+        self.omitted.extend(other.omitted);
+        self.failed.extend(other.failed);
+        for (table, keys) in other.affected_keys {
+            self.affected_keys.entry(table).or_default().extend(keys);
+        }
+    }
+}
+
+impl Sum for ApplyStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::empty(), |mut total, stats| {
+            total.absorb(stats);
+            total
+        })
+    }
+}
 
 /// Conflict handler callback context.
-struct ConflictContext<F> {
-    handler: F,
+// The flags mirror independent apply options and outcomes that the callback
+// checks one at a time, so grouping them would only add indirection.
+#[allow(clippy::struct_excessive_bools)]
+struct ConflictContext<'r, R: ?Sized> {
+    handler: &'r mut R,
+    /// Connection being applied to, set once the raw handle is available.
+    db: *mut sqlite3,
+    max_conflicts: Option<usize>,
+    collect_omitted: bool,
+    insert_on_not_found: bool,
+    append_only: bool,
+    defer_foreign_keys: bool,
+    conflicts: usize,
+    /// Operations that were not applied because of an omitted conflict.
+    skipped: OpCounts,
+    /// Rows inserted in place of updates that found no row.
+    inserted_missing: usize,
+    omitted: Vec<OmittedChange>,
+    /// Foreign key violations reported as pending when the apply finished.
+    deferred_violations: c_int,
+    read_error: Option<ChangesetError>,
+    insert_error: Option<SqliteErrorCode>,
+    duplicate: Option<ChangeOp>,
     aborted: bool,
     panicked: bool,
+    limit_exceeded: bool,
+}
+
+impl<'r, R: ?Sized> ConflictContext<'r, R> {
+    fn new(handler: &'r mut R, options: &ApplyOptions) -> Self {
+        Self {
+            handler,
+            db: ptr::null_mut(),
+            max_conflicts: options.max_conflicts,
+            // Omitted changes are needed to tell which ones were applied.
+            collect_omitted: options.collect_omitted
+                || options.on_applied.is_some()
+                || options.collect_affected_keys,
+            insert_on_not_found: options.insert_on_not_found,
+            append_only: options.append_only,
+            defer_foreign_keys: options.defer_foreign_keys,
+            conflicts: 0,
+            skipped: OpCounts::default(),
+            inserted_missing: 0,
+            omitted: Vec::new(),
+            deferred_violations: 0,
+            read_error: None,
+            insert_error: None,
+            duplicate: None,
+            aborted: false,
+            panicked: false,
+            limit_exceeded: false,
+        }
+    }
 }
 
 /// External C callback for conflict handling.
@@ -24,26 +834,119 @@ struct ConflictContext<F> {
 /// # Safety
 ///
 /// This function is called by `SQLite` with valid pointers.
-unsafe extern "C" fn conflict_callback<F>(
-    context: *mut std::ffi::c_void,
+unsafe extern "C" fn conflict_callback<R>(
+    context: *mut c_void,
     conflict_type: c_int,
-    _iter: *mut sqlite3_changeset_iter,
+    iter: *mut sqlite3_changeset_iter,
 ) -> c_int
 where
-    F: Fn(ConflictType) -> ConflictAction,
+    R: ConflictResolver + ?Sized,
 {
     // SAFETY: SQLite invokes this callback with the same context pointer we
-    // provided to `sqlite3changeset_apply`.
-    let ctx = unsafe { &mut *context.cast::<ConflictContext<F>>() };
+    // provided to `sqlite3changeset_apply_v2`.
+    let ctx = unsafe { &mut *context.cast::<ConflictContext<'_, R>>() };
 
-    let action = ConflictType::from_raw(conflict_type).map_or(ConflictAction::Abort, |conflict| {
-        if let Ok(action) = catch_unwind(AssertUnwindSafe(|| (ctx.handler)(conflict))) {
+    if ctx.defer_foreign_keys && conflict_type == ConflictType::ForeignKey.to_raw() {
+        // The violations are counted again once the apply returns, so the
+        // commit still fails while they remain.
+        // SAFETY: ForeignKey conflicts are reported with an iterator holding
+        // the number of outstanding violations.
+        let rc = unsafe { sqlite3changeset_fk_conflicts(iter, &mut ctx.deferred_violations) };
+        if rc != SQLITE_OK {
+            ctx.aborted = true;
+            return ConflictAction::Abort.to_raw();
+        }
+        return ConflictAction::Omit.to_raw();
+    }
+
+    if ctx
+        .max_conflicts
+        .is_some_and(|limit| ctx.conflicts >= limit)
+    {
+        ctx.limit_exceeded = true;
+        ctx.aborted = true;
+        return ConflictAction::Abort.to_raw();
+    }
+    ctx.conflicts += 1;
+
+    let Some(conflict) = ConflictType::from_raw(conflict_type) else {
+        ctx.aborted = true;
+        return ConflictAction::Abort.to_raw();
+    };
+
+    if conflict == ConflictType::Conflict && ctx.append_only {
+        // SAFETY: Conflict conflicts are reported with an iterator positioned on
+        // the conflicting change.
+        match unsafe { read_op(iter) } {
+            Ok(op) if op.op() == OpKind::Insert => ctx.duplicate = Some(op),
+            Ok(_) => {}
+            Err(err) => ctx.read_error = Some(err),
+        }
+        if ctx.duplicate.is_some() || ctx.read_error.is_some() {
+            ctx.aborted = true;
+            return ConflictAction::Abort.to_raw();
+        }
+    }
+
+    if conflict == ConflictType::NotFound && ctx.insert_on_not_found {
+        // SAFETY: NotFound conflicts are reported with an iterator positioned on
+        // the conflicting change.
+        let op = match unsafe { read_op(iter) } {
+            Ok(op) => op,
+            Err(err) => {
+                ctx.read_error = Some(err);
+                ctx.aborted = true;
+                return ConflictAction::Abort.to_raw();
+            }
+        };
+        if op.op() == OpKind::Update {
+            // SAFETY: `ctx.db` is the connection SQLite is applying to.
+            if let Err(rc) = unsafe { insert_missing_row(ctx.db, &op) } {
+                ctx.insert_error = Some(SqliteErrorCode::from_error(rc));
+                ctx.aborted = true;
+                return ConflictAction::Abort.to_raw();
+            }
+            ctx.skipped.updates += 1;
+            ctx.inserted_missing += 1;
+            return ConflictAction::Omit.to_raw();
+        }
+    }
+
+    // SAFETY: `iter` is the iterator SQLite passed for this conflict, valid
+    // until the callback returns, and `ctx.db` is null or the connection
+    // being applied to.
+    let details = unsafe { Conflict::new(conflict, iter, ctx.db) };
+    let action =
+        if let Ok(action) = catch_unwind(AssertUnwindSafe(|| ctx.handler.resolve(&details))) {
             action
         } else {
             ctx.panicked = true;
             ConflictAction::Abort
+        };
+
+    // Foreign key conflicts are reported once for the whole changeset, with an
+    // iterator that is not positioned on any particular change.
+    if action == ConflictAction::Omit && conflict != ConflictType::ForeignKey {
+        let skipped = if ctx.collect_omitted {
+            // SAFETY: for every other conflict type SQLite passes an iterator
+            // positioned on the conflicting change.
+            unsafe { read_op(iter) }.map(|op| {
+                ctx.omitted.push(OmittedChange::new(&op, conflict));
+                op.op()
+            })
+        } else {
+            // SAFETY: the iterator is positioned on the conflicting change, as above.
+            unsafe { read_op_kind(iter) }
+        };
+        match skipped {
+            Ok(kind) => *ctx.skipped.of_mut(kind) += 1,
+            Err(err) => {
+                ctx.read_error = Some(err);
+                ctx.aborted = true;
+                return ConflictAction::Abort.to_raw();
+            }
         }
-    });
+    }
 
     if action == ConflictAction::Abort {
         ctx.aborted = true;
@@ -52,6 +955,168 @@ where
     action.to_raw()
 }
 
+/// Insert the row an update expected to find.
+///
+/// Each column takes the update's new value, or the old value for primary key
+/// columns; columns without either are left to their defaults.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn insert_missing_row(db: *mut sqlite3, op: &ChangeOp) -> Result<(), c_int> {
+    // SAFETY: the caller guarantees `db` is valid.
+    let names = unsafe { column_names(db, "main", op.table()) }?;
+    let values: Vec<(&str, &SqliteValue)> = names
+        .iter()
+        .zip(
+            op.new_values()
+                .iter()
+                .zip(op.old_values())
+                .zip(op.primary_key()),
+        )
+        .filter_map(|(name, ((new, old), is_pk))| {
+            new.as_ref()
+                .or_else(|| old.as_ref().filter(|_| *is_pk))
+                .map(|value| (name.as_str(), value))
+        })
+        .collect();
+
+    let columns: Vec<String> = values
+        .iter()
+        .map(|(name, _)| quote_identifier(name))
+        .collect();
+    let params: Vec<String> = (1..=values.len())
+        .map(|index| format!("?{index}"))
+        .collect();
+    let sql = format!(
+        "INSERT INTO main.{} ({}) VALUES ({})",
+        quote_identifier(op.table()),
+        columns.join(", "),
+        params.join(", ")
+    );
+
+    // SAFETY: the caller guarantees `db` is valid; the statement is finalized
+    // before this function returns.
+    let mut stmt = unsafe { Statement::prepare(db, &sql) }?;
+    for (index, (_, value)) in (1..).zip(&values) {
+        stmt.bind_value(index, value)?;
+    }
+    stmt.execute()
+}
+
+/// Call `callback` for every operation of `data` that is not in `omitted`.
+fn report_applied(
+    data: &[u8],
+    omitted: &[OmittedChange],
+    callback: &mut dyn FnMut(&AppliedChange),
+) -> Result<(), ChangesetError> {
+    for change in read_changeset(data)? {
+        let change = change?;
+        let was_omitted = !omitted.is_empty()
+            && omitted.iter().any(|skipped| {
+                skipped.op == change.op()
+                    && skipped.table == change.table()
+                    && skipped.primary_key == change.primary_key_values()
+            });
+        if !was_omitted {
+            callback(&AppliedChange { change });
+        }
+    }
+    Ok(())
+}
+
+/// Temporary table whose rows stand in for foreign key violations deferred
+/// past an apply.
+const PENDING_VIOLATIONS: &str = "diesel_sqlite_session_pending_foreign_keys";
+
+/// Count `violations` pending foreign key violations again after an apply.
+///
+/// `sqlite3changeset_apply_v2` switches `PRAGMA defer_foreign_keys` off before
+/// returning, which discards the violations deferred through it; only those of
+/// constraints declared `DEFERRABLE INITIALLY DEFERRED` stay counted. The rest
+/// are recreated as rows of a temporary table referencing a row that does not
+/// exist, inserted with the pragma back on, so the commit fails unless later
+/// changes resolve the real violations first. The table is dropped and
+/// recreated after every deferring apply.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn keep_foreign_key_violations(db: *mut sqlite3, violations: c_int) -> Result<(), c_int> {
+    let (mut still_counted, mut highwater) = (0, 0);
+    // SAFETY: the caller guarantees `db` is valid.
+    let rc = unsafe {
+        sqlite3_db_status(
+            db,
+            SQLITE_DBSTATUS_DEFERRED_FKS,
+            &mut still_counted,
+            &mut highwater,
+            0,
+        )
+    };
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    let lost = violations.saturating_sub(still_counted);
+
+    // Dropped while the pragma is off, so its orphan rows leave the count of
+    // the violations still deferred alone.
+    // SAFETY: the caller guarantees `db` is valid.
+    unsafe {
+        execute(
+            db,
+            &format!("DROP TABLE IF EXISTS temp.{PENDING_VIOLATIONS}"),
+        )
+    }?;
+    if lost <= 0 {
+        return Ok(());
+    }
+    for sql in [
+        "PRAGMA defer_foreign_keys = ON".to_owned(),
+        format!(
+            "CREATE TEMP TABLE {PENDING_VIOLATIONS} \
+             (id INTEGER PRIMARY KEY, parent INTEGER REFERENCES {PENDING_VIOLATIONS} (id))"
+        ),
+        format!(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {lost}) \
+             INSERT INTO temp.{PENDING_VIOLATIONS} (parent) SELECT -i FROM n"
+        ),
+    ] {
+        // SAFETY: the caller guarantees `db` is valid.
+        unsafe { execute(db, &sql) }?;
+    }
+    Ok(())
+}
+
+/// Run a single SQL statement that returns no rows.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn execute(db: *mut sqlite3, sql: &str) -> Result<(), c_int> {
+    // SAFETY: the caller guarantees `db` is valid.
+    unsafe { Statement::prepare(db, sql) }?.execute()
+}
+
+fn apply_failed(rc: c_int) -> ApplyError {
+    ApplyError::ApplyFailed(SqliteErrorCode::from_error(rc))
+}
+
+/// Map the result code of `sqlite3changeset_apply_v2`.
+///
+/// `SQLite` returns `SQLITE_ABORT` when a conflict handler aborts, but also
+/// when a statement is aborted for other reasons, so only `aborted`, set by
+/// the conflict callback, identifies an abort requested by the handler.
+fn apply_result(rc: c_int, aborted: bool) -> Result<(), ApplyError> {
+    if aborted {
+        Err(ApplyError::ConflictAborted)
+    } else if rc == SQLITE_OK {
+        Ok(())
+    } else {
+        Err(apply_failed(rc))
+    }
+}
+
 /// Apply a changeset to a Diesel connection.
 ///
 /// A changeset contains complete information about changes, including old
@@ -67,7 +1132,32 @@ pub(crate) fn apply_changeset<F>(
 where
     F: Fn(ConflictType) -> ConflictAction,
 {
-    apply_impl(conn, changeset, on_conflict)
+    apply_impl(
+        conn,
+        changeset,
+        &ApplyOptions::default(),
+        &mut ByKind(on_conflict),
+    )
+    .map(drop)
+}
+
+/// Apply a changeset recorded on tables whose columns are declared in another order.
+///
+/// The columns are moved with [`reorder_changeset_columns`] before applying.
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_changeset_mapped` instead.
+pub(crate) fn apply_changeset_mapped<M, F>(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+    column_map: M,
+    on_conflict: F,
+) -> Result<(), ApplyError>
+where
+    M: Fn(&str, usize) -> usize,
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    let reordered = reorder_changeset_columns(changeset, column_map)?;
+    apply_changeset(conn, &reordered, on_conflict)
 }
 
 /// Apply a patchset to a Diesel connection.
@@ -85,73 +1175,689 @@ pub(crate) fn apply_patchset<F>(
 where
     F: Fn(ConflictType) -> ConflictAction,
 {
-    apply_impl(conn, patchset, on_conflict)
+    apply_impl(
+        conn,
+        patchset,
+        &ApplyOptions::default(),
+        &mut ByKind(on_conflict),
+    )
+    .map(drop)
 }
 
-/// Internal implementation for applying both changesets and patchsets.
+/// Apply a changeset or patchset to a Diesel connection with explicit options.
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_changeset_with`
+/// or `SqliteSessionExt::apply_patchset_with` instead.
 #[inline]
-fn apply_impl<F>(conn: &mut SqliteConnection, data: &[u8], on_conflict: F) -> Result<(), ApplyError>
+pub(crate) fn apply_with_options<F>(
+    conn: &mut SqliteConnection,
+    data: &[u8],
+    options: &ApplyOptions,
+    on_conflict: F,
+) -> Result<ApplyStats, ApplyError>
 where
     F: Fn(ConflictType) -> ConflictAction,
 {
-    if data.is_empty() {
-        return Ok(());
-    }
+    apply_impl(conn, data, options, &mut ByKind(on_conflict))
+}
 
-    let mut context = ConflictContext {
-        handler: on_conflict,
-        aborted: false,
-        panicked: false,
-    };
-    let data_len = c_int::try_from(data.len())
-        .map_err(|_| ApplyError::ApplyFailed(SqliteErrorCode::from_error(SQLITE_TOOBIG)))?;
+/// Apply a changeset, returning the raw `SQLite` result code with any error.
+///
+/// The code is the one returned by the last `sqlite3changeset_apply_v2` call
+/// when it was not `SQLITE_OK`, else the code carried by an
+/// `ApplyError::ApplyFailed`, else zero: the apply failed outside `SQLite`.
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_changeset_verbose` instead.
+pub(crate) fn apply_verbose<F>(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+    options: &ApplyOptions,
+    on_conflict: F,
+) -> Result<ApplyStats, (ApplyError, c_int)>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    let result_code = Rc::new(Cell::new(SQLITE_OK));
+    let mut options = options.clone();
+    options.result_code = Some(Rc::clone(&result_code));
+    apply_impl(conn, changeset, &options, &mut ByKind(on_conflict)).map_err(|err| {
+        let rc = match (result_code.get(), &err) {
+            (SQLITE_OK, ApplyError::ApplyFailed(code)) => code.to_raw(),
+            (rc, _) => rc,
+        };
+        (err, rc)
+    })
+}
+
+/// Apply a changeset or patchset, resolving conflicts with a [`ConflictResolver`].
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_changeset_resolving`
+/// or `SqliteSessionExt::apply_patchset_resolving` instead.
+#[inline]
+pub(crate) fn apply_resolving<R>(
+    conn: &mut SqliteConnection,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    apply_impl(conn, data, options, resolver)
+}
+
+/// Apply a changeset or patchset with a fixed conflict action, recording every
+/// conflict.
+///
+/// This is an internal function. Use
+/// `SqliteSessionExt::apply_collecting_conflicts` instead.
+#[inline]
+pub(crate) fn apply_collecting_conflicts(
+    conn: &mut SqliteConnection,
+    data: &[u8],
+    action: ConflictAction,
+) -> Result<(ApplyStats, Vec<ConflictRecord>), ApplyError> {
+    let mut conflicts = Vec::new();
+    let stats = apply_impl(
+        conn,
+        data,
+        &ApplyOptions::default(),
+        &mut |conflict: &Conflict<'_>| {
+            conflicts.push(ConflictRecord::capture(conflict));
+            action
+        },
+    )?;
+    Ok((stats, conflicts))
+}
+
+/// Number of conflict events buffered before the apply waits for the consumer.
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+const EVENT_BUFFER: usize = 64;
+
+/// Apply a changeset or patchset streamed from `reader` on a worker thread,
+/// handing the conflict events to `consume` on the calling thread.
+///
+/// This is an internal function. Use
+/// `SqliteSessionExt::apply_changeset_from_reader_iter` instead.
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+pub(crate) fn apply_from_reader_iter<I, F, C, T>(
+    conn: &mut SqliteConnection,
+    reader: I,
+    on_conflict: F,
+    consume: C,
+) -> Result<T, ApplyError>
+where
+    I: std::io::Read + Send,
+    F: Fn(ConflictType) -> ConflictAction + Send,
+    C: FnOnce(ConflictEvents<'_>) -> T,
+{
+    let (events, receiver) = std::sync::mpsc::sync_channel(EVENT_BUFFER);
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(move || {
+            let mut resolver = EventResolver {
+                handler: on_conflict,
+                events,
+            };
+            // SAFETY: `with_raw_connection` provides a valid SQLite connection
+            // pointer for the duration of the callback, and the connection is
+            // borrowed by this thread alone until it is joined.
+            unsafe { conn.with_raw_connection(|raw| apply_stream(raw, reader, &mut resolver)) }
+        });
+        // Dropping the events when `consume` returns lets the apply finish
+        // without waiting for them.
+        let output = consume(ConflictEvents::new(receiver));
+        let result = worker
+            .join()
+            .unwrap_or_else(|payload| std::panic::resume_unwind(payload));
+        result.map(|()| output)
+    })
+}
+
+/// Apply a changeset or patchset read incrementally from `reader`.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+unsafe fn apply_stream<I, R>(
+    db: *mut sqlite3,
+    reader: I,
+    resolver: &mut R,
+) -> Result<(), ApplyError>
+where
+    I: std::io::Read,
+    R: ConflictResolver + ?Sized,
+{
+    let _timer = Timer::start(APPLY_DURATION);
+    let options = ApplyOptions::default();
+    let mut context = ConflictContext::new(resolver, &options);
+    context.db = db;
+    let mut input = InputContext::new(reader);
 
-    // SAFETY: `with_raw_connection` provides a valid SQLite connection pointer for
-    // the callback duration, `data` lives through the FFI call, and `context`
-    // points to stack storage that also outlives the call.
+    // SAFETY: the caller guarantees `db` is valid, and `input` and `context`
+    // point to stack storage that outlives the call and matches the callbacks.
     let rc = unsafe {
+        sqlite3changeset_apply_v2_strm(
+            db,
+            Some(input_callback::<I>),
+            ptr::addr_of_mut!(input).cast(),
+            None, // xFilter - no filtering
+            Some(conflict_callback::<R>),
+            ptr::addr_of_mut!(context).cast(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+        )
+    };
+
+    input.finish().map_err(ChangesetError::from)?;
+    if context.panicked {
+        return Err(ApplyError::ConflictHandlerPanicked);
+    }
+    apply_result(rc, context.aborted)
+}
+
+/// Apply a changeset and return the rebase data of the conflicts it resolved.
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_changeset_rebasing`
+/// instead.
+#[inline]
+pub(crate) fn apply_rebasing<F>(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+    options: &ApplyOptions,
+    on_conflict: F,
+) -> Result<(ApplyStats, RebaseData), ApplyError>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    let mut rebase = Vec::new();
+    // SAFETY: `with_raw_connection` provides a valid SQLite connection pointer
+    // for the duration of the callback.
+    let stats = unsafe {
         conn.with_raw_connection(|raw| {
-            sqlite3changeset_apply(
+            apply_raw_rebasing(
                 raw,
-                data_len,
-                data.as_ptr().cast::<std::ffi::c_void>().cast_mut(),
-                None, // xFilter - no filtering
-                Some(conflict_callback::<F>),
-                ptr::addr_of_mut!(context).cast(),
+                changeset,
+                options,
+                &mut ByKind(on_conflict),
+                Some(&mut rebase),
             )
         })
+    }?;
+    Ok((stats, RebaseData::from_bytes(rebase)))
+}
+
+/// Apply a changeset and return its inverse for undo purposes.
+///
+/// The inverse is computed before applying so that an invalid changeset is
+/// rejected without touching the database.
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_with_undo` instead.
+#[inline]
+pub(crate) fn apply_with_undo<F>(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+    on_conflict: F,
+) -> Result<Changeset, ApplyError>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    let undo = invert_changeset(changeset)?;
+    apply_impl(
+        conn,
+        changeset,
+        &ApplyOptions::default(),
+        &mut ByKind(on_conflict),
+    )?;
+    Ok(undo)
+}
+
+/// Apply a changeset and return the inverse of the changes that were applied.
+///
+/// Changes omitted by the conflict handler are left out of the inverse, so it
+/// stays exact for partial applies. Patchsets are rejected before anything is
+/// applied.
+///
+/// This is an internal function. Use `SqliteSessionExt::apply_with_scoped_undo` instead.
+#[inline]
+pub(crate) fn apply_with_scoped_undo<F>(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+    on_conflict: F,
+) -> Result<(ApplyStats, Changeset), ApplyError>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    if Format::of(changeset) == Format::Patchset {
+        return Err(ChangesetError::InvertFailed(SqliteErrorCode::Misuse).into());
+    }
+    let stats = apply_impl(
+        conn,
+        changeset,
+        &ApplyOptions::default().collect_omitted(true),
+        &mut ByKind(on_conflict),
+    )?;
+
+    let mut applied = Vec::new();
+    report_applied(changeset, stats.omitted(), &mut |change: &AppliedChange| {
+        applied.push(change.change().clone());
+    })?;
+    let undo = invert_changeset(&applied.into_iter().collect::<Changeset>())?;
+    Ok((stats, undo))
+}
+
+/// Internal implementation for applying both changesets and patchsets.
+#[inline]
+fn apply_impl<R>(
+    conn: &mut SqliteConnection,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    // SAFETY: `with_raw_connection` provides a valid SQLite connection pointer
+    // for the duration of the callback.
+    unsafe { conn.with_raw_connection(|raw| apply_raw(raw, data, options, resolver)) }
+}
+
+/// Apply a changeset or patchset to a raw connection handle, retrying as
+/// configured by [`ApplyOptions::busy_retry`] and routing to the database set
+/// with [`ApplyOptions::target_db`].
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+pub(crate) unsafe fn apply_raw<R>(
+    db: *mut sqlite3,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    // SAFETY: the caller guarantees `db` is valid.
+    unsafe { apply_raw_rebasing(db, data, options, resolver, None) }
+}
+
+/// Like [`apply_raw`], additionally storing the rebase data of the apply in
+/// `rebase` when given.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn apply_raw_rebasing<R>(
+    db: *mut sqlite3,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+    rebase: Option<&mut Vec<u8>>,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    let _timer = Timer::start(APPLY_DURATION);
+    input_len(data.len())?;
+    let target = match options.target_db.as_deref() {
+        None | Some("main") => None,
+        // SAFETY: the caller guarantees `db` is valid.
+        Some(schema) => Some(unsafe { TargetConnection::open(db, schema) }?),
+    };
+    let db = target.as_ref().map_or(db, |target| target.0);
+
+    let small = options.optimize_small
+        && !options.per_operation_isolation
+        && !options.no_savepoint
+        && options.savepoint_name.is_none()
+        && holds_single_op(data)?;
+    // Without an enclosing transaction the apply commits on its own, leaving
+    // no later commit to defer foreign key checks to.
+    let undeferred = options.defer_foreign_keys
+        // SAFETY: `db` is the caller's valid handle or the open target connection.
+        && unsafe { sqlite3_get_autocommit(db) } != 0;
+    let adjusted;
+    let options = if small || undeferred {
+        adjusted = ApplyOptions {
+            no_savepoint: options.no_savepoint || small,
+            defer_foreign_keys: options.defer_foreign_keys && !undeferred,
+            ..options.clone()
+        };
+        &adjusted
+    } else {
+        options
+    };
+
+    if options.per_operation_isolation {
+        // SAFETY: `db` is the caller's valid handle or the open target connection.
+        unsafe { apply_isolated(db, data, options, resolver, rebase) }
+    } else {
+        // SAFETY: `db` is the caller's valid handle or the open target connection.
+        unsafe { apply_retrying(db, data, options, resolver, rebase) }
+    }
+}
+
+/// Apply every change of `data` separately, recording the ones that fail.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn apply_isolated<R>(
+    db: *mut sqlite3,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+    mut rebase: Option<&mut Vec<u8>>,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    if data.is_empty() {
+        return Ok(ApplyStats::empty());
+    }
+
+    let format = Format::of(data);
+    let mut stats = ApplyStats::default();
+    let mut op_options = options.clone();
+    let mut op_rebase = Vec::new();
+    for op in read_changeset(data)? {
+        let op = op?;
+        let mut encoder = Encoder::new(format);
+        encoder.push(&op);
+        let single = encoder.finish();
+
+        // Conflicts are limited across the whole input, not per change.
+        if let Some(limit) = options.max_conflicts {
+            op_options.max_conflicts = Some(limit.saturating_sub(stats.conflicts));
+        }
+        let rebase_out = rebase.is_some().then_some(&mut op_rebase);
+        // SAFETY: the caller guarantees `db` is valid.
+        match unsafe { apply_retrying(db, &single, &op_options, resolver, rebase_out) } {
+            Ok(op_stats) => {
+                stats.absorb(op_stats);
+                if let Some(rebase) = rebase.as_deref_mut() {
+                    rebase.extend_from_slice(&op_rebase);
+                }
+            }
+            Err(ApplyError::ConflictLimitExceeded { .. }) => {
+                return Err(ApplyError::ConflictLimitExceeded {
+                    limit: options.max_conflicts.unwrap_or_default(),
+                });
+            }
+            Err(err @ (ApplyError::ConflictHandlerPanicked | ApplyError::Changeset(_))) => {
+                return Err(err);
+            }
+            Err(err) if is_busy(&err) => return Err(err),
+            Err(err) => {
+                // An aborted change stops at the conflict the handler saw.
+                if matches!(err, ApplyError::ConflictAborted) {
+                    stats.conflicts += 1;
+                }
+                stats.failed.push(FailedChange::new(&op, &err));
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Apply a changeset or patchset, retrying as configured by
+/// [`ApplyOptions::busy_retry`].
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn apply_retrying<R>(
+    db: *mut sqlite3,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+    mut rebase: Option<&mut Vec<u8>>,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    let mut attempt = 1;
+    loop {
+        // SAFETY: the caller guarantees `db` is valid.
+        let result = unsafe { apply_once(db, data, options, resolver, rebase.as_deref_mut()) };
+        match options.busy_retry {
+            Some(retry) if attempt < retry.max_attempts && result.as_ref().is_err_and(is_busy) => {
+                #[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+                std::thread::sleep(retry.delay_after(attempt));
+                attempt += 1;
+            }
+            _ => return result,
+        }
+    }
+}
+
+/// Apply a changeset or patchset to a raw connection handle once, inside the
+/// savepoint named with [`ApplyOptions::savepoint_name`] if any.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn apply_once<R>(
+    db: *mut sqlite3,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+    rebase: Option<&mut Vec<u8>>,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    let Some(name) = options.named_savepoint() else {
+        // SAFETY: the caller guarantees `db` is valid.
+        return unsafe { apply_v2(db, data, options, resolver, rebase) };
+    };
+    let name = quote_identifier(name);
+    // SAFETY: the caller guarantees `db` is valid.
+    unsafe { execute(db, &format!("SAVEPOINT {name}")) }.map_err(apply_failed)?;
+
+    // SAFETY: the caller guarantees `db` is valid.
+    let result = unsafe { apply_v2(db, data, options, resolver, rebase) };
+    let rolled_back = if result.is_ok() {
+        Ok(())
+    } else {
+        // SAFETY: the caller guarantees `db` is valid.
+        unsafe { execute(db, &format!("ROLLBACK TO {name}")) }
     };
+    // SAFETY: the caller guarantees `db` is valid.
+    let released = rolled_back.and_then(|()| unsafe { execute(db, &format!("RELEASE {name}")) });
+    let stats = result?;
+    released.map_err(apply_failed)?;
+    Ok(stats)
+}
+
+/// Whether `data` holds exactly one operation, read without decoding values.
+fn holds_single_op(data: &[u8]) -> Result<bool, ChangesetError> {
+    if data.is_empty() {
+        return Ok(false);
+    }
+    let mut iter = read_changeset(data)?;
+    match iter.next_kind().transpose()? {
+        Some(_) => Ok(iter.next_kind().transpose()?.is_none()),
+        None => Ok(false),
+    }
+}
+
+/// Convert the length of an input to the `c_int` `SQLite` takes.
+fn input_len(len: usize) -> Result<c_int, ApplyError> {
+    c_int::try_from(len).map_err(|_| ApplyError::TooLarge { len })
+}
+
+/// Apply a changeset or patchset with a single `sqlite3changeset_apply_v2` call.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn apply_v2<R>(
+    db: *mut sqlite3,
+    data: &[u8],
+    options: &ApplyOptions,
+    resolver: &mut R,
+    rebase: Option<&mut Vec<u8>>,
+) -> Result<ApplyStats, ApplyError>
+where
+    R: ConflictResolver + ?Sized,
+{
+    if data.is_empty() {
+        return Ok(ApplyStats::empty());
+    }
+    validate_changeset(data)?;
+
+    let total = OpCounts::of_changeset(data)?;
+    let mut context = ConflictContext::new(resolver, options);
+    let data_len = input_len(data.len())?;
+
+    context.db = db;
+
+    let mut rebase_buffer: *mut c_void = ptr::null_mut();
+    let mut rebase_len: c_int = 0;
+    // Null rebase out-pointers tell SQLite not to produce rebase data.
+    let (rebase_buffer_ptr, rebase_len_ptr) = if rebase.is_some() {
+        (
+            ptr::addr_of_mut!(rebase_buffer),
+            ptr::addr_of_mut!(rebase_len),
+        )
+    } else {
+        (ptr::null_mut(), ptr::null_mut())
+    };
+
+    // SAFETY: the caller guarantees `db` is valid.
+    let changes_before = unsafe { sqlite3_total_changes64(db) };
+    // SAFETY: the caller guarantees `db` is valid, `data` lives through the FFI
+    // call, and `context` and the rebase out-pointers point to stack storage
+    // that also outlives the call.
+    let rc = unsafe {
+        sqlite3changeset_apply_v2(
+            db,
+            data_len,
+            data.as_ptr().cast::<c_void>().cast_mut(),
+            None, // xFilter - no filtering
+            Some(conflict_callback::<R>),
+            ptr::addr_of_mut!(context).cast(),
+            rebase_buffer_ptr,
+            rebase_len_ptr,
+            options.flags(),
+        )
+    };
+    if let Some(result_code) = &options.result_code {
+        result_code.set(rc);
+    }
+    // SAFETY: the caller guarantees `db` is valid.
+    let rows_changed = unsafe { sqlite3_total_changes64(db) } - changes_before;
+    // SAFETY: SQLite only sets the rebase buffer on success, handing over a
+    // `sqlite3_malloc` allocation of `rebase_len` bytes; it is taken, and so
+    // freed, whatever the outcome.
+    let rebase_data = unsafe { take_sqlite_buffer(rebase_buffer, rebase_len) };
 
     if context.panicked {
         return Err(ApplyError::ConflictHandlerPanicked);
     }
 
-    if context.aborted {
-        return Err(ApplyError::ConflictAborted);
+    if let Some(err) = context.read_error {
+        return Err(err.into());
     }
 
-    if rc != SQLITE_OK && rc != SQLITE_CHANGESET_ABORT {
-        return Err(ApplyError::ApplyFailed(SqliteErrorCode::from_error(rc)));
+    if let Some(code) = context.insert_error {
+        return Err(ApplyError::ApplyFailed(code));
     }
 
-    Ok(())
+    if let Some(op) = context.duplicate {
+        return Err(ApplyError::DuplicatePrimaryKey {
+            table: op.table().to_owned(),
+            primary_key: op.primary_key_values(),
+        });
+    }
+
+    if context.limit_exceeded {
+        return Err(ApplyError::ConflictLimitExceeded {
+            limit: options.max_conflicts.unwrap_or_default(),
+        });
+    }
+
+    apply_result(rc, context.aborted)?;
+
+    if options.defer_foreign_keys {
+        // SAFETY: the caller guarantees `db` is valid.
+        unsafe { keep_foreign_key_violations(db, context.deferred_violations) }
+            .map_err(apply_failed)?;
+    }
+
+    if let Some(rebase) = rebase {
+        *rebase =
+            rebase_data.map_err(|size| ApplyError::ApplyFailed(SqliteErrorCode::Unknown(size)))?;
+    }
+
+    applied_stats(data, options, context, total, rows_changed)
+}
+
+/// Build the stats of a successful apply, first reporting the applied changes
+/// to the `on_applied` callback and collecting their keys if requested.
+fn applied_stats<R: ?Sized>(
+    data: &[u8],
+    options: &ApplyOptions,
+    context: ConflictContext<'_, R>,
+    total: OpCounts,
+    rows_changed: i64,
+) -> Result<ApplyStats, ApplyError> {
+    if let Some(AppliedHook(callback)) = &options.on_applied {
+        report_applied(data, &context.omitted, &mut *callback.borrow_mut())?;
+    }
+
+    let mut affected_keys: HashMap<String, Vec<Vec<SqliteValue>>> = HashMap::new();
+    if options.collect_affected_keys {
+        report_applied(data, &context.omitted, &mut |change: &AppliedChange| {
+            affected_keys
+                .entry(change.table().to_owned())
+                .or_default()
+                .push(change.primary_key());
+        })?;
+    }
+
+    let skipped = context.skipped;
+    Ok(ApplyStats {
+        conflicts: context.conflicts,
+        applied: OpCounts {
+            inserts: total.inserts.saturating_sub(skipped.inserts) + context.inserted_missing,
+            updates: total.updates.saturating_sub(skipped.updates),
+            deletes: total.deletes.saturating_sub(skipped.deletes),
+        },
+        omitted: if options.collect_omitted {
+            context.omitted
+        } else {
+            Vec::new()
+        },
+        failed: Vec::new(),
+        affected_keys,
+        rows_changed: u64::try_from(rows_changed).unwrap_or_default(),
+        empty_input: false,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use std::ptr;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
     use super::*;
+    use crate::ffi::SQLITE_ABORT;
 
-    fn invoke_conflict_callback<F>(context: &mut ConflictContext<F>, conflict_type: i32) -> c_int
+    fn invoke_conflict_callback<F>(
+        context: &mut ConflictContext<'_, ByKind<F>>,
+        conflict_type: i32,
+    ) -> c_int
     where
         F: Fn(ConflictType) -> ConflictAction,
     {
         // SAFETY: `context` points to valid storage for the callback duration and
         // iterator pointer is null because the callback implementation does not use it.
         unsafe {
-            conflict_callback::<F>(
+            conflict_callback::<ByKind<F>>(
                 ptr::addr_of_mut!(*context).cast(),
                 conflict_type,
                 ptr::null_mut(),
@@ -161,17 +1867,14 @@ mod tests {
 
     #[test]
     fn conflict_callback_uses_handler_for_known_conflicts() {
-        let mut context = ConflictContext {
-            handler: |conflict: ConflictType| {
-                if conflict == ConflictType::Data {
-                    ConflictAction::Replace
-                } else {
-                    ConflictAction::Abort
-                }
-            },
-            aborted: false,
-            panicked: false,
-        };
+        let mut handler = ByKind(|conflict: ConflictType| {
+            if conflict == ConflictType::Data {
+                ConflictAction::Replace
+            } else {
+                ConflictAction::Abort
+            }
+        });
+        let mut context = ConflictContext::new(&mut handler, &ApplyOptions::default());
 
         let rc = invoke_conflict_callback(&mut context, ConflictType::Data.to_raw());
 
@@ -183,14 +1886,11 @@ mod tests {
     #[test]
     fn conflict_callback_aborts_unknown_conflict_codes() {
         let invoked = AtomicBool::new(false);
-        let mut context = ConflictContext {
-            handler: |_| {
-                invoked.store(true, Ordering::SeqCst);
-                ConflictAction::Replace
-            },
-            aborted: false,
-            panicked: false,
-        };
+        let mut handler = ByKind(|_: ConflictType| {
+            invoked.store(true, Ordering::SeqCst);
+            ConflictAction::Replace
+        });
+        let mut context = ConflictContext::new(&mut handler, &ApplyOptions::default());
 
         let rc = invoke_conflict_callback(&mut context, 999);
 
@@ -202,13 +1902,10 @@ mod tests {
 
     #[test]
     fn conflict_callback_marks_panicked_handlers() {
-        let mut context = ConflictContext {
-            handler: |_| -> ConflictAction {
-                panic!("boom");
-            },
-            aborted: false,
-            panicked: false,
-        };
+        let mut handler = ByKind(|_: ConflictType| -> ConflictAction {
+            panic!("boom");
+        });
+        let mut context = ConflictContext::new(&mut handler, &ApplyOptions::default());
 
         let rc = invoke_conflict_callback(&mut context, ConflictType::Data.to_raw());
 
@@ -216,4 +1913,54 @@ mod tests {
         assert!(context.aborted);
         assert!(context.panicked);
     }
+
+    #[test]
+    fn apply_result_distinguishes_handler_aborts_from_sqlite_aborts() {
+        assert!(matches!(
+            apply_result(SQLITE_ABORT, true),
+            Err(ApplyError::ConflictAborted)
+        ));
+        assert_eq!(
+            apply_result(SQLITE_ABORT, false).unwrap_err().to_string(),
+            apply_failed(SQLITE_ABORT).to_string()
+        );
+        // The conflict handler's abort action is not a result code.
+        assert!(matches!(
+            apply_result(ConflictAction::Abort.to_raw(), false),
+            Err(ApplyError::ApplyFailed(_))
+        ));
+        assert!(apply_result(SQLITE_OK, false).is_ok());
+    }
+
+    #[test]
+    fn input_len_reports_inputs_over_the_c_int_limit() {
+        let limit = usize::try_from(c_int::MAX).unwrap();
+        assert_eq!(input_len(limit).unwrap(), c_int::MAX);
+        assert!(matches!(
+            input_len(limit + 1),
+            Err(ApplyError::TooLarge { len }) if len == limit + 1
+        ));
+    }
+
+    #[test]
+    fn conflict_callback_aborts_once_limit_is_reached() {
+        let invocations = AtomicUsize::new(0);
+        let mut handler = ByKind(|_: ConflictType| {
+            invocations.fetch_add(1, Ordering::SeqCst);
+            ConflictAction::Replace
+        });
+        let mut context = ConflictContext::new(&mut handler, &ApplyOptions::new().max_conflicts(2));
+
+        for _ in 0..2 {
+            let rc = invoke_conflict_callback(&mut context, ConflictType::Data.to_raw());
+            assert_eq!(rc, ConflictAction::Replace.to_raw());
+        }
+        assert!(!context.limit_exceeded);
+
+        let rc = invoke_conflict_callback(&mut context, ConflictType::Data.to_raw());
+        assert_eq!(rc, ConflictAction::Abort.to_raw());
+        assert!(context.limit_exceeded);
+        assert!(context.aborted);
+        assert_eq!(invocations.load(Ordering::SeqCst), 2);
+    }
 }
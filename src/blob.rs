@@ -0,0 +1,33 @@
+//! Store changesets and patchsets in Diesel `BLOB` columns.
+
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Binary;
+use diesel::sqlite::Sqlite;
+
+use crate::changeset::{Changeset, Patchset};
+
+impl ToSql<Binary, Sqlite> for Changeset {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <[u8] as ToSql<Binary, Sqlite>>::to_sql(self.as_bytes(), out)
+    }
+}
+
+impl FromSql<Binary, Sqlite> for Changeset {
+    fn from_sql(value: <Sqlite as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        <Vec<u8> as FromSql<Binary, Sqlite>>::from_sql(value).map(Self::from_bytes)
+    }
+}
+
+impl ToSql<Binary, Sqlite> for Patchset {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <[u8] as ToSql<Binary, Sqlite>>::to_sql(self.as_bytes(), out)
+    }
+}
+
+impl FromSql<Binary, Sqlite> for Patchset {
+    fn from_sql(value: <Sqlite as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        <Vec<u8> as FromSql<Binary, Sqlite>>::from_sql(value).map(Self::from_bytes)
+    }
+}
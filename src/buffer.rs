@@ -0,0 +1,38 @@
+//! Ownership helpers for output buffers allocated by `SQLite`.
+
+use std::ffi::{c_int, c_void};
+
+use crate::ffi::sqlite3_free;
+
+/// Copy an output buffer allocated by `SQLite` into an owned `Vec<u8>` and free it.
+///
+/// The buffer is always released, even when `size` is not a valid byte length,
+/// in which case `Err(size)` is returned.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to at least `size` bytes allocated with the
+/// `sqlite3_malloc` family of functions, and must not be used after this call.
+pub(crate) unsafe fn take_sqlite_buffer(
+    buffer: *mut c_void,
+    size: c_int,
+) -> Result<Vec<u8>, c_int> {
+    let result = if size <= 0 || buffer.is_null() {
+        Ok(Vec::new())
+    } else {
+        usize::try_from(size).map_err(|_| size).map(|byte_len| {
+            // SAFETY: the caller guarantees `buffer` holds `byte_len` readable bytes;
+            // we copy those bytes immediately into an owned `Vec<u8>`.
+            let bytes = unsafe { std::slice::from_raw_parts(buffer.cast::<u8>(), byte_len) };
+            bytes.to_vec()
+        })
+    };
+
+    if !buffer.is_null() {
+        // SAFETY: SQLite allocates output buffers with sqlite3_malloc-family APIs
+        // and requires release via `sqlite3_free`.
+        unsafe { sqlite3_free(buffer) };
+    }
+
+    result
+}
@@ -0,0 +1,283 @@
+//! Build changesets from operations computed in Rust.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::changeset::Changeset;
+use crate::encode::{Encoder, Format};
+use crate::errors::ChangesetError;
+use crate::iter::{ChangeOp, OpKind};
+use crate::value::SqliteValue;
+
+/// Builder synthesizing a changeset from typed operations.
+///
+/// Use this when changes are computed in Rust rather than recorded by a
+/// [`Session`](crate::Session), for example when importing from a source that
+/// is not `SQLite`. Every table must be declared with
+/// [`table`](Self::table) before operations on it are added, since a
+/// changeset records which columns form the primary key.
+///
+/// Values are positional, in the column order of the table. Operations are
+/// written in the order they are added.
+///
+/// # Example
+///
+/// ```
+/// use diesel_sqlite_session::{ChangesetBuilder, SqliteValue};
+///
+/// let mut builder = ChangesetBuilder::new();
+/// builder.table("items", &[true, false]);
+/// builder
+///     .insert("items", vec![SqliteValue::Integer(1), SqliteValue::Text(b"one".to_vec())])
+///     .unwrap()
+///     .delete("items", vec![SqliteValue::Integer(2), SqliteValue::Text(b"two".to_vec())])
+///     .unwrap();
+/// let changeset = builder.build();
+/// assert!(!changeset.is_empty());
+/// ```
+#[derive(Debug)]
+pub struct ChangesetBuilder {
+    tables: HashMap<String, Vec<u8>>,
+    encoder: Encoder,
+}
+
+impl Default for ChangesetBuilder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChangesetBuilder {
+    /// Create an empty builder.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+            encoder: Encoder::new(Format::Changeset),
+        }
+    }
+
+    /// Declare a table and which of its columns form the primary key.
+    ///
+    /// `primary_key` holds one flag per column. Declaring a table again
+    /// replaces its layout for the operations added afterwards.
+    pub fn table(&mut self, name: &str, primary_key: &[bool]) -> &mut Self {
+        let flags = primary_key.iter().map(|&is_pk| u8::from(is_pk)).collect();
+        self.tables.insert(name.to_owned(), flags);
+        self
+    }
+
+    /// Add the insertion of a row with the given column values.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::UnknownTable` if `table` was not declared.
+    /// Returns `ChangesetError::ColumnCountMismatch` if `values` does not hold
+    /// one value per column.
+    pub fn insert(
+        &mut self,
+        table: &str,
+        values: Vec<SqliteValue>,
+    ) -> Result<&mut Self, ChangesetError> {
+        let new = values.into_iter().map(Some).collect();
+        self.push(table, OpKind::Insert, Vec::new(), new)
+    }
+
+    /// Add an update of an existing row.
+    ///
+    /// `old` must hold the primary key values and the prior values of the
+    /// changed columns; `new` holds the updated values of the changed
+    /// columns. Use `None` for every other column.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::UnknownTable` if `table` was not declared.
+    /// Returns `ChangesetError::ColumnCountMismatch` if `old` or `new` does not
+    /// hold one entry per column.
+    /// Returns `ChangesetError::MissingPrimaryKey` if `old` lacks a primary key value.
+    pub fn update(
+        &mut self,
+        table: &str,
+        old: Vec<Option<SqliteValue>>,
+        new: Vec<Option<SqliteValue>>,
+    ) -> Result<&mut Self, ChangesetError> {
+        self.push(table, OpKind::Update, old, new)
+    }
+
+    /// Add the deletion of a row with the given prior column values.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::UnknownTable` if `table` was not declared.
+    /// Returns `ChangesetError::ColumnCountMismatch` if `old` does not hold one
+    /// value per column.
+    pub fn delete(
+        &mut self,
+        table: &str,
+        old: Vec<SqliteValue>,
+    ) -> Result<&mut Self, ChangesetError> {
+        let old = old.into_iter().map(Some).collect();
+        self.push(table, OpKind::Delete, old, Vec::new())
+    }
+
+    /// Finish building and return the encoded changeset.
+    #[must_use]
+    pub fn build(self) -> Changeset {
+        Changeset::from_bytes(self.encoder.finish())
+    }
+
+    fn push(
+        &mut self,
+        table: &str,
+        op: OpKind,
+        old: Vec<Option<SqliteValue>>,
+        new: Vec<Option<SqliteValue>>,
+    ) -> Result<&mut Self, ChangesetError> {
+        let flags = self
+            .tables
+            .get(table)
+            .ok_or_else(|| ChangesetError::UnknownTable(table.to_owned()))?;
+
+        // Inserts carry no old record and deletes no new record.
+        let records = match op {
+            OpKind::Insert => [&new, &new],
+            OpKind::Update => [&old, &new],
+            OpKind::Delete => [&old, &old],
+        };
+        let expected = flags.len();
+        if let Some(found) = records
+            .into_iter()
+            .map(Vec::len)
+            .find(|&len| len != expected)
+        {
+            return Err(ChangesetError::ColumnCountMismatch {
+                table: table.to_owned(),
+                expected,
+                found,
+            });
+        }
+
+        if op == OpKind::Update
+            && flags
+                .iter()
+                .zip(&old)
+                .any(|(&flag, value)| flag != 0 && value.is_none())
+        {
+            return Err(ChangesetError::MissingPrimaryKey(table.to_owned()));
+        }
+
+        let op = ChangeOp::from_parts(table.to_owned(), op, flags.clone(), old, new);
+        self.encoder.push(&op);
+        Ok(self)
+    }
+}
+
+/// Compute the changeset that turns the rows `old` of `table` into `new`.
+///
+/// Rows hold one value per column in table order, and `primary_key` flags
+/// the columns that identify a row, as for [`ChangesetBuilder::table`]. Rows
+/// are matched by primary key: rows only in `old` are deleted, rows only in
+/// `new` are inserted, and matched rows that differ are updated in the
+/// columns that changed. Each primary key must appear at most once per side.
+///
+/// Deletes and updates follow the order of `old`, then inserts follow the
+/// order of `new`.
+///
+/// # Example
+///
+/// ```
+/// use diesel_sqlite_session::{diff_rows, SqliteValue};
+///
+/// let old = vec![vec![SqliteValue::Integer(1), SqliteValue::Text(b"one".to_vec())]];
+/// let new = vec![vec![SqliteValue::Integer(1), SqliteValue::Text(b"uno".to_vec())]];
+/// let changeset = diff_rows("items", &[true, false], &old, &new).unwrap();
+/// assert!(!changeset.is_empty());
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::ColumnCountMismatch` if a row does not hold one
+/// value per entry of `primary_key`.
+pub fn diff_rows(
+    table: &str,
+    primary_key: &[bool],
+    old: &[Vec<SqliteValue>],
+    new: &[Vec<SqliteValue>],
+) -> Result<Changeset, ChangesetError> {
+    let key = |row: &[SqliteValue]| -> Vec<RowKeyPart> {
+        row.iter()
+            .zip(primary_key)
+            .filter(|(_, &is_pk)| is_pk)
+            .map(|(value, _)| RowKeyPart::of(value))
+            .collect()
+    };
+    let new_by_key: HashMap<_, _> = new.iter().map(|row| (key(row), row)).collect();
+
+    let mut builder = ChangesetBuilder::new();
+    builder.table(table, primary_key);
+    let mut matched = Vec::with_capacity(old.len());
+    for old_row in old {
+        let row_key = key(old_row);
+        let Some(&new_row) = new_by_key.get(&row_key) else {
+            builder.delete(table, old_row.clone())?;
+            continue;
+        };
+        matched.push(row_key);
+        if new_row.len() != old_row.len() {
+            return Err(ChangesetError::ColumnCountMismatch {
+                table: table.to_owned(),
+                expected: old_row.len(),
+                found: new_row.len(),
+            });
+        }
+        if old_row == new_row {
+            continue;
+        }
+
+        let (before, after): (Vec<_>, Vec<_>) = old_row
+            .iter()
+            .zip(new_row)
+            .zip(primary_key)
+            .map(|((before, after), &is_pk)| {
+                if before == after {
+                    (is_pk.then(|| before.clone()), None)
+                } else {
+                    (Some(before.clone()), Some(after.clone()))
+                }
+            })
+            .unzip();
+        builder.update(table, before, after)?;
+    }
+
+    let matched: HashSet<_> = matched.into_iter().collect();
+    for new_row in new {
+        if !matched.contains(&key(new_row)) {
+            builder.insert(table, new_row.clone())?;
+        }
+    }
+    Ok(builder.build())
+}
+
+/// Hashable form of a primary key value, used to match rows in [`diff_rows`].
+///
+/// Reals compare by bit pattern, which is what identifies a stored key.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum RowKeyPart {
+    Null,
+    Integer(i64),
+    Real(u64),
+    Text(Vec<u8>),
+    Blob(Vec<u8>),
+}
+
+impl RowKeyPart {
+    fn of(value: &SqliteValue) -> Self {
+        match value {
+            SqliteValue::Null => Self::Null,
+            SqliteValue::Integer(int) => Self::Integer(*int),
+            SqliteValue::Real(real) => Self::Real(real.to_bits()),
+            SqliteValue::Text(bytes) => Self::Text(bytes.clone()),
+            SqliteValue::Blob(bytes) => Self::Blob(bytes.clone()),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Combine several changesets or patchsets into one.
+
+use std::ffi::{c_int, c_void};
+use std::io::{Read, Write};
+use std::ptr;
+
+use crate::buffer::take_sqlite_buffer;
+use crate::changeset::Changeset;
+use crate::errors::{ChangesetError, SqliteErrorCode};
+use crate::ffi::{
+    sqlite3_changegroup, sqlite3changegroup_add, sqlite3changegroup_add_strm,
+    sqlite3changegroup_delete, sqlite3changegroup_new, sqlite3changegroup_output,
+    sqlite3changegroup_output_strm, SQLITE_OK, SQLITE_TOOBIG,
+};
+use crate::stream::{input_callback, output_callback, InputContext, OutputContext};
+use crate::validate::validate_changeset;
+
+/// Merges changesets into a single changeset.
+///
+/// Changes to the same row are combined: an insert followed by an update
+/// becomes a single insert, an insert followed by a delete disappears, and so
+/// on. The result is equivalent to applying the inputs in the order they were
+/// added. A group holds either changesets or patchsets, never a mix of both.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::ChangeGroup;
+///
+/// # let first: Vec<u8> = Vec::new();
+/// # let second: Vec<u8> = Vec::new();
+/// let mut group = ChangeGroup::new().unwrap();
+/// group.add(&first).unwrap();
+/// group.add(&second).unwrap();
+/// let combined = group.output().unwrap();
+/// ```
+pub struct ChangeGroup {
+    group: *mut sqlite3_changegroup,
+}
+
+impl ChangeGroup {
+    /// Create an empty change group.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::GroupFailed` if `SQLite` fails to allocate the group.
+    pub fn new() -> Result<Self, ChangesetError> {
+        let mut group: *mut sqlite3_changegroup = ptr::null_mut();
+        // SAFETY: `group` is a valid out-pointer.
+        let rc = unsafe { sqlite3changegroup_new(&mut group) };
+        check(rc)?;
+        Ok(Self { group })
+    }
+
+    /// Add a changeset or patchset held in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::Malformed` if the input is not a well-formed
+    /// changeset or patchset, or `ChangesetError::GroupFailed` if it does not
+    /// match the format or schema of the changes already added.
+    pub fn add(&mut self, changeset: &[u8]) -> Result<(), ChangesetError> {
+        if changeset.is_empty() {
+            return Ok(());
+        }
+        validate_changeset(changeset)?;
+        let input_len = c_int::try_from(changeset.len())
+            .map_err(|_| ChangesetError::GroupFailed(SqliteErrorCode::from_error(SQLITE_TOOBIG)))?;
+
+        // SAFETY: `self.group` is a live change group, and SQLite copies what it
+        // needs from `changeset`, only reading it despite the mutable pointer.
+        let rc = unsafe {
+            sqlite3changegroup_add(
+                self.group,
+                input_len,
+                changeset.as_ptr().cast::<c_void>().cast_mut(),
+            )
+        };
+        check(rc)
+    }
+
+    /// Add a changeset or patchset read incrementally from `reader`.
+    ///
+    /// The input is never held in memory as a whole, which keeps memory use
+    /// bounded when merging large on-disk changesets.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::Io` if reading from `reader` fails.
+    /// Returns `ChangesetError::GroupFailed` if the input is malformed or does
+    /// not match the changes already added.
+    pub fn add_from_reader<R: Read>(&mut self, reader: R) -> Result<(), ChangesetError> {
+        let mut context = InputContext::new(reader);
+
+        // SAFETY: `self.group` is a live change group, and `context` points to
+        // stack storage that outlives the call and matches `input_callback::<R>`.
+        let rc = unsafe {
+            sqlite3changegroup_add_strm(
+                self.group,
+                Some(input_callback::<R>),
+                ptr::addr_of_mut!(context).cast(),
+            )
+        };
+
+        context.finish()?;
+        check(rc)
+    }
+
+    /// Produce the combined changeset in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::GroupFailed` if `SQLite` fails to generate the output.
+    pub fn output(&mut self) -> Result<Changeset, ChangesetError> {
+        let mut size: c_int = 0;
+        let mut buffer: *mut c_void = ptr::null_mut();
+
+        // SAFETY: `self.group` is a live change group and `size`/`buffer` are
+        // valid out-pointers.
+        let rc = unsafe { sqlite3changegroup_output(self.group, &mut size, &mut buffer) };
+        check(rc)?;
+
+        // SAFETY: on success SQLite hands us ownership of `buffer`, which holds
+        // `size` bytes allocated with `sqlite3_malloc`.
+        unsafe { take_sqlite_buffer(buffer, size) }
+            .map(Changeset::from_bytes)
+            .map_err(|size| ChangesetError::GroupFailed(SqliteErrorCode::Unknown(size)))
+    }
+
+    /// Stream the combined changeset into `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::Io` if writing to `writer` fails.
+    /// Returns `ChangesetError::GroupFailed` if `SQLite` fails to generate the output.
+    pub fn output_to_writer<W: Write>(&mut self, writer: W) -> Result<(), ChangesetError> {
+        let mut context = OutputContext::new(writer);
+
+        // SAFETY: `self.group` is a live change group, and `context` points to
+        // stack storage that outlives the call and matches `output_callback::<W>`.
+        let rc = unsafe {
+            sqlite3changegroup_output_strm(
+                self.group,
+                Some(output_callback::<W>),
+                ptr::addr_of_mut!(context).cast(),
+            )
+        };
+
+        context.finish()?;
+        check(rc)
+    }
+}
+
+impl Drop for ChangeGroup {
+    fn drop(&mut self) {
+        // SAFETY: `self.group` is owned by this type and must be released
+        // exactly once with `sqlite3changegroup_delete`.
+        unsafe {
+            sqlite3changegroup_delete(self.group);
+        }
+    }
+}
+
+fn check(rc: c_int) -> Result<(), ChangesetError> {
+    if rc == SQLITE_OK {
+        Ok(())
+    } else {
+        Err(ChangesetError::GroupFailed(SqliteErrorCode::from_error(rc)))
+    }
+}
@@ -0,0 +1,817 @@
+//! Owned changesets and changeset-level transformations.
+
+use std::ffi::{c_int, c_void};
+use std::fmt;
+use std::ops::Deref;
+use std::ptr;
+
+use crate::buffer::take_sqlite_buffer;
+use crate::changegroup::ChangeGroup;
+use crate::encode::{Encoder, Format};
+use crate::errors::{ChangesetError, ConflictType, SqliteErrorCode};
+use crate::ffi::{sqlite3changeset_invert, SQLITE_OK, SQLITE_TOOBIG};
+use crate::iter::{read_changeset, ChangeOp, OpKind, OpKindSet};
+use crate::validate::validate_changeset;
+use crate::value::SqliteValue;
+
+/// An owned `SQLite` changeset.
+///
+/// `Changeset` wraps the raw changeset bytes and dereferences to `[u8]`, so it
+/// can be passed anywhere a byte slice is expected. It is accepted by
+/// [`SqliteSessionExt::apply_changeset`](crate::SqliteSessionExt::apply_changeset)
+/// but not by [`SqliteSessionExt::apply_patchset`](crate::SqliteSessionExt::apply_patchset).
+///
+/// With the `blob` feature, a `Changeset` can be written to and read from a
+/// Diesel [`Binary`](diesel::sql_types::Binary) column directly, for example
+/// to keep an outbox of changes to send:
+///
+/// ```no_run
+/// # #[cfg(feature = "blob")]
+/// # fn outbox() -> diesel::QueryResult<()> {
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::Changeset;
+///
+/// # diesel::table! {
+/// #     outbox (id) {
+/// #         id -> Integer,
+/// #         data -> Binary,
+/// #     }
+/// # }
+/// # let mut conn = SqliteConnection::establish("app.db").unwrap();
+/// # let changeset = Changeset::default();
+/// diesel::insert_into(outbox::table)
+///     .values(outbox::data.eq(&changeset))
+///     .execute(&mut conn)?;
+/// let pending: Vec<Changeset> = outbox::table.select(outbox::data).load(&mut conn)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "blob",
+    derive(diesel::expression::AsExpression, diesel::deserialize::FromSqlRow),
+    diesel(sql_type = diesel::sql_types::Binary)
+)]
+pub struct Changeset(Vec<u8>);
+
+impl Changeset {
+    /// Wrap raw changeset bytes.
+    #[inline]
+    #[must_use]
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Borrow the raw changeset bytes.
+    #[inline]
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consume the changeset and return the raw bytes.
+    #[inline]
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Deref for Changeset {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Changeset {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Changeset {
+    #[inline]
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Changeset> for Vec<u8> {
+    #[inline]
+    fn from(changeset: Changeset) -> Self {
+        changeset.0
+    }
+}
+
+/// Summarize the changeset for logs, for example
+/// `Changeset{tables: [items], inserts: 3, updates: 0, deletes: 1, bytes: 142}`.
+///
+/// Tables are listed in the order they first appear. Operations that cannot be
+/// read are reported as `malformed` after the ones counted so far.
+impl fmt::Display for Changeset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tables: Vec<String> = Vec::new();
+        let (mut inserts, mut updates, mut deletes) = (0_usize, 0_usize, 0_usize);
+        let mut malformed = false;
+        match read_changeset(&self.0) {
+            Ok(iter) => {
+                for op in iter {
+                    let Ok(op) = op else {
+                        malformed = true;
+                        break;
+                    };
+                    if !tables.iter().any(|table| table == op.table()) {
+                        tables.push(op.table().to_owned());
+                    }
+                    match op.op() {
+                        OpKind::Insert => inserts += 1,
+                        OpKind::Update => updates += 1,
+                        OpKind::Delete => deletes += 1,
+                    }
+                }
+            }
+            Err(_) => malformed = true,
+        }
+
+        write!(
+            f,
+            "Changeset{{tables: [{}], inserts: {inserts}, updates: {updates}, deletes: {deletes}, bytes: {}",
+            tables.join(", "),
+            self.0.len()
+        )?;
+        if malformed {
+            write!(f, ", malformed")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// An owned `SQLite` patchset.
+///
+/// Like [`Changeset`], `Patchset` dereferences to `[u8]`. It is accepted by
+/// [`SqliteSessionExt::apply_patchset`](crate::SqliteSessionExt::apply_patchset)
+/// but not by [`SqliteSessionExt::apply_changeset`](crate::SqliteSessionExt::apply_changeset).
+/// Keeping the two apart in types also documents which conflicts to expect:
+/// a patchset records only the primary key of deleted rows and the new values
+/// of updated ones, so `SQLite` has no old values to compare the existing row
+/// with, and [`ConflictType::Data`] is never reported while applying it. Use
+/// [`can_report`](Self::can_report) to check a conflict type.
+///
+/// With the `blob` feature, a `Patchset` can be stored in a Diesel
+/// [`Binary`](diesel::sql_types::Binary) column like a [`Changeset`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "blob",
+    derive(diesel::expression::AsExpression, diesel::deserialize::FromSqlRow),
+    diesel(sql_type = diesel::sql_types::Binary)
+)]
+pub struct Patchset(Vec<u8>);
+
+impl Patchset {
+    /// Wrap raw patchset bytes.
+    #[inline]
+    #[must_use]
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Borrow the raw patchset bytes.
+    #[inline]
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consume the patchset and return the raw bytes.
+    #[inline]
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Whether a conflict of type `kind` can occur while applying a patchset.
+    ///
+    /// Only [`ConflictType::Data`] cannot: updates and deletes are applied to
+    /// whatever row has their primary key, or reported as
+    /// [`ConflictType::NotFound`] if there is none. An insert onto an existing
+    /// primary key is still reported as [`ConflictType::Conflict`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::{ConflictType, Patchset};
+    ///
+    /// assert!(!Patchset::can_report(ConflictType::Data));
+    /// assert!(Patchset::can_report(ConflictType::NotFound));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub const fn can_report(kind: ConflictType) -> bool {
+        !matches!(kind, ConflictType::Data)
+    }
+}
+
+impl Deref for Patchset {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Patchset {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Patchset {
+    #[inline]
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Patchset> for Vec<u8> {
+    #[inline]
+    fn from(patchset: Patchset) -> Self {
+        patchset.0
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::Changeset {}
+    impl Sealed for super::Patchset {}
+}
+
+/// Data accepted by
+/// [`SqliteSessionExt::apply_changeset`](crate::SqliteSessionExt::apply_changeset)
+/// and the other changeset apply methods.
+///
+/// Implemented only for [`Changeset`], so neither a [`Patchset`] nor bytes of
+/// unknown kind can be applied as a changeset by mistake. Raw bytes go
+/// through [`SqliteSessionExt::apply_bytes`](crate::SqliteSessionExt::apply_bytes),
+/// or are wrapped with [`Changeset::from_bytes`] once their kind is known.
+///
+/// This trait is sealed and cannot be implemented outside this crate.
+pub trait ChangesetInput: AsRef<[u8]> + sealed::Sealed {}
+
+impl ChangesetInput for Changeset {}
+
+/// Data accepted by
+/// [`SqliteSessionExt::apply_patchset`](crate::SqliteSessionExt::apply_patchset)
+/// and the other patchset apply methods.
+///
+/// Implemented only for [`Patchset`]; see [`ChangesetInput`].
+///
+/// This trait is sealed and cannot be implemented outside this crate.
+pub trait PatchsetInput: AsRef<[u8]> + sealed::Sealed {}
+
+impl PatchsetInput for Patchset {}
+
+/// Encode operations into a changeset, in iteration order.
+///
+/// Combined with [`read_changeset`] this allows transforming a changeset with
+/// ordinary iterator adapters. The operations must carry changeset values:
+/// operations read from a patchset lack the old values a changeset requires.
+impl FromIterator<ChangeOp> for Changeset {
+    fn from_iter<I: IntoIterator<Item = ChangeOp>>(ops: I) -> Self {
+        let mut encoder = Encoder::new(Format::Changeset);
+        for op in ops {
+            encoder.push(&op);
+        }
+        Self(encoder.finish())
+    }
+}
+
+/// Compute the inverse of a changeset.
+///
+/// Applying the inverse undoes the original changeset: inserts become deletes,
+/// deletes become inserts, and updates swap their old and new values. Only
+/// changesets can be inverted; patchsets lack the old values required.
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::InvertFailed` if `SQLite`
+/// rejects it, for example because it is a patchset.
+pub fn invert_changeset(changeset: &[u8]) -> Result<Changeset, ChangesetError> {
+    if changeset.is_empty() {
+        return Ok(Changeset::default());
+    }
+    validate_changeset(changeset)?;
+
+    let input_len = c_int::try_from(changeset.len())
+        .map_err(|_| ChangesetError::InvertFailed(SqliteErrorCode::from_error(SQLITE_TOOBIG)))?;
+    let mut size: c_int = 0;
+    let mut buffer: *mut c_void = ptr::null_mut();
+
+    // SAFETY: `changeset` is a live slice of `input_len` bytes for the duration of
+    // the call, and `size`/`buffer` are valid out-pointers.
+    let rc = unsafe {
+        sqlite3changeset_invert(
+            input_len,
+            changeset.as_ptr().cast::<c_void>(),
+            &mut size,
+            &mut buffer,
+        )
+    };
+    if rc != SQLITE_OK {
+        return Err(ChangesetError::InvertFailed(SqliteErrorCode::from_error(
+            rc,
+        )));
+    }
+
+    // SAFETY: on success SQLite hands us ownership of `buffer`, which holds
+    // `size` bytes allocated with `sqlite3_malloc`.
+    unsafe { take_sqlite_buffer(buffer, size) }
+        .map(Changeset)
+        .map_err(|size| ChangesetError::InvertFailed(SqliteErrorCode::Unknown(size)))
+}
+
+/// Convert a changeset into the equivalent patchset.
+///
+/// A patchset drops the old values a changeset keeps for updates and deletes,
+/// so it is smaller to transmit, at the cost of the conflict detection those
+/// values allow: see [`Patchset`]. This spares running the session again in
+/// patchset mode. A patchset input is returned unchanged.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::changeset_to_patchset;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// let patchset = changeset_to_patchset(&changeset).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn changeset_to_patchset(changeset: &[u8]) -> Result<Patchset, ChangesetError> {
+    if Format::of(changeset) == Format::Patchset {
+        return Ok(Patchset(changeset.to_vec()));
+    }
+
+    let mut encoder = Encoder::new(Format::Patchset);
+    for op in read_changeset(changeset)? {
+        encoder.push(&op?);
+    }
+    Ok(Patchset(encoder.finish()))
+}
+
+/// Keep only the rows of `table` whose value in column `column` equals `value`.
+///
+/// This is a post-filter for cases the session extension cannot express, such
+/// as syncing a single tenant of a multi-tenant table. Columns are identified by
+/// their zero-based position in the table, since changesets do not store
+/// column names. Operations on other tables are kept unchanged, so the filter
+/// can be chained once per table. The output has the same format as the input,
+/// changeset or patchset.
+///
+/// The column value is taken from the new values of inserts and updates and
+/// from the old values of deletes. An update that does not modify the column
+/// records no value for it unless the column is part of the primary key; such
+/// updates cannot be attributed and are dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::{filter_changeset_by_value, SqliteValue};
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// // Keep the rows of tenant 7, whose id is stored in the second column.
+/// let tenant = filter_changeset_by_value(&changeset, "accounts", 1, &SqliteValue::Integer(7))
+///     .unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn filter_changeset_by_value(
+    changeset: &[u8],
+    table: &str,
+    column: usize,
+    value: &SqliteValue,
+) -> Result<Vec<u8>, ChangesetError> {
+    rewrite_changeset(changeset, |op| {
+        if op.table() != table {
+            return Some(op);
+        }
+        let recorded = op
+            .new_values()
+            .get(column)
+            .and_then(Option::as_ref)
+            .or_else(|| op.old_values().get(column).and_then(Option::as_ref));
+        (recorded == Some(value)).then_some(op)
+    })
+}
+
+/// Rewrite the integer primary key values of `table` through `map`.
+///
+/// Supports offset-based id allocation when importing rows from a peer into a
+/// shared table: for example `|id| id + 1000` moves the peer's rows out of the
+/// local id range. Every integer value in a primary key column of `table` is
+/// mapped, in both the old and new values; other tables and non-integer keys
+/// are left unchanged. The output has the same format as the input.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::remap_changeset_pks;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// let remapped = remap_changeset_pks(&changeset, "items", |id| id + 1000).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn remap_changeset_pks<F>(
+    changeset: &[u8],
+    table: &str,
+    map: F,
+) -> Result<Vec<u8>, ChangesetError>
+where
+    F: Fn(i64) -> i64,
+{
+    rewrite_changeset(changeset, |mut op| {
+        if op.table() == table {
+            let primary_key = op.primary_key().to_vec();
+            let (old, new) = op.values_mut();
+            for values in [old, new] {
+                for (value, _) in values
+                    .iter_mut()
+                    .zip(&primary_key)
+                    .filter(|(_, is_pk)| **is_pk)
+                {
+                    if let Some(SqliteValue::Integer(id)) = value {
+                        *id = map(*id);
+                    }
+                }
+            }
+        }
+        Some(op)
+    })
+}
+
+/// Move the columns of every operation to the positions given by `column_map`.
+///
+/// Changesets identify columns by position, so a changeset recorded on a
+/// table declared as `(id, name, value)` writes names into the `value` column
+/// of a replica declared as `(id, value, name)`. `column_map` is called with
+/// the table name and the position of each column in the input, and returns
+/// its position in the target table; return the position unchanged for
+/// tables that need no reordering. The output has the same format as the
+/// input.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::reorder_changeset_columns;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// // The replica declares `items` as (id, value, name).
+/// let reordered = reorder_changeset_columns(&changeset, |table, column| match (table, column) {
+///     ("items", 1) => 2,
+///     ("items", 2) => 1,
+///     (_, column) => column,
+/// })
+/// .unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+/// Returns `ChangesetError::InvalidColumnMap` if the map sends a column out of
+/// range or two columns of a table to the same position.
+pub fn reorder_changeset_columns<F>(
+    changeset: &[u8],
+    column_map: F,
+) -> Result<Vec<u8>, ChangesetError>
+where
+    F: Fn(&str, usize) -> usize,
+{
+    let mut encoder = Encoder::new(Format::of(changeset));
+    for op in read_changeset(changeset)? {
+        let mut op = op?;
+        let columns = op.column_count();
+        let mut targets = Vec::with_capacity(columns);
+        let mut used = vec![false; columns];
+        for column in 0..columns {
+            let mapped = column_map(op.table(), column);
+            if used.get(mapped) != Some(&false) {
+                return Err(ChangesetError::InvalidColumnMap {
+                    table: op.table().to_owned(),
+                    column,
+                    mapped,
+                });
+            }
+            used[mapped] = true;
+            targets.push(mapped);
+        }
+        op.move_columns(&targets);
+        encoder.push(&op);
+    }
+    Ok(encoder.finish())
+}
+
+/// Keep only the columns of `table` listed in `keep_columns`, plus its
+/// primary key.
+///
+/// Meant for replicating part of a wide table, for example leaving a large
+/// blob column out of sync. Columns are identified by their zero-based
+/// position in the table, since changesets do not store column names. In
+/// updates the dropped columns are marked as unchanged, so applying the result
+/// leaves them untouched on the replica; updates that only modified dropped
+/// columns are removed. Inserts and deletes must record every column, so the
+/// dropped columns are written as `NULL` there: a replica fed only projected
+/// changesets holds `NULL` in them, which is what its deletes then expect.
+/// Operations on other tables are kept unchanged. The output has the same
+/// format as the input.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::project_changeset;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// // Sync the id and name of `documents`, but not the blob in column 2.
+/// let projected = project_changeset(&changeset, "documents", &[1]).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn project_changeset(
+    changeset: &[u8],
+    table: &str,
+    keep_columns: &[usize],
+) -> Result<Vec<u8>, ChangesetError> {
+    rewrite_changeset(changeset, |mut op| {
+        if op.table() != table {
+            return Some(op);
+        }
+        let dropped: Vec<usize> = (0..op.primary_key().len())
+            .filter(|column| !op.primary_key()[*column] && !keep_columns.contains(column))
+            .collect();
+        let kind = op.op();
+        let (old, new) = op.values_mut();
+        for &column in &dropped {
+            match kind {
+                OpKind::Update => {
+                    old[column] = None;
+                    new[column] = None;
+                }
+                OpKind::Insert => new[column] = Some(SqliteValue::Null),
+                OpKind::Delete => {
+                    if old[column].is_some() {
+                        old[column] = Some(SqliteValue::Null);
+                    }
+                }
+            }
+        }
+        let modifies_kept_column = kind != OpKind::Update
+            || op
+                .new_values()
+                .iter()
+                .zip(op.primary_key())
+                .any(|(value, is_pk)| !is_pk && value.is_some());
+        modifies_kept_column.then_some(op)
+    })
+}
+
+/// Combine the operations of a changeset or patchset into one net operation
+/// per row.
+///
+/// Changesets produced by a session are already coalesced, but concatenating
+/// several of them can leave multiple operations for the same primary key.
+/// Coalescing merges them following `SQLite`'s change group rules: two updates
+/// become one update with the final values, an insert followed by a delete
+/// disappears, and so on. The output has the same format as the input and is
+/// usually smaller and faster to apply.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::coalesce_changeset;
+///
+/// # let first: Vec<u8> = Vec::new();
+/// # let second: Vec<u8> = Vec::new();
+/// let concatenated = [first, second].concat();
+/// let coalesced = coalesce_changeset(&concatenated).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::GroupFailed` if `SQLite` fails
+/// to combine it.
+pub fn coalesce_changeset(changeset: &[u8]) -> Result<Vec<u8>, ChangesetError> {
+    let mut group = ChangeGroup::new()?;
+    group.add(changeset)?;
+    group.output().map(Changeset::into_bytes)
+}
+
+/// Remove every delete from a changeset or patchset.
+///
+/// Meant for soft-delete replicas that never remove rows: the result applies
+/// the inserts and updates of the input and leaves rows it would have deleted
+/// in place. The output has the same format as the input.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::strip_deletes;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// let without_deletes = strip_deletes(&changeset).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn strip_deletes(changeset: &[u8]) -> Result<Vec<u8>, ChangesetError> {
+    rewrite_changeset(changeset, |op| (op.op() != OpKind::Delete).then_some(op))
+}
+
+/// Whether a changeset or patchset contains any delete.
+///
+/// Stops at the first delete and decodes no values, so it is cheaper than
+/// parsing the input, for example to decide whether an apply needs
+/// confirmation first.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::changeset_has_deletes;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// if changeset_has_deletes(&changeset).unwrap() {
+///     // ask before applying
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn changeset_has_deletes(changeset: &[u8]) -> Result<bool, ChangesetError> {
+    contains_kind(changeset, OpKind::Delete)
+}
+
+/// Whether a changeset or patchset contains any update.
+///
+/// See [`changeset_has_deletes`].
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn changeset_has_updates(changeset: &[u8]) -> Result<bool, ChangesetError> {
+    contains_kind(changeset, OpKind::Update)
+}
+
+/// Whether a changeset or patchset contains any insert.
+///
+/// See [`changeset_has_deletes`].
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn changeset_has_inserts(changeset: &[u8]) -> Result<bool, ChangesetError> {
+    contains_kind(changeset, OpKind::Insert)
+}
+
+/// The distinct kinds of operation in a changeset or patchset.
+///
+/// Reading stops as soon as inserts, updates and deletes have all been seen,
+/// so this is cheap enough to call on every pending changeset, for example to
+/// pick how to display it. Use [`parse_changeset`](crate::parse_changeset)
+/// to inspect the operations themselves.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::{changeset_op_kinds, OpKind};
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// let kinds = changeset_op_kinds(&changeset).unwrap();
+/// if kinds.contains(OpKind::Delete) {
+///     // show a warning icon
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn changeset_op_kinds(changeset: &[u8]) -> Result<OpKindSet, ChangesetError> {
+    let mut kinds = OpKindSet::new();
+    let mut iter = read_changeset(changeset)?;
+    while kinds != OpKindSet::ALL {
+        match iter.next_kind() {
+            Some(kind) => kinds.insert(kind?),
+            None => break,
+        }
+    }
+    Ok(kinds)
+}
+
+/// Whether any operation of `changeset` is of `kind`, stopping at the first one.
+fn contains_kind(changeset: &[u8], kind: OpKind) -> Result<bool, ChangesetError> {
+    let mut iter = read_changeset(changeset)?;
+    while let Some(op) = iter.next_kind() {
+        if op? == kind {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Split a changeset or patchset into chunks of at most `max_bytes` bytes each.
+///
+/// Meant for transports with a maximum message size. Every chunk is a
+/// complete changeset of the same format as the input, with its own table
+/// headers, so it can be applied on its own. Operations keep their order, and
+/// applying the chunks in order has the same effect as applying the input.
+/// Each chunk is applied in its own transaction, so foreign key constraints
+/// between rows in different chunks must be deferrable or disabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::split_changeset;
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// for chunk in split_changeset(&changeset, 64 * 1024).unwrap() {
+///     // send `chunk` as one message
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+/// Returns `ChangesetError::OperationTooLarge` if a single operation does not
+/// fit in `max_bytes` on its own.
+pub fn split_changeset(changeset: &[u8], max_bytes: usize) -> Result<Vec<Vec<u8>>, ChangesetError> {
+    let format = Format::of(changeset);
+    let mut chunks = Vec::new();
+    let mut current = Encoder::new(format);
+
+    for op in read_changeset(changeset)? {
+        let op = op?;
+        // Encoded alone, the operation pays for its table header too, so this
+        // bounds what it adds to the current chunk.
+        let mut alone = Encoder::new(format);
+        alone.push(&op);
+        let size = alone.len();
+        if size > max_bytes {
+            return Err(ChangesetError::OperationTooLarge {
+                table: op.table().to_owned(),
+                size,
+                limit: max_bytes,
+            });
+        }
+
+        if !current.is_empty() && current.len() + size > max_bytes {
+            chunks.push(std::mem::replace(&mut current, Encoder::new(format)).finish());
+        }
+        current.push(&op);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current.finish());
+    }
+    Ok(chunks)
+}
+
+/// Re-encode a changeset or patchset, replacing or dropping each operation.
+fn rewrite_changeset<F>(data: &[u8], mut rewrite: F) -> Result<Vec<u8>, ChangesetError>
+where
+    F: FnMut(ChangeOp) -> Option<ChangeOp>,
+{
+    let mut encoder = Encoder::new(Format::of(data));
+    for op in read_changeset(data)? {
+        if let Some(op) = rewrite(op?) {
+            encoder.push(&op);
+        }
+    }
+    Ok(encoder.finish())
+}
@@ -0,0 +1,271 @@
+//! Check that a changeset fits the schema of the database it will be applied to.
+
+use std::collections::HashSet;
+
+use diesel::SqliteConnection;
+
+use crate::errors::{
+    ChangesetError, ConflictType, Incompatibility, IncompatibilityReport, SqliteErrorCode,
+};
+use crate::ffi::sqlite3;
+use crate::iter::{read_changeset, ChangeOp, OpKind};
+use crate::query::{column_names, quote_identifier, Statement};
+use crate::value::SqliteValue;
+
+/// Check that every table a changeset modifies exists in `conn` with enough
+/// columns.
+///
+/// `SQLite` skips changes to missing or narrower tables without reporting a
+/// conflict, so running this first turns a confusing partial apply into an
+/// explicit error. Tables with more columns than the changeset records are
+/// accepted, as `SQLite` fills the extra columns with their defaults. Works
+/// with patchsets as well.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::check_changeset_compatible;
+///
+/// let mut replica = SqliteConnection::establish(":memory:").unwrap();
+/// # let changeset: Vec<u8> = Vec::new();
+/// if let Err(report) = check_changeset_compatible(&mut replica, &changeset) {
+///     for issue in report.issues() {
+///         eprintln!("{issue}");
+///     }
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns an [`IncompatibilityReport`] listing each missing table and column
+/// count mismatch, or the error that stopped the check if the changeset cannot
+/// be read or the schema cannot be queried.
+pub fn check_changeset_compatible(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+) -> Result<(), IncompatibilityReport> {
+    // SAFETY: `with_raw_connection` provides a valid SQLite handle for the
+    // duration of the callback, which owns every statement it prepares.
+    let issues = unsafe { conn.with_raw_connection(|db| find_issues(db, changeset)) };
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(IncompatibilityReport::new(issues))
+    }
+}
+
+/// Collect the problems [`check_changeset_compatible`] reports.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn find_issues(db: *mut sqlite3, changeset: &[u8]) -> Vec<Incompatibility> {
+    let mut issues = Vec::new();
+    let ops = match read_changeset(changeset) {
+        Ok(ops) => ops,
+        Err(err) => return vec![Incompatibility::Changeset(err)],
+    };
+
+    let mut checked = HashSet::new();
+    for op in ops {
+        let op = match op {
+            Ok(op) => op,
+            Err(err) => {
+                issues.push(Incompatibility::Changeset(err));
+                break;
+            }
+        };
+        if checked.contains(op.table()) {
+            continue;
+        }
+        checked.insert(op.table().to_owned());
+
+        // SAFETY: the caller guarantees `db` is valid.
+        let found = match unsafe { column_names(db, "main", op.table()) } {
+            Ok(names) => names.len(),
+            Err(rc) => {
+                issues.push(Incompatibility::QueryFailed(SqliteErrorCode::from_error(
+                    rc,
+                )));
+                break;
+            }
+        };
+        if found == 0 {
+            issues.push(Incompatibility::MissingTable(op.table().to_owned()));
+        } else if found < op.column_count() {
+            issues.push(Incompatibility::ColumnCountMismatch {
+                table: op.table().to_owned(),
+                expected: op.column_count(),
+                found,
+            });
+        }
+    }
+    issues
+}
+
+/// A conflict [`predict_conflicts`] expects applying a change to raise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PredictedConflict {
+    kind: ConflictType,
+    table: String,
+    op: OpKind,
+    primary_key: Vec<SqliteValue>,
+}
+
+impl PredictedConflict {
+    /// The conflict type `SQLite` is expected to report.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> ConflictType {
+        self.kind
+    }
+
+    /// Name of the table the change targets.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Kind of the conflicting change.
+    #[inline]
+    #[must_use]
+    pub const fn op(&self) -> OpKind {
+        self.op
+    }
+
+    /// Primary key values of the affected row.
+    #[inline]
+    #[must_use]
+    pub fn primary_key(&self) -> &[SqliteValue] {
+        &self.primary_key
+    }
+}
+
+/// Predict which changes of a changeset would conflict with the current
+/// contents of `conn`, without applying anything.
+///
+/// Each change is checked against the row with its primary key, the way
+/// `SQLite` checks it on apply: an insert whose row exists is a
+/// [`ConflictType::Conflict`], an update or delete whose row is missing is a
+/// [`ConflictType::NotFound`], and one whose recorded old values no longer
+/// match the row is a [`ConflictType::Data`]. Changes are checked
+/// independently against the current state, so a change that would only
+/// conflict after an earlier change of the same changeset is applied is not
+/// reported; neither are constraint or foreign key violations. Changes to
+/// tables missing from `conn` are ignored, as `SQLite` skips them; see
+/// [`check_changeset_compatible`]. Works with patchsets too, which only carry
+/// primary keys to compare.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::predict_conflicts;
+///
+/// let mut replica = SqliteConnection::establish(":memory:").unwrap();
+/// # let changeset: Vec<u8> = Vec::new();
+/// for conflict in predict_conflicts(&mut replica, &changeset).unwrap() {
+///     eprintln!("{:?} on {}", conflict.kind(), conflict.table());
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the changeset is not well formed, or
+/// `ChangesetError::IterFailed` if it cannot be read.
+/// Returns `ChangesetError::QueryFailed` if the database cannot be queried.
+pub fn predict_conflicts(
+    conn: &mut SqliteConnection,
+    changeset: &[u8],
+) -> Result<Vec<PredictedConflict>, ChangesetError> {
+    // SAFETY: `with_raw_connection` provides a valid SQLite handle for the
+    // duration of the callback, which owns every statement it prepares.
+    unsafe {
+        conn.with_raw_connection(|db| {
+            let mut predicted = Vec::new();
+            for op in read_changeset(changeset)? {
+                let op = op?;
+                if let Some(kind) = predict_one(db, &op)? {
+                    predicted.push(PredictedConflict {
+                        kind,
+                        table: op.table().to_owned(),
+                        op: op.op(),
+                        primary_key: op.primary_key_values(),
+                    });
+                }
+            }
+            Ok(predicted)
+        })
+    }
+}
+
+/// Predict the conflict applying `op` to `db` would raise, if any.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn predict_one(
+    db: *mut sqlite3,
+    op: &ChangeOp,
+) -> Result<Option<ConflictType>, ChangesetError> {
+    let query_failed = |rc| ChangesetError::QueryFailed(SqliteErrorCode::from_error(rc));
+    // SAFETY: the caller guarantees `db` is valid.
+    let names = unsafe { column_names(db, "main", op.table()) }.map_err(query_failed)?;
+    if names.len() < op.column_count() {
+        return Ok(None);
+    }
+
+    // Inserts are matched on their new primary key values; updates and
+    // deletes on the old values they recorded, which include the key.
+    let key_values = match op.op() {
+        OpKind::Insert => op.new_values(),
+        OpKind::Update | OpKind::Delete => op.old_values(),
+    };
+    let mut keys = Vec::new();
+    let mut expected = Vec::new();
+    for ((name, value), &is_pk) in names.iter().zip(key_values).zip(op.primary_key()) {
+        let Some(value) = value else { continue };
+        if is_pk {
+            keys.push((name, value));
+        } else if op.op() != OpKind::Insert {
+            expected.push((name, value));
+        }
+    }
+
+    let condition = |columns: &[(&String, &SqliteValue)], first: usize| -> String {
+        columns
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| format!("{} IS ?{}", quote_identifier(name), first + index))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    };
+    let matches = if expected.is_empty() {
+        "1".to_owned()
+    } else {
+        condition(&expected, keys.len() + 1)
+    };
+    let sql = format!(
+        "SELECT {matches} FROM main.{} WHERE {}",
+        quote_identifier(op.table()),
+        condition(&keys, 1)
+    );
+
+    // SAFETY: the caller guarantees `db` is valid; the statement is dropped
+    // before returning.
+    let mut stmt = unsafe { Statement::prepare(db, &sql) }.map_err(query_failed)?;
+    for (index, (_, value)) in (1..).zip(keys.iter().chain(&expected)) {
+        stmt.bind_value(index, value).map_err(query_failed)?;
+    }
+    let found = stmt.step().map_err(query_failed)?;
+
+    Ok(match (op.op(), found) {
+        (OpKind::Insert, true) => Some(ConflictType::Conflict),
+        (OpKind::Insert, false) => None,
+        (_, false) => Some(ConflictType::NotFound),
+        (_, true) if stmt.column_int(0) == 0 => Some(ConflictType::Data),
+        (_, true) => None,
+    })
+}
@@ -0,0 +1,905 @@
+//! Row-aware conflict resolution.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::mpsc::{Receiver, Sender};
+
+use diesel::SqliteConnection;
+
+use crate::apply::{apply_resolving, ApplyOptions, ApplyStats};
+use crate::changegroup::ChangeGroup;
+use crate::changeset::Changeset;
+use crate::encode::{Encoder, Format};
+use crate::errors::{ApplyError, ChangesetError, ConflictAction, ConflictType, SqliteErrorCode};
+use crate::ffi::{sqlite3, sqlite3_changeset_iter, SQLITE_MISUSE};
+use crate::iter::{read_changeset, read_conflicting_values, read_op, ChangeOp, OpKind};
+use crate::query::{column_names, quote_identifier, Statement};
+use crate::value::SqliteValue;
+
+/// A conflict reported while applying a changeset or patchset.
+///
+/// Passed to a [`ConflictResolver`], which may inspect the conflicting change
+/// and the row it collided with before choosing a [`ConflictAction`]. Values
+/// are read on demand, so resolvers that only look at [`kind`](Self::kind)
+/// pay nothing extra. A `Conflict` is only valid for the duration of the
+/// resolver call.
+pub struct Conflict<'a> {
+    kind: ConflictType,
+    iter: *mut sqlite3_changeset_iter,
+    db: *mut sqlite3,
+    _callback: PhantomData<&'a mut sqlite3_changeset_iter>,
+}
+
+impl Conflict<'_> {
+    /// Wrap the arguments of an `SQLite` conflict callback.
+    ///
+    /// # Safety
+    ///
+    /// `iter` must be the iterator `SQLite` passed to the conflict callback for
+    /// a conflict of type `kind`, and must remain valid while the returned
+    /// value is alive. `db` must be null or the connection being applied to.
+    pub(crate) unsafe fn new(
+        kind: ConflictType,
+        iter: *mut sqlite3_changeset_iter,
+        db: *mut sqlite3,
+    ) -> Self {
+        Self {
+            kind,
+            iter,
+            db,
+            _callback: PhantomData,
+        }
+    }
+
+    /// The type of conflict.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> ConflictType {
+        self.kind
+    }
+
+    /// Decode the change that caused the conflict.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::IterFailed` if the change cannot be read,
+    /// including for [`ConflictType::ForeignKey`] conflicts, which are reported
+    /// once for the whole changeset rather than for a particular change.
+    pub fn change(&self) -> Result<ChangeOp, ChangesetError> {
+        if self.kind == ConflictType::ForeignKey {
+            return Err(misuse());
+        }
+        // SAFETY: for every other conflict type SQLite passes an iterator
+        // positioned on the conflicting change.
+        unsafe { read_op(self.iter) }
+    }
+
+    /// Values of the existing row the change collided with, in column order.
+    ///
+    /// Available for [`ConflictType::Data`] and [`ConflictType::Conflict`];
+    /// `None` for the other conflict types, which have no conflicting row.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::IterFailed` if the values cannot be read.
+    pub fn existing_values(&self) -> Result<Option<Vec<Option<SqliteValue>>>, ChangesetError> {
+        match self.kind {
+            ConflictType::Data | ConflictType::Conflict => {
+                // SAFETY: SQLite provides conflicting values for these conflict types.
+                unsafe { read_conflicting_values(self.iter) }.map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Read the whole existing row the change collided with from the database.
+    ///
+    /// [`existing_values`](Self::existing_values) only covers the columns the
+    /// changeset records; when the replica table has more columns, for
+    /// example after a column was added on the replica only, this returns
+    /// them all, in the replica's column order. The row is read through the
+    /// connection being applied to, which the apply keeps borrowed, so no
+    /// connection is passed in.
+    ///
+    /// Available for [`ConflictType::Data`] and [`ConflictType::Conflict`];
+    /// `None` for the other conflict types, which have no conflicting row.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ApplyOptions, Conflict, ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// # let changeset = diesel_sqlite_session::Changeset::default();
+    /// let mut resolver = |conflict: &Conflict<'_>| match conflict.existing_row() {
+    ///     Ok(Some(row)) if row.len() > 3 && !row[3].is_null() => ConflictAction::Omit,
+    ///     _ => ConflictAction::Replace,
+    /// };
+    /// replica
+    ///     .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::IterFailed` if the change cannot be read.
+    /// Returns `ChangesetError::QueryFailed` if the row cannot be queried.
+    pub fn existing_row(&self) -> Result<Option<Vec<SqliteValue>>, ChangesetError> {
+        if !matches!(self.kind, ConflictType::Data | ConflictType::Conflict) {
+            return Ok(None);
+        }
+        if self.db.is_null() {
+            return Err(misuse());
+        }
+        let change = self.change()?;
+        // SAFETY: `self.db` is the connection being applied to, which stays
+        // open for the duration of the conflict callback.
+        unsafe { read_row(self.db, &change) }
+    }
+}
+
+/// Read the row of `main` that `change` targets, identified by its primary key.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn read_row(
+    db: *mut sqlite3,
+    change: &ChangeOp,
+) -> Result<Option<Vec<SqliteValue>>, ChangesetError> {
+    let query_failed = |rc| ChangesetError::QueryFailed(SqliteErrorCode::from_error(rc));
+    // SAFETY: the caller guarantees `db` is valid.
+    let names = unsafe { column_names(db, "main", change.table()) }.map_err(query_failed)?;
+    let keys = change.primary_key_values();
+    let conditions: Vec<String> = change
+        .primary_key()
+        .iter()
+        .zip(&names)
+        .filter(|(is_pk, _)| **is_pk)
+        .enumerate()
+        .map(|(index, (_, name))| format!("{} IS ?{}", quote_identifier(name), index + 1))
+        .collect();
+    if conditions.len() != keys.len() {
+        return Err(misuse());
+    }
+    let sql = format!(
+        "SELECT * FROM main.{} WHERE {}",
+        quote_identifier(change.table()),
+        conditions.join(" AND ")
+    );
+
+    // SAFETY: the caller guarantees `db` is valid, and it outlives the statement.
+    let mut stmt = unsafe { Statement::prepare(db, &sql) }.map_err(query_failed)?;
+    for (index, key) in (1..).zip(&keys) {
+        stmt.bind_value(index, key).map_err(query_failed)?;
+    }
+    if !stmt.step().map_err(query_failed)? {
+        return Ok(None);
+    }
+    Ok(Some(
+        (0..stmt.column_count())
+            .map(|index| stmt.column_value(index))
+            .collect(),
+    ))
+}
+
+/// An owned copy of a [`Conflict`], returned by
+/// [`SqliteSessionExt::apply_collecting_conflicts`](crate::SqliteSessionExt::apply_collecting_conflicts).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictRecord {
+    kind: ConflictType,
+    change: Option<ChangeOp>,
+    existing_values: Option<Vec<Option<SqliteValue>>>,
+}
+
+impl ConflictRecord {
+    /// Copy the details of `conflict` while it is still valid.
+    ///
+    /// Details that cannot be read are left out rather than failing the apply.
+    pub(crate) fn capture(conflict: &Conflict<'_>) -> Self {
+        Self {
+            kind: conflict.kind(),
+            change: conflict.change().ok(),
+            existing_values: conflict.existing_values().ok().flatten(),
+        }
+    }
+
+    /// Record a [`ConflictType::Data`] conflict found before the apply, for a
+    /// change that was never handed to `SQLite`.
+    fn stale(change: ChangeOp, existing_values: Vec<Option<SqliteValue>>) -> Self {
+        Self {
+            kind: ConflictType::Data,
+            change: Some(change),
+            existing_values: Some(existing_values),
+        }
+    }
+
+    /// The type of conflict.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> ConflictType {
+        self.kind
+    }
+
+    /// The change that caused the conflict.
+    ///
+    /// `None` for [`ConflictType::ForeignKey`] conflicts, which are reported
+    /// once for the whole changeset rather than for a particular change.
+    #[inline]
+    #[must_use]
+    pub fn change(&self) -> Option<&ChangeOp> {
+        self.change.as_ref()
+    }
+
+    /// Values of the existing row the change collided with, in column order.
+    ///
+    /// Only recorded for [`ConflictType::Data`] and [`ConflictType::Conflict`].
+    #[inline]
+    #[must_use]
+    pub fn existing_values(&self) -> Option<&[Option<SqliteValue>]> {
+        self.existing_values.as_deref()
+    }
+}
+
+fn misuse() -> ChangesetError {
+    ChangesetError::IterFailed(SqliteErrorCode::from_error(SQLITE_MISUSE))
+}
+
+/// Decides how to resolve each conflict of an apply.
+///
+/// Implemented for closures taking a [`Conflict`], and by ready-made policies
+/// such as [`ConflictPolicy`]. Use with
+/// [`SqliteSessionExt::apply_changeset_resolving`](crate::SqliteSessionExt::apply_changeset_resolving).
+pub trait ConflictResolver {
+    /// Choose the action for one conflict.
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: FnMut(&Conflict<'_>) -> ConflictAction,
+{
+    #[inline]
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        self(conflict)
+    }
+}
+
+/// Adapter for handlers that only look at the conflict type.
+pub(crate) struct ByKind<F>(pub(crate) F);
+
+impl<F> ConflictResolver for ByKind<F>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    #[inline]
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        (self.0)(conflict.kind())
+    }
+}
+
+/// A resolver that sets conflicting changes aside for manual review.
+///
+/// Every conflicting change is omitted from the apply and added to a
+/// dead-letter changeset, returned by [`into_dead_letter`](Self::into_dead_letter)
+/// once the apply is done. [`ConflictType::ForeignKey`] conflicts are not
+/// tied to a single change, so they abort the apply instead. Only use it with
+/// changesets: patchset changes lack the old values a dead-letter changeset
+/// needs.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, DeadLetterResolver, SqliteSessionExt};
+///
+/// let mut replica = SqliteConnection::establish(":memory:").unwrap();
+/// # let changeset = diesel_sqlite_session::Changeset::default();
+/// let mut resolver = DeadLetterResolver::new().unwrap();
+/// replica
+///     .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+///     .unwrap();
+/// let rejected = resolver.into_dead_letter().unwrap();
+/// ```
+pub struct DeadLetterResolver {
+    group: ChangeGroup,
+    error: Option<ChangesetError>,
+}
+
+impl DeadLetterResolver {
+    /// Create a resolver with an empty dead-letter changeset.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::GroupFailed` if `SQLite` fails to allocate the
+    /// change group holding the rejected changes.
+    pub fn new() -> Result<Self, ChangesetError> {
+        Ok(Self {
+            group: ChangeGroup::new()?,
+            error: None,
+        })
+    }
+
+    /// Return the changeset of every change omitted so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error met while recording a rejected change, or
+    /// `ChangesetError::GroupFailed` if the changeset cannot be produced.
+    pub fn into_dead_letter(mut self) -> Result<Changeset, ChangesetError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.group.output()
+    }
+
+    fn record(&mut self, conflict: &Conflict<'_>) -> Result<(), ChangesetError> {
+        let change: Changeset = std::iter::once(conflict.change()?).collect();
+        self.group.add(&change)
+    }
+}
+
+impl ConflictResolver for DeadLetterResolver {
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        if conflict.kind() == ConflictType::ForeignKey {
+            return ConflictAction::Abort;
+        }
+        if self.error.is_none() {
+            self.error = self.record(conflict).err();
+        }
+        ConflictAction::Omit
+    }
+}
+
+/// A conflict sent to another thread by a [`ChannelResolver`].
+///
+/// Owns a copy of the conflict details, so it can outlive the apply call
+/// that reported it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictPrompt {
+    record: ConflictRecord,
+}
+
+impl ConflictPrompt {
+    /// The type of conflict.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> ConflictType {
+        self.record.kind()
+    }
+
+    /// The table of the conflicting change.
+    ///
+    /// `None` for [`ConflictType::ForeignKey`] conflicts, which are reported
+    /// once for the whole changeset rather than for a particular change.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> Option<&str> {
+        self.record.change().map(ChangeOp::table)
+    }
+
+    /// Old values of the conflicting change, empty if there is no change.
+    #[inline]
+    #[must_use]
+    pub fn old_values(&self) -> &[Option<SqliteValue>] {
+        self.record.change().map_or(&[], ChangeOp::old_values)
+    }
+
+    /// New values of the conflicting change, empty if there is no change.
+    #[inline]
+    #[must_use]
+    pub fn new_values(&self) -> &[Option<SqliteValue>] {
+        self.record.change().map_or(&[], ChangeOp::new_values)
+    }
+
+    /// Values of the existing row the change collided with, in column order.
+    ///
+    /// Only present for [`ConflictType::Data`] and [`ConflictType::Conflict`].
+    #[inline]
+    #[must_use]
+    pub fn existing_values(&self) -> Option<&[Option<SqliteValue>]> {
+        self.record.existing_values()
+    }
+
+    /// The conflict details as a [`ConflictRecord`].
+    #[inline]
+    #[must_use]
+    pub fn into_record(self) -> ConflictRecord {
+        self.record
+    }
+}
+
+/// A resolver that hands each conflict to another thread and waits for the answer.
+///
+/// The conflict callback runs synchronously inside the apply, so an
+/// interactive application cannot ask the user from it directly. This
+/// resolver sends a [`ConflictPrompt`] on `prompts` and blocks until a
+/// [`ConflictAction`] arrives on `replies`, which lets a UI thread resolve
+/// conflicts one by one. If either channel is disconnected, the apply is
+/// aborted with [`ConflictAction::Abort`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, ChannelResolver, ConflictAction, SqliteSessionExt};
+///
+/// let (prompt_tx, prompt_rx) = mpsc::channel();
+/// let (reply_tx, reply_rx) = mpsc::channel();
+/// thread::spawn(move || {
+///     for prompt in prompt_rx {
+///         // Show `prompt` to the user and send back their choice.
+///         reply_tx.send(ConflictAction::Omit).unwrap();
+///     }
+/// });
+///
+/// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+/// # let changeset = diesel_sqlite_session::Changeset::default();
+/// let mut resolver = ChannelResolver::new(prompt_tx, reply_rx);
+/// replica
+///     .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ChannelResolver {
+    prompts: Sender<ConflictPrompt>,
+    replies: Receiver<ConflictAction>,
+}
+
+impl ChannelResolver {
+    /// Create a resolver sending prompts on `prompts` and reading actions from `replies`.
+    #[inline]
+    #[must_use]
+    pub fn new(prompts: Sender<ConflictPrompt>, replies: Receiver<ConflictAction>) -> Self {
+        Self { prompts, replies }
+    }
+}
+
+impl ConflictResolver for ChannelResolver {
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        let prompt = ConflictPrompt {
+            record: ConflictRecord::capture(conflict),
+        };
+        if self.prompts.send(prompt).is_err() {
+            return ConflictAction::Abort;
+        }
+        self.replies.recv().unwrap_or(ConflictAction::Abort)
+    }
+}
+
+/// A resolver that settles conflicts by comparing against a common ancestor.
+///
+/// For every [`ConflictType::Data`] or [`ConflictType::Conflict`] conflict the
+/// `ancestor` lookup is called with the table name and primary key of the
+/// conflicting change, and returns the row as it was in the common ancestor,
+/// in column order, or `None` if the row did not exist there. Then:
+///
+/// - if the existing row equals the ancestor, only the incoming side changed
+///   it, so the change is applied with [`ConflictAction::Replace`];
+/// - if the old values of the change equal the ancestor, only the local side
+///   changed it, so the existing row is kept with [`ConflictAction::Omit`];
+/// - otherwise both sides changed the row. The conflict is recorded in
+///   [`unresolved`](Self::unresolved) and resolved with the action set by
+///   [`otherwise`](Self::otherwise), [`ConflictAction::Abort`] by default.
+///
+/// Old values are compared only for the columns the change recorded. Other
+/// conflict types go straight to the fallback action without being recorded.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, SqliteSessionExt, SqliteValue, ThreeWayMerge};
+///
+/// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+/// # let changeset = diesel_sqlite_session::Changeset::default();
+/// let mut merge = ThreeWayMerge::new(|_table: &str, _pk: &[SqliteValue]| {
+///     // Look the row up in a snapshot of the common ancestor.
+///     None
+/// });
+/// replica
+///     .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut merge)
+///     .unwrap();
+/// ```
+pub struct ThreeWayMerge<F> {
+    ancestor: F,
+    otherwise: ConflictAction,
+    unresolved: Vec<ConflictRecord>,
+}
+
+impl<F> ThreeWayMerge<F>
+where
+    F: FnMut(&str, &[SqliteValue]) -> Option<Vec<Option<SqliteValue>>>,
+{
+    /// Create a resolver looking up ancestor rows with `ancestor`.
+    #[inline]
+    #[must_use]
+    pub fn new(ancestor: F) -> Self {
+        Self {
+            ancestor,
+            otherwise: ConflictAction::Abort,
+            unresolved: Vec::new(),
+        }
+    }
+
+    /// Set the action for conflicts the ancestor cannot settle.
+    #[inline]
+    #[must_use]
+    pub fn otherwise(mut self, action: ConflictAction) -> Self {
+        self.otherwise = action;
+        self
+    }
+
+    /// The conflicts where both sides changed the row.
+    #[inline]
+    #[must_use]
+    pub fn unresolved(&self) -> &[ConflictRecord] {
+        &self.unresolved
+    }
+
+    fn merge(&mut self, conflict: &Conflict<'_>) -> Option<ConflictAction> {
+        let change = conflict.change().ok()?;
+        let existing = conflict.existing_values().ok()??;
+        let ancestor = (self.ancestor)(change.table(), &change.primary_key_values());
+
+        if ancestor.as_ref() == Some(&existing) {
+            return Some(ConflictAction::Replace);
+        }
+        let incoming_from_ancestor = change.op() != OpKind::Insert
+            && ancestor.is_some_and(|ancestor| {
+                change.old_values().len() == ancestor.len()
+                    && change
+                        .old_values()
+                        .iter()
+                        .zip(&ancestor)
+                        .all(|(old, ancestor)| old.is_none() || old == ancestor)
+            });
+        incoming_from_ancestor.then_some(ConflictAction::Omit)
+    }
+}
+
+impl<F> ConflictResolver for ThreeWayMerge<F>
+where
+    F: FnMut(&str, &[SqliteValue]) -> Option<Vec<Option<SqliteValue>>>,
+{
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        if !matches!(conflict.kind(), ConflictType::Data | ConflictType::Conflict) {
+            return self.otherwise;
+        }
+        if let Some(action) = self.merge(conflict) {
+            return action;
+        }
+        self.unresolved.push(ConflictRecord::capture(conflict));
+        self.otherwise
+    }
+}
+
+/// Ready-made conflict resolution policies.
+///
+/// A policy resolves the conflicts it recognizes and falls back to
+/// [`ConflictAction::Abort`] for every other conflict, or to the action set
+/// with [`otherwise`](Self::otherwise).
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, ConflictAction, ConflictPolicy, SqliteSessionExt};
+///
+/// let mut replica = SqliteConnection::establish(":memory:").unwrap();
+/// # let changeset = diesel_sqlite_session::Changeset::default();
+/// let mut policy = ConflictPolicy::nocase_text_equal_is_not_conflict().otherwise(ConflictAction::Omit);
+/// replica
+///     .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut policy)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConflictPolicy {
+    rule: Rule,
+    otherwise: ConflictAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    NocaseTextEqual,
+    FieldLevelMerge,
+}
+
+impl ConflictPolicy {
+    /// Apply changes whose expected values differ from the existing row only
+    /// in the ASCII case of text.
+    ///
+    /// The session extension compares old values byte for byte, so a replica
+    /// storing `'ALICE'` where the source had `'Alice'` reports a
+    /// [`ConflictType::Data`] conflict even when the column is declared
+    /// `COLLATE NOCASE`. This policy re-compares the old values with the
+    /// existing row using the same folding as `NOCASE` and forces the change
+    /// through with [`ConflictAction::Replace`] when they match.
+    #[inline]
+    #[must_use]
+    pub const fn nocase_text_equal_is_not_conflict() -> Self {
+        Self {
+            rule: Rule::NocaseTextEqual,
+            otherwise: ConflictAction::Abort,
+        }
+    }
+
+    /// Merge concurrent updates of a row column by column.
+    ///
+    /// A changeset update records the old and new values of the columns it
+    /// modified only. When the existing row no longer matches those old
+    /// values, `SQLite` reports a [`ConflictType::Data`] conflict; this policy
+    /// resolves it with [`ConflictAction::Replace`], which writes only the
+    /// modified columns. Columns the incoming change did not touch keep the
+    /// existing row's values, so edits two peers made to different columns
+    /// both survive. Where both peers changed the same column, the incoming
+    /// value wins.
+    #[inline]
+    #[must_use]
+    pub const fn field_level_merge() -> Self {
+        Self {
+            rule: Rule::FieldLevelMerge,
+            otherwise: ConflictAction::Abort,
+        }
+    }
+
+    /// Set the action for conflicts the policy does not resolve.
+    #[inline]
+    #[must_use]
+    pub const fn otherwise(mut self, action: ConflictAction) -> Self {
+        self.otherwise = action;
+        self
+    }
+}
+
+impl ConflictResolver for ConflictPolicy {
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        let resolved = match self.rule {
+            Rule::NocaseTextEqual => resolve_nocase_text_equal(conflict),
+            Rule::FieldLevelMerge => resolve_field_level_merge(conflict),
+        };
+        resolved.unwrap_or(self.otherwise)
+    }
+}
+
+fn resolve_nocase_text_equal(conflict: &Conflict<'_>) -> Option<ConflictAction> {
+    if conflict.kind() != ConflictType::Data {
+        return None;
+    }
+    let change = conflict.change().ok()?;
+    let existing = conflict.existing_values().ok()??;
+
+    let equal_ignoring_case =
+        change
+            .old_values()
+            .iter()
+            .zip(&existing)
+            .all(|(old, existing)| match (old, existing) {
+                (Some(SqliteValue::Text(old)), Some(SqliteValue::Text(existing))) => {
+                    old.eq_ignore_ascii_case(existing)
+                }
+                (Some(old), Some(existing)) => old == existing,
+                _ => true,
+            });
+    equal_ignoring_case.then_some(ConflictAction::Replace)
+}
+
+fn resolve_field_level_merge(conflict: &Conflict<'_>) -> Option<ConflictAction> {
+    if conflict.kind() != ConflictType::Data {
+        return None;
+    }
+    let change = conflict.change().ok()?;
+    (change.op() == OpKind::Update).then_some(ConflictAction::Replace)
+}
+
+/// A resolver enforcing optimistic concurrency through a version column.
+///
+/// Each table listed with [`table`](Self::table) names the column holding a
+/// row version that writers bump on every change. When a change runs into a
+/// [`ConflictType::Data`] conflict, the version it expected is compared with
+/// the version of the existing row: if they match, the change is forced
+/// through with [`ConflictAction::Replace`], and if not, the replica row was
+/// changed concurrently and the change is rejected with
+/// [`ConflictAction::Omit`] and recorded in [`rejected`](Self::rejected).
+/// Other conflicts, changes to unlisted tables and updates that do not bump
+/// the version get the action set with [`otherwise`](Self::otherwise),
+/// [`ConflictAction::Abort`] by default.
+///
+/// A patchset update records no old values, so `SQLite` applies it without
+/// comparing anything and no conflict is ever reported for a stale version.
+/// Apply patchsets with [`apply`](Self::apply), which checks their updates
+/// against the replica before applying them.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, SqliteSessionExt, VersionCheck};
+///
+/// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+/// # let changeset = diesel_sqlite_session::Changeset::default();
+/// // `documents (id, body, version)`: the version is column 2.
+/// let mut check = VersionCheck::new().table("documents", 2);
+/// replica
+///     .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut check)
+///     .unwrap();
+/// for stale in check.rejected() {
+///     println!("rejected stale change: {:?}", stale.change());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct VersionCheck {
+    columns: HashMap<String, usize>,
+    otherwise: ConflictAction,
+    rejected: Vec<ConflictRecord>,
+}
+
+impl Default for VersionCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VersionCheck {
+    /// Create a resolver checking no table yet.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            columns: HashMap::new(),
+            otherwise: ConflictAction::Abort,
+            rejected: Vec::new(),
+        }
+    }
+
+    /// Check the version stored in column `column` of `table`, counting from 0.
+    #[inline]
+    #[must_use]
+    pub fn table(mut self, table: &str, column: usize) -> Self {
+        self.columns.insert(table.to_owned(), column);
+        self
+    }
+
+    /// Set the action for conflicts the version cannot settle.
+    #[inline]
+    #[must_use]
+    pub fn otherwise(mut self, action: ConflictAction) -> Self {
+        self.otherwise = action;
+        self
+    }
+
+    /// The changes rejected because their expected version was stale.
+    #[inline]
+    #[must_use]
+    pub fn rejected(&self) -> &[ConflictRecord] {
+        &self.rejected
+    }
+
+    /// Apply `data` to `conn`, checking versions even when it is a patchset.
+    ///
+    /// A changeset is applied with this resolver, as by
+    /// [`apply_changeset_resolving`](crate::SqliteSessionExt::apply_changeset_resolving).
+    /// In a patchset, each update to a listed table is first compared with
+    /// the replica row. The version it expected is taken to be the new one
+    /// minus one, since writers bump it by one on every change. Stale updates
+    /// are left out of the apply and recorded in [`rejected`](Self::rejected)
+    /// as [`ConflictType::Data`] conflicts, but are not counted in the returned
+    /// stats. Updates that do not set an integer version get the
+    /// [`otherwise`](Self::otherwise) action. The rest of the patchset is then
+    /// applied with this resolver.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ApplyOptions, VersionCheck};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// # let patchset: Vec<u8> = Vec::new();
+    /// let mut check = VersionCheck::new().table("documents", 2);
+    /// check.apply(&mut replica, &patchset, &ApplyOptions::new()).unwrap();
+    /// println!("{} stale updates rejected", check.rejected().len());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as
+    /// [`apply_changeset_resolving`](crate::SqliteSessionExt::apply_changeset_resolving).
+    /// Returns `ApplyError::ConflictAborted` if a patchset update does not set
+    /// a version and [`otherwise`](Self::otherwise) is
+    /// [`ConflictAction::Abort`]; nothing is applied then.
+    pub fn apply(
+        &mut self,
+        conn: &mut SqliteConnection,
+        data: &[u8],
+        options: &ApplyOptions,
+    ) -> Result<ApplyStats, ApplyError> {
+        if Format::of(data) == Format::Changeset {
+            return apply_resolving(conn, data, options, self);
+        }
+        // SAFETY: `with_raw_connection` provides a valid SQLite handle for the
+        // duration of the callback.
+        let current = unsafe { conn.with_raw_connection(|db| self.drop_stale(db, data)) }?;
+        apply_resolving(conn, &current, options, self)
+    }
+
+    /// Re-encode `patchset` without its stale updates, recording them as
+    /// rejected.
+    ///
+    /// # Safety
+    ///
+    /// `db` must be a valid connection handle.
+    unsafe fn drop_stale(
+        &mut self,
+        db: *mut sqlite3,
+        patchset: &[u8],
+    ) -> Result<Vec<u8>, ApplyError> {
+        let mut encoder = Encoder::new(Format::Patchset);
+        for op in read_changeset(patchset)? {
+            let op = op?;
+            let column = match self.columns.get(op.table()) {
+                Some(&column) if op.op() == OpKind::Update => column,
+                _ => {
+                    encoder.push(&op);
+                    continue;
+                }
+            };
+            let expected = match op.new_values().get(column) {
+                Some(Some(SqliteValue::Integer(new))) => new.checked_sub(1),
+                _ => None,
+            };
+            let Some(expected) = expected else {
+                match self.otherwise {
+                    ConflictAction::Omit => {}
+                    ConflictAction::Replace => encoder.push(&op),
+                    ConflictAction::Abort => return Err(ApplyError::ConflictAborted),
+                }
+                continue;
+            };
+            // SAFETY: the caller guarantees `db` is valid.
+            match unsafe { read_row(db, &op) }? {
+                Some(row) if row.get(column) != Some(&SqliteValue::Integer(expected)) => {
+                    let existing = row
+                        .into_iter()
+                        .take(op.new_values().len())
+                        .map(Some)
+                        .collect();
+                    self.rejected.push(ConflictRecord::stale(op, existing));
+                }
+                // A missing row is left for SQLite to report as `NotFound`.
+                _ => encoder.push(&op),
+            }
+        }
+        Ok(encoder.finish())
+    }
+
+    /// Whether the version `conflict` expected matches the existing row, or
+    /// `None` if there is no version to compare.
+    fn version_matches(&self, conflict: &Conflict<'_>) -> Option<bool> {
+        let change = conflict.change().ok()?;
+        let column = *self.columns.get(change.table())?;
+        let expected = change.old_values().get(column)?.as_ref()?;
+        let existing = conflict.existing_values().ok()??;
+        Some(existing.get(column)?.as_ref() == Some(expected))
+    }
+}
+
+impl ConflictResolver for VersionCheck {
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        if conflict.kind() != ConflictType::Data {
+            return self.otherwise;
+        }
+        match self.version_matches(conflict) {
+            Some(true) => ConflictAction::Replace,
+            Some(false) => {
+                self.rejected.push(ConflictRecord::capture(conflict));
+                ConflictAction::Omit
+            }
+            None => self.otherwise,
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Plain data types that depend only on `core` and `alloc`.
+//!
+//! The `SQLite` result codes, conflict kinds and column values carry no FFI
+//! handles, so they live apart from the rest of the crate and build without
+//! `std`. The `no-std-check` workspace crate compiles this file under
+//! `#![no_std]` to keep it that way; code that needs `std` or the `SQLite`
+//! bindings belongs in `errors.rs` or `value.rs`.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::Utf8Error;
+
+/// `SQLite` result codes returned by the session extension.
+///
+/// These correspond to `SQLite`'s [result codes](https://www.sqlite.org/rescode.html).
+/// Only codes relevant to session operations are enumerated; others are captured
+/// in the `Unknown` variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SqliteErrorCode {
+    /// Generic error (`SQLITE_ERROR` = 1).
+    Error,
+    /// Internal logic error (`SQLITE_INTERNAL` = 2).
+    Internal,
+    /// Access permission denied (`SQLITE_PERM` = 3).
+    Permission,
+    /// Database file is locked (`SQLITE_BUSY` = 5).
+    Busy,
+    /// A table in the database is locked (`SQLITE_LOCKED` = 6).
+    Locked,
+    /// Memory allocation failed (`SQLITE_NOMEM` = 7).
+    NoMemory,
+    /// Attempt to write a readonly database (`SQLITE_READONLY` = 8).
+    ReadOnly,
+    /// Database schema changed (`SQLITE_SCHEMA` = 17).
+    Schema,
+    /// Library used incorrectly (`SQLITE_MISUSE` = 21).
+    Misuse,
+    /// Unknown or unhandled `SQLite` error code.
+    Unknown(i32),
+}
+
+impl SqliteErrorCode {
+    #[must_use]
+    const fn from_nonzero_raw(code: i32) -> Self {
+        match code {
+            1 => Self::Error,
+            2 => Self::Internal,
+            3 => Self::Permission,
+            5 => Self::Busy,
+            6 => Self::Locked,
+            7 => Self::NoMemory,
+            8 => Self::ReadOnly,
+            17 => Self::Schema,
+            21 => Self::Misuse,
+            other => Self::Unknown(other),
+        }
+    }
+
+    /// Create from a raw `SQLite` result code.
+    ///
+    /// Returns `None` for `SQLITE_OK` (0) since that indicates success.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::SqliteErrorCode;
+    ///
+    /// assert_eq!(SqliteErrorCode::from_raw(0), None);
+    /// assert_eq!(SqliteErrorCode::from_raw(5), Some(SqliteErrorCode::Busy));
+    /// ```
+    #[must_use]
+    pub const fn from_raw(code: i32) -> Option<Self> {
+        if code == 0 {
+            None
+        } else {
+            Some(Self::from_nonzero_raw(code))
+        }
+    }
+
+    /// Create from a non-zero `SQLite` error code.
+    ///
+    /// Use this when you've already verified the code is not `SQLITE_OK`.
+    /// Falls back to `Unknown(code)` if the code is 0 or unrecognized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::SqliteErrorCode;
+    ///
+    /// assert_eq!(SqliteErrorCode::from_error(21), SqliteErrorCode::Misuse);
+    /// assert_eq!(SqliteErrorCode::from_error(1234), SqliteErrorCode::Unknown(1234));
+    /// ```
+    #[must_use]
+    pub const fn from_error(code: i32) -> Self {
+        Self::from_nonzero_raw(code)
+    }
+
+    /// Get the raw `SQLite` result code.
+    #[must_use]
+    pub const fn to_raw(self) -> i32 {
+        match self {
+            Self::Error => 1,
+            Self::Internal => 2,
+            Self::Permission => 3,
+            Self::Busy => 5,
+            Self::Locked => 6,
+            Self::NoMemory => 7,
+            Self::ReadOnly => 8,
+            Self::Schema => 17,
+            Self::Misuse => 21,
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for SqliteErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "SQLITE_ERROR (1)"),
+            Self::Internal => write!(f, "SQLITE_INTERNAL (2)"),
+            Self::Permission => write!(f, "SQLITE_PERM (3)"),
+            Self::Busy => write!(f, "SQLITE_BUSY (5)"),
+            Self::Locked => write!(f, "SQLITE_LOCKED (6)"),
+            Self::NoMemory => write!(f, "SQLITE_NOMEM (7)"),
+            Self::ReadOnly => write!(f, "SQLITE_READONLY (8)"),
+            Self::Schema => write!(f, "SQLITE_SCHEMA (17)"),
+            Self::Misuse => write!(f, "SQLITE_MISUSE (21)"),
+            Self::Unknown(code) => write!(f, "SQLITE_UNKNOWN ({code})"),
+        }
+    }
+}
+
+/// Types of conflicts that can occur when applying changes.
+///
+/// These correspond to `SQLite`'s `SQLITE_CHANGESET_*` conflict codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum ConflictType {
+    /// The row to be updated/deleted has different values than expected.
+    ///
+    /// Never reported for patchsets, which carry no old values to compare.
+    Data = 1,
+    /// The row to be updated/deleted was not found.
+    NotFound = 2,
+    /// A row with the same primary key already exists (INSERT conflict).
+    Conflict = 3,
+    /// A constraint (other than foreign key) was violated.
+    Constraint = 4,
+    /// A foreign key constraint was violated.
+    ForeignKey = 5,
+}
+
+impl ConflictType {
+    /// Create a `ConflictType` from an `SQLite` conflict code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::ConflictType;
+    ///
+    /// assert_eq!(ConflictType::from_raw(1), Some(ConflictType::Data));
+    /// assert_eq!(ConflictType::from_raw(42), None);
+    /// ```
+    #[must_use]
+    pub const fn from_raw(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(Self::Data),
+            2 => Some(Self::NotFound),
+            3 => Some(Self::Conflict),
+            4 => Some(Self::Constraint),
+            5 => Some(Self::ForeignKey),
+            _ => None,
+        }
+    }
+
+    /// Convert to the raw `SQLite` conflict code.
+    #[must_use]
+    pub const fn to_raw(self) -> i32 {
+        self as i32
+    }
+
+    /// Whether an insert found a row with the same primary key
+    /// ([`Conflict`](Self::Conflict)).
+    #[inline]
+    #[must_use]
+    pub const fn is_primary_key_conflict(self) -> bool {
+        matches!(self, Self::Conflict)
+    }
+
+    /// Whether the row to update or delete does not exist
+    /// ([`NotFound`](Self::NotFound)).
+    #[inline]
+    #[must_use]
+    pub const fn is_missing_row(self) -> bool {
+        matches!(self, Self::NotFound)
+    }
+
+    /// Whether the row to update or delete no longer holds the expected
+    /// values ([`Data`](Self::Data)).
+    #[inline]
+    #[must_use]
+    pub const fn is_value_mismatch(self) -> bool {
+        matches!(self, Self::Data)
+    }
+
+    /// Whether a constraint other than a foreign key was violated
+    /// ([`Constraint`](Self::Constraint)).
+    #[inline]
+    #[must_use]
+    pub const fn is_constraint(self) -> bool {
+        matches!(self, Self::Constraint)
+    }
+
+    /// Whether foreign key constraints were violated
+    /// ([`ForeignKey`](Self::ForeignKey)).
+    #[inline]
+    #[must_use]
+    pub const fn is_foreign_key(self) -> bool {
+        matches!(self, Self::ForeignKey)
+    }
+}
+
+/// Action to take when a conflict occurs during changeset application.
+///
+/// These correspond to `SQLite`'s `SQLITE_CHANGESET_*` resolution codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum ConflictAction {
+    /// Skip this conflicting change and continue with the next one.
+    Omit = 0,
+    /// Force apply this change, replacing the existing row.
+    Replace = 1,
+    /// Stop processing and return an error.
+    Abort = 2,
+}
+
+impl ConflictAction {
+    /// Convert to the raw `SQLite` conflict resolution code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::ConflictAction;
+    ///
+    /// assert_eq!(ConflictAction::Omit.to_raw(), 0);
+    /// assert_eq!(ConflictAction::Replace.to_raw(), 1);
+    /// assert_eq!(ConflictAction::Abort.to_raw(), 2);
+    /// ```
+    #[must_use]
+    pub const fn to_raw(self) -> i32 {
+        self as i32
+    }
+}
+
+/// A single column value stored in a changeset.
+///
+/// Text is kept as the raw bytes `SQLite` recorded. `SQLite` does not validate
+/// the encoding of text it stores, so a source that wrote non-UTF-8 bytes into
+/// a `TEXT` column produces a `Text` value that is not valid UTF-8. Use
+/// [`text`](Self::text) to decode strictly or [`text_lossy`](Self::text_lossy)
+/// to substitute `U+FFFD` for invalid sequences.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqliteValue {
+    /// SQL `NULL`.
+    Null,
+    /// A 64-bit signed integer.
+    Integer(i64),
+    /// A 64-bit IEEE floating point number.
+    Real(f64),
+    /// Text as the raw bytes stored by `SQLite`, normally UTF-8.
+    Text(Vec<u8>),
+    /// A binary blob.
+    Blob(Vec<u8>),
+}
+
+impl SqliteValue {
+    /// Decode a `Text` value as UTF-8.
+    ///
+    /// Returns `None` if the value is not `Text`, and `Some(Err(_))` if the
+    /// stored bytes are not valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::SqliteValue;
+    ///
+    /// let value = SqliteValue::Text(b"hello".to_vec());
+    /// assert_eq!(value.text(), Some(Ok("hello")));
+    /// assert!(SqliteValue::Text(vec![0xff]).text().unwrap().is_err());
+    /// assert_eq!(SqliteValue::Integer(1).text(), None);
+    /// ```
+    #[must_use]
+    pub fn text(&self) -> Option<Result<&str, Utf8Error>> {
+        match self {
+            Self::Text(bytes) => Some(core::str::from_utf8(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Decode a `Text` value as UTF-8, replacing invalid sequences with `U+FFFD`.
+    ///
+    /// Returns `None` if the value is not `Text`. Valid text is borrowed without
+    /// copying.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::SqliteValue;
+    ///
+    /// let value = SqliteValue::Text(vec![b'a', 0xff]);
+    /// assert_eq!(value.text_lossy().as_deref(), Some("a\u{FFFD}"));
+    /// ```
+    #[must_use]
+    pub fn text_lossy(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Self::Text(bytes) => Some(String::from_utf8_lossy(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Return the value of an `Integer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::SqliteValue;
+    ///
+    /// assert_eq!(SqliteValue::Integer(7).as_i64(), Some(7));
+    /// assert_eq!(SqliteValue::Real(7.0).as_i64(), None);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(int) => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// Return the value of a `Real`.
+    ///
+    /// Integers are not converted; use [`as_i64`](Self::as_i64) for those.
+    #[inline]
+    #[must_use]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Real(real) => Some(*real),
+            _ => None,
+        }
+    }
+
+    /// Borrow a `Text` value as a string.
+    ///
+    /// Returns `None` if the value is not `Text` or is not valid UTF-8; use
+    /// [`text`](Self::text) to tell the two apart.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::SqliteValue;
+    ///
+    /// assert_eq!(SqliteValue::Text(b"hi".to_vec()).as_str(), Some("hi"));
+    /// assert_eq!(SqliteValue::Text(vec![0xff]).as_str(), None);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        self.text().and_then(Result::ok)
+    }
+
+    /// Borrow the raw bytes of a `Text` or `Blob` value.
+    #[inline]
+    #[must_use]
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Text(bytes) | Self::Blob(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Whether this is SQL `NULL`.
+    #[inline]
+    #[must_use]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+}
@@ -0,0 +1,213 @@
+//! Encode operations back into the changeset and patchset binary formats.
+//!
+//! See <https://www.sqlite.org/sessionintro.html> and the comments in
+//! `sqlite3session.c` for the layout written here.
+
+use crate::iter::{ChangeOp, OpKind};
+use crate::value::SqliteValue;
+
+/// Which of the two binary formats an encoder writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Changeset,
+    Patchset,
+}
+
+impl Format {
+    /// Detect the format of encoded input from its first table header.
+    ///
+    /// Empty input is treated as a changeset.
+    pub(crate) fn of(data: &[u8]) -> Self {
+        if data.first() == Some(&b'P') {
+            Self::Patchset
+        } else {
+            Self::Changeset
+        }
+    }
+
+    const fn table_marker(self) -> u8 {
+        match self {
+            Self::Changeset => b'T',
+            Self::Patchset => b'P',
+        }
+    }
+}
+
+/// Incrementally writes operations into a changeset or patchset buffer.
+///
+/// Operations are written in the order they are pushed. A table header is
+/// emitted whenever the table (or its primary key layout) differs from the
+/// previous operation.
+#[derive(Debug)]
+pub(crate) struct Encoder {
+    format: Format,
+    buf: Vec<u8>,
+    current: Option<(String, Vec<u8>)>,
+}
+
+impl Encoder {
+    pub(crate) fn new(format: Format) -> Self {
+        Self {
+            format,
+            buf: Vec::new(),
+            current: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, op: &ChangeOp) {
+        let header_matches = self
+            .current
+            .as_ref()
+            .is_some_and(|(table, flags)| table == op.table() && flags == op.pk_flags());
+        if !header_matches {
+            self.write_table_header(op);
+        }
+
+        self.buf.push(u8::try_from(op.op().to_raw()).unwrap_or(0));
+        self.buf.push(u8::from(op.is_indirect()));
+
+        let pk = op.primary_key();
+        match (self.format, op.op()) {
+            (_, OpKind::Insert) => self.write_record(op.new_values()),
+            (Format::Changeset, OpKind::Delete) => self.write_record(op.old_values()),
+            (Format::Changeset, OpKind::Update) => {
+                self.write_record(op.old_values());
+                self.write_record(op.new_values());
+            }
+            (Format::Patchset, OpKind::Delete) => {
+                for (value, _) in op.old_values().iter().zip(pk).filter(|(_, is_pk)| **is_pk) {
+                    self.write_value(value.as_ref());
+                }
+            }
+            (Format::Patchset, OpKind::Update) => {
+                // The iterator reports primary key values of a patchset update as
+                // old values; the format stores them in the single new record.
+                let values = op.old_values().iter().zip(op.new_values()).zip(pk);
+                for ((old, new), is_pk) in values {
+                    self.write_value(if *is_pk { old.as_ref() } else { new.as_ref() });
+                }
+            }
+        }
+    }
+
+    /// Number of bytes written so far.
+    pub(crate) fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Whether no operation has been written yet.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn write_table_header(&mut self, op: &ChangeOp) {
+        self.buf.push(self.format.table_marker());
+        put_varint(&mut self.buf, op.pk_flags().len() as u64);
+        self.buf.extend_from_slice(op.pk_flags());
+        self.buf.extend_from_slice(op.table().as_bytes());
+        self.buf.push(0);
+        self.current = Some((op.table().to_owned(), op.pk_flags().to_vec()));
+    }
+
+    fn write_record(&mut self, values: &[Option<SqliteValue>]) {
+        for value in values {
+            self.write_value(value.as_ref());
+        }
+    }
+
+    fn write_value(&mut self, value: Option<&SqliteValue>) {
+        match value {
+            None => self.buf.push(0),
+            Some(SqliteValue::Integer(int)) => {
+                self.buf.push(1);
+                self.buf.extend_from_slice(&int.to_be_bytes());
+            }
+            Some(SqliteValue::Real(real)) => {
+                self.buf.push(2);
+                self.buf.extend_from_slice(&real.to_bits().to_be_bytes());
+            }
+            Some(SqliteValue::Text(bytes)) => {
+                self.buf.push(3);
+                put_varint(&mut self.buf, bytes.len() as u64);
+                self.buf.extend_from_slice(bytes);
+            }
+            Some(SqliteValue::Blob(bytes)) => {
+                self.buf.push(4);
+                put_varint(&mut self.buf, bytes.len() as u64);
+                self.buf.extend_from_slice(bytes);
+            }
+            Some(SqliteValue::Null) => self.buf.push(5),
+        }
+    }
+}
+
+/// Append `value` as an `SQLite` varint: big-endian groups of seven bits with
+/// the high bit set on all but the last byte, and a full eight bits in the
+/// ninth byte.
+fn put_varint(buf: &mut Vec<u8>, value: u64) {
+    if value >> 56 != 0 {
+        let mut bytes = [0_u8; 9];
+        bytes[8] = value.to_le_bytes()[0];
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = u8::try_from(rest & 0x7f).unwrap_or(0) | 0x80;
+            rest >>= 7;
+        }
+        buf.extend_from_slice(&bytes);
+        return;
+    }
+
+    let mut groups = [0_u8; 8];
+    let mut count = 0;
+    let mut rest = value;
+    loop {
+        groups[count] = u8::try_from(rest & 0x7f).unwrap_or(0) | 0x80;
+        count += 1;
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    groups[0] &= 0x7f;
+    buf.extend(groups[..count].iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn varint_encodes_small_values_in_one_byte() {
+        assert_eq!(varint(0), [0x00]);
+        assert_eq!(varint(0x7f), [0x7f]);
+    }
+
+    #[test]
+    fn varint_sets_continuation_bits() {
+        assert_eq!(varint(0x80), [0x81, 0x00]);
+        assert_eq!(varint(0x3fff), [0xff, 0x7f]);
+        assert_eq!(varint(0x4000), [0x81, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn varint_uses_full_ninth_byte() {
+        assert_eq!(varint(u64::MAX), [0xff; 9]);
+    }
+
+    #[test]
+    fn format_is_detected_from_table_marker() {
+        assert_eq!(Format::of(b"T\x01"), Format::Changeset);
+        assert_eq!(Format::of(b"P\x01"), Format::Patchset);
+        assert_eq!(Format::of(&[]), Format::Changeset);
+    }
+}
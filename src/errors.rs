@@ -1,128 +1,12 @@
 //! Error types for session operations.
 
 use std::fmt;
+use std::str::FromStr;
 
 use thiserror::Error;
 
-/// `SQLite` result codes returned by the session extension.
-///
-/// These correspond to `SQLite`'s [result codes](https://www.sqlite.org/rescode.html).
-/// Only codes relevant to session operations are enumerated; others are captured
-/// in the `Unknown` variant.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum SqliteErrorCode {
-    /// Generic error (`SQLITE_ERROR` = 1).
-    Error,
-    /// Internal logic error (`SQLITE_INTERNAL` = 2).
-    Internal,
-    /// Access permission denied (`SQLITE_PERM` = 3).
-    Permission,
-    /// Database file is locked (`SQLITE_BUSY` = 5).
-    Busy,
-    /// A table in the database is locked (`SQLITE_LOCKED` = 6).
-    Locked,
-    /// Memory allocation failed (`SQLITE_NOMEM` = 7).
-    NoMemory,
-    /// Attempt to write a readonly database (`SQLITE_READONLY` = 8).
-    ReadOnly,
-    /// Database schema changed (`SQLITE_SCHEMA` = 17).
-    Schema,
-    /// Library used incorrectly (`SQLITE_MISUSE` = 21).
-    Misuse,
-    /// Unknown or unhandled `SQLite` error code.
-    Unknown(i32),
-}
-
-impl SqliteErrorCode {
-    #[must_use]
-    const fn from_nonzero_raw(code: i32) -> Self {
-        match code {
-            1 => Self::Error,
-            2 => Self::Internal,
-            3 => Self::Permission,
-            5 => Self::Busy,
-            6 => Self::Locked,
-            7 => Self::NoMemory,
-            8 => Self::ReadOnly,
-            17 => Self::Schema,
-            21 => Self::Misuse,
-            other => Self::Unknown(other),
-        }
-    }
-
-    /// Create from a raw `SQLite` result code.
-    ///
-    /// Returns `None` for `SQLITE_OK` (0) since that indicates success.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use diesel_sqlite_session::SqliteErrorCode;
-    ///
-    /// assert_eq!(SqliteErrorCode::from_raw(0), None);
-    /// assert_eq!(SqliteErrorCode::from_raw(5), Some(SqliteErrorCode::Busy));
-    /// ```
-    #[must_use]
-    pub const fn from_raw(code: i32) -> Option<Self> {
-        if code == 0 {
-            None
-        } else {
-            Some(Self::from_nonzero_raw(code))
-        }
-    }
-
-    /// Create from a non-zero `SQLite` error code.
-    ///
-    /// Use this when you've already verified the code is not `SQLITE_OK`.
-    /// Falls back to `Unknown(code)` if the code is 0 or unrecognized.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use diesel_sqlite_session::SqliteErrorCode;
-    ///
-    /// assert_eq!(SqliteErrorCode::from_error(21), SqliteErrorCode::Misuse);
-    /// assert_eq!(SqliteErrorCode::from_error(1234), SqliteErrorCode::Unknown(1234));
-    /// ```
-    #[must_use]
-    pub const fn from_error(code: i32) -> Self {
-        Self::from_nonzero_raw(code)
-    }
-
-    /// Get the raw `SQLite` result code.
-    #[must_use]
-    pub const fn to_raw(self) -> i32 {
-        match self {
-            Self::Error => 1,
-            Self::Internal => 2,
-            Self::Permission => 3,
-            Self::Busy => 5,
-            Self::Locked => 6,
-            Self::NoMemory => 7,
-            Self::ReadOnly => 8,
-            Self::Schema => 17,
-            Self::Misuse => 21,
-            Self::Unknown(code) => code,
-        }
-    }
-}
-
-impl fmt::Display for SqliteErrorCode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Error => write!(f, "SQLITE_ERROR (1)"),
-            Self::Internal => write!(f, "SQLITE_INTERNAL (2)"),
-            Self::Permission => write!(f, "SQLITE_PERM (3)"),
-            Self::Busy => write!(f, "SQLITE_BUSY (5)"),
-            Self::Locked => write!(f, "SQLITE_LOCKED (6)"),
-            Self::NoMemory => write!(f, "SQLITE_NOMEM (7)"),
-            Self::ReadOnly => write!(f, "SQLITE_READONLY (8)"),
-            Self::Schema => write!(f, "SQLITE_SCHEMA (17)"),
-            Self::Misuse => write!(f, "SQLITE_MISUSE (21)"),
-            Self::Unknown(code) => write!(f, "SQLITE_UNKNOWN ({code})"),
-        }
-    }
-}
+pub use crate::core_types::{ConflictAction, ConflictType, SqliteErrorCode};
+use crate::value::SqliteValue;
 
 /// Errors that can occur when working with `SQLite` sessions.
 #[derive(Debug, Error)]
@@ -146,6 +30,71 @@ pub enum SessionError {
     /// Table name contains invalid characters.
     #[error("Table name contains null byte")]
     InvalidTableName,
+
+    /// Database name contains invalid characters.
+    #[error("Database name contains null byte")]
+    InvalidDatabaseName,
+
+    /// Failed to diff a table against another database.
+    #[error("Failed to diff table: {message} ({code})")]
+    DiffFailed {
+        /// The `SQLite` result code.
+        code: SqliteErrorCode,
+        /// The error message reported by `SQLite`, empty if none was given.
+        message: String,
+    },
+
+    /// Reading the database schema failed.
+    #[error("Failed to query schema: {0}")]
+    QueryFailed(SqliteErrorCode),
+
+    /// The caller-provided buffer cannot hold the generated output.
+    #[error("Output does not fit in a buffer of {capacity} bytes")]
+    BufferTooSmall {
+        /// Size of the buffer that was provided.
+        capacity: usize,
+    },
+
+    /// A Diesel table declared in one database was attached to a session
+    /// tracking another.
+    #[error("Table {table:?} is declared in database {table_schema:?}, but the session tracks {session_schema:?}")]
+    SchemaMismatch {
+        /// Name of the table.
+        table: String,
+        /// Database the table is declared in.
+        table_schema: String,
+        /// Database the session tracks.
+        session_schema: String,
+    },
+
+    /// The schema of the tracked database changed after the first table was
+    /// attached, so the recorded changes may not match the current tables.
+    #[error("Schema changed during session (schema version {attached} at attach, {current} now)")]
+    SchemaChangedDuringSession {
+        /// `PRAGMA schema_version` when the first table was attached.
+        attached: i32,
+        /// `PRAGMA schema_version` when the changes were exported.
+        current: i32,
+    },
+
+    /// The output does not fit in a single `SQLite` buffer.
+    #[error("Output of {len} bytes is too large for one buffer; use the streaming API")]
+    TooLarge {
+        /// Size of the output in bytes.
+        len: usize,
+    },
+
+    /// Changing an option of the session failed.
+    #[error("Failed to configure session: {0}")]
+    ConfigFailed(SqliteErrorCode),
+
+    /// Writing streamed output failed.
+    #[error("I/O error while streaming changes: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Post-processing the generated changeset failed.
+    #[error("Changeset processing failed: {0}")]
+    Changeset(#[from] ChangesetError),
 }
 
 /// Errors that can occur when applying changesets or patchsets.
@@ -162,85 +111,266 @@ pub enum ApplyError {
     /// The conflict handler panicked while resolving a conflict.
     #[error("Conflict handler panicked")]
     ConflictHandlerPanicked,
+
+    /// More conflicts were reported than allowed by
+    /// [`ApplyOptions::max_conflicts`](crate::ApplyOptions::max_conflicts).
+    #[error("Conflict limit of {limit} exceeded")]
+    ConflictLimitExceeded {
+        /// The configured maximum number of conflicts.
+        limit: usize,
+    },
+
+    /// An insert collided with an existing row while applying in
+    /// [`ApplyOptions::append_only`](crate::ApplyOptions::append_only) mode.
+    #[error("Duplicate primary key in append-only table {table:?}")]
+    DuplicatePrimaryKey {
+        /// Table the insert targeted.
+        table: String,
+        /// Primary key values of the colliding row.
+        primary_key: Vec<SqliteValue>,
+    },
+
+    /// The database named with
+    /// [`ApplyOptions::target_db`](crate::ApplyOptions::target_db) is not
+    /// attached or has no file that can be opened.
+    #[error("Cannot apply into database {schema:?}: not attached to a file")]
+    TargetDatabaseUnavailable {
+        /// The requested database name.
+        schema: String,
+    },
+
+    /// The input does not fit in the `c_int` length `SQLite` takes.
+    #[error(
+        "Input of {len} bytes is too large to apply at once; split it or use the streaming API"
+    )]
+    TooLarge {
+        /// Size of the input in bytes.
+        len: usize,
+    },
+
+    /// The changeset could not be processed before or after applying it.
+    #[error("Changeset processing failed: {0}")]
+    Changeset(#[from] ChangesetError),
+
+    /// The session recording the effect of the apply failed.
+    #[error("Recording session failed: {0}")]
+    Session(#[from] SessionError),
 }
 
-/// Types of conflicts that can occur when applying changes.
-///
-/// These correspond to `SQLite`'s `SQLITE_CHANGESET_*` conflict codes.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(i32)]
-pub enum ConflictType {
-    /// A row with the same primary key already exists (INSERT conflict).
-    Data = 1,
-    /// The row to be updated/deleted was not found.
-    NotFound = 2,
-    /// The row to be updated/deleted has different values than expected.
-    Conflict = 3,
-    /// A constraint (other than foreign key) was violated.
-    Constraint = 4,
-    /// A foreign key constraint was violated.
-    ForeignKey = 5,
+/// Errors that can occur when reading or transforming changesets.
+#[derive(Debug, Error)]
+pub enum ChangesetError {
+    /// Failed to invert the changeset.
+    #[error("Failed to invert changeset: {0}")]
+    InvertFailed(SqliteErrorCode),
+
+    /// Failed to read an operation from the changeset.
+    #[error("Failed to iterate changeset: {0}")]
+    IterFailed(SqliteErrorCode),
+
+    /// A change group failed to combine or output changes.
+    #[error("Failed to combine changesets: {0}")]
+    GroupFailed(SqliteErrorCode),
+
+    /// A rebaser failed to read rebase data or rebase a changeset.
+    #[error("Failed to rebase changeset: {0}")]
+    RebaseFailed(SqliteErrorCode),
+
+    /// Reading or writing streamed changes failed.
+    #[error("I/O error while streaming changeset: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// An operation names a table that was not declared.
+    #[error("Table {0:?} was not declared")]
+    UnknownTable(String),
+
+    /// An operation has a different number of values than its table has columns.
+    #[error("Table {table:?} has {expected} columns but {found} values were given")]
+    ColumnCountMismatch {
+        /// Table the operation targets.
+        table: String,
+        /// Number of columns declared for the table.
+        expected: usize,
+        /// Number of values given.
+        found: usize,
+    },
+
+    /// An update or delete does not provide every primary key value.
+    #[error("Missing primary key value for table {0:?}")]
+    MissingPrimaryKey(String),
+
+    /// A single operation is larger than the requested chunk size.
+    #[error("Operation on table {table:?} takes {size} bytes, over the limit of {limit}")]
+    OperationTooLarge {
+        /// Table the operation targets.
+        table: String,
+        /// Encoded size of the operation, including its table header.
+        size: usize,
+        /// The requested maximum chunk size.
+        limit: usize,
+    },
+
+    /// A column map does not send every column of a table to a distinct column.
+    #[error("Column {column} of table {table:?} maps to column {mapped}, which is out of range or already used")]
+    InvalidColumnMap {
+        /// Table the operation targets.
+        table: String,
+        /// Position of the column in the input.
+        column: usize,
+        /// Position the map sent it to.
+        mapped: usize,
+    },
+
+    /// Reading the database schema failed.
+    #[error("Failed to query schema: {0}")]
+    QueryFailed(SqliteErrorCode),
+
+    /// The input is not a well-formed changeset or patchset.
+    #[error("Malformed changeset at byte {offset}")]
+    Malformed {
+        /// Offset of the table header, record or value that cannot be read.
+        offset: usize,
+    },
+}
+
+/// A reason a changeset cannot be applied cleanly to a database.
+#[derive(Debug, Error)]
+pub enum Incompatibility {
+    /// A table the changeset modifies does not exist in the target.
+    #[error("Table {0:?} does not exist")]
+    MissingTable(String),
+
+    /// A table in the target has fewer columns than the changeset records.
+    #[error("Table {table:?} has {found} columns but the changeset has {expected}")]
+    ColumnCountMismatch {
+        /// Table the changeset modifies.
+        table: String,
+        /// Number of columns recorded in the changeset.
+        expected: usize,
+        /// Number of columns the table has in the target.
+        found: usize,
+    },
+
+    /// The changeset itself could not be read.
+    #[error("Failed to read changeset: {0}")]
+    Changeset(ChangesetError),
+
+    /// Reading the target schema failed.
+    #[error("Failed to query schema: {0}")]
+    QueryFailed(SqliteErrorCode),
+}
+
+/// Every [`Incompatibility`] found by
+/// [`check_changeset_compatible`](crate::check_changeset_compatible).
+#[derive(Debug)]
+pub struct IncompatibilityReport {
+    issues: Vec<Incompatibility>,
 }
 
-impl ConflictType {
-    /// Create a `ConflictType` from an `SQLite` conflict code.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use diesel_sqlite_session::ConflictType;
-    ///
-    /// assert_eq!(ConflictType::from_raw(1), Some(ConflictType::Data));
-    /// assert_eq!(ConflictType::from_raw(42), None);
-    /// ```
+impl IncompatibilityReport {
+    pub(crate) fn new(issues: Vec<Incompatibility>) -> Self {
+        Self { issues }
+    }
+
+    /// The problems found, in the order the changeset references them.
+    #[inline]
     #[must_use]
-    pub const fn from_raw(code: i32) -> Option<Self> {
-        match code {
-            1 => Some(Self::Data),
-            2 => Some(Self::NotFound),
-            3 => Some(Self::Conflict),
-            4 => Some(Self::Constraint),
-            5 => Some(Self::ForeignKey),
-            _ => None,
+    pub fn issues(&self) -> &[Incompatibility] {
+        &self.issues
+    }
+}
+
+impl fmt::Display for IncompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Changeset is incompatible with the target database")?;
+        for (index, issue) in self.issues.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { "; " };
+            write!(f, "{separator}{issue}")?;
         }
+        Ok(())
     }
+}
 
-    /// Convert to the raw `SQLite` conflict code.
-    #[must_use]
-    pub const fn to_raw(self) -> i32 {
-        self as i32
+impl std::error::Error for IncompatibilityReport {}
+
+/// Parse a conflict type from its name, as used in configuration files.
+///
+/// Accepts `data`, `not_found`, `conflict`, `constraint` and `foreign_key`,
+/// ignoring ASCII case.
+///
+/// # Examples
+///
+/// ```
+/// use diesel_sqlite_session::ConflictType;
+///
+/// assert_eq!("not_found".parse(), Ok(ConflictType::NotFound));
+/// assert!("missing".parse::<ConflictType>().is_err());
+/// ```
+impl FromStr for ConflictType {
+    type Err = ParseConflictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ("data", Self::Data),
+            ("not_found", Self::NotFound),
+            ("conflict", Self::Conflict),
+            ("constraint", Self::Constraint),
+            ("foreign_key", Self::ForeignKey),
+        ]
+        .into_iter()
+        .find_map(|(name, kind)| name.eq_ignore_ascii_case(s).then_some(kind))
+        .ok_or_else(|| ParseConflictError {
+            expected: "conflict type",
+            input: s.to_owned(),
+        })
     }
 }
 
-/// Action to take when a conflict occurs during changeset application.
+/// Parse a conflict action from its name, as used in configuration files.
+///
+/// Accepts `omit`, `replace` and `abort`, ignoring ASCII case.
 ///
-/// These correspond to `SQLite`'s `SQLITE_CHANGESET_*` resolution codes.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(i32)]
-pub enum ConflictAction {
-    /// Skip this conflicting change and continue with the next one.
-    Omit = 0,
-    /// Force apply this change, replacing the existing row.
-    Replace = 1,
-    /// Stop processing and return an error.
-    Abort = 2,
+/// # Examples
+///
+/// ```
+/// use diesel_sqlite_session::ConflictAction;
+///
+/// assert_eq!("replace".parse(), Ok(ConflictAction::Replace));
+/// assert!("skip".parse::<ConflictAction>().is_err());
+/// ```
+impl FromStr for ConflictAction {
+    type Err = ParseConflictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ("omit", Self::Omit),
+            ("replace", Self::Replace),
+            ("abort", Self::Abort),
+        ]
+        .into_iter()
+        .find_map(|(name, action)| name.eq_ignore_ascii_case(s).then_some(action))
+        .ok_or_else(|| ParseConflictError {
+            expected: "conflict action",
+            input: s.to_owned(),
+        })
+    }
 }
 
-impl ConflictAction {
-    /// Convert to the raw `SQLite` conflict resolution code.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use diesel_sqlite_session::ConflictAction;
-    ///
-    /// assert_eq!(ConflictAction::Omit.to_raw(), 0);
-    /// assert_eq!(ConflictAction::Replace.to_raw(), 1);
-    /// assert_eq!(ConflictAction::Abort.to_raw(), 2);
-    /// ```
+/// Error returned when a [`ConflictType`] or [`ConflictAction`] name is not
+/// recognized.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown {expected} {input:?}")]
+pub struct ParseConflictError {
+    expected: &'static str,
+    input: String,
+}
+
+impl ParseConflictError {
+    /// The string that failed to parse.
+    #[inline]
     #[must_use]
-    pub const fn to_raw(self) -> i32 {
-        self as i32
+    pub fn input(&self) -> &str {
+        &self.input
     }
 }
 
@@ -393,12 +523,106 @@ mod tests {
             );
         }
 
+        #[test]
+        fn display_config_failed() {
+            let err = SessionError::ConfigFailed(SqliteErrorCode::Misuse);
+            assert_eq!(
+                err.to_string(),
+                "Failed to configure session: SQLITE_MISUSE (21)"
+            );
+        }
+
         #[test]
         fn display_invalid_table_name() {
             let err = SessionError::InvalidTableName;
             assert_eq!(err.to_string(), "Table name contains null byte");
         }
 
+        #[test]
+        fn display_changeset() {
+            let err = SessionError::from(ChangesetError::InvertFailed(SqliteErrorCode::Misuse));
+            assert_eq!(
+                err.to_string(),
+                "Changeset processing failed: Failed to invert changeset: SQLITE_MISUSE (21)"
+            );
+        }
+
+        #[test]
+        fn display_invalid_database_name() {
+            let err = SessionError::InvalidDatabaseName;
+            assert_eq!(err.to_string(), "Database name contains null byte");
+        }
+
+        #[test]
+        fn display_diff_failed() {
+            let err = SessionError::DiffFailed {
+                code: SqliteErrorCode::Schema,
+                message: "table schemas do not match".to_owned(),
+            };
+            assert_eq!(
+                err.to_string(),
+                "Failed to diff table: table schemas do not match (SQLITE_SCHEMA (17))"
+            );
+        }
+
+        #[test]
+        fn display_query_failed() {
+            let err = SessionError::QueryFailed(SqliteErrorCode::Error);
+            assert_eq!(err.to_string(), "Failed to query schema: SQLITE_ERROR (1)");
+        }
+
+        #[test]
+        fn display_buffer_too_small() {
+            let err = SessionError::BufferTooSmall { capacity: 16 };
+            assert_eq!(
+                err.to_string(),
+                "Output does not fit in a buffer of 16 bytes"
+            );
+        }
+
+        #[test]
+        fn display_schema_mismatch() {
+            let err = SessionError::SchemaMismatch {
+                table: "notes".to_owned(),
+                table_schema: "aux".to_owned(),
+                session_schema: "main".to_owned(),
+            };
+            assert_eq!(
+                err.to_string(),
+                "Table \"notes\" is declared in database \"aux\", but the session tracks \"main\""
+            );
+        }
+
+        #[test]
+        fn display_schema_changed_during_session() {
+            let err = SessionError::SchemaChangedDuringSession {
+                attached: 3,
+                current: 4,
+            };
+            assert_eq!(
+                err.to_string(),
+                "Schema changed during session (schema version 3 at attach, 4 now)"
+            );
+        }
+
+        #[test]
+        fn display_too_large() {
+            let err = SessionError::TooLarge { len: 3_000_000_000 };
+            assert_eq!(
+                err.to_string(),
+                "Output of 3000000000 bytes is too large for one buffer; use the streaming API"
+            );
+        }
+
+        #[test]
+        fn display_io() {
+            let err = SessionError::from(std::io::Error::other("disk full"));
+            assert_eq!(
+                err.to_string(),
+                "I/O error while streaming changes: disk full"
+            );
+        }
+
         #[test]
         fn is_std_error() {
             fn assert_error<E: std::error::Error>() {}
@@ -430,6 +654,62 @@ mod tests {
             assert_eq!(err.to_string(), "Conflict handler panicked");
         }
 
+        #[test]
+        fn display_conflict_limit_exceeded() {
+            let err = ApplyError::ConflictLimitExceeded { limit: 3 };
+            assert_eq!(err.to_string(), "Conflict limit of 3 exceeded");
+        }
+
+        #[test]
+        fn display_duplicate_primary_key() {
+            let err = ApplyError::DuplicatePrimaryKey {
+                table: "events".to_owned(),
+                primary_key: vec![SqliteValue::Integer(1)],
+            };
+            assert_eq!(
+                err.to_string(),
+                "Duplicate primary key in append-only table \"events\""
+            );
+        }
+
+        #[test]
+        fn display_target_database_unavailable() {
+            let err = ApplyError::TargetDatabaseUnavailable {
+                schema: "aux".to_owned(),
+            };
+            assert_eq!(
+                err.to_string(),
+                "Cannot apply into database \"aux\": not attached to a file"
+            );
+        }
+
+        #[test]
+        fn display_too_large() {
+            let err = ApplyError::TooLarge { len: 3_000_000_000 };
+            assert_eq!(
+                err.to_string(),
+                "Input of 3000000000 bytes is too large to apply at once; split it or use the streaming API"
+            );
+        }
+
+        #[test]
+        fn display_changeset() {
+            let err = ApplyError::from(ChangesetError::InvertFailed(SqliteErrorCode::Error));
+            assert_eq!(
+                err.to_string(),
+                "Changeset processing failed: Failed to invert changeset: SQLITE_ERROR (1)"
+            );
+        }
+
+        #[test]
+        fn display_session() {
+            let err = ApplyError::from(SessionError::AttachFailed(SqliteErrorCode::Error));
+            assert_eq!(
+                err.to_string(),
+                "Recording session failed: Failed to attach table: SQLITE_ERROR (1)"
+            );
+        }
+
         #[test]
         fn is_std_error() {
             fn assert_error<E: std::error::Error>() {}
@@ -437,6 +717,189 @@ mod tests {
         }
     }
 
+    mod changeset_error {
+        use super::*;
+
+        #[test]
+        fn display_invert_failed() {
+            let err = ChangesetError::InvertFailed(SqliteErrorCode::Misuse);
+            assert_eq!(
+                err.to_string(),
+                "Failed to invert changeset: SQLITE_MISUSE (21)"
+            );
+        }
+
+        #[test]
+        fn display_iter_failed() {
+            let err = ChangesetError::IterFailed(SqliteErrorCode::Unknown(11));
+            assert_eq!(
+                err.to_string(),
+                "Failed to iterate changeset: SQLITE_UNKNOWN (11)"
+            );
+        }
+
+        #[test]
+        fn display_group_failed() {
+            let err = ChangesetError::GroupFailed(SqliteErrorCode::Schema);
+            assert_eq!(
+                err.to_string(),
+                "Failed to combine changesets: SQLITE_SCHEMA (17)"
+            );
+        }
+
+        #[test]
+        fn display_io() {
+            let err = ChangesetError::from(std::io::Error::other("disk full"));
+            assert_eq!(
+                err.to_string(),
+                "I/O error while streaming changeset: disk full"
+            );
+        }
+
+        #[test]
+        fn display_unknown_table() {
+            let err = ChangesetError::UnknownTable("items".to_owned());
+            assert_eq!(err.to_string(), "Table \"items\" was not declared");
+        }
+
+        #[test]
+        fn display_column_count_mismatch() {
+            let err = ChangesetError::ColumnCountMismatch {
+                table: "items".to_owned(),
+                expected: 3,
+                found: 2,
+            };
+            assert_eq!(
+                err.to_string(),
+                "Table \"items\" has 3 columns but 2 values were given"
+            );
+        }
+
+        #[test]
+        fn display_missing_primary_key() {
+            let err = ChangesetError::MissingPrimaryKey("items".to_owned());
+            assert_eq!(
+                err.to_string(),
+                "Missing primary key value for table \"items\""
+            );
+        }
+
+        #[test]
+        fn display_operation_too_large() {
+            let err = ChangesetError::OperationTooLarge {
+                table: "files".to_owned(),
+                size: 4096,
+                limit: 1024,
+            };
+            assert_eq!(
+                err.to_string(),
+                "Operation on table \"files\" takes 4096 bytes, over the limit of 1024"
+            );
+        }
+
+        #[test]
+        fn display_invalid_column_map() {
+            let err = ChangesetError::InvalidColumnMap {
+                table: "items".to_owned(),
+                column: 2,
+                mapped: 5,
+            };
+            assert_eq!(
+                err.to_string(),
+                "Column 2 of table \"items\" maps to column 5, which is out of range or already used"
+            );
+        }
+
+        #[test]
+        fn display_rebase_failed() {
+            let err = ChangesetError::RebaseFailed(SqliteErrorCode::Misuse);
+            assert_eq!(
+                err.to_string(),
+                "Failed to rebase changeset: SQLITE_MISUSE (21)"
+            );
+        }
+
+        #[test]
+        fn display_query_failed() {
+            let err = ChangesetError::QueryFailed(SqliteErrorCode::Locked);
+            assert_eq!(err.to_string(), "Failed to query schema: SQLITE_LOCKED (6)");
+        }
+
+        #[test]
+        fn display_malformed() {
+            let err = ChangesetError::Malformed { offset: 7 };
+            assert_eq!(err.to_string(), "Malformed changeset at byte 7");
+        }
+
+        #[test]
+        fn is_std_error() {
+            fn assert_error<E: std::error::Error>() {}
+            assert_error::<ChangesetError>();
+        }
+    }
+
+    mod incompatibility {
+        use super::*;
+
+        #[test]
+        fn display_missing_table() {
+            let err = Incompatibility::MissingTable("items".to_owned());
+            assert_eq!(err.to_string(), "Table \"items\" does not exist");
+        }
+
+        #[test]
+        fn display_column_count_mismatch() {
+            let err = Incompatibility::ColumnCountMismatch {
+                table: "items".to_owned(),
+                expected: 3,
+                found: 2,
+            };
+            assert_eq!(
+                err.to_string(),
+                "Table \"items\" has 2 columns but the changeset has 3"
+            );
+        }
+
+        #[test]
+        fn display_changeset() {
+            let err =
+                Incompatibility::Changeset(ChangesetError::IterFailed(SqliteErrorCode::Misuse));
+            assert_eq!(
+                err.to_string(),
+                "Failed to read changeset: Failed to iterate changeset: SQLITE_MISUSE (21)"
+            );
+        }
+
+        #[test]
+        fn display_query_failed() {
+            let err = Incompatibility::QueryFailed(SqliteErrorCode::Busy);
+            assert_eq!(err.to_string(), "Failed to query schema: SQLITE_BUSY (5)");
+        }
+    }
+
+    mod incompatibility_report {
+        use super::*;
+
+        #[test]
+        fn display_lists_every_issue() {
+            let report = IncompatibilityReport::new(vec![
+                Incompatibility::MissingTable("a".to_owned()),
+                Incompatibility::MissingTable("b".to_owned()),
+            ]);
+            assert_eq!(
+                report.to_string(),
+                "Changeset is incompatible with the target database: \
+                 Table \"a\" does not exist; Table \"b\" does not exist"
+            );
+        }
+
+        #[test]
+        fn is_std_error() {
+            fn assert_error<E: std::error::Error>() {}
+            assert_error::<IncompatibilityReport>();
+        }
+    }
+
     mod conflict_type {
         use super::*;
 
@@ -464,6 +927,43 @@ mod tests {
             assert_eq!(ConflictType::Constraint.to_raw(), 4);
             assert_eq!(ConflictType::ForeignKey.to_raw(), 5);
         }
+
+        #[test]
+        fn predicates_match_only_their_variant() {
+            let all = [
+                ConflictType::Data,
+                ConflictType::NotFound,
+                ConflictType::Conflict,
+                ConflictType::Constraint,
+                ConflictType::ForeignKey,
+            ];
+            for kind in all {
+                assert_eq!(kind.is_value_mismatch(), kind == ConflictType::Data);
+                assert_eq!(kind.is_missing_row(), kind == ConflictType::NotFound);
+                assert_eq!(
+                    kind.is_primary_key_conflict(),
+                    kind == ConflictType::Conflict
+                );
+                assert_eq!(kind.is_constraint(), kind == ConflictType::Constraint);
+                assert_eq!(kind.is_foreign_key(), kind == ConflictType::ForeignKey);
+            }
+        }
+
+        #[test]
+        fn from_str_parses_names() {
+            assert_eq!("data".parse(), Ok(ConflictType::Data));
+            assert_eq!("not_found".parse(), Ok(ConflictType::NotFound));
+            assert_eq!("Conflict".parse(), Ok(ConflictType::Conflict));
+            assert_eq!("CONSTRAINT".parse(), Ok(ConflictType::Constraint));
+            assert_eq!("foreign_key".parse(), Ok(ConflictType::ForeignKey));
+        }
+
+        #[test]
+        fn from_str_rejects_unknown_names() {
+            let err = "notfound".parse::<ConflictType>().unwrap_err();
+            assert_eq!(err.input(), "notfound");
+            assert_eq!(err.to_string(), "Unknown conflict type \"notfound\"");
+        }
     }
 
     mod conflict_action {
@@ -475,5 +975,19 @@ mod tests {
             assert_eq!(ConflictAction::Replace.to_raw(), 1);
             assert_eq!(ConflictAction::Abort.to_raw(), 2);
         }
+
+        #[test]
+        fn from_str_parses_names() {
+            assert_eq!("omit".parse(), Ok(ConflictAction::Omit));
+            assert_eq!("Replace".parse(), Ok(ConflictAction::Replace));
+            assert_eq!("ABORT".parse(), Ok(ConflictAction::Abort));
+        }
+
+        #[test]
+        fn from_str_rejects_unknown_names() {
+            let err = " omit".parse::<ConflictAction>().unwrap_err();
+            assert_eq!(err.input(), " omit");
+            assert_eq!(err.to_string(), "Unknown conflict action \" omit\"");
+        }
     }
 }
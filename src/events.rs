@@ -0,0 +1,99 @@
+//! Conflict events reported while a streamed apply is still running.
+
+use std::marker::PhantomData;
+use std::sync::mpsc::{Receiver, SyncSender};
+
+use crate::conflict::{Conflict, ConflictRecord, ConflictResolver};
+use crate::errors::{ConflictAction, ConflictType};
+
+/// A conflict met by
+/// [`SqliteSessionExt::apply_changeset_from_reader_iter`](crate::SqliteSessionExt::apply_changeset_from_reader_iter),
+/// with the action it was resolved with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictEvent {
+    record: ConflictRecord,
+    action: ConflictAction,
+}
+
+impl ConflictEvent {
+    /// The type of conflict.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> ConflictType {
+        self.record.kind()
+    }
+
+    /// The action the conflict handler chose.
+    #[inline]
+    #[must_use]
+    pub const fn action(&self) -> ConflictAction {
+        self.action
+    }
+
+    /// The details of the conflict.
+    #[inline]
+    #[must_use]
+    pub fn record(&self) -> &ConflictRecord {
+        &self.record
+    }
+
+    /// The details of the conflict as a [`ConflictRecord`].
+    #[inline]
+    #[must_use]
+    pub fn into_record(self) -> ConflictRecord {
+        self.record
+    }
+}
+
+/// Iterator over the [`ConflictEvent`]s of a running apply.
+///
+/// Handed to the consumer of
+/// [`SqliteSessionExt::apply_changeset_from_reader_iter`](crate::SqliteSessionExt::apply_changeset_from_reader_iter).
+/// Each call to `next` blocks until the apply reports the next conflict, and
+/// iteration ends once the apply has finished.
+#[derive(Debug)]
+pub struct ConflictEvents<'a> {
+    events: Receiver<ConflictEvent>,
+    _apply: PhantomData<&'a ()>,
+}
+
+impl ConflictEvents<'_> {
+    pub(crate) fn new(events: Receiver<ConflictEvent>) -> Self {
+        Self {
+            events,
+            _apply: PhantomData,
+        }
+    }
+}
+
+impl Iterator for ConflictEvents<'_> {
+    type Item = ConflictEvent;
+
+    fn next(&mut self) -> Option<ConflictEvent> {
+        self.events.recv().ok()
+    }
+}
+
+/// Resolver forwarding every conflict, with the chosen action, to a channel.
+///
+/// The apply does not depend on the events being read: once the receiver is
+/// gone, conflicts are still resolved by the handler.
+pub(crate) struct EventResolver<F> {
+    pub(crate) handler: F,
+    pub(crate) events: SyncSender<ConflictEvent>,
+}
+
+impl<F> ConflictResolver for EventResolver<F>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    fn resolve(&mut self, conflict: &Conflict<'_>) -> ConflictAction {
+        let action = (self.handler)(conflict.kind());
+        // A full channel blocks the apply until the consumer catches up.
+        let _ = self.events.send(ConflictEvent {
+            record: ConflictRecord::capture(conflict),
+            action,
+        });
+        action
+    }
+}
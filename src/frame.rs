@@ -0,0 +1,87 @@
+//! Length-prefixed framing for sending several changesets over one stream.
+
+use std::io::{self, Read, Write};
+
+use crate::changeset::Changeset;
+
+/// Size of the length prefix written before each changeset.
+const PREFIX_LEN: usize = 8;
+
+/// Length-prefixed framing for changesets sent over a byte stream.
+///
+/// A changeset does not record its own length, so several of them written
+/// back to back to a socket or file cannot be told apart. Each frame is the
+/// changeset's length as a little-endian `u64` followed by its bytes, letting
+/// the reader recover the individual changesets. Patchsets can be framed the
+/// same way and converted back with
+/// [`Patchset::from_bytes`](crate::Patchset::from_bytes) after reading.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::TcpStream;
+/// use diesel_sqlite_session::ChangesetFrame;
+///
+/// # let changesets: Vec<Vec<u8>> = Vec::new();
+/// let mut stream = TcpStream::connect("replica:4000").unwrap();
+/// for changeset in &changesets {
+///     ChangesetFrame::write_to(&mut stream, changeset).unwrap();
+/// }
+///
+/// // On the other end:
+/// # let mut stream = TcpStream::connect("replica:4000").unwrap();
+/// while let Some(changeset) = ChangesetFrame::read_from(&mut stream).unwrap() {
+///     // apply `changeset`
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ChangesetFrame;
+
+impl ChangesetFrame {
+    /// Write `changeset` to `writer` as one frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of `writer`.
+    pub fn write_to<W: Write>(mut writer: W, changeset: &[u8]) -> io::Result<()> {
+        let len = u64::try_from(changeset.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "changeset too large"))?;
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(changeset)
+    }
+
+    /// Read the next frame from `reader`.
+    ///
+    /// Returns `None` if the stream ends cleanly before a new frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::ErrorKind::UnexpectedEof` if the stream ends inside a
+    /// frame, `io::ErrorKind::InvalidData` if the length does not fit in
+    /// memory, and any error of `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Option<Changeset>> {
+        let mut prefix = [0u8; PREFIX_LEN];
+        let mut filled = 0;
+        while filled < PREFIX_LEN {
+            match reader.read(&mut prefix[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let len = u64::from_le_bytes(prefix);
+        let expected = usize::try_from(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "frame too large"))?;
+        // Read through `take` rather than allocating `len` bytes up front, so
+        // a corrupt prefix cannot trigger a huge allocation.
+        let mut bytes = Vec::new();
+        reader.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() != expected {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Some(Changeset::from_bytes(bytes)))
+    }
+}
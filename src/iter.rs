@@ -0,0 +1,841 @@
+//! Read the operations stored in changesets and patchsets.
+
+use std::ffi::{c_char, c_int, c_uchar, c_void, CStr};
+use std::marker::PhantomData;
+use std::ptr;
+
+use diesel::SqliteConnection;
+
+use crate::errors::{ChangesetError, SqliteErrorCode};
+use crate::ffi::{
+    sqlite3_changeset_iter, sqlite3_value, sqlite3changeset_conflict, sqlite3changeset_finalize,
+    sqlite3changeset_new, sqlite3changeset_next, sqlite3changeset_old, sqlite3changeset_op,
+    sqlite3changeset_pk, sqlite3changeset_start_v2, SQLITE_CHANGESETSTART_INVERT, SQLITE_CORRUPT,
+    SQLITE_DELETE, SQLITE_DONE, SQLITE_INSERT, SQLITE_OK, SQLITE_ROW, SQLITE_TOOBIG, SQLITE_UPDATE,
+};
+use crate::query::column_names;
+use crate::validate::validate_changeset;
+use crate::value::SqliteValue;
+
+/// The kind of change recorded by a changeset operation.
+///
+/// These correspond to `SQLite`'s `SQLITE_INSERT`, `SQLITE_UPDATE` and
+/// `SQLITE_DELETE` codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum OpKind {
+    /// A row was inserted.
+    Insert = SQLITE_INSERT,
+    /// A row was updated.
+    Update = SQLITE_UPDATE,
+    /// A row was deleted.
+    Delete = SQLITE_DELETE,
+}
+
+impl OpKind {
+    /// Create an `OpKind` from an `SQLite` operation code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use diesel_sqlite_session::OpKind;
+    ///
+    /// assert_eq!(OpKind::from_raw(18), Some(OpKind::Insert));
+    /// assert_eq!(OpKind::from_raw(0), None);
+    /// ```
+    #[must_use]
+    pub const fn from_raw(code: i32) -> Option<Self> {
+        match code {
+            SQLITE_INSERT => Some(Self::Insert),
+            SQLITE_UPDATE => Some(Self::Update),
+            SQLITE_DELETE => Some(Self::Delete),
+            _ => None,
+        }
+    }
+
+    /// Convert to the raw `SQLite` operation code.
+    #[must_use]
+    pub const fn to_raw(self) -> i32 {
+        self as i32
+    }
+}
+
+/// A set of [`OpKind`]s, as returned by
+/// [`changeset_op_kinds`](crate::changeset_op_kinds).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OpKindSet(u8);
+
+impl OpKindSet {
+    /// The set holding every kind.
+    pub const ALL: Self = Self(0b111);
+
+    /// An empty set.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Add `kind` to the set.
+    #[inline]
+    pub fn insert(&mut self, kind: OpKind) {
+        self.0 |= Self::bit(kind);
+    }
+
+    /// Whether the set holds `kind`.
+    #[inline]
+    #[must_use]
+    pub const fn contains(self, kind: OpKind) -> bool {
+        self.0 & Self::bit(kind) != 0
+    }
+
+    /// Whether the set holds no kind.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Number of kinds in the set.
+    #[inline]
+    #[must_use]
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// The kinds in the set, in insert, update, delete order.
+    pub fn iter(self) -> impl Iterator<Item = OpKind> {
+        [OpKind::Insert, OpKind::Update, OpKind::Delete]
+            .into_iter()
+            .filter(move |&kind| self.contains(kind))
+    }
+
+    const fn bit(kind: OpKind) -> u8 {
+        match kind {
+            OpKind::Insert => 0b001,
+            OpKind::Update => 0b010,
+            OpKind::Delete => 0b100,
+        }
+    }
+}
+
+impl FromIterator<OpKind> for OpKindSet {
+    fn from_iter<I: IntoIterator<Item = OpKind>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for kind in iter {
+            set.insert(kind);
+        }
+        set
+    }
+}
+
+/// A single operation read from a changeset or patchset.
+///
+/// Values are stored positionally, in the column order of the table. The
+/// [`old_values`](Self::old_values) are empty for inserts and the
+/// [`new_values`](Self::new_values) are empty for deletes; otherwise both hold one entry per column,
+/// with `None` marking a column the operation does not record (for example an
+/// unmodified column of an update).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeOp {
+    table: String,
+    op: OpKind,
+    indirect: bool,
+    primary_key: Vec<bool>,
+    /// Primary key flags exactly as stored in the table header.
+    pk_flags: Vec<u8>,
+    old: Vec<Option<SqliteValue>>,
+    new: Vec<Option<SqliteValue>>,
+}
+
+impl ChangeOp {
+    /// Assemble a direct operation from already validated parts.
+    pub(crate) fn from_parts(
+        table: String,
+        op: OpKind,
+        pk_flags: Vec<u8>,
+        old: Vec<Option<SqliteValue>>,
+        new: Vec<Option<SqliteValue>>,
+    ) -> Self {
+        Self {
+            table,
+            op,
+            indirect: false,
+            primary_key: pk_flags.iter().map(|&flag| flag != 0).collect(),
+            pk_flags,
+            old,
+            new,
+        }
+    }
+
+    /// Name of the table the operation applies to.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Kind of operation.
+    #[inline]
+    #[must_use]
+    pub const fn op(&self) -> OpKind {
+        self.op
+    }
+
+    /// Number of columns in the table.
+    #[inline]
+    #[must_use]
+    pub fn column_count(&self) -> usize {
+        self.primary_key.len()
+    }
+
+    /// Which columns are part of the table's primary key.
+    #[inline]
+    #[must_use]
+    pub fn primary_key(&self) -> &[bool] {
+        &self.primary_key
+    }
+
+    /// Values before the change, empty for inserts.
+    #[inline]
+    #[must_use]
+    pub fn old_values(&self) -> &[Option<SqliteValue>] {
+        &self.old
+    }
+
+    /// Values after the change, empty for deletes.
+    #[inline]
+    #[must_use]
+    pub fn new_values(&self) -> &[Option<SqliteValue>] {
+        &self.new
+    }
+
+    /// Values of the primary key columns identifying the affected row.
+    ///
+    /// Read from the new values for inserts and from the old values otherwise.
+    #[must_use]
+    pub fn primary_key_values(&self) -> Vec<SqliteValue> {
+        let values = if self.op == OpKind::Insert {
+            &self.new
+        } else {
+            &self.old
+        };
+        self.primary_key
+            .iter()
+            .zip(values)
+            .filter(|(is_pk, _)| **is_pk)
+            .map(|(_, value)| value.clone().unwrap_or(SqliteValue::Null))
+            .collect()
+    }
+
+    /// Whether the change was recorded as indirect.
+    ///
+    /// A change is indirect when it was made by a trigger or a foreign key
+    /// action rather than by a statement of the application, or while the
+    /// recording session had [`Session::set_indirect`](crate::Session::set_indirect)
+    /// enabled.
+    #[inline]
+    #[must_use]
+    pub const fn is_indirect(&self) -> bool {
+        self.indirect
+    }
+
+    /// Mutable access to the old and new values, for rewriting operations.
+    #[inline]
+    pub(crate) fn values_mut(
+        &mut self,
+    ) -> (&mut [Option<SqliteValue>], &mut [Option<SqliteValue>]) {
+        (&mut self.old, &mut self.new)
+    }
+
+    /// Move every column `i` to position `targets[i]`.
+    ///
+    /// `targets` must be a permutation of the column positions.
+    pub(crate) fn move_columns(&mut self, targets: &[usize]) {
+        fn permute<T: Clone>(values: &mut Vec<T>, targets: &[usize]) {
+            if values.is_empty() {
+                return;
+            }
+            let mut moved = values.clone();
+            for (value, &target) in values.drain(..).zip(targets) {
+                moved[target] = value;
+            }
+            *values = moved;
+        }
+        permute(&mut self.old, targets);
+        permute(&mut self.new, targets);
+        permute(&mut self.pk_flags, targets);
+        permute(&mut self.primary_key, targets);
+    }
+
+    /// Raw primary key flags, as needed to re-encode the table header.
+    #[inline]
+    pub(crate) fn pk_flags(&self) -> &[u8] {
+        &self.pk_flags
+    }
+}
+
+/// Iterator over the operations of a changeset or patchset.
+///
+/// Created by [`read_changeset`]. Each item is either a decoded [`ChangeOp`]
+/// or the error that stopped iteration; no items follow an error.
+pub struct ChangesetIter<'a> {
+    iter: *mut sqlite3_changeset_iter,
+    done: bool,
+    /// Whether `iter` has been stepped at least once.
+    started: bool,
+    /// Whether `iter` sits on an operation that has not been yielded yet.
+    positioned: bool,
+    _input: PhantomData<&'a [u8]>,
+}
+
+/// Start reading the operations of a changeset or patchset.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::{read_changeset, OpKind};
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// for op in read_changeset(&changeset).unwrap() {
+///     let op = op.unwrap();
+///     if op.op() == OpKind::Insert {
+///         println!("insert into {}", op.table());
+///     }
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if `SQLite` cannot
+/// start iterating it.
+pub fn read_changeset(changeset: &[u8]) -> Result<ChangesetIter<'_>, ChangesetError> {
+    start(changeset, 0)
+}
+
+/// Start reading the operations of a changeset as if it had been inverted.
+///
+/// Yields the same operations [`read_changeset`] would yield for the output
+/// of [`invert_changeset`](crate::invert_changeset): inserts become deletes,
+/// deletes become inserts, and updates have their old and new values
+/// swapped. Nothing is materialized, so this is cheaper when the inverse only
+/// needs to be inspected. Patchsets lack the old values needed to invert them
+/// and fail to iterate.
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if `SQLite` cannot
+/// start iterating it.
+pub fn read_changeset_inverted(changeset: &[u8]) -> Result<ChangesetIter<'_>, ChangesetError> {
+    start(changeset, SQLITE_CHANGESETSTART_INVERT)
+}
+
+/// Start an iterator over `changeset` with `sqlite3changeset_start_v2` flags.
+fn start(changeset: &[u8], flags: c_int) -> Result<ChangesetIter<'_>, ChangesetError> {
+    validate_changeset(changeset)?;
+    let input_len = c_int::try_from(changeset.len())
+        .map_err(|_| ChangesetError::IterFailed(SqliteErrorCode::from_error(SQLITE_TOOBIG)))?;
+    let mut iter: *mut sqlite3_changeset_iter = ptr::null_mut();
+
+    // SAFETY: `changeset` outlives the returned iterator (tied by the `'a`
+    // lifetime), and SQLite only reads from the buffer despite the mutable pointer.
+    let rc = unsafe {
+        sqlite3changeset_start_v2(
+            &mut iter,
+            input_len,
+            changeset.as_ptr().cast::<c_void>().cast_mut(),
+            flags,
+        )
+    };
+    if rc != SQLITE_OK {
+        return Err(ChangesetError::IterFailed(SqliteErrorCode::from_error(rc)));
+    }
+
+    Ok(ChangesetIter {
+        iter,
+        done: false,
+        started: false,
+        positioned: false,
+        _input: PhantomData,
+    })
+}
+
+/// Every operation of a changeset or patchset, decoded up front.
+///
+/// Created by [`parse_changeset`]. Unlike [`ChangesetIter`], a parsed changeset
+/// owns its operations, so it can be cloned and iterated any number of times,
+/// which suits analyses that make several passes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedChangeset {
+    ops: Vec<ChangeOp>,
+}
+
+impl ParsedChangeset {
+    /// The operations, in the order they are stored.
+    #[inline]
+    #[must_use]
+    pub fn ops(&self) -> &[ChangeOp] {
+        &self.ops
+    }
+
+    /// Iterate over the operations.
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, ChangeOp> {
+        self.ops.iter()
+    }
+
+    /// Number of operations.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the changeset holds no operations.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Take ownership of the operations.
+    #[inline]
+    #[must_use]
+    pub fn into_ops(self) -> Vec<ChangeOp> {
+        self.ops
+    }
+}
+
+impl<'a> IntoIterator for &'a ParsedChangeset {
+    type Item = &'a ChangeOp;
+    type IntoIter = std::slice::Iter<'a, ChangeOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.iter()
+    }
+}
+
+impl IntoIterator for ParsedChangeset {
+    type Item = ChangeOp;
+    type IntoIter = std::vec::IntoIter<ChangeOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
+/// Decode every operation of a changeset or patchset into a [`ParsedChangeset`].
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::{parse_changeset, OpKind};
+///
+/// # let changeset: Vec<u8> = Vec::new();
+/// let parsed = parse_changeset(&changeset).unwrap();
+/// let inserts = parsed.iter().filter(|op| op.op() == OpKind::Insert).count();
+/// let tables: Vec<&str> = parsed.iter().map(|op| op.table()).collect();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` if the input is not a well-formed
+/// changeset or patchset, or `ChangesetError::IterFailed` if it cannot be read.
+pub fn parse_changeset(changeset: &[u8]) -> Result<ParsedChangeset, ChangesetError> {
+    let ops = read_changeset(changeset)?.collect::<Result<_, _>>()?;
+    Ok(ParsedChangeset { ops })
+}
+
+/// Decode the operation at the current position of a changeset iterator.
+///
+/// # Safety
+///
+/// `iter` must be a valid iterator positioned on an operation: either the last
+/// `sqlite3changeset_next` call returned `SQLITE_ROW`, or `iter` was passed to a
+/// conflict handler for a conflict other than `SQLITE_CHANGESET_FOREIGN_KEY`.
+pub(crate) unsafe fn read_op(
+    iter: *mut sqlite3_changeset_iter,
+) -> Result<ChangeOp, ChangesetError> {
+    let mut table: *const c_char = ptr::null();
+    let mut column_count: c_int = 0;
+    let mut op: c_int = 0;
+    let mut indirect: c_int = 0;
+
+    // SAFETY: the caller guarantees `iter` points at an operation, and all
+    // out-pointers are valid locals.
+    let rc =
+        unsafe { sqlite3changeset_op(iter, &mut table, &mut column_count, &mut op, &mut indirect) };
+    check(rc)?;
+    let op = OpKind::from_raw(op).ok_or_else(corrupt)?;
+    // SAFETY: SQLite returns a NUL-terminated table name that stays valid while
+    // the iterator points at this operation; we copy it immediately.
+    let table = unsafe { CStr::from_ptr(table) }
+        .to_string_lossy()
+        .into_owned();
+
+    let mut pk_flags: *mut c_uchar = ptr::null_mut();
+    // SAFETY: same iterator state as above; out-pointers are valid locals.
+    let rc = unsafe { sqlite3changeset_pk(iter, &mut pk_flags, &mut column_count) };
+    check(rc)?;
+    let column_count = usize::try_from(column_count).map_err(|_| corrupt())?;
+    let pk_flags = if pk_flags.is_null() {
+        vec![0; column_count]
+    } else {
+        // SAFETY: SQLite returns an array of one flag per column that stays
+        // valid while the iterator points at this operation; we copy it immediately.
+        unsafe { std::slice::from_raw_parts(pk_flags, column_count) }.to_vec()
+    };
+    let primary_key = pk_flags.iter().map(|&flag| flag != 0).collect();
+
+    let old = if op == OpKind::Insert {
+        Vec::new()
+    } else {
+        // SAFETY: old values exist for updates and deletes.
+        unsafe { read_values(iter, column_count, sqlite3changeset_old) }?
+    };
+    let new = if op == OpKind::Delete {
+        Vec::new()
+    } else {
+        // SAFETY: new values exist for inserts and updates.
+        unsafe { read_values(iter, column_count, sqlite3changeset_new) }?
+    };
+
+    Ok(ChangeOp {
+        table,
+        op,
+        indirect: indirect != 0,
+        primary_key,
+        pk_flags,
+        old,
+        new,
+    })
+}
+
+/// Read only the kind of the operation at the current position of an iterator.
+///
+/// # Safety
+///
+/// Same requirements as [`read_op`].
+pub(crate) unsafe fn read_op_kind(
+    iter: *mut sqlite3_changeset_iter,
+) -> Result<OpKind, ChangesetError> {
+    let mut table: *const c_char = ptr::null();
+    let mut column_count: c_int = 0;
+    let mut op: c_int = 0;
+    let mut indirect: c_int = 0;
+
+    // SAFETY: the caller guarantees `iter` points at an operation, and all
+    // out-pointers are valid locals.
+    let rc =
+        unsafe { sqlite3changeset_op(iter, &mut table, &mut column_count, &mut op, &mut indirect) };
+    check(rc)?;
+    OpKind::from_raw(op).ok_or_else(corrupt)
+}
+
+/// Read only the table name of the operation `iter` points at.
+///
+/// # Safety
+///
+/// `iter` must point at an operation.
+unsafe fn read_op_table(iter: *mut sqlite3_changeset_iter) -> Result<String, ChangesetError> {
+    let mut table: *const c_char = ptr::null();
+    let mut column_count: c_int = 0;
+    let mut op: c_int = 0;
+    let mut indirect: c_int = 0;
+
+    // SAFETY: the caller guarantees `iter` points at an operation, and all
+    // out-pointers are valid locals.
+    let rc =
+        unsafe { sqlite3changeset_op(iter, &mut table, &mut column_count, &mut op, &mut indirect) };
+    check(rc)?;
+    // SAFETY: SQLite returns a NUL-terminated table name that stays valid while
+    // the iterator points at this operation; we copy it immediately.
+    Ok(unsafe { CStr::from_ptr(table) }
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Read the values of the row an operation conflicts with.
+///
+/// # Safety
+///
+/// `iter` must have been passed to a conflict handler for a
+/// `SQLITE_CHANGESET_DATA` or `SQLITE_CHANGESET_CONFLICT` conflict.
+pub(crate) unsafe fn read_conflicting_values(
+    iter: *mut sqlite3_changeset_iter,
+) -> Result<Vec<Option<SqliteValue>>, ChangesetError> {
+    let mut table: *const c_char = ptr::null();
+    let mut column_count: c_int = 0;
+    let mut op: c_int = 0;
+    let mut indirect: c_int = 0;
+
+    // SAFETY: the caller guarantees `iter` points at an operation, and all
+    // out-pointers are valid locals.
+    let rc =
+        unsafe { sqlite3changeset_op(iter, &mut table, &mut column_count, &mut op, &mut indirect) };
+    check(rc)?;
+    let column_count = usize::try_from(column_count).map_err(|_| corrupt())?;
+
+    // SAFETY: conflicting values exist for the conflict types the caller guarantees.
+    unsafe { read_values(iter, column_count, sqlite3changeset_conflict) }
+}
+
+/// Read every column through `sqlite3changeset_old`, `sqlite3changeset_new`
+/// or `sqlite3changeset_conflict`.
+///
+/// # Safety
+///
+/// `iter` must be positioned on an operation that carries the values read by
+/// `read_fn`, and `column_count` must match the operation's column count.
+unsafe fn read_values(
+    iter: *mut sqlite3_changeset_iter,
+    column_count: usize,
+    read_fn: ValueReadFn,
+) -> Result<Vec<Option<SqliteValue>>, ChangesetError> {
+    (0..column_count)
+        .map(|column| {
+            let column = c_int::try_from(column).map_err(|_| corrupt())?;
+            let mut value: *mut sqlite3_value = ptr::null_mut();
+            // SAFETY: the caller guarantees the iterator state and column range;
+            // `value` is a valid out-pointer.
+            let rc = unsafe { read_fn(iter, column, &mut value) };
+            check(rc)?;
+            if value.is_null() {
+                Ok(None)
+            } else {
+                // SAFETY: SQLite returned a non-null value owned by the iterator
+                // that stays valid while it points at this operation.
+                Ok(Some(unsafe { SqliteValue::from_raw(value) }))
+            }
+        })
+        .collect()
+}
+
+type ValueReadFn =
+    unsafe extern "C" fn(*mut sqlite3_changeset_iter, c_int, *mut *mut sqlite3_value) -> c_int;
+
+impl ChangesetIter<'_> {
+    /// Read the column names of `table` in the `main` database of `conn`.
+    ///
+    /// Changesets store values by position only. The names come from the live
+    /// schema, in the same column order, so they can label the values of
+    /// operations on `table` as long as the schema has not changed since the
+    /// changeset was recorded. A missing table yields no names.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{read_changeset, ChangesetIter};
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// # let changeset: Vec<u8> = Vec::new();
+    /// for op in read_changeset(&changeset).unwrap() {
+    ///     let op = op.unwrap();
+    ///     let names = ChangesetIter::column_names(&mut conn, op.table()).unwrap();
+    ///     for (name, value) in names.iter().zip(op.new_values()) {
+    ///         println!("{name} = {value:?}");
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::QueryFailed` if the schema cannot be read.
+    pub fn column_names(
+        conn: &mut SqliteConnection,
+        table: &str,
+    ) -> Result<Vec<String>, ChangesetError> {
+        // SAFETY: `with_raw_connection` provides a valid SQLite handle for the
+        // duration of the callback.
+        unsafe { conn.with_raw_connection(|raw| column_names(raw, "main", table)) }
+            .map_err(|rc| ChangesetError::QueryFailed(SqliteErrorCode::from_error(rc)))
+    }
+
+    /// Finalize the iterator and report any error `SQLite` met while reading.
+    ///
+    /// Dropping the iterator finalizes it too, but discards the result. Call
+    /// this after iterating to check that the input was read cleanly; only
+    /// errors met by the operations read so far are reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::IterFailed` with the first error met while
+    /// iterating, for example because the input is truncated.
+    pub fn finish(mut self) -> Result<(), ChangesetError> {
+        let iter = std::mem::replace(&mut self.iter, ptr::null_mut());
+        // SAFETY: `iter` was created by `sqlite3changeset_start_v2` and is
+        // finalized exactly once here; `Drop` then finalizes a null pointer,
+        // which is a no-op.
+        let rc = unsafe { sqlite3changeset_finalize(iter) };
+        check(rc)
+    }
+
+    /// Skip the remaining operations of the current table.
+    ///
+    /// The current table is the one of the operation last yielded, or of the
+    /// next operation if none was yielded yet or since the previous skip.
+    /// Afterwards iteration resumes
+    /// with the first operation of the next table. Operations of a table are
+    /// stored contiguously, so a table is not met again unless the input
+    /// concatenates several changesets.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel_sqlite_session::read_changeset;
+    ///
+    /// # let changeset: Vec<u8> = Vec::new();
+    /// let mut iter = read_changeset(&changeset).unwrap();
+    /// while let Some(op) = iter.next() {
+    ///     let op = op.unwrap();
+    ///     if op.table() == "audit_log" {
+    ///         iter.skip_current_table().unwrap();
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::IterFailed` if an operation cannot be read;
+    /// iteration then stops.
+    pub fn skip_current_table(&mut self) -> Result<(), ChangesetError> {
+        if self.done || (!self.started && !self.step()?) {
+            return Ok(());
+        }
+        // SAFETY: the iterator is started and not done, so it is on an operation.
+        let table = unsafe { read_op_table(self.iter) };
+        let table = table.inspect_err(|_| self.done = true)?;
+        while self.step()? {
+            // SAFETY: `step` just positioned the iterator on an operation.
+            let next = unsafe { read_op_table(self.iter) };
+            if next.inspect_err(|_| self.done = true)? != table {
+                self.positioned = true;
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the operation last yielded by [`Iterator::next`] again.
+    ///
+    /// Returns a fully decoded [`ChangeOp`] carrying the table, kind,
+    /// indirect flag, old and new values and primary key columns, for code
+    /// that handed the yielded operation away but needs it again. Returns
+    /// `None` before the first operation, at the end of the input and after
+    /// [`skip_current_table`](Self::skip_current_table).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel_sqlite_session::read_changeset;
+    ///
+    /// # let changeset: Vec<u8> = Vec::new();
+    /// let mut iter = read_changeset(&changeset).unwrap();
+    /// while let Some(op) = iter.next() {
+    ///     drop(op);
+    ///     let op = iter.current().unwrap().unwrap();
+    ///     println!("{:?} on {}", op.op(), op.table());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::IterFailed` if the operation cannot be read.
+    pub fn current(&self) -> Result<Option<ChangeOp>, ChangesetError> {
+        if !self.started || self.done || self.positioned {
+            return Ok(None);
+        }
+        // SAFETY: the iterator is started and not done, and not positioned on
+        // an operation that was never yielded, so it still points at the
+        // operation last yielded.
+        unsafe { read_op(self.iter) }.map(Some)
+    }
+
+    /// Advance to the next operation and report only its kind.
+    ///
+    /// Cheaper than [`Iterator::next`] because no values are decoded.
+    pub(crate) fn next_kind(&mut self) -> Option<Result<OpKind, ChangesetError>> {
+        self.advance(|iter| {
+            // SAFETY: `advance` only calls this once the iterator is on a row.
+            unsafe { read_op_kind(iter) }
+        })
+    }
+
+    /// Step the underlying iterator and decode the new position with `read`.
+    fn advance<T>(
+        &mut self,
+        read: impl FnOnce(*mut sqlite3_changeset_iter) -> Result<T, ChangesetError>,
+    ) -> Option<Result<T, ChangesetError>> {
+        if self.done {
+            return None;
+        }
+        if !std::mem::take(&mut self.positioned) {
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        let item = read(self.iter);
+        self.done = item.is_err();
+        Some(item)
+    }
+
+    /// Step the underlying iterator, returning `false` at the end of the input.
+    fn step(&mut self) -> Result<bool, ChangesetError> {
+        self.started = true;
+        // SAFETY: `self.iter` was created by `sqlite3changeset_start_v2` and has not
+        // been finalized; the input buffer outlives `self`.
+        let rc = unsafe { sqlite3changeset_next(self.iter) };
+        match rc {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => {
+                self.done = true;
+                Ok(false)
+            }
+            rc => {
+                self.done = true;
+                Err(ChangesetError::IterFailed(SqliteErrorCode::from_error(rc)))
+            }
+        }
+    }
+}
+
+impl Iterator for ChangesetIter<'_> {
+    type Item = Result<ChangeOp, ChangesetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.advance(|iter| {
+            // SAFETY: `advance` only calls this once `sqlite3changeset_next` has
+            // positioned the iterator on a row.
+            unsafe { read_op(iter) }
+        })
+    }
+}
+
+impl Drop for ChangesetIter<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `self.iter` is owned by this type and must be released
+        // exactly once with `sqlite3changeset_finalize`; it is null if
+        // `finish` already released it.
+        unsafe {
+            sqlite3changeset_finalize(self.iter);
+        }
+    }
+}
+
+fn check(rc: c_int) -> Result<(), ChangesetError> {
+    if rc == SQLITE_OK {
+        Ok(())
+    } else {
+        Err(ChangesetError::IterFailed(SqliteErrorCode::from_error(rc)))
+    }
+}
+
+fn corrupt() -> ChangesetError {
+    ChangesetError::IterFailed(SqliteErrorCode::from_error(SQLITE_CORRUPT))
+}
@@ -2,15 +2,84 @@
 #![warn(clippy::all, clippy::pedantic, clippy::undocumented_unsafe_blocks)]
 #![allow(clippy::module_name_repetitions)]
 
+extern crate alloc;
+
 mod apply;
+#[cfg(feature = "blob")]
+mod blob;
+mod buffer;
+mod builder;
+mod changegroup;
+mod changeset;
+mod compat;
+mod conflict;
+mod core_types;
+mod encode;
 mod errors;
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+mod events;
 mod ffi;
+mod frame;
+mod iter;
+mod query;
+mod rebase;
+#[cfg(all(
+    feature = "rusqlite",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+mod rusqlite_compat;
 mod session;
+mod stream;
+mod telemetry;
+mod validate;
+mod value;
+mod version;
 
-pub use errors::{ApplyError, ConflictAction, ConflictType, SessionError, SqliteErrorCode};
-pub use session::Session;
+pub use apply::{AppliedChange, ApplyOptions, ApplyStats, FailedChange, OmittedChange};
+pub use builder::{diff_rows, ChangesetBuilder};
+pub use changegroup::ChangeGroup;
+pub use changeset::{
+    changeset_has_deletes, changeset_has_inserts, changeset_has_updates, changeset_op_kinds,
+    changeset_to_patchset, coalesce_changeset, filter_changeset_by_value, invert_changeset,
+    project_changeset, remap_changeset_pks, reorder_changeset_columns, split_changeset,
+    strip_deletes, Changeset, ChangesetInput, Patchset, PatchsetInput,
+};
+pub use compat::{check_changeset_compatible, predict_conflicts, PredictedConflict};
+pub use conflict::{
+    ChannelResolver, Conflict, ConflictPolicy, ConflictPrompt, ConflictRecord, ConflictResolver,
+    DeadLetterResolver, ThreeWayMerge, VersionCheck,
+};
+pub use errors::{
+    ApplyError, ChangesetError, ConflictAction, ConflictType, Incompatibility,
+    IncompatibilityReport, ParseConflictError, SessionError, SqliteErrorCode,
+};
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+pub use events::{ConflictEvent, ConflictEvents};
+pub use frame::ChangesetFrame;
+pub use iter::{
+    parse_changeset, read_changeset, read_changeset_inverted, ChangeOp, ChangesetIter, OpKind,
+    OpKindSet, ParsedChangeset,
+};
+pub use rebase::{RebaseData, Rebaser};
+#[cfg(all(
+    feature = "rusqlite",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+pub use rusqlite_compat::apply_changeset_raw;
+pub use session::{
+    apply_and_recapture, changeset_between, AttachmentSummary, PauseGuard, Session, SessionConfig,
+    SessionMark, TableName,
+};
+#[cfg(all(
+    feature = "metrics",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+pub use telemetry::{APPLY_DURATION, CHANGESET_DURATION, PATCHSET_DURATION};
+pub use value::SqliteValue;
+pub use version::sqlite_version;
 
-use diesel::SqliteConnection;
+use diesel::internal::table_macro::StaticQueryFragment;
+use diesel::{Connection, SqliteConnection};
 
 /// Extension trait adding session capabilities to `SqliteConnection`.
 ///
@@ -56,6 +125,97 @@ pub trait SqliteSessionExt {
     /// Returns `SessionError::CreateFailed` if `SQLite` fails to create the session.
     fn create_session(&mut self) -> Result<Session, SessionError>;
 
+    /// Create a new session tracking changes to the database named `schema`.
+    ///
+    /// Use this for databases added with `ATTACH DATABASE`, or `temp` for
+    /// temporary tables; [`create_session`](Self::create_session) tracks `main`.
+    ///
+    /// Tables created with `CREATE TEMP TABLE` only exist on this connection
+    /// until it closes, so a changeset recorded on `temp` is only meaningful
+    /// when applied to a table of the same shape, such as another temporary
+    /// table on the same connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::InvalidDatabaseName` if `schema` contains a null byte.
+    /// Returns `SessionError::CreateFailed` if `SQLite` fails to create the session.
+    fn create_session_for(&mut self, schema: &str) -> Result<Session, SessionError>;
+
+    /// List the user tables of the main database, in name order.
+    ///
+    /// These are the tables [`Session::attach_all`] would currently track;
+    /// `SQLite`'s internal `sqlite_%` tables are excluded.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish("app.db").unwrap();
+    /// for table in conn.user_tables().unwrap() {
+    ///     println!("tracking {table}");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if the schema cannot be read.
+    fn user_tables(&mut self) -> Result<Vec<String>, SessionError>;
+
+    /// Run `f` in a transaction with a session tracking table `T`, and return
+    /// the changeset of the transaction.
+    ///
+    /// The session is created and attached after the transaction begins, so
+    /// the changeset holds exactly the changes `f` made. The changeset is
+    /// returned once the transaction commits. If `f` fails, the transaction is
+    /// rolled back and the session discarded.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{SessionError, SqliteSessionExt};
+    ///
+    /// diesel::table! {
+    ///     items (id) {
+    ///         id -> Integer,
+    ///         name -> Text,
+    ///     }
+    /// }
+    ///
+    /// #[derive(Debug)]
+    /// enum Error {
+    ///     Diesel(diesel::result::Error),
+    ///     Session(SessionError),
+    /// }
+    /// # impl From<diesel::result::Error> for Error {
+    /// #     fn from(err: diesel::result::Error) -> Self { Self::Diesel(err) }
+    /// # }
+    /// # impl From<SessionError> for Error {
+    /// #     fn from(err: SessionError) -> Self { Self::Session(err) }
+    /// # }
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let changeset = conn
+    ///     .transaction_with_session::<items::table, _, Error>(|conn, _session| {
+    ///         diesel::sql_query("INSERT INTO items (id, name) VALUES (1, 'one')").execute(conn)?;
+    ///         Ok(())
+    ///     })
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `f`, or a `SessionError` or Diesel error converted
+    /// into `E` if the transaction, the session or the changeset fails.
+    fn transaction_with_session<T, F, E>(&mut self, f: F) -> Result<Changeset, E>
+    where
+        T: StaticQueryFragment,
+        T::Component: TableName,
+        F: FnOnce(&mut SqliteConnection, &mut Session) -> Result<(), E>,
+        E: From<diesel::result::Error> + From<SessionError>;
+
     /// Apply a changeset to this connection.
     ///
     /// A changeset contains complete information about changes, including old
@@ -63,17 +223,79 @@ pub trait SqliteSessionExt {
     ///
     /// # Arguments
     ///
-    /// * `changeset` - The changeset bytes generated by `Session::changeset()`
+    /// * `changeset` - The [`Changeset`] generated by `Session::changeset()`;
+    ///   a [`Patchset`] or raw bytes are rejected at compile time, see
+    ///   [`apply_bytes`](Self::apply_bytes)
     /// * `on_conflict` - A callback to handle conflicts; receives the conflict type
     ///   and returns the action to take
     ///
+    /// Passing a patchset does not compile:
+    ///
+    /// ```compile_fail
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let patchset = {
+    ///     let mut session = conn.create_session().unwrap();
+    ///     session.attach_all().unwrap();
+    ///     session.patchset().unwrap()
+    /// };
+    /// conn.apply_changeset(&patchset, |_| ConflictAction::Abort).unwrap();
+    /// ```
+    ///
     /// # Errors
     ///
+    /// Returns `ApplyError::Changeset` if the input is malformed; its structure
+    /// is checked before `SQLite` reads any of it, so truncated or corrupted
+    /// input leaves the database untouched.
     /// Returns `ApplyError::ApplyFailed` if `SQLite` fails to apply the changeset.
     /// Returns `ApplyError::ConflictAborted` if the conflict handler returns `Abort`.
     /// Returns `ApplyError::ConflictHandlerPanicked` if the conflict handler panics.
-    fn apply_changeset<F>(&mut self, changeset: &[u8], on_conflict: F) -> Result<(), ApplyError>
+    fn apply_changeset<C, F>(&mut self, changeset: &C, on_conflict: F) -> Result<(), ApplyError>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset recorded on tables whose columns are declared in
+    /// another order than on this connection.
+    ///
+    /// `column_map` receives the table name and the position of a column in
+    /// the changeset, and returns its position in this connection's table.
+    /// See [`reorder_changeset_columns`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// # let changeset = diesel_sqlite_session::Changeset::default();
+    /// // The source declares (id, name, value), the replica (id, value, name).
+    /// replica
+    ///     .apply_changeset_mapped(
+    ///         &changeset,
+    ///         |_, column| [0, 2, 1][column],
+    ///         |_| ConflictAction::Abort,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ApplyError::Changeset` if the input is malformed or the map is
+    /// not a permutation of a table's columns; nothing is applied then.
+    /// Otherwise fails like [`apply_changeset`](Self::apply_changeset).
+    fn apply_changeset_mapped<C, M, F>(
+        &mut self,
+        changeset: &C,
+        column_map: M,
+        on_conflict: F,
+    ) -> Result<(), ApplyError>
     where
+        C: ChangesetInput,
+        M: Fn(&str, usize) -> usize,
         F: Fn(ConflictType) -> ConflictAction;
 
     /// Apply a patchset to this connection.
@@ -81,41 +303,542 @@ pub trait SqliteSessionExt {
     /// A patchset contains only new values (not old values), making it smaller
     /// but with less precise conflict detection.
     ///
+    /// Without old values `SQLite` cannot tell whether the existing row was
+    /// modified, so the handler never sees [`ConflictType::Data`]: updates and
+    /// deletes overwrite whatever row has their primary key, and only missing
+    /// rows ([`ConflictType::NotFound`]), inserts onto an existing key
+    /// ([`ConflictType::Conflict`]) and constraint violations are reported.
+    /// See [`Patchset::can_report`].
+    ///
     /// # Arguments
     ///
-    /// * `patchset` - The patchset bytes generated by `Session::patchset()`
+    /// * `patchset` - The [`Patchset`] generated by `Session::patchset()`; a
+    ///   [`Changeset`] or raw bytes are rejected at compile time
     /// * `on_conflict` - A callback to handle conflicts; receives the conflict type
     ///   and returns the action to take
     ///
+    /// Passing a [`Changeset`] does not compile:
+    ///
+    /// ```compile_fail
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{Changeset, ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let changeset = Changeset::default();
+    /// conn.apply_patchset(&changeset, |_| ConflictAction::Abort).unwrap();
+    /// ```
+    ///
     /// # Errors
     ///
     /// Returns `ApplyError::ApplyFailed` if `SQLite` fails to apply the patchset.
     /// Returns `ApplyError::ConflictAborted` if the conflict handler returns `Abort`.
     /// Returns `ApplyError::ConflictHandlerPanicked` if the conflict handler panics.
-    fn apply_patchset<F>(&mut self, patchset: &[u8], on_conflict: F) -> Result<(), ApplyError>
+    fn apply_patchset<P, F>(&mut self, patchset: &P, on_conflict: F) -> Result<(), ApplyError>
+    where
+        P: PatchsetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply raw bytes holding either a changeset or a patchset.
+    ///
+    /// The escape hatch for blobs whose kind is only known at runtime, such as
+    /// data read back from storage. Prefer [`apply_changeset`](Self::apply_changeset)
+    /// and [`apply_patchset`](Self::apply_patchset), which keep a typed
+    /// [`Changeset`] and [`Patchset`] apart.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_changeset`](Self::apply_changeset).
+    fn apply_bytes<F>(&mut self, data: &[u8], on_conflict: F) -> Result<(), ApplyError>
+    where
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset to this connection with explicit [`ApplyOptions`].
+    ///
+    /// Behaves like [`apply_changeset`](Self::apply_changeset) but honors the
+    /// given options and reports [`ApplyStats`] on success.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_changeset`](Self::apply_changeset).
+    /// Returns `ApplyError::ConflictLimitExceeded` if more conflicts occur than
+    /// allowed by [`ApplyOptions::max_conflicts`].
+    fn apply_changeset_with<C, F>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset like [`apply_changeset_with`](Self::apply_changeset_with),
+    /// returning the raw `SQLite` result code alongside any error.
+    ///
+    /// Meant for diagnosing unusual failures: the code is exactly what
+    /// `sqlite3changeset_apply_v2` returned, including extended result codes,
+    /// rather than what [`ApplyError`] maps it to. It is the code of an
+    /// `ApplyError::ApplyFailed` raised outside that call, and zero when the
+    /// apply failed before reaching `SQLite`, for example on malformed input.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ApplyOptions, ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// # let changeset = diesel_sqlite_session::Changeset::default();
+    /// if let Err((err, rc)) =
+    ///     replica.apply_changeset_verbose(&changeset, &ApplyOptions::new(), |_| ConflictAction::Abort)
+    /// {
+    ///     eprintln!("apply failed: {err} (rc = {rc})");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`apply_changeset_with`](Self::apply_changeset_with), with
+    /// the error paired with the raw result code.
+    fn apply_changeset_verbose<C, F>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<ApplyStats, (ApplyError, i32)>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset or patchset read incrementally from `reader`,
+    /// surfacing its conflicts while the apply is still running.
+    ///
+    /// The input is never held in memory as a whole. `SQLite` applies it
+    /// synchronously, so the apply runs on a worker thread borrowing this
+    /// connection, and `consume` runs on the calling thread with a
+    /// [`ConflictEvents`] iterator. Each [`ConflictEvent`] arrives as soon as
+    /// `on_conflict` has resolved it, which lets a UI show progress on a
+    /// large changeset. Up to 64 events are buffered; beyond that the apply
+    /// waits for `consume` to catch up. If `consume` returns before reading
+    /// every event, the apply carries on without them. Returns what
+    /// `consume` returned once the apply has finished. Not available on
+    /// `wasm32-unknown-unknown`, which has no threads.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    ///
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// let input = File::open("changes.bin").unwrap();
+    /// let conflicts = replica
+    ///     .apply_changeset_from_reader_iter(input, |_| ConflictAction::Omit, |events| {
+    ///         events
+    ///             .inspect(|event| println!("{:?} resolved with {:?}", event.kind(), event.action()))
+    ///             .count()
+    ///     })
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ApplyError::Changeset` if reading from `reader` fails.
+    /// Otherwise fails like [`apply_changeset`](Self::apply_changeset); the
+    /// apply is rolled back then, but the events already reported stay
+    /// visible to `consume`.
+    #[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+    fn apply_changeset_from_reader_iter<R, F, C, T>(
+        &mut self,
+        reader: R,
+        on_conflict: F,
+        consume: C,
+    ) -> Result<T, ApplyError>
+    where
+        R: std::io::Read + Send,
+        F: Fn(ConflictType) -> ConflictAction + Send,
+        C: FnOnce(ConflictEvents<'_>) -> T;
+
+    /// Apply a patchset to this connection with explicit [`ApplyOptions`].
+    ///
+    /// Behaves like [`apply_patchset`](Self::apply_patchset) but honors the
+    /// given options and reports [`ApplyStats`] on success.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_patchset`](Self::apply_patchset).
+    /// Returns `ApplyError::ConflictLimitExceeded` if more conflicts occur than
+    /// allowed by [`ApplyOptions::max_conflicts`].
+    fn apply_patchset_with<P, F>(
+        &mut self,
+        patchset: &P,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        P: PatchsetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset, resolving conflicts with a [`ConflictResolver`].
+    ///
+    /// Unlike the handlers of the other apply methods, a resolver receives a
+    /// [`Conflict`] and can inspect the conflicting change and the existing
+    /// row before deciding. Pass `&mut` a closure or a [`ConflictPolicy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_changeset_with`](Self::apply_changeset_with).
+    fn apply_changeset_resolving<C, R>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        resolver: &mut R,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        C: ChangesetInput,
+        R: ConflictResolver + ?Sized;
+
+    /// Apply a patchset, resolving conflicts with a [`ConflictResolver`].
+    ///
+    /// See [`apply_changeset_resolving`](Self::apply_changeset_resolving).
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_patchset_with`](Self::apply_patchset_with).
+    fn apply_patchset_resolving<P, R>(
+        &mut self,
+        patchset: &P,
+        options: &ApplyOptions,
+        resolver: &mut R,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        P: PatchsetInput,
+        R: ConflictResolver + ?Sized;
+
+    /// Apply a changeset and return the [`RebaseData`] of the conflicts it
+    /// resolved, for use with a [`Rebaser`].
+    ///
+    /// Conflicts resolved with [`ConflictAction::Omit`] or
+    /// [`ConflictAction::Replace`] are recorded; the rebase data is empty when
+    /// there were none. Patchsets never produce rebase data.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_changeset_with`](Self::apply_changeset_with).
+    fn apply_changeset_rebasing<C, F>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<(ApplyStats, RebaseData), ApplyError>
     where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset resolving every conflict with `action`, and return
+    /// a record of each conflict encountered.
+    ///
+    /// This saves writing a callback in the common case of applying with a
+    /// fixed action and reviewing the conflicts afterwards. With
+    /// [`ConflictAction::Abort`] the apply stops at the first conflict and the
+    /// error is returned, so nothing is recorded.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    /// # let changeset = diesel_sqlite_session::Changeset::default();
+    /// let (stats, conflicts) = replica
+    ///     .apply_collecting_conflicts(&changeset, ConflictAction::Omit)
+    ///     .unwrap();
+    /// for conflict in &conflicts {
+    ///     eprintln!("skipped {:?} conflict", conflict.kind());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`apply_changeset_with`](Self::apply_changeset_with).
+    fn apply_collecting_conflicts<C>(
+        &mut self,
+        changeset: &C,
+        action: ConflictAction,
+    ) -> Result<(ApplyStats, Vec<ConflictRecord>), ApplyError>
+    where
+        C: ChangesetInput;
+
+    /// Apply a changeset and return the inverse changeset that undoes it.
+    ///
+    /// This is a convenience for undo stacks: push the returned changeset and
+    /// apply it later with [`apply_changeset`](Self::apply_changeset) to revert.
+    /// The inverse covers the whole input, so it is only exact when no change
+    /// was omitted by the conflict handler.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ApplyError::Changeset` if the input cannot be inverted (for
+    /// example when passing a patchset), before anything is applied.
+    /// Otherwise returns the same errors as [`apply_changeset`](Self::apply_changeset).
+    fn apply_with_undo<C, F>(
+        &mut self,
+        changeset: &C,
+        on_conflict: F,
+    ) -> Result<Changeset, ApplyError>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction;
+
+    /// Apply a changeset and return the inverse of only the changes that
+    /// were applied.
+    ///
+    /// Unlike [`apply_with_undo`](Self::apply_with_undo), changes omitted by
+    /// the conflict handler are left out of the inverse, so applying it
+    /// restores the exact state from before a partial apply. The returned
+    /// stats report the omitted changes as with
+    /// [`ApplyOptions::collect_omitted`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// # let changeset = diesel_sqlite_session::Changeset::default();
+    /// let (_, undo) = replica
+    ///     .apply_with_scoped_undo(&changeset, |_| ConflictAction::Omit)
+    ///     .unwrap();
+    /// // Later, revert exactly what landed.
+    /// replica.apply_changeset(&undo, |_| ConflictAction::Abort).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ApplyError::Changeset` if the input is a patchset, before
+    /// anything is applied. Otherwise returns the same errors as
+    /// [`apply_changeset`](Self::apply_changeset).
+    fn apply_with_scoped_undo<C, F>(
+        &mut self,
+        changeset: &C,
+        on_conflict: F,
+    ) -> Result<(ApplyStats, Changeset), ApplyError>
+    where
+        C: ChangesetInput,
         F: Fn(ConflictType) -> ConflictAction;
 }
 
 impl SqliteSessionExt for SqliteConnection {
     #[inline]
     fn create_session(&mut self) -> Result<Session, SessionError> {
-        Session::new_internal(self)
+        Session::new_internal(self, "main")
+    }
+
+    #[inline]
+    fn create_session_for(&mut self, schema: &str) -> Result<Session, SessionError> {
+        Session::new_internal(self, schema)
+    }
+
+    #[inline]
+    fn user_tables(&mut self) -> Result<Vec<String>, SessionError> {
+        session::user_tables(self)
+    }
+
+    fn transaction_with_session<T, F, E>(&mut self, f: F) -> Result<Changeset, E>
+    where
+        T: StaticQueryFragment,
+        T::Component: TableName,
+        F: FnOnce(&mut SqliteConnection, &mut Session) -> Result<(), E>,
+        E: From<diesel::result::Error> + From<SessionError>,
+    {
+        self.transaction(|conn| {
+            let mut session = conn.create_session()?;
+            session.attach::<T>()?;
+            f(conn, &mut session)?;
+            Ok(session.into_changeset()?)
+        })
+    }
+
+    #[inline]
+    fn apply_changeset<C, F>(&mut self, changeset: &C, on_conflict: F) -> Result<(), ApplyError>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_changeset(self, changeset.as_ref(), on_conflict)
+    }
+
+    #[inline]
+    fn apply_changeset_mapped<C, M, F>(
+        &mut self,
+        changeset: &C,
+        column_map: M,
+        on_conflict: F,
+    ) -> Result<(), ApplyError>
+    where
+        C: ChangesetInput,
+        M: Fn(&str, usize) -> usize,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_changeset_mapped(self, changeset.as_ref(), column_map, on_conflict)
+    }
+
+    #[inline]
+    fn apply_patchset<P, F>(&mut self, patchset: &P, on_conflict: F) -> Result<(), ApplyError>
+    where
+        P: PatchsetInput,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_patchset(self, patchset.as_ref(), on_conflict)
+    }
+
+    #[inline]
+    fn apply_bytes<F>(&mut self, data: &[u8], on_conflict: F) -> Result<(), ApplyError>
+    where
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_changeset(self, data, on_conflict)
+    }
+
+    #[inline]
+    fn apply_changeset_with<C, F>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_with_options(self, changeset.as_ref(), options, on_conflict)
+    }
+
+    #[inline]
+    fn apply_changeset_verbose<C, F>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<ApplyStats, (ApplyError, i32)>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_verbose(self, changeset.as_ref(), options, on_conflict)
+    }
+
+    #[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+    #[inline]
+    fn apply_changeset_from_reader_iter<R, F, C, T>(
+        &mut self,
+        reader: R,
+        on_conflict: F,
+        consume: C,
+    ) -> Result<T, ApplyError>
+    where
+        R: std::io::Read + Send,
+        F: Fn(ConflictType) -> ConflictAction + Send,
+        C: FnOnce(ConflictEvents<'_>) -> T,
+    {
+        apply::apply_from_reader_iter(self, reader, on_conflict, consume)
+    }
+
+    #[inline]
+    fn apply_patchset_with<P, F>(
+        &mut self,
+        patchset: &P,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        P: PatchsetInput,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_with_options(self, patchset.as_ref(), options, on_conflict)
+    }
+
+    #[inline]
+    fn apply_changeset_resolving<C, R>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        resolver: &mut R,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        C: ChangesetInput,
+        R: ConflictResolver + ?Sized,
+    {
+        apply::apply_resolving(self, changeset.as_ref(), options, resolver)
+    }
+
+    #[inline]
+    fn apply_patchset_resolving<P, R>(
+        &mut self,
+        patchset: &P,
+        options: &ApplyOptions,
+        resolver: &mut R,
+    ) -> Result<ApplyStats, ApplyError>
+    where
+        P: PatchsetInput,
+        R: ConflictResolver + ?Sized,
+    {
+        apply::apply_resolving(self, patchset.as_ref(), options, resolver)
+    }
+
+    #[inline]
+    fn apply_changeset_rebasing<C, F>(
+        &mut self,
+        changeset: &C,
+        options: &ApplyOptions,
+        on_conflict: F,
+    ) -> Result<(ApplyStats, RebaseData), ApplyError>
+    where
+        C: ChangesetInput,
+        F: Fn(ConflictType) -> ConflictAction,
+    {
+        apply::apply_rebasing(self, changeset.as_ref(), options, on_conflict)
+    }
+
+    #[inline]
+    fn apply_collecting_conflicts<C>(
+        &mut self,
+        changeset: &C,
+        action: ConflictAction,
+    ) -> Result<(ApplyStats, Vec<ConflictRecord>), ApplyError>
+    where
+        C: ChangesetInput,
+    {
+        apply::apply_collecting_conflicts(self, changeset.as_ref(), action)
     }
 
     #[inline]
-    fn apply_changeset<F>(&mut self, changeset: &[u8], on_conflict: F) -> Result<(), ApplyError>
+    fn apply_with_undo<C, F>(
+        &mut self,
+        changeset: &C,
+        on_conflict: F,
+    ) -> Result<Changeset, ApplyError>
     where
+        C: ChangesetInput,
         F: Fn(ConflictType) -> ConflictAction,
     {
-        apply::apply_changeset(self, changeset, on_conflict)
+        apply::apply_with_undo(self, changeset.as_ref(), on_conflict)
     }
 
     #[inline]
-    fn apply_patchset<F>(&mut self, patchset: &[u8], on_conflict: F) -> Result<(), ApplyError>
+    fn apply_with_scoped_undo<C, F>(
+        &mut self,
+        changeset: &C,
+        on_conflict: F,
+    ) -> Result<(ApplyStats, Changeset), ApplyError>
     where
+        C: ChangesetInput,
         F: Fn(ConflictType) -> ConflictAction,
     {
-        apply::apply_patchset(self, patchset, on_conflict)
+        apply::apply_with_scoped_undo(self, changeset.as_ref(), on_conflict)
     }
 }
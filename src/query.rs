@@ -0,0 +1,221 @@
+//! Minimal prepared-statement wrapper for the queries this crate runs itself.
+
+use std::ffi::{c_char, c_int, c_void};
+use std::marker::PhantomData;
+use std::ptr;
+
+use crate::ffi::{
+    sqlite3, sqlite3_bind_blob, sqlite3_bind_double, sqlite3_bind_int64, sqlite3_bind_null,
+    sqlite3_bind_text, sqlite3_column_bytes, sqlite3_column_count, sqlite3_column_int,
+    sqlite3_column_text, sqlite3_column_value, sqlite3_finalize, sqlite3_prepare_v2, sqlite3_step,
+    sqlite3_stmt, SQLITE_DONE, SQLITE_OK, SQLITE_ROW, SQLITE_TOOBIG,
+};
+use crate::value::SqliteValue;
+
+/// A prepared statement, finalized on drop.
+///
+/// Bound text and blob parameters are borrowed for `'a` because they are bound
+/// without being copied by `SQLite`.
+pub(crate) struct Statement<'a> {
+    stmt: *mut sqlite3_stmt,
+    _params: PhantomData<&'a [u8]>,
+}
+
+impl<'a> Statement<'a> {
+    /// Prepare a single SQL statement.
+    ///
+    /// # Safety
+    ///
+    /// `db` must be a valid connection handle that outlives the statement.
+    pub(crate) unsafe fn prepare(db: *mut sqlite3, sql: &str) -> Result<Self, c_int> {
+        let sql_len = c_int::try_from(sql.len()).map_err(|_| SQLITE_TOOBIG)?;
+        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
+
+        // SAFETY: the caller guarantees `db` is valid; `sql` holds `sql_len`
+        // bytes and `stmt` is a valid out-pointer.
+        let rc = unsafe {
+            sqlite3_prepare_v2(
+                db,
+                sql.as_ptr().cast::<c_char>(),
+                sql_len,
+                &mut stmt,
+                ptr::null_mut(),
+            )
+        };
+        if rc != SQLITE_OK {
+            // SAFETY: finalizing a null or partially prepared statement is allowed.
+            unsafe { sqlite3_finalize(stmt) };
+            return Err(rc);
+        }
+
+        Ok(Self {
+            stmt,
+            _params: PhantomData,
+        })
+    }
+
+    /// Bind `value` to the 1-based parameter `index`.
+    pub(crate) fn bind_text(&mut self, index: c_int, value: &'a str) -> Result<(), c_int> {
+        let value_len = c_int::try_from(value.len()).map_err(|_| SQLITE_TOOBIG)?;
+        // SAFETY: `self.stmt` is a live statement. `value` outlives the statement
+        // (tied by `'a`), so SQLite may use it without copying (`SQLITE_STATIC`).
+        let rc = unsafe {
+            sqlite3_bind_text(
+                self.stmt,
+                index,
+                value.as_ptr().cast::<c_char>(),
+                value_len,
+                None,
+            )
+        };
+        if rc == SQLITE_OK {
+            Ok(())
+        } else {
+            Err(rc)
+        }
+    }
+
+    /// Bind `value` to the 1-based parameter `index`.
+    pub(crate) fn bind_value(&mut self, index: c_int, value: &'a SqliteValue) -> Result<(), c_int> {
+        // SAFETY: `self.stmt` is a live statement. Text and blob data outlive the
+        // statement (tied by `'a`), so SQLite may use them without copying.
+        let rc = unsafe {
+            match value {
+                SqliteValue::Null => sqlite3_bind_null(self.stmt, index),
+                SqliteValue::Integer(int) => sqlite3_bind_int64(self.stmt, index, *int),
+                SqliteValue::Real(real) => sqlite3_bind_double(self.stmt, index, *real),
+                SqliteValue::Text(bytes) => {
+                    let len = c_int::try_from(bytes.len()).map_err(|_| SQLITE_TOOBIG)?;
+                    sqlite3_bind_text(self.stmt, index, bytes.as_ptr().cast::<c_char>(), len, None)
+                }
+                SqliteValue::Blob(bytes) => {
+                    let len = c_int::try_from(bytes.len()).map_err(|_| SQLITE_TOOBIG)?;
+                    sqlite3_bind_blob(self.stmt, index, bytes.as_ptr().cast::<c_void>(), len, None)
+                }
+            }
+        };
+        if rc == SQLITE_OK {
+            Ok(())
+        } else {
+            Err(rc)
+        }
+    }
+
+    /// Run a statement that returns no rows.
+    pub(crate) fn execute(&mut self) -> Result<(), c_int> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Advance to the next row, returning `false` once the statement is done.
+    pub(crate) fn step(&mut self) -> Result<bool, c_int> {
+        // SAFETY: `self.stmt` is a live statement.
+        match unsafe { sqlite3_step(self.stmt) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            rc => Err(rc),
+        }
+    }
+
+    /// Read column `index` of the current row as text.
+    ///
+    /// `NULL` reads as an empty string and invalid UTF-8 is replaced with `U+FFFD`.
+    pub(crate) fn column_text(&self, index: c_int) -> String {
+        // SAFETY: `self.stmt` is positioned on a row. The text pointer is fetched
+        // before the length, as SQLite recommends, and copied immediately.
+        unsafe {
+            let data = sqlite3_column_text(self.stmt, index);
+            let len = usize::try_from(sqlite3_column_bytes(self.stmt, index)).unwrap_or(0);
+            if data.is_null() || len == 0 {
+                String::new()
+            } else {
+                String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned()
+            }
+        }
+    }
+
+    /// Number of columns in the result set.
+    pub(crate) fn column_count(&self) -> c_int {
+        // SAFETY: `self.stmt` is a live statement.
+        unsafe { sqlite3_column_count(self.stmt) }
+    }
+
+    /// Read column `index` of the current row as an integer.
+    pub(crate) fn column_int(&self, index: c_int) -> c_int {
+        // SAFETY: `self.stmt` is positioned on a row.
+        unsafe { sqlite3_column_int(self.stmt, index) }
+    }
+
+    /// Copy column `index` of the current row into an owned value.
+    pub(crate) fn column_value(&self, index: c_int) -> SqliteValue {
+        // SAFETY: `self.stmt` is positioned on a row, so the returned value is
+        // valid until the next step; `from_raw` copies it immediately.
+        unsafe { SqliteValue::from_raw(sqlite3_column_value(self.stmt, index)) }
+    }
+}
+
+/// Quote an SQL identifier with double quotes.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Read the column names of a table in database `schema`, in column order.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+pub(crate) unsafe fn column_names(
+    db: *mut sqlite3,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, c_int> {
+    // SAFETY: the caller guarantees `db` is valid.
+    let mut stmt = unsafe {
+        Statement::prepare(
+            db,
+            "SELECT name FROM pragma_table_info(?1, ?2) ORDER BY cid",
+        )
+    }?;
+    stmt.bind_text(1, table)?;
+    stmt.bind_text(2, schema)?;
+    let mut names = Vec::new();
+    while stmt.step()? {
+        names.push(stmt.column_text(0));
+    }
+    Ok(names)
+}
+
+/// Read the primary key flags of a table in database `schema`, in column order.
+///
+/// Each flag is the column's position in the primary key, or zero for columns
+/// outside it, as reported by `PRAGMA table_info`.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+pub(crate) unsafe fn primary_key_flags(
+    db: *mut sqlite3,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<u8>, c_int> {
+    // SAFETY: the caller guarantees `db` is valid.
+    let mut stmt =
+        unsafe { Statement::prepare(db, "SELECT pk FROM pragma_table_info(?1, ?2) ORDER BY cid") }?;
+    stmt.bind_text(1, table)?;
+    stmt.bind_text(2, schema)?;
+    let mut flags = Vec::new();
+    while stmt.step()? {
+        flags.push(u8::try_from(stmt.column_int(0)).unwrap_or(u8::MAX));
+    }
+    Ok(flags)
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `self.stmt` is owned by this type and must be released
+        // exactly once with `sqlite3_finalize`.
+        unsafe {
+            sqlite3_finalize(self.stmt);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Rebase local changesets over conflicts resolved while applying remote ones.
+
+use std::ffi::{c_int, c_void};
+use std::ptr;
+
+use crate::buffer::take_sqlite_buffer;
+use crate::changeset::Changeset;
+use crate::errors::{ChangesetError, SqliteErrorCode};
+use crate::ffi::{
+    sqlite3_rebaser, sqlite3rebaser_configure, sqlite3rebaser_create, sqlite3rebaser_delete,
+    sqlite3rebaser_rebase, SQLITE_OK, SQLITE_TOOBIG,
+};
+use crate::validate::validate_changeset;
+
+/// Rebase data produced by
+/// [`SqliteSessionExt::apply_changeset_rebasing`](crate::SqliteSessionExt::apply_changeset_rebasing).
+///
+/// It records how the conflicts of an apply were resolved, and is only
+/// meaningful as input to [`Rebaser::configure`]. Unlike [`Changeset`] it does
+/// not dereference to bytes, so it cannot be passed to an apply method by
+/// mistake.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RebaseData(Vec<u8>);
+
+impl RebaseData {
+    /// Wrap raw rebase data, for example after reading it back from storage.
+    #[inline]
+    #[must_use]
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Borrow the raw rebase data.
+    #[inline]
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consume the rebase data and return the raw bytes.
+    #[inline]
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Whether no conflict was recorded.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Rewrites local changesets so they account for how remote changes were
+/// merged.
+///
+/// When a remote changeset is applied locally and its conflicts are resolved
+/// with [`ConflictAction::Omit`](crate::ConflictAction::Omit) or
+/// [`ConflictAction::Replace`](crate::ConflictAction::Replace), the local
+/// changes made concurrently no longer describe the state to send back to
+/// the remote. Configure a rebaser with the [`RebaseData`] of those applies,
+/// then [`rebase`](Self::rebase) the local changesets before sending them.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{ApplyOptions, ConflictAction, Rebaser, SqliteSessionExt};
+///
+/// let mut local = SqliteConnection::establish("local.db").unwrap();
+/// # let remote_changes = diesel_sqlite_session::Changeset::default();
+/// # let local_changes: Vec<u8> = Vec::new();
+/// let (_, rebase) = local
+///     .apply_changeset_rebasing(&remote_changes, &ApplyOptions::new(), |_| ConflictAction::Omit)
+///     .unwrap();
+///
+/// let mut rebaser = Rebaser::new().unwrap();
+/// rebaser.configure(&rebase).unwrap();
+/// let to_send = rebaser.rebase(&local_changes).unwrap();
+/// ```
+pub struct Rebaser {
+    rebaser: *mut sqlite3_rebaser,
+}
+
+impl Rebaser {
+    /// Create a rebaser with no rebase data.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::RebaseFailed` if `SQLite` fails to allocate the rebaser.
+    pub fn new() -> Result<Self, ChangesetError> {
+        let mut rebaser: *mut sqlite3_rebaser = ptr::null_mut();
+        // SAFETY: `rebaser` is a valid out-pointer.
+        let rc = unsafe { sqlite3rebaser_create(&mut rebaser) };
+        check(rc)?;
+        Ok(Self { rebaser })
+    }
+
+    /// Add the conflict resolutions recorded in `data`.
+    ///
+    /// May be called several times to rebase over more than one apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::RebaseFailed` if `data` is malformed.
+    pub fn configure(&mut self, data: &RebaseData) -> Result<(), ChangesetError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let data_len = c_int::try_from(data.0.len()).map_err(|_| {
+            ChangesetError::RebaseFailed(SqliteErrorCode::from_error(SQLITE_TOOBIG))
+        })?;
+
+        // SAFETY: `self.rebaser` is a live rebaser, and SQLite copies what it
+        // needs from `data`.
+        let rc = unsafe {
+            sqlite3rebaser_configure(self.rebaser, data_len, data.0.as_ptr().cast::<c_void>())
+        };
+        check(rc)
+    }
+
+    /// Rebase `changeset` over the configured conflict resolutions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChangesetError::Malformed` if `changeset` is not well formed, or
+    /// `ChangesetError::RebaseFailed` if `SQLite` fails to produce the output.
+    pub fn rebase(&mut self, changeset: &[u8]) -> Result<Changeset, ChangesetError> {
+        if changeset.is_empty() {
+            return Ok(Changeset::default());
+        }
+        validate_changeset(changeset)?;
+        let input_len = c_int::try_from(changeset.len()).map_err(|_| {
+            ChangesetError::RebaseFailed(SqliteErrorCode::from_error(SQLITE_TOOBIG))
+        })?;
+        let mut size: c_int = 0;
+        let mut buffer: *mut c_void = ptr::null_mut();
+
+        // SAFETY: `self.rebaser` is a live rebaser, `changeset` holds
+        // `input_len` readable bytes and `size`/`buffer` are valid out-pointers.
+        let rc = unsafe {
+            sqlite3rebaser_rebase(
+                self.rebaser,
+                input_len,
+                changeset.as_ptr().cast::<c_void>(),
+                &mut size,
+                &mut buffer,
+            )
+        };
+        check(rc)?;
+
+        // SAFETY: on success SQLite hands us ownership of `buffer`, which holds
+        // `size` bytes allocated with `sqlite3_malloc`.
+        unsafe { take_sqlite_buffer(buffer, size) }
+            .map(Changeset::from_bytes)
+            .map_err(|size| ChangesetError::RebaseFailed(SqliteErrorCode::Unknown(size)))
+    }
+}
+
+impl Drop for Rebaser {
+    fn drop(&mut self) {
+        // SAFETY: `self.rebaser` is owned by this type and must be released
+        // exactly once with `sqlite3rebaser_delete`.
+        unsafe {
+            sqlite3rebaser_delete(self.rebaser);
+        }
+    }
+}
+
+fn check(rc: c_int) -> Result<(), ChangesetError> {
+    if rc == SQLITE_OK {
+        Ok(())
+    } else {
+        Err(ChangesetError::RebaseFailed(SqliteErrorCode::from_error(
+            rc,
+        )))
+    }
+}
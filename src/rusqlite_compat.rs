@@ -0,0 +1,48 @@
+//! Apply changesets to `rusqlite` connections.
+
+use rusqlite::Connection;
+
+use crate::apply::{apply_raw, ApplyOptions};
+use crate::conflict::ByKind;
+use crate::errors::{ApplyError, ConflictAction, ConflictType};
+
+/// Apply a changeset to a `rusqlite` connection.
+///
+/// Behaves like [`SqliteSessionExt::apply_changeset`](crate::SqliteSessionExt::apply_changeset),
+/// for code that holds a [`rusqlite::Connection`] rather than a Diesel
+/// connection. Patchsets are accepted as well. Requires the `rusqlite` feature.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel_sqlite_session::{apply_changeset_raw, ConflictAction};
+///
+/// let replica = rusqlite::Connection::open_in_memory().unwrap();
+/// # let changeset: Vec<u8> = Vec::new();
+/// apply_changeset_raw(&replica, &changeset, |_| ConflictAction::Abort).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ApplyError::ApplyFailed` if `SQLite` fails to apply the changeset.
+/// Returns `ApplyError::ConflictAborted` if the conflict handler aborts.
+pub fn apply_changeset_raw<F>(
+    conn: &Connection,
+    changeset: &[u8],
+    on_conflict: F,
+) -> Result<(), ApplyError>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    // SAFETY: `conn` is borrowed for the whole apply, so the handle it owns
+    // stays open until `apply_raw` returns.
+    unsafe {
+        apply_raw(
+            conn.handle(),
+            changeset,
+            &ApplyOptions::default(),
+            &mut ByKind(on_conflict),
+        )
+    }
+    .map(drop)
+}
@@ -1,19 +1,35 @@
 //! `SQLite` session management for Diesel connections.
 
-use std::ffi::{c_int, c_void, CString};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fmt;
+use std::io::{self, Write};
 use std::marker::PhantomData;
 use std::ptr;
 use std::rc::Rc;
 
-use diesel::internal::table_macro::{Identifier, StaticQueryFragment};
+use diesel::internal::table_macro::{Identifier, InfixNode, StaticQueryFragment};
+use diesel::query_builder::{QueryBuilder, QueryFragment};
+use diesel::sqlite::{Sqlite, SqliteQueryBuilder};
 use diesel::SqliteConnection;
 
-use crate::errors::{SessionError, SqliteErrorCode};
+use crate::apply::apply_changeset;
+use crate::buffer::take_sqlite_buffer;
+use crate::changegroup::ChangeGroup;
+use crate::changeset::{invert_changeset, Changeset, Patchset};
+use crate::encode::{Encoder, Format};
+use crate::errors::{ApplyError, ConflictAction, ConflictType, SessionError, SqliteErrorCode};
 use crate::ffi::{
-    sqlite3_free, sqlite3_session, sqlite3session_attach, sqlite3session_changeset,
-    sqlite3session_create, sqlite3session_delete, sqlite3session_enable, sqlite3session_isempty,
-    sqlite3session_patchset, SQLITE_OK,
+    sqlite3, sqlite3_free, sqlite3_session, sqlite3session_attach, sqlite3session_changeset,
+    sqlite3session_changeset_strm, sqlite3session_create, sqlite3session_delete,
+    sqlite3session_diff, sqlite3session_enable, sqlite3session_indirect, sqlite3session_isempty,
+    sqlite3session_object_config, sqlite3session_patchset, sqlite3session_patchset_strm,
+    SQLITE_NOMEM, SQLITE_OK, SQLITE_SESSION_OBJCONFIG_ROWID, SQLITE_TOOBIG,
 };
+use crate::iter::{read_changeset, ChangeOp, OpKind};
+use crate::query::{primary_key_flags, quote_identifier, Statement};
+use crate::stream::{output_callback, ByteCounter, ChunkCollector, OutputContext, OutputFn};
+use crate::telemetry::{Timer, CHANGESET_DURATION, PATCHSET_DURATION};
 
 /// A session tracking changes on a Diesel `SQLite` connection.
 ///
@@ -27,6 +43,16 @@ use crate::ffi::{
 /// created from. Using a session after its connection has been dropped is
 /// undefined behavior.
 ///
+/// This cannot be checked at runtime, not even in debug builds: the session
+/// has no way to observe the connection being dropped, because
+/// `SqliteConnection` closes its handle in its own `Drop` impl without any
+/// hook this crate could register. Nor can the borrow checker enforce it: a
+/// session is created from `&mut SqliteConnection`, so a `Session` borrowing
+/// the connection would keep it mutably borrowed for as long as the session
+/// lives, and the changes the session exists to record could not be made.
+/// Keep the session in a narrower scope than the connection, for example by
+/// creating, using and consuming it within a single function.
+///
 /// # Threading
 ///
 /// `Session` is intentionally neither [`Send`] nor [`Sync`]. Session handles
@@ -74,18 +100,47 @@ use crate::ffi::{
 /// assert!(!patchset.is_empty());
 /// ```
 pub struct Session {
-    session: *mut sqlite3_session,
+    /// The underlying `SQLite` session handle.
+    raw: *mut sqlite3_session,
+    /// Connection the session was created on, used for schema queries.
+    db: *mut sqlite3,
+    /// Name of the database the session tracks, such as `main`.
+    schema: String,
+    /// Names of the tables attached through [`Session::attach_by_name`].
+    tables: Vec<String>,
+    /// Whether [`Session::attach_all`] was called.
+    all_tables: bool,
+    /// Capacity set with [`Session::changeset_reserve`], if any.
+    reserve: Option<usize>,
+    /// Change count set with [`Session::with_snapshot_fallback`], if any.
+    snapshot_threshold: Option<usize>,
+    /// `PRAGMA schema_version` when the first table was attached.
+    schema_version: Option<i32>,
     _not_send_or_sync: PhantomData<Rc<()>>,
 }
 
 type SessionExportFn =
     unsafe extern "C" fn(*mut sqlite3_session, *mut c_int, *mut *mut c_void) -> c_int;
-const MAIN_DB_NAME: &std::ffi::CStr = c"main";
+type SessionStreamFn =
+    unsafe extern "C" fn(*mut sqlite3_session, Option<OutputFn>, *mut c_void) -> c_int;
+
+/// Query selecting the names of the user tables of database `schema` that
+/// match the `LIKE` pattern `?1`.
+fn tables_like_sql(schema: &str) -> String {
+    format!(
+        "SELECT name FROM {}.sqlite_master \
+         WHERE type = 'table' AND name LIKE ?1 \
+         AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+         ORDER BY name",
+        quote_identifier(schema)
+    )
+}
 
 impl Session {
-    /// Internal constructor - called by `SqliteSessionExt::create_session`.
+    /// Internal constructor - called by `SqliteSessionExt::create_session` and
+    /// `SqliteSessionExt::create_session_for`.
     ///
-    /// The session will track changes made to the "main" database.
+    /// The session will track changes made to the database named `schema`.
     ///
     /// # Safety
     ///
@@ -94,23 +149,27 @@ impl Session {
     ///
     /// # Errors
     ///
+    /// Returns `SessionError::InvalidDatabaseName` if `schema` contains a null byte.
     /// Returns `SessionError::CreateFailed` if `SQLite` fails to create the session.
-    pub(crate) fn new_internal(conn: &mut SqliteConnection) -> Result<Self, SessionError> {
-        // SAFETY: `with_raw_connection` provides a valid SQLite handle for the duration
-        // of the callback, and `MAIN_DB_NAME` is a static NUL-terminated C string.
-        let session = unsafe {
-            conn.with_raw_connection(|raw| {
-                let mut session: *mut sqlite3_session = ptr::null_mut();
-                let rc = sqlite3session_create(raw, MAIN_DB_NAME.as_ptr(), &mut session);
-                if rc != SQLITE_OK {
-                    return Err(SessionError::CreateFailed(SqliteErrorCode::from_error(rc)));
-                }
-                Ok(session)
-            })
+    pub(crate) fn new_internal(
+        conn: &mut SqliteConnection,
+        schema: &str,
+    ) -> Result<Self, SessionError> {
+        // SAFETY: `with_raw_connection` provides a valid SQLite handle for the
+        // duration of the callback.
+        let (session, db) = unsafe {
+            conn.with_raw_connection(|raw| create_session_handle(raw, schema).map(|s| (s, raw)))
         }?;
 
         Ok(Self {
-            session,
+            raw: session,
+            db,
+            schema: schema.to_owned(),
+            tables: Vec::new(),
+            all_tables: false,
+            reserve: None,
+            snapshot_threshold: None,
+            schema_version: None,
             _not_send_or_sync: PhantomData,
         })
     }
@@ -118,6 +177,10 @@ impl Session {
     /// Attach a table to track using a Diesel table type.
     ///
     /// This provides type-safe table attachment using Diesel's table macro types.
+    /// Tables declared with a schema, such as `aux.notes`, are attached by
+    /// their bare name and must belong to the database the session tracks;
+    /// create the session with
+    /// [`create_session_for`](crate::SqliteSessionExt::create_session_for).
     ///
     /// # Example
     ///
@@ -139,13 +202,26 @@ impl Session {
     ///
     /// # Errors
     ///
+    /// Returns `SessionError::SchemaMismatch` if the table is declared in a
+    /// database other than the tracked one.
     /// Returns `SessionError::AttachFailed` if `SQLite` fails to attach the table.
     pub fn attach<T>(&mut self) -> Result<(), SessionError>
     where
-        T: StaticQueryFragment<Component = Identifier<'static>>,
+        T: StaticQueryFragment,
+        T::Component: TableName,
     {
-        let table_name: &'static str = T::STATIC_COMPONENT.0;
-        self.attach_by_name(table_name)
+        let component = T::STATIC_COMPONENT;
+        let table = component.name();
+        if let Some(table_schema) = component.schema() {
+            if !table_schema.eq_ignore_ascii_case(&self.schema) {
+                return Err(SessionError::SchemaMismatch {
+                    table,
+                    table_schema,
+                    session_schema: self.schema.clone(),
+                });
+            }
+        }
+        self.attach_by_name(&table)
     }
 
     /// Attach ALL tables to track.
@@ -155,16 +231,18 @@ impl Session {
     /// # Errors
     ///
     /// Returns `SessionError::AttachFailed` if `SQLite` fails to attach.
+    /// Returns `SessionError::QueryFailed` if the schema version cannot be read.
     pub fn attach_all(&mut self) -> Result<(), SessionError> {
-        // SAFETY: `self.session` is created by `sqlite3session_create` and remains valid
+        // SAFETY: `self.raw` is created by `sqlite3session_create` and remains valid
         // for the lifetime of `Session`; passing null tracks all tables per SQLite API.
-        let rc = unsafe { sqlite3session_attach(self.session, ptr::null()) };
+        let rc = unsafe { sqlite3session_attach(self.raw, ptr::null()) };
 
         if rc != SQLITE_OK {
             return Err(SessionError::AttachFailed(SqliteErrorCode::from_error(rc)));
         }
 
-        Ok(())
+        self.all_tables = true;
+        self.record_schema_version()
     }
 
     /// Attach a table by name.
@@ -172,20 +250,208 @@ impl Session {
     /// Use this for dynamic schemas where the table name is determined at runtime.
     /// For static table names, prefer [`attach`](Self::attach) with a Diesel table type.
     ///
+    /// Views cannot be tracked themselves. Writes routed through a view by
+    /// `INSTEAD OF` triggers are recorded against the base tables they modify,
+    /// so attach those instead; like every change made by a trigger, they are
+    /// recorded as indirect (see [`ChangeOp::is_indirect`](crate::ChangeOp::is_indirect)).
+    ///
     /// # Errors
     ///
     /// Returns `SessionError::InvalidTableName` if the table name contains a null byte.
     /// Returns `SessionError::AttachFailed` if `SQLite` fails to attach the table.
+    /// Returns `SessionError::QueryFailed` if the schema version cannot be read.
     pub fn attach_by_name(&mut self, table: &str) -> Result<(), SessionError> {
         let c_name = CString::new(table).map_err(|_| SessionError::InvalidTableName)?;
-        // SAFETY: `self.session` is a live session handle and `c_name` is a valid
+        // SAFETY: `self.raw` is a live session handle and `c_name` is a valid
         // NUL-terminated table name for the duration of this call.
-        let rc = unsafe { sqlite3session_attach(self.session, c_name.as_ptr()) };
+        let rc = unsafe { sqlite3session_attach(self.raw, c_name.as_ptr()) };
 
         if rc != SQLITE_OK {
             return Err(SessionError::AttachFailed(SqliteErrorCode::from_error(rc)));
         }
 
+        if !self.tables.iter().any(|name| name == table) {
+            self.tables.push(table.to_owned());
+        }
+        self.record_schema_version()
+    }
+
+    /// Attach every table whose name matches a SQL `LIKE` pattern.
+    ///
+    /// Table names are read from `sqlite_master`, so views and `SQLite`'s
+    /// internal `sqlite_*` tables are never attached. As with SQL `LIKE`, `%`
+    /// matches any sequence of characters, `_` matches a single character, and
+    /// matching is case-insensitive for ASCII letters. Only tables that exist
+    /// when this is called are attached.
+    ///
+    /// Returns the names of the attached tables, in alphabetical order.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// // Attaches `events_2023`, `events_2024`, ...
+    /// let attached = session.attach_like("events_%").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if the schema cannot be read.
+    /// Returns `SessionError::AttachFailed` if `SQLite` fails to attach a table.
+    pub fn attach_like(&mut self, pattern: &str) -> Result<Vec<String>, SessionError> {
+        let tables = self.query_table_names(&tables_like_sql(&self.schema), pattern)?;
+        for table in &tables {
+            self.attach_by_name(table)?;
+        }
+        Ok(tables)
+    }
+
+    /// Attach every table except the ones named in `deny`.
+    ///
+    /// The complement of attaching an allow-list by name, for tracking
+    /// everything but a few audit or bookkeeping tables. Tables are enumerated
+    /// like [`attach_like`](Self::attach_like) does, and names in `deny` are
+    /// compared case-insensitively for ASCII letters, as `SQLite` compares
+    /// table names. Unlike [`attach_all`](Self::attach_all), tables created
+    /// after this call are not tracked.
+    ///
+    /// Returns the names of the attached tables, in alphabetical order.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.attach_all_except(&["audit_log"]).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if the schema cannot be read.
+    /// Returns `SessionError::AttachFailed` if `SQLite` fails to attach a table.
+    pub fn attach_all_except(&mut self, deny: &[&str]) -> Result<Vec<String>, SessionError> {
+        let mut tables = self.query_table_names(&tables_like_sql(&self.schema), "%")?;
+        tables.retain(|table| !deny.iter().any(|denied| denied.eq_ignore_ascii_case(table)));
+        for table in &tables {
+            self.attach_by_name(table)?;
+        }
+        Ok(tables)
+    }
+
+    /// Attach every table a Diesel query reads or writes.
+    ///
+    /// The query is rendered to SQL and compiled with `EXPLAIN`, without
+    /// running it, and the tables its bytecode opens are attached, so joins,
+    /// subqueries and the base tables behind views are all found. Tables of
+    /// other databases than the tracked one are ignored. This keeps the
+    /// tracked set in sync with the queries the application actually runs.
+    ///
+    /// An authorizer installed on the connection stays in place and is
+    /// consulted while the query is compiled, so a query it denies fails here
+    /// as it would when run.
+    ///
+    /// Returns the names of the attached tables, in alphabetical order.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// diesel::table! {
+    ///     users (id) {
+    ///         id -> Integer,
+    ///         name -> Text,
+    ///     }
+    /// }
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// diesel::sql_query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+    ///     .execute(&mut conn)
+    ///     .unwrap();
+    ///
+    /// let mut session = conn.create_session().unwrap();
+    /// let query = users::table.filter(users::name.eq("Alice")).select(users::id);
+    /// let attached = session.attach_from_query(&query).unwrap();
+    /// assert_eq!(attached, ["users"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if the query cannot be rendered or prepared.
+    /// Returns `SessionError::AttachFailed` if `SQLite` fails to attach a table.
+    pub fn attach_from_query<Q>(&mut self, query: &Q) -> Result<Vec<String>, SessionError>
+    where
+        Q: QueryFragment<Sqlite>,
+    {
+        let mut builder = SqliteQueryBuilder::new();
+        query
+            .to_sql(&mut builder, &Sqlite)
+            .map_err(|_| SessionError::QueryFailed(SqliteErrorCode::Misuse))?;
+        let sql = builder.finish();
+
+        // SAFETY: `self.db` is the connection this session was created on,
+        // which must outlive the session.
+        let tables = unsafe { opened_tables(self.db, &self.schema, &sql) }
+            .map_err(|rc| SessionError::QueryFailed(SqliteErrorCode::from_error(rc)))?;
+        for table in &tables {
+            self.attach_by_name(table)?;
+        }
+        Ok(tables)
+    }
+
+    /// Record the changes that turn `table` in database `from_schema` into the
+    /// same table in the database this session tracks.
+    ///
+    /// The differences are added to the session as if they had been made
+    /// directly, so the next [`changeset`](Self::changeset) transforms a
+    /// copy of the `from_schema` table into the tracked one. Both tables need
+    /// the same columns and primary key. The table must be attached first;
+    /// nothing needs to have been tracked live on either side.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::InvalidTableName` or
+    /// `SessionError::InvalidDatabaseName` if a name contains a null byte.
+    /// Returns `SessionError::DiffFailed` if the tables are incompatible or
+    /// `SQLite` fails to compare them.
+    pub fn diff(&mut self, from_schema: &str, table: &str) -> Result<(), SessionError> {
+        let c_from = CString::new(from_schema).map_err(|_| SessionError::InvalidDatabaseName)?;
+        let c_table = CString::new(table).map_err(|_| SessionError::InvalidTableName)?;
+        let mut message: *mut c_char = ptr::null_mut();
+
+        // SAFETY: `self.raw` is a live session handle, both names are valid
+        // NUL-terminated strings for the duration of the call and `message` is a
+        // valid out-pointer.
+        let rc = unsafe {
+            sqlite3session_diff(self.raw, c_from.as_ptr(), c_table.as_ptr(), &mut message)
+        };
+
+        let text = if message.is_null() {
+            String::new()
+        } else {
+            // SAFETY: SQLite returns a NUL-terminated message allocated with
+            // `sqlite3_malloc`, which we copy and then free exactly once.
+            unsafe {
+                let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+                sqlite3_free(message.cast());
+                text
+            }
+        };
+
+        if rc != SQLITE_OK {
+            return Err(SessionError::DiffFailed {
+                code: SqliteErrorCode::from_error(rc),
+                message: text,
+            });
+        }
         Ok(())
     }
 
@@ -194,11 +460,174 @@ impl Session {
     /// A changeset contains all information needed to recreate the changes,
     /// including both old and new values for updated rows.
     ///
+    /// Changes are recorded against the table layout at the time they were
+    /// made, so a schema change after attaching, such as `ALTER TABLE ... ADD
+    /// COLUMN`, can leave them inconsistent with the current tables. Exports
+    /// therefore fail once `PRAGMA schema_version` of the tracked database has
+    /// moved since the first attach; note that every schema change bumps it,
+    /// including creating unrelated tables or indexes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    /// Returns `SessionError::TooLarge` if the changeset does not fit in one
+    /// buffer; stream it with [`changeset_to_writer`](Self::changeset_to_writer) instead.
+    /// Returns `SessionError::SchemaChangedDuringSession` if the schema of the
+    /// tracked database changed since the first table was attached.
+    pub fn changeset(&mut self) -> Result<Changeset, SessionError> {
+        let _timer = Timer::start(CHANGESET_DURATION);
+        if let Some(hint) = self.reserve {
+            let mut buf = Vec::with_capacity(hint);
+            self.changeset_to_writer(&mut buf)?;
+            return Ok(Changeset::from_bytes(buf));
+        }
+        self.export_changes(
+            sqlite3session_changeset,
+            sqlite3session_changeset_strm,
+            SessionError::ChangesetFailed,
+        )
+        .map(Changeset::from_bytes)
+    }
+
+    /// Hint that changesets of this session are about `hint` bytes long.
+    ///
+    /// From now on [`changeset`](Self::changeset) pre-allocates a buffer of
+    /// `hint` bytes and streams the changeset into it, instead of letting
+    /// `SQLite` build its own buffer that is then copied. For known-large
+    /// deltas this avoids both the copy and repeated reallocation. The output
+    /// is identical either way; a `hint` of zero restores the default path.
+    #[inline]
+    pub fn changeset_reserve(&mut self, hint: usize) {
+        self.reserve = (hint > 0).then_some(hint);
+    }
+
+    /// Generate a changeset of the changes recorded so far and start tracking
+    /// afresh.
+    ///
+    /// The next `flush` returns only the changes made after this one, which
+    /// makes this the primitive for periodic incremental sync. `SQLite` cannot
+    /// clear a session, so the underlying session is replaced by a new one
+    /// with the same attached tables, rowid option and enabled and indirect
+    /// state. Because the session and its connection live on one thread, no
+    /// change can slip in between the export and the replacement. Changes recorded with
+    /// [`diff`](Self::diff) are flushed like any other change and are not
+    /// recorded again.
+    ///
+    /// With [`with_snapshot_fallback`](Self::with_snapshot_fallback), a
+    /// snapshot of the attached tables is returned instead once the recorded
+    /// changes exceed the threshold.
+    ///
+    /// On error the session is left as it was, still holding its changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    /// Returns `SessionError::QueryFailed` if the fallback snapshot cannot be read.
+    /// Returns `SessionError::CreateFailed` or `SessionError::AttachFailed` if
+    /// the replacement session cannot be set up.
+    pub fn flush(&mut self) -> Result<Changeset, SessionError> {
+        let mut changeset = self.changeset()?;
+        if let Some(threshold) = self.snapshot_threshold {
+            if count_ops(&changeset)? > threshold {
+                changeset = self.snapshot_changeset()?;
+            }
+        }
+
+        // SAFETY: `self.db` is the connection this session was created on,
+        // which must outlive the session.
+        let fresh = unsafe { create_session_handle(self.db, &self.schema) }?;
+        // Dropping `replacement` on error deletes the new handle.
+        let mut replacement = Self {
+            raw: fresh,
+            db: self.db,
+            schema: self.schema.clone(),
+            tables: Vec::new(),
+            all_tables: false,
+            reserve: self.reserve,
+            snapshot_threshold: self.snapshot_threshold,
+            schema_version: None,
+            _not_send_or_sync: PhantomData,
+        };
+        // The rowid option must be set before the first table is attached.
+        replacement.track_rowid_tables(self.tracks_rowid_tables())?;
+        if self.all_tables {
+            replacement.attach_all()?;
+        }
+        for table in &self.tables {
+            replacement.attach_by_name(table)?;
+        }
+        replacement.set_enabled(self.tracking_state());
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`; a
+        // negative argument only queries the current state.
+        let indirect = unsafe { sqlite3session_indirect(self.raw, -1) != 0 };
+        replacement.set_indirect(indirect);
+
+        // The old handle is deleted when `replacement` is dropped.
+        std::mem::swap(self, &mut replacement);
+        Ok(changeset)
+    }
+
+    /// Make [`flush`](Self::flush) return a full snapshot once more than
+    /// `threshold` changes were recorded since the previous flush.
+    ///
+    /// Below the threshold, flushes stay incremental. Above it, replaying
+    /// every change costs more than copying the tables, so `flush` returns
+    /// [`snapshot_changeset`](Self::snapshot_changeset) instead. The snapshot
+    /// only holds inserts of the current rows, so apply it to an emptied
+    /// replica; applied with [`ConflictAction::Replace`] to a populated one,
+    /// it leaves rows deleted since the last flush in place. Tracking itself
+    /// is unaffected.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish("app.db").unwrap();
+    /// let mut session = conn.create_session().unwrap().with_snapshot_fallback(10_000);
+    /// session.attach_by_name("events").unwrap();
+    /// // ... later, periodically:
+    /// let batch = session.flush().unwrap();
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_snapshot_fallback(mut self, threshold: usize) -> Self {
+        self.snapshot_threshold = Some(threshold);
+        self
+    }
+
+    /// Remember the changes recorded so far, to later export only newer ones.
+    ///
+    /// The session keeps accumulating; the mark only captures its current
+    /// changeset. Pass the mark to [`changeset_since`](Self::changeset_since)
+    /// to get incremental flush points without resetting the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    pub fn mark(&mut self) -> Result<SessionMark, SessionError> {
+        Ok(SessionMark {
+            changeset: self.changeset()?,
+        })
+    }
+
+    /// Generate a changeset of the changes recorded since `mark` was taken.
+    ///
+    /// The result is the net difference between the state at the mark and the
+    /// current one: a row inserted before the mark and updated after it shows
+    /// up as an update. `mark` must come from this session.
+    ///
     /// # Errors
     ///
     /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
-    pub fn changeset(&mut self) -> Result<Vec<u8>, SessionError> {
-        self.export_changes(sqlite3session_changeset, SessionError::ChangesetFailed)
+    /// Returns `SessionError::Changeset` if the changes cannot be combined.
+    pub fn changeset_since(&mut self, mark: &SessionMark) -> Result<Changeset, SessionError> {
+        let current = self.changeset()?;
+        let mut group = ChangeGroup::new()?;
+        group.add(&invert_changeset(&mark.changeset)?)?;
+        group.add(&current)?;
+        Ok(group.output()?)
     }
 
     /// Generate a patchset from tracked changes.
@@ -210,77 +639,1190 @@ impl Session {
     /// # Errors
     ///
     /// Returns `SessionError::PatchsetFailed` if `SQLite` fails to generate the patchset.
-    pub fn patchset(&mut self) -> Result<Vec<u8>, SessionError> {
-        self.export_changes(sqlite3session_patchset, SessionError::PatchsetFailed)
+    /// Returns `SessionError::TooLarge` if the patchset does not fit in one
+    /// buffer; stream it with [`patchset_to_writer`](Self::patchset_to_writer) instead.
+    /// Returns `SessionError::SchemaChangedDuringSession` if the schema of the
+    /// tracked database changed since the first table was attached.
+    pub fn patchset(&mut self) -> Result<Patchset, SessionError> {
+        let _timer = Timer::start(PATCHSET_DURATION);
+        self.export_changes(
+            sqlite3session_patchset,
+            sqlite3session_patchset_strm,
+            SessionError::PatchsetFailed,
+        )
+        .map(Patchset::from_bytes)
     }
 
-    /// Check if the session has recorded any changes.
+    /// Stream a changeset of tracked changes into `writer`.
     ///
-    /// Returns `true` if no changes have been recorded, `false` otherwise.
-    #[inline]
-    #[must_use]
-    pub fn is_empty(&self) -> bool {
-        // SAFETY: `self.session` is a valid handle owned by this `Session`.
-        unsafe { sqlite3session_isempty(self.session) != 0 }
+    /// Produces the same bytes as [`changeset`](Self::changeset), but `SQLite`
+    /// hands them over in small chunks instead of one contiguous buffer. Unlike
+    /// the in-memory path, whose size is limited to `i32::MAX` bytes by the C
+    /// API, the streamed output has no size ceiling.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::Io` if `writer` fails.
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    pub fn changeset_to_writer<W: Write>(&mut self, writer: W) -> Result<(), SessionError> {
+        self.stream_changes(
+            sqlite3session_changeset_strm,
+            writer,
+            SessionError::ChangesetFailed,
+        )
     }
 
-    /// Enable or disable change tracking.
+    /// Generate a changeset of tracked changes as the chunks `SQLite` streams
+    /// it in.
     ///
-    /// When disabled, changes are not recorded. This can be useful for
-    /// temporarily suspending tracking during bulk operations.
-    #[inline]
-    pub fn set_enabled(&mut self, enabled: bool) {
-        // SAFETY: `self.session` is a valid handle owned by this `Session`.
-        unsafe {
-            sqlite3session_enable(self.session, i32::from(enabled));
-        }
+    /// Concatenating the chunks yields the same bytes as
+    /// [`changeset`](Self::changeset), but no single buffer of the full size
+    /// is allocated, so the chunks can be handed to a writer or a network
+    /// sink one at a time. The chunks are produced before this returns; use
+    /// [`changeset_to_writer`](Self::changeset_to_writer) to avoid holding
+    /// them all at once.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    ///
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.attach_all().unwrap();
+    /// // ... make changes ...
+    /// let mut out = std::io::stdout();
+    /// for chunk in session.take_changeset_chunks().unwrap() {
+    ///     out.write_all(&chunk).unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    pub fn take_changeset_chunks(&mut self) -> Result<impl Iterator<Item = Vec<u8>>, SessionError> {
+        let mut chunks = ChunkCollector::default();
+        self.changeset_to_writer(&mut chunks)?;
+        Ok(chunks.0.into_iter())
     }
 
-    fn export_changes(
-        &mut self,
-        export_fn: SessionExportFn,
-        map_error: fn(SqliteErrorCode) -> SessionError,
-    ) -> Result<Vec<u8>, SessionError> {
-        let mut size: c_int = 0;
-        let mut buffer: *mut c_void = ptr::null_mut();
+    /// Stream a patchset of tracked changes into `writer`.
+    ///
+    /// Produces the same bytes as [`patchset`](Self::patchset) without the
+    /// `i32::MAX` size ceiling of the in-memory path.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::Io` if `writer` fails.
+    /// Returns `SessionError::PatchsetFailed` if `SQLite` fails to generate the patchset.
+    pub fn patchset_to_writer<W: Write>(&mut self, writer: W) -> Result<(), SessionError> {
+        self.stream_changes(
+            sqlite3session_patchset_strm,
+            writer,
+            SessionError::PatchsetFailed,
+        )
+    }
 
-        // SAFETY: `export_fn` is one of SQLite's session export functions and
-        // receives valid out-pointers to write size and buffer.
-        let rc = unsafe { export_fn(self.session, &mut size, &mut buffer) };
-        if rc != SQLITE_OK {
-            return Err(map_error(SqliteErrorCode::from_error(rc)));
+    /// Generate the final changeset and destroy the session.
+    ///
+    /// Consuming the session guarantees at compile time that no further
+    /// changes are tracked or exported once the result has been taken.
+    ///
+    /// ```compile_fail
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// let changeset = session.into_changeset().unwrap();
+    /// session.attach_all().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    pub fn into_changeset(mut self) -> Result<Changeset, SessionError> {
+        self.changeset()
+    }
+
+    /// Generate the final patchset and destroy the session.
+    ///
+    /// See [`into_changeset`](Self::into_changeset).
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::PatchsetFailed` if `SQLite` fails to generate the patchset.
+    pub fn into_patchset(mut self) -> Result<Patchset, SessionError> {
+        self.patchset()
+    }
+
+    /// Generate the changeset of the recorded changes split into one changeset
+    /// per table.
+    ///
+    /// The changeset is generated once and its operations are sorted by
+    /// table, so each table's delta can be shipped and applied independently,
+    /// for example by parallel workers on a sharded replica. Tables without
+    /// changes have no entry. Like [`changeset`](Self::changeset), this leaves
+    /// the recorded changes in place.
+    ///
+    /// Applying the parts separately loses the atomicity of one apply:
+    /// foreign keys between rows of different tables must be deferrable or
+    /// disabled.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.attach_all().unwrap();
+    /// for (table, changeset) in session.changesets_by_table().unwrap() {
+    ///     println!("{table}: {} bytes", changeset.len());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    /// Returns `SessionError::Changeset` if the generated changeset cannot be read.
+    pub fn changesets_by_table(&mut self) -> Result<HashMap<String, Changeset>, SessionError> {
+        let changeset = self.changeset()?;
+        let mut encoders: HashMap<String, Encoder> = HashMap::new();
+        for op in read_changeset(&changeset)? {
+            let op = op?;
+            encoders
+                .entry(op.table().to_owned())
+                .or_insert_with(|| Encoder::new(Format::Changeset))
+                .push(&op);
         }
+        Ok(encoders
+            .into_iter()
+            .map(|(table, encoder)| (table, Changeset::from_bytes(encoder.finish())))
+            .collect())
+    }
 
-        let result = if size <= 0 || buffer.is_null() {
-            Ok(Vec::new())
+    /// Capture the current contents of the attached tables as an insert-only changeset.
+    ///
+    /// Applying the snapshot to an empty replica reproduces every row, which
+    /// lets a new replica catch up in one shot before switching to incremental
+    /// changesets. Changes recorded by the session are not affected.
+    ///
+    /// Tables without a declared primary key are skipped, matching the tables
+    /// the session extension itself can track, unless
+    /// [`track_rowid_tables`](Self::track_rowid_tables) is enabled. Their rows
+    /// are then captured the way the session records them, with the rowid as
+    /// an extra leading primary key column. After
+    /// [`attach_all`](Self::attach_all), every table of the main database is
+    /// included.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if reading the schema or a table fails.
+    pub fn snapshot_changeset(&self) -> Result<Changeset, SessionError> {
+        let tables = if self.all_tables {
+            self.query_table_names(&tables_like_sql(&self.schema), "%")?
         } else {
-            usize::try_from(size)
-                .map_err(|_| map_error(SqliteErrorCode::Unknown(size)))
-                .map(|byte_len| {
-                    // SAFETY: SQLite returned a non-null buffer with `byte_len` bytes;
-                    // we copy those bytes immediately into an owned `Vec<u8>`.
-                    let bytes =
-                        unsafe { std::slice::from_raw_parts(buffer.cast::<u8>(), byte_len) };
-                    bytes.to_vec()
-                })
+            self.tables.clone()
         };
 
-        if !buffer.is_null() {
-            // SAFETY: SQLite allocates export buffers with sqlite3_malloc-family APIs
-            // and requires release via `sqlite3_free`.
-            unsafe { sqlite3_free(buffer) };
+        let mut encoder = Encoder::new(Format::Changeset);
+        for table in tables {
+            self.push_table_rows(&mut encoder, &table)?;
         }
 
-        result
+        Ok(Changeset::from_bytes(encoder.finish()))
     }
-}
 
-impl Drop for Session {
-    fn drop(&mut self) {
-        // SAFETY: `self.session` is owned by this type and must be released
-        // exactly once with `sqlite3session_delete`.
-        unsafe {
-            sqlite3session_delete(self.session);
+    /// Capture every row of `table` as a changeset meant to be applied with
+    /// [`ConflictAction::Replace`](crate::ConflictAction::Replace).
+    ///
+    /// Each row is encoded as an insert, so applying the changeset with a
+    /// handler that replaces on [`ConflictType::Conflict`](crate::ConflictType::Conflict)
+    /// overwrites stale rows of a replica and inserts missing ones. Applying it
+    /// again leaves the same contents, which makes it safe for seeding that
+    /// may be retried. Rows that exist only on the replica are left in place.
+    ///
+    /// `table` does not need to be attached. A table without a declared
+    /// primary key is captured by rowid or yields an empty changeset, as in
+    /// [`snapshot_changeset`](Self::snapshot_changeset).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+    ///
+    /// let mut source = SqliteConnection::establish("source.db").unwrap();
+    /// let mut replica = SqliteConnection::establish("replica.db").unwrap();
+    /// let snapshot = source.create_session().unwrap().upsert_snapshot("users").unwrap();
+    /// replica
+    ///     .apply_changeset(&snapshot, |_| ConflictAction::Replace)
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if reading the schema or the table fails.
+    pub fn upsert_snapshot(&self, table: &str) -> Result<Changeset, SessionError> {
+        let mut encoder = Encoder::new(Format::Changeset);
+        self.push_table_rows(&mut encoder, table)?;
+        Ok(Changeset::from_bytes(encoder.finish()))
+    }
+
+    /// Encode every row of `table` as an insert, skipping tables without a
+    /// primary key unless rowid tables are tracked.
+    fn push_table_rows(&self, encoder: &mut Encoder, table: &str) -> Result<(), SessionError> {
+        let query_failed = |rc| SessionError::QueryFailed(SqliteErrorCode::from_error(rc));
+        // SAFETY: `self.db` is the connection this session was created on,
+        // which must outlive the session.
+        let mut pk_flags =
+            unsafe { primary_key_flags(self.db, &self.schema, table) }.map_err(query_failed)?;
+        let by_rowid = pk_flags.iter().all(|&flag| flag == 0);
+        if by_rowid {
+            if !self.tracks_rowid_tables() {
+                return Ok(());
+            }
+            pk_flags.insert(0, 1);
+        }
+
+        let sql = format!(
+            "SELECT {}* FROM {}.{}",
+            if by_rowid { "_rowid_, " } else { "" },
+            quote_identifier(&self.schema),
+            quote_identifier(table)
+        );
+        // SAFETY: as above; the statement is finalized before returning.
+        let mut stmt = unsafe { Statement::prepare(self.db, &sql) }.map_err(query_failed)?;
+        while stmt.step().map_err(query_failed)? {
+            let values = (0..stmt.column_count())
+                .map(|column| Some(stmt.column_value(column)))
+                .collect();
+            encoder.push(&ChangeOp::from_parts(
+                table.to_owned(),
+                OpKind::Insert,
+                pk_flags.clone(),
+                Vec::new(),
+                values,
+            ));
         }
+        Ok(())
+    }
+
+    /// Compute the exact size in bytes of the changeset [`changeset`](Self::changeset)
+    /// would return.
+    ///
+    /// The changeset is generated through the streaming API and discarded, so
+    /// no buffer of the full size is allocated. Together with
+    /// [`changeset_into`](Self::changeset_into) this lets callers size their
+    /// own buffer once up front.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    pub fn changeset_len(&mut self) -> Result<usize, SessionError> {
+        let mut counter = ByteCounter::default();
+        self.changeset_to_writer(&mut counter)?;
+        Ok(counter.0)
+    }
+
+    /// Write the changeset into a caller-provided buffer.
+    ///
+    /// Returns the number of bytes written. Use
+    /// [`changeset_len`](Self::changeset_len) to size `buf`. The contents of
+    /// `buf` are unspecified when an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::BufferTooSmall` if the changeset does not fit in `buf`.
+    /// Returns `SessionError::ChangesetFailed` if `SQLite` fails to generate the changeset.
+    pub fn changeset_into(&mut self, buf: &mut [u8]) -> Result<usize, SessionError> {
+        let capacity = buf.len();
+        let mut remaining: &mut [u8] = buf;
+        match self.changeset_to_writer(&mut remaining) {
+            Ok(()) => Ok(capacity - remaining.len()),
+            Err(SessionError::Io(err)) if err.kind() == io::ErrorKind::WriteZero => {
+                Err(SessionError::BufferTooSmall { capacity })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Check if the session has recorded any changes.
+    ///
+    /// Returns `true` if no changes have been recorded, `false` otherwise.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`.
+        unsafe { sqlite3session_isempty(self.raw) != 0 }
+    }
+
+    /// Summarize what the session is attached to and whether it holds changes.
+    ///
+    /// Meant as a startup or test diagnostic: a session attached to tables but
+    /// still empty after the application has written often means the tables
+    /// were attached after the writes, which the session cannot see.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.attach_by_name("items").unwrap();
+    /// let summary = session.attachment_summary();
+    /// if summary.is_attached() && !summary.has_changes() {
+    ///     eprintln!(
+    ///         "session attached to {} tables but empty: did you attach after writing?",
+    ///         summary.attached_tables()
+    ///     );
+    /// }
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn attachment_summary(&self) -> AttachmentSummary {
+        AttachmentSummary {
+            attached_tables: self.tables.len(),
+            all_tables: self.all_tables,
+            has_changes: !self.is_empty(),
+        }
+    }
+
+    /// List the attached tables whose changes the session will record.
+    ///
+    /// `SQLite` silently ignores changes to tables without a declared primary
+    /// key, and attaching a table that does not exist is not an error either.
+    /// This returns only the attached tables that exist and have a primary
+    /// key, or any existing table when
+    /// [`track_rowid_tables`](Self::track_rowid_tables) is enabled, so
+    /// comparing it with what was attached reveals tables whose changes would
+    /// be lost. After [`attach_all`](Self::attach_all) every
+    /// table currently in the tracked database is considered.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.attach_all().unwrap();
+    /// for table in session.trackable_tables().unwrap() {
+    ///     println!("tracking {table}");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::QueryFailed` if reading the schema fails.
+    pub fn trackable_tables(&self) -> Result<Vec<String>, SessionError> {
+        let tables = if self.all_tables {
+            self.query_table_names(&tables_like_sql(&self.schema), "%")?
+        } else {
+            self.tables.clone()
+        };
+
+        let query_failed = |rc| SessionError::QueryFailed(SqliteErrorCode::from_error(rc));
+        let rowid_tables = self.tracks_rowid_tables();
+        let mut trackable = Vec::new();
+        for table in tables {
+            // SAFETY: `self.db` is the connection this session was created on,
+            // which must outlive the session.
+            let pk_flags = unsafe { primary_key_flags(self.db, &self.schema, &table) }
+                .map_err(query_failed)?;
+            let has_key = if rowid_tables {
+                !pk_flags.is_empty()
+            } else {
+                pk_flags.iter().any(|&flag| flag != 0)
+            };
+            if has_key {
+                trackable.push(table);
+            }
+        }
+        Ok(trackable)
+    }
+
+    /// Enable or disable change tracking.
+    ///
+    /// When disabled, changes are not recorded. This can be useful for
+    /// temporarily suspending tracking during bulk operations.
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        let _ = self.try_set_enabled(enabled);
+    }
+
+    /// Enable or disable change tracking, returning the resulting state.
+    ///
+    /// Behaves like [`set_enabled`](Self::set_enabled), but returns whether
+    /// tracking is enabled after the call as reported by `SQLite`, so callers
+    /// can confirm the change took effect.
+    #[inline]
+    #[must_use]
+    pub fn try_set_enabled(&mut self, enabled: bool) -> bool {
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`.
+        unsafe { sqlite3session_enable(self.raw, i32::from(enabled)) != 0 }
+    }
+
+    /// Query whether change tracking is currently enabled.
+    ///
+    /// The state is read from `SQLite` rather than mirrored on the Rust side,
+    /// so it reflects every call to [`set_enabled`](Self::set_enabled) and
+    /// [`try_set_enabled`](Self::try_set_enabled).
+    #[inline]
+    #[must_use]
+    pub fn tracking_state(&self) -> bool {
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`; a
+        // negative argument only queries the current state.
+        unsafe { sqlite3session_enable(self.raw, -1) != 0 }
+    }
+
+    /// Suspend change tracking until the returned guard is dropped.
+    ///
+    /// Unlike pairing [`set_enabled(false)`](Self::set_enabled) with a later
+    /// `set_enabled(true)`, tracking cannot be left off by an early return or a
+    /// panic. On drop the guard restores the state tracking had before the
+    /// call, so pausing an already disabled session leaves it disabled.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.attach_by_name("users").unwrap();
+    /// {
+    ///     let _paused = session.pause();
+    ///     // Bulk-load rows that should not be replicated.
+    /// }
+    /// // Tracking is enabled again here.
+    /// ```
+    #[inline]
+    #[must_use = "tracking resumes as soon as the guard is dropped"]
+    pub fn pause(&mut self) -> PauseGuard<'_> {
+        let was_enabled = self.tracking_state();
+        self.set_enabled(false);
+        PauseGuard {
+            session: self,
+            was_enabled,
+        }
+    }
+
+    /// Track tables that have no explicit `PRIMARY KEY`.
+    ///
+    /// By default `SQLite` ignores changes to such tables: attaching them
+    /// succeeds, but nothing is recorded. With this enabled, changes to
+    /// ordinary rowid tables are recorded as if the table had an
+    /// `INTEGER PRIMARY KEY` column holding the rowid inserted before its
+    /// first column. Operations read from the changeset therefore have one
+    /// more column than the table declares: the first value is the rowid and
+    /// [`ChangeOp::primary_key`](crate::ChangeOp::primary_key) marks only
+    /// that column. Applying such a changeset matches rows by rowid, so the
+    /// replica must keep the same rowids; `VACUUM` may renumber the rows of
+    /// tables without an explicit key.
+    ///
+    /// Must be called before the first table is attached.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use diesel::prelude::*;
+    /// use diesel_sqlite_session::SqliteSessionExt;
+    ///
+    /// let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// diesel::sql_query("CREATE TABLE log (message TEXT)")
+    ///     .execute(&mut conn)
+    ///     .unwrap();
+    /// let mut session = conn.create_session().unwrap();
+    /// session.track_rowid_tables(true).unwrap();
+    /// session.attach_by_name("log").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::ConfigFailed` if a table was already attached.
+    pub fn track_rowid_tables(&mut self, enabled: bool) -> Result<(), SessionError> {
+        let mut value = c_int::from(enabled);
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`, and
+        // `value` is the `int` the option reads and writes.
+        let rc = unsafe {
+            sqlite3session_object_config(
+                self.raw,
+                SQLITE_SESSION_OBJCONFIG_ROWID,
+                ptr::addr_of_mut!(value).cast::<c_void>(),
+            )
+        };
+        if rc == SQLITE_OK {
+            Ok(())
+        } else {
+            Err(SessionError::ConfigFailed(SqliteErrorCode::from_error(rc)))
+        }
+    }
+
+    /// Whether tables without an explicit `PRIMARY KEY` are tracked.
+    ///
+    /// See [`track_rowid_tables`](Self::track_rowid_tables).
+    #[must_use]
+    pub fn tracks_rowid_tables(&self) -> bool {
+        let mut value: c_int = -1;
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`; a
+        // negative value only queries the option, which is written back.
+        let rc = unsafe {
+            sqlite3session_object_config(
+                self.raw,
+                SQLITE_SESSION_OBJCONFIG_ROWID,
+                ptr::addr_of_mut!(value).cast::<c_void>(),
+            )
+        };
+        rc == SQLITE_OK && value > 0
+    }
+
+    /// Mark changes recorded from now on as indirect.
+    ///
+    /// Changes made by triggers and foreign key actions are always indirect;
+    /// this flags the application's own changes too, so that readers can tell
+    /// them apart with [`ChangeOp::is_indirect`].
+    #[inline]
+    pub fn set_indirect(&mut self, indirect: bool) {
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`.
+        unsafe {
+            sqlite3session_indirect(self.raw, i32::from(indirect));
+        }
+    }
+
+    /// Capture this session's configuration, without its recorded changes.
+    ///
+    /// See [`SessionConfig`].
+    #[must_use]
+    pub fn config(&self) -> SessionConfig {
+        // SAFETY: `self.raw` is a valid handle owned by this `Session`; a
+        // negative argument only queries the current flag.
+        let indirect = unsafe { sqlite3session_indirect(self.raw, -1) != 0 };
+        SessionConfig {
+            schema: self.schema.clone(),
+            tables: self.tables.clone(),
+            all_tables: self.all_tables,
+            rowid_tables: self.tracks_rowid_tables(),
+            indirect,
+            enabled: self.tracking_state(),
+        }
+    }
+
+    /// Run a query selecting a single text column with one text parameter.
+    fn query_table_names(&self, sql: &str, param: &str) -> Result<Vec<String>, SessionError> {
+        let query_failed = |rc| SessionError::QueryFailed(SqliteErrorCode::from_error(rc));
+        // SAFETY: `self.db` is the connection this session was created on, which
+        // must outlive the session and therefore this statement.
+        let mut stmt = unsafe { Statement::prepare(self.db, sql) }.map_err(query_failed)?;
+        stmt.bind_text(1, param).map_err(query_failed)?;
+
+        let mut names = Vec::new();
+        while stmt.step().map_err(query_failed)? {
+            names.push(stmt.column_text(0));
+        }
+        Ok(names)
+    }
+
+    /// Read `PRAGMA schema_version` of the tracked database.
+    fn read_schema_version(&self) -> Result<i32, SessionError> {
+        let query_failed = |rc| SessionError::QueryFailed(SqliteErrorCode::from_error(rc));
+        let sql = format!("PRAGMA {}.schema_version", quote_identifier(&self.schema));
+        // SAFETY: `self.db` is the connection this session was created on, which
+        // must outlive the session and therefore this statement.
+        let mut stmt = unsafe { Statement::prepare(self.db, &sql) }.map_err(query_failed)?;
+        stmt.step().map_err(query_failed)?;
+        Ok(stmt.column_int(0))
+    }
+
+    /// Remember the schema version when the first table is attached.
+    fn record_schema_version(&mut self) -> Result<(), SessionError> {
+        if self.schema_version.is_none() {
+            self.schema_version = Some(self.read_schema_version()?);
+        }
+        Ok(())
+    }
+
+    /// Fail if the schema changed since the first table was attached.
+    fn check_schema_version(&self) -> Result<(), SessionError> {
+        let Some(attached) = self.schema_version else {
+            return Ok(());
+        };
+        let current = self.read_schema_version()?;
+        if current == attached {
+            Ok(())
+        } else {
+            Err(SessionError::SchemaChangedDuringSession { attached, current })
+        }
+    }
+
+    /// Export the changes into one buffer with `export_fn`.
+    ///
+    /// `SQLite` cannot hand out buffers of `c_int::MAX` bytes or more. When
+    /// `export_fn` fails in a way that may come from that limit, the output is
+    /// measured with the matching `stream_fn` to report
+    /// `SessionError::TooLarge` instead.
+    fn export_changes(
+        &mut self,
+        export_fn: SessionExportFn,
+        stream_fn: SessionStreamFn,
+        map_error: fn(SqliteErrorCode) -> SessionError,
+    ) -> Result<Vec<u8>, SessionError> {
+        self.check_schema_version()?;
+        let mut size: c_int = 0;
+        let mut buffer: *mut c_void = ptr::null_mut();
+
+        // SAFETY: `export_fn` is one of SQLite's session export functions and
+        // receives valid out-pointers to write size and buffer.
+        let rc = unsafe { export_fn(self.raw, &mut size, &mut buffer) };
+        if rc == SQLITE_TOOBIG || rc == SQLITE_NOMEM {
+            let mut counter = ByteCounter::default();
+            if self
+                .stream_changes(stream_fn, &mut counter, map_error)
+                .is_ok()
+                && c_int::try_from(counter.0).is_err()
+            {
+                return Err(SessionError::TooLarge { len: counter.0 });
+            }
+        }
+        if rc != SQLITE_OK {
+            return Err(map_error(SqliteErrorCode::from_error(rc)));
+        }
+
+        // SAFETY: on success SQLite hands us ownership of `buffer`, which holds
+        // `size` bytes allocated with `sqlite3_malloc`.
+        unsafe { take_sqlite_buffer(buffer, size) }
+            .map_err(|size| map_error(SqliteErrorCode::Unknown(size)))
+    }
+
+    fn stream_changes<W: Write>(
+        &mut self,
+        stream_fn: SessionStreamFn,
+        writer: W,
+        map_error: fn(SqliteErrorCode) -> SessionError,
+    ) -> Result<(), SessionError> {
+        self.check_schema_version()?;
+        let mut context = OutputContext::new(writer);
+
+        // SAFETY: `self.raw` is a live session handle, and `context` points to
+        // stack storage that outlives the call and matches `output_callback::<W>`.
+        let rc = unsafe {
+            stream_fn(
+                self.raw,
+                Some(output_callback::<W>),
+                ptr::addr_of_mut!(context).cast(),
+            )
+        };
+
+        context.finish()?;
+        if rc != SQLITE_OK {
+            return Err(map_error(SqliteErrorCode::from_error(rc)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The name of a Diesel table, as generated by `diesel::table!`.
+///
+/// Implemented for the identifier of tables declared without a schema and for
+/// the `schema.table` pair of tables declared with one, so that
+/// [`Session::attach`] accepts both.
+pub trait TableName {
+    /// The database the table is declared in, if it was declared with one.
+    fn schema(&self) -> Option<String>;
+
+    /// The table name, without schema.
+    fn name(&self) -> String;
+}
+
+impl TableName for Identifier<'static> {
+    #[inline]
+    fn schema(&self) -> Option<String> {
+        None
+    }
+
+    #[inline]
+    fn name(&self) -> String {
+        self.0.to_owned()
+    }
+}
+
+impl TableName for InfixNode<Identifier<'static>, Identifier<'static>, &'static str> {
+    fn schema(&self) -> Option<String> {
+        let mut parts = rendered_identifiers(self);
+        (parts.len() == 2).then(|| parts.swap_remove(0))
+    }
+
+    fn name(&self) -> String {
+        rendered_identifiers(self).pop().unwrap_or_default()
+    }
+}
+
+/// Render `fragment` as `SQLite` SQL and split out the quoted identifiers.
+///
+/// Diesel does not expose the parts of a schema-qualified table name, so they
+/// are recovered from the `` `schema`.`table` `` text it renders.
+fn rendered_identifiers<Q: QueryFragment<Sqlite>>(fragment: &Q) -> Vec<String> {
+    let mut builder = SqliteQueryBuilder::new();
+    if fragment.to_sql(&mut builder, &Sqlite).is_err() {
+        return Vec::new();
+    }
+    let sql = builder.finish();
+
+    let mut identifiers = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, current.as_mut()) {
+            ('`', Some(ident)) if chars.peek() == Some(&'`') => {
+                chars.next();
+                ident.push('`');
+            }
+            ('`', Some(_)) => identifiers.extend(current.take()),
+            ('`', None) => current = Some(String::new()),
+            (c, Some(ident)) => ident.push(c),
+            (_, None) => {}
+        }
+    }
+    identifiers
+}
+
+/// Count the operations of a changeset without decoding their values.
+fn count_ops(changeset: &[u8]) -> Result<usize, SessionError> {
+    let mut iter = read_changeset(changeset)?;
+    let mut count = 0;
+    while let Some(op) = iter.next_kind() {
+        op?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Read the names of the tables of database `schema` that `sql` opens, in
+/// name order.
+///
+/// The statement is compiled with `EXPLAIN` and never run. Every table or
+/// index its bytecode opens is looked up by root page in the schema, and
+/// indexes count as their table. Internal `sqlite_` tables are left out.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn opened_tables(db: *mut sqlite3, schema: &str, sql: &str) -> Result<Vec<String>, c_int> {
+    let database = {
+        // SAFETY: the caller guarantees `db` is valid.
+        let mut stmt = unsafe {
+            Statement::prepare(
+                db,
+                "SELECT seq FROM pragma_database_list WHERE name = ?1 COLLATE NOCASE",
+            )
+        }?;
+        stmt.bind_text(1, schema)?;
+        if !stmt.step()? {
+            return Ok(Vec::new());
+        }
+        stmt.column_int(0)
+    };
+
+    // SAFETY: the caller guarantees `db` is valid.
+    let mut stmt = unsafe { Statement::prepare(db, &format!("EXPLAIN {sql}")) }?;
+    let mut root_pages = Vec::new();
+    // Columns are addr, opcode, p1, p2, p3, ...; opening opcodes take the
+    // root page in p2 and the database in p3.
+    while stmt.step()? {
+        let opens = matches!(
+            stmt.column_text(1).as_str(),
+            "OpenRead" | "OpenWrite" | "ReopenIdx"
+        );
+        if opens && stmt.column_int(4) == database {
+            root_pages.push(stmt.column_int(3).to_string());
+        }
+    }
+    if root_pages.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let sql = format!(
+        "SELECT DISTINCT tbl_name FROM {}.sqlite_master \
+         WHERE type IN ('table', 'index') AND rootpage IN ({}) \
+         AND tbl_name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+         ORDER BY tbl_name",
+        quote_identifier(schema),
+        root_pages.join(", ")
+    );
+    // SAFETY: the caller guarantees `db` is valid.
+    let mut stmt = unsafe { Statement::prepare(db, &sql) }?;
+    let mut tables = Vec::new();
+    while stmt.step()? {
+        tables.push(stmt.column_text(0));
+    }
+    Ok(tables)
+}
+
+/// Create a session handle on database `schema` of `db`.
+///
+/// # Safety
+///
+/// `db` must be a valid connection handle.
+unsafe fn create_session_handle(
+    db: *mut sqlite3,
+    schema: &str,
+) -> Result<*mut sqlite3_session, SessionError> {
+    let c_schema = CString::new(schema).map_err(|_| SessionError::InvalidDatabaseName)?;
+    let mut session: *mut sqlite3_session = ptr::null_mut();
+    // SAFETY: the caller guarantees `db` is valid, `c_schema` is a valid
+    // NUL-terminated database name and `session` is a valid out-pointer.
+    let rc = unsafe { sqlite3session_create(db, c_schema.as_ptr(), &mut session) };
+    if rc != SQLITE_OK {
+        return Err(SessionError::CreateFailed(SqliteErrorCode::from_error(rc)));
+    }
+    Ok(session)
+}
+
+/// The changes a [`Session`] had recorded at some point, created by
+/// [`Session::mark`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionMark {
+    changeset: Changeset,
+}
+
+/// Keeps change tracking of a [`Session`] suspended, created by
+/// [`Session::pause`].
+///
+/// Dropping the guard restores the tracking state the session had before it
+/// was paused.
+#[derive(Debug)]
+pub struct PauseGuard<'a> {
+    session: &'a mut Session,
+    was_enabled: bool,
+}
+
+impl Drop for PauseGuard<'_> {
+    fn drop(&mut self) {
+        self.session.set_enabled(self.was_enabled);
+    }
+}
+
+/// What a [`Session`] is attached to, returned by
+/// [`Session::attachment_summary`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentSummary {
+    attached_tables: usize,
+    all_tables: bool,
+    has_changes: bool,
+}
+
+impl AttachmentSummary {
+    /// Number of tables attached by name.
+    ///
+    /// Tables covered only by [`Session::attach_all`] are not counted; see
+    /// [`tracks_all_tables`](Self::tracks_all_tables).
+    #[inline]
+    #[must_use]
+    pub fn attached_tables(&self) -> usize {
+        self.attached_tables
+    }
+
+    /// Whether [`Session::attach_all`] was called.
+    #[inline]
+    #[must_use]
+    pub fn tracks_all_tables(&self) -> bool {
+        self.all_tables
+    }
+
+    /// Whether the session tracks at least one table.
+    #[inline]
+    #[must_use]
+    pub fn is_attached(&self) -> bool {
+        self.all_tables || self.attached_tables > 0
+    }
+
+    /// Whether the session has recorded any changes.
+    #[inline]
+    #[must_use]
+    pub fn has_changes(&self) -> bool {
+        self.has_changes
+    }
+}
+
+/// A reusable description of how to set up a [`Session`].
+///
+/// Captures the configuration of a session, not its recorded changes: the
+/// tracked database, the attached tables, whether rowid tables are tracked and
+/// whether tracking is enabled and indirect. Define the tracking once and stamp out identically configured
+/// sessions on any number of connections with [`apply_to`](Self::apply_to).
+/// [`Session::config`] captures the configuration of an existing session.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::SessionConfig;
+///
+/// let config = SessionConfig::new().attach_by_name("users").attach_by_name("posts");
+///
+/// let mut first = SqliteConnection::establish("first.db").unwrap();
+/// let mut second = SqliteConnection::establish("second.db").unwrap();
+/// let first_session = config.apply_to(&mut first).unwrap();
+/// let second_session = config.apply_to(&mut second).unwrap();
+/// ```
+// The flags map to separate builder methods and session settings, none of
+// which exclude each other.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionConfig {
+    schema: String,
+    tables: Vec<String>,
+    all_tables: bool,
+    rowid_tables: bool,
+    indirect: bool,
+    enabled: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            schema: "main".to_owned(),
+            tables: Vec::new(),
+            all_tables: false,
+            rowid_tables: false,
+            indirect: false,
+            enabled: true,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// A configuration tracking nothing in the `main` database, with tracking enabled.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track the database named `schema` instead of `main`.
+    ///
+    /// See [`create_session_for`](crate::SqliteSessionExt::create_session_for).
+    #[inline]
+    #[must_use]
+    pub fn schema(mut self, schema: &str) -> Self {
+        schema.clone_into(&mut self.schema);
+        self
+    }
+
+    /// Attach `table`, as [`Session::attach_by_name`] does.
+    #[inline]
+    #[must_use]
+    pub fn attach_by_name(mut self, table: &str) -> Self {
+        if !self.tables.iter().any(|name| name == table) {
+            self.tables.push(table.to_owned());
+        }
+        self
+    }
+
+    /// Attach every table, as [`Session::attach_all`] does.
+    #[inline]
+    #[must_use]
+    pub fn attach_all(mut self) -> Self {
+        self.all_tables = true;
+        self
+    }
+
+    /// Track tables without an explicit primary key, as
+    /// [`Session::track_rowid_tables`] does.
+    #[inline]
+    #[must_use]
+    pub fn track_rowid_tables(mut self, enabled: bool) -> Self {
+        self.rowid_tables = enabled;
+        self
+    }
+
+    /// Mark recorded changes as indirect, as [`Session::set_indirect`] does.
+    #[inline]
+    #[must_use]
+    pub fn indirect(mut self, indirect: bool) -> Self {
+        self.indirect = indirect;
+        self
+    }
+
+    /// Start with tracking enabled or disabled, as [`Session::set_enabled`] does.
+    #[inline]
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Names of the tables attached by name, in attach order.
+    #[inline]
+    #[must_use]
+    pub fn tables(&self) -> &[String] {
+        &self.tables
+    }
+
+    /// Create a session on `conn` configured as described.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionError::InvalidDatabaseName` if the schema name contains a null byte.
+    /// Returns `SessionError::CreateFailed` if `SQLite` fails to create the session.
+    /// Returns `SessionError::ConfigFailed` if rowid tracking cannot be enabled.
+    /// Returns `SessionError::InvalidTableName` or `SessionError::AttachFailed`
+    /// if a table cannot be attached.
+    /// Returns `SessionError::QueryFailed` if the schema version cannot be read.
+    pub fn apply_to(&self, conn: &mut SqliteConnection) -> Result<Session, SessionError> {
+        let mut session = Session::new_internal(conn, &self.schema)?;
+        if self.rowid_tables {
+            session.track_rowid_tables(true)?;
+        }
+        if self.all_tables {
+            session.attach_all()?;
+        }
+        for table in &self.tables {
+            session.attach_by_name(table)?;
+        }
+        session.set_indirect(self.indirect);
+        session.set_enabled(self.enabled);
+        Ok(session)
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("empty", &self.is_empty())
+            .field("enabled", &self.tracking_state())
+            .field("schema", &self.schema)
+            .field("all_tables", &self.all_tables)
+            .field("tables", &self.tables)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // SAFETY: `self.raw` is owned by this type and must be released
+        // exactly once with `sqlite3session_delete`.
+        unsafe {
+            sqlite3session_delete(self.raw);
+        }
+    }
+}
+
+/// Compute the changeset that turns `tables` in database `from_schema` into
+/// the same tables in database `to_schema`.
+///
+/// Both databases must be reachable from `conn`, typically one being `main`
+/// and the other added with `ATTACH DATABASE`. This is the snapshot-diff
+/// workflow: neither side needs to have been tracked while it was edited.
+/// Applying the result to a copy of `from_schema` reproduces `to_schema`.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::changeset_between;
+///
+/// let mut conn = SqliteConnection::establish("after.db").unwrap();
+/// diesel::sql_query("ATTACH DATABASE 'before.db' AS before")
+///     .execute(&mut conn)
+///     .unwrap();
+/// let changeset = changeset_between(&mut conn, "before", "main", &["items"]).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `SessionError::CreateFailed` if the session cannot be created.
+/// Returns `SessionError::AttachFailed` or `SessionError::DiffFailed` if a
+/// table cannot be compared.
+/// Returns `SessionError::ChangesetFailed` if the changeset cannot be generated.
+pub fn changeset_between(
+    conn: &mut SqliteConnection,
+    from_schema: &str,
+    to_schema: &str,
+    tables: &[&str],
+) -> Result<Changeset, SessionError> {
+    let mut session = Session::new_internal(conn, to_schema)?;
+    for table in tables {
+        session.attach_by_name(table)?;
+        session.diff(from_schema, table)?;
+    }
+    session.into_changeset()
+}
+
+/// Apply `incoming` and return the changeset of what it changed locally.
+///
+/// A session tracking `tables` records the apply, so the result reflects how
+/// conflicts were resolved rather than repeating the input: a change replaced
+/// onto a diverged row carries that row's old values, and omitted changes are
+/// left out. This suits a node that receives changesets, applies them and
+/// forwards a normalized changeset downstream. Changes to tables not listed
+/// are applied but not recaptured.
+///
+/// # Example
+///
+/// ```no_run
+/// use diesel::prelude::*;
+/// use diesel_sqlite_session::{apply_and_recapture, ConflictAction};
+///
+/// let mut relay = SqliteConnection::establish("relay.db").unwrap();
+/// # let incoming: Vec<u8> = Vec::new();
+/// let forward = apply_and_recapture(&mut relay, &incoming, &["items"], |_| {
+///     ConflictAction::Replace
+/// })
+/// .unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns `ApplyError::Session` if the recording session cannot be set up
+/// or its changeset generated.
+/// Otherwise returns the same errors as
+/// [`SqliteSessionExt::apply_changeset`](crate::SqliteSessionExt::apply_changeset).
+pub fn apply_and_recapture<F>(
+    conn: &mut SqliteConnection,
+    incoming: &[u8],
+    tables: &[&str],
+    on_conflict: F,
+) -> Result<Changeset, ApplyError>
+where
+    F: Fn(ConflictType) -> ConflictAction,
+{
+    let mut session = Session::new_internal(conn, "main")?;
+    for table in tables {
+        session.attach_by_name(table)?;
+    }
+    apply_changeset(conn, incoming, on_conflict)?;
+    Ok(session.into_changeset()?)
+}
+
+/// Read the names of the user tables of the main database, in name order.
+///
+/// This is an internal function. Use `SqliteSessionExt::user_tables` instead.
+pub(crate) fn user_tables(conn: &mut SqliteConnection) -> Result<Vec<String>, SessionError> {
+    let query_failed = |rc| SessionError::QueryFailed(SqliteErrorCode::from_error(rc));
+    let sql = tables_like_sql("main");
+    // SAFETY: `with_raw_connection` provides a valid SQLite handle for the
+    // duration of the callback, which finalizes the statement before returning.
+    unsafe {
+        conn.with_raw_connection(|db| {
+            let mut stmt = Statement::prepare(db, &sql)?;
+            stmt.bind_text(1, "%")?;
+            let mut names = Vec::new();
+            while stmt.step()? {
+                names.push(stmt.column_text(0));
+            }
+            Ok(names)
+        })
     }
+    .map_err(query_failed)
 }
@@ -0,0 +1,184 @@
+//! Adapters between `SQLite`'s streaming session APIs and `std::io`.
+
+use std::any::Any;
+use std::ffi::{c_int, c_void};
+use std::io::{self, Read, Write};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+use crate::ffi::{SQLITE_IOERR, SQLITE_OK};
+
+/// Signature of the `xOutput` callback taken by the `*_strm` output APIs.
+pub(crate) type OutputFn = unsafe extern "C" fn(*mut c_void, *const c_void, c_int) -> c_int;
+
+/// State shared with [`output_callback`] while `SQLite` streams output.
+pub(crate) struct OutputContext<W> {
+    writer: W,
+    error: Option<io::Error>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl<W: Write> OutputContext<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+            panic: None,
+        }
+    }
+
+    /// Surface what happened inside the callback once `SQLite` has returned.
+    ///
+    /// A panic raised by the writer is resumed here, on the Rust side of the
+    /// FFI boundary.
+    pub(crate) fn finish(self) -> Result<(), io::Error> {
+        if let Some(payload) = self.panic {
+            resume_unwind(payload);
+        }
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+/// Writer that discards its input and only counts the bytes it receives.
+#[derive(Debug, Default)]
+pub(crate) struct ByteCounter(pub(crate) usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer that keeps each chunk it receives as a separate buffer.
+#[derive(Debug, Default)]
+pub(crate) struct ChunkCollector(pub(crate) Vec<Vec<u8>>);
+
+impl Write for ChunkCollector {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.0.push(buf.to_vec());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// External C callback forwarding each output chunk to the wrapped writer.
+///
+/// # Safety
+///
+/// `context` must point to a live `OutputContext<W>` and `data` must hold
+/// `len` readable bytes, as guaranteed by `SQLite` for `xOutput` callbacks.
+pub(crate) unsafe extern "C" fn output_callback<W: Write>(
+    context: *mut c_void,
+    data: *const c_void,
+    len: c_int,
+) -> c_int {
+    // SAFETY: SQLite passes back the context pointer we supplied.
+    let ctx = unsafe { &mut *context.cast::<OutputContext<W>>() };
+
+    let bytes: &[u8] = match usize::try_from(len) {
+        Ok(byte_len) if byte_len > 0 && !data.is_null() => {
+            // SAFETY: SQLite guarantees `data` holds `len` bytes for this call.
+            unsafe { std::slice::from_raw_parts(data.cast::<u8>(), byte_len) }
+        }
+        _ => &[],
+    };
+
+    match catch_unwind(AssertUnwindSafe(|| ctx.writer.write_all(bytes))) {
+        Ok(Ok(())) => SQLITE_OK,
+        Ok(Err(err)) => {
+            ctx.error = Some(err);
+            SQLITE_IOERR
+        }
+        Err(payload) => {
+            ctx.panic = Some(payload);
+            SQLITE_IOERR
+        }
+    }
+}
+
+/// State shared with [`input_callback`] while `SQLite` streams input.
+pub(crate) struct InputContext<R> {
+    reader: R,
+    error: Option<io::Error>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl<R: Read> InputContext<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            error: None,
+            panic: None,
+        }
+    }
+
+    /// Surface what happened inside the callback once `SQLite` has returned.
+    ///
+    /// A panic raised by the reader is resumed here, on the Rust side of the
+    /// FFI boundary.
+    pub(crate) fn finish(self) -> Result<(), io::Error> {
+        if let Some(payload) = self.panic {
+            resume_unwind(payload);
+        }
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+/// External C callback filling `SQLite`'s input buffer from the wrapped reader.
+///
+/// Reports end of input by setting `*len` to zero.
+///
+/// # Safety
+///
+/// `context` must point to a live `InputContext<R>`, `data` must hold `*len`
+/// writable bytes and `len` must be valid, as guaranteed by `SQLite` for
+/// `xInput` callbacks.
+pub(crate) unsafe extern "C" fn input_callback<R: Read>(
+    context: *mut c_void,
+    data: *mut c_void,
+    len: *mut c_int,
+) -> c_int {
+    // SAFETY: SQLite passes back the context pointer we supplied.
+    let ctx = unsafe { &mut *context.cast::<InputContext<R>>() };
+    // SAFETY: SQLite passes a valid pointer to the buffer capacity.
+    let capacity = usize::try_from(unsafe { *len }).unwrap_or(0);
+
+    let buf: &mut [u8] = if capacity > 0 && !data.is_null() {
+        // SAFETY: SQLite guarantees `data` holds `capacity` writable bytes.
+        unsafe { std::slice::from_raw_parts_mut(data.cast::<u8>(), capacity) }
+    } else {
+        &mut []
+    };
+
+    let read = catch_unwind(AssertUnwindSafe(|| loop {
+        match ctx.reader.read(buf) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            result => break result,
+        }
+    }));
+    let filled = match read {
+        Ok(Ok(filled)) => filled,
+        Ok(Err(err)) => {
+            ctx.error = Some(err);
+            return SQLITE_IOERR;
+        }
+        Err(payload) => {
+            ctx.panic = Some(payload);
+            return SQLITE_IOERR;
+        }
+    };
+
+    // SAFETY: `len` is valid for writes; `filled` never exceeds the capacity
+    // SQLite passed in, which came from a `c_int`.
+    unsafe { *len = c_int::try_from(filled).unwrap_or(0) };
+    SQLITE_OK
+}
@@ -0,0 +1,65 @@
+//! Optional timing metrics, recorded through the `metrics` facade.
+//!
+//! With the `metrics` feature disabled the timer is a zero-sized type and
+//! records nothing.
+
+/// Histogram of [`Session::changeset`](crate::Session::changeset) durations,
+/// in seconds.
+pub const CHANGESET_DURATION: &str = "diesel_sqlite_session_changeset_duration_seconds";
+
+/// Histogram of [`Session::patchset`](crate::Session::patchset) durations,
+/// in seconds.
+pub const PATCHSET_DURATION: &str = "diesel_sqlite_session_patchset_duration_seconds";
+
+/// Histogram of changeset and patchset apply durations, in seconds.
+pub const APPLY_DURATION: &str = "diesel_sqlite_session_apply_duration_seconds";
+
+#[cfg(all(
+    feature = "metrics",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+mod imp {
+    use std::time::Instant;
+
+    /// Records the time until it is dropped into a histogram.
+    #[must_use = "the duration is recorded when the timer is dropped"]
+    pub(crate) struct Timer {
+        histogram: &'static str,
+        start: Instant,
+    }
+
+    impl Timer {
+        #[inline]
+        pub(crate) fn start(histogram: &'static str) -> Self {
+            Self {
+                histogram,
+                start: Instant::now(),
+            }
+        }
+    }
+
+    impl Drop for Timer {
+        fn drop(&mut self) {
+            metrics::histogram!(self.histogram).record(self.start.elapsed().as_secs_f64());
+        }
+    }
+}
+
+#[cfg(not(all(
+    feature = "metrics",
+    not(all(target_family = "wasm", target_os = "unknown"))
+)))]
+mod imp {
+    /// Stand-in for the metrics timer that records nothing.
+    #[must_use = "the duration is recorded when the timer is dropped"]
+    pub(crate) struct Timer;
+
+    impl Timer {
+        #[inline]
+        pub(crate) fn start(_histogram: &'static str) -> Self {
+            Self
+        }
+    }
+}
+
+pub(crate) use imp::Timer;
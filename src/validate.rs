@@ -0,0 +1,226 @@
+//! Check the structure of changeset and patchset input before `SQLite` reads it.
+//!
+//! `SQLite` trusts its input to be well formed, and some truncated or
+//! corrupted buffers make `sqlite3changeset_next` loop instead of failing.
+//! Every entry point handing caller-provided bytes to `SQLite` runs them
+//! through [`validate_changeset`] first, so malformed input is rejected with
+//! an error before any of it is read or applied.
+
+use crate::encode::Format;
+use crate::errors::ChangesetError;
+use crate::iter::OpKind;
+
+/// Largest column count `SQLite` accepts in a table header.
+const MAX_COLUMNS: u64 = 65_536;
+
+/// Check that `data` is a sequence of complete table headers and records.
+///
+/// Every table header must use the marker of the first one, every record
+/// must name a known operation, and every value must have a known type and
+/// fit in the input. The values themselves are not interpreted.
+///
+/// # Errors
+///
+/// Returns `ChangesetError::Malformed` with the offset of the first header,
+/// record or value that cannot be read.
+pub(crate) fn validate_changeset(data: &[u8]) -> Result<(), ChangesetError> {
+    let format = Format::of(data);
+    let marker = match format {
+        Format::Changeset => b'T',
+        Format::Patchset => b'P',
+    };
+    let mut reader = Reader { data, pos: 0 };
+    let mut pk_flags: Option<&[u8]> = None;
+
+    while let Some(byte) = reader.peek() {
+        if byte == marker {
+            pk_flags = Some(reader.table_header()?);
+            continue;
+        }
+        let start = reader.pos;
+        let (Some(pk_flags), Some(op)) = (pk_flags, OpKind::from_raw(i32::from(byte))) else {
+            return Err(ChangesetError::Malformed { offset: start });
+        };
+        reader.pos += 1;
+        reader.take(1, start)?;
+        match (format, op) {
+            (Format::Changeset, OpKind::Update) => {
+                reader.record(pk_flags.len())?;
+                reader.record(pk_flags.len())?;
+            }
+            (Format::Changeset, OpKind::Delete) => reader.record(pk_flags.len())?,
+            (Format::Patchset, OpKind::Delete) => {
+                reader.record(pk_flags.iter().filter(|&&flag| flag != 0).count())?;
+            }
+            (_, OpKind::Insert) | (Format::Patchset, OpKind::Update) => {
+                reader.record(pk_flags.len())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cursor over the input, reporting the offset of whatever it fails to read.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    /// Consume `len` bytes, or fail at `start` if fewer remain.
+    fn take(&mut self, len: usize, start: usize) -> Result<&'a [u8], ChangesetError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(ChangesetError::Malformed { offset: start })?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Read an `SQLite` varint; the ninth byte, if reached, is used whole.
+    fn varint(&mut self, start: usize) -> Result<u64, ChangesetError> {
+        let mut value = 0_u64;
+        for index in 0..9 {
+            let byte = self.take(1, start)?[0];
+            if index == 8 {
+                return Ok(value << 8 | u64::from(byte));
+            }
+            value = value << 7 | u64::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Read a table header and return its primary key flags.
+    fn table_header(&mut self) -> Result<&'a [u8], ChangesetError> {
+        let start = self.pos;
+        self.pos += 1;
+        let columns = self.varint(start)?;
+        if columns == 0 || columns > MAX_COLUMNS {
+            return Err(ChangesetError::Malformed { offset: start });
+        }
+        let pk_flags = self.take(usize::try_from(columns).unwrap_or(usize::MAX), start)?;
+        let name_len = self.data[self.pos..]
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(ChangesetError::Malformed { offset: start })?;
+        self.take(name_len + 1, start)?;
+        Ok(pk_flags)
+    }
+
+    /// Read a record of `count` values.
+    fn record(&mut self, count: usize) -> Result<(), ChangesetError> {
+        for _ in 0..count {
+            let start = self.pos;
+            match self.take(1, start)?[0] {
+                // Undefined and NULL values carry no data.
+                0 | 5 => {}
+                // Integers and floats are eight big-endian bytes.
+                1 | 2 => {
+                    self.take(8, start)?;
+                }
+                // Text and blobs are a varint length followed by the bytes.
+                3 | 4 => {
+                    let len = self.varint(start)?;
+                    self.take(usize::try_from(len).unwrap_or(usize::MAX), start)?;
+                }
+                _ => return Err(ChangesetError::Malformed { offset: start }),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::Encoder;
+    use crate::iter::ChangeOp;
+    use crate::value::SqliteValue;
+
+    fn encoded(format: Format) -> Vec<u8> {
+        let mut encoder = Encoder::new(format);
+        encoder.push(&ChangeOp::from_parts(
+            "items".to_owned(),
+            OpKind::Insert,
+            vec![1, 0],
+            vec![None, None],
+            vec![
+                Some(SqliteValue::Integer(1)),
+                Some(SqliteValue::Text(b"alpha".to_vec())),
+            ],
+        ));
+        encoder.push(&ChangeOp::from_parts(
+            "items".to_owned(),
+            OpKind::Delete,
+            vec![1, 0],
+            vec![Some(SqliteValue::Integer(2)), Some(SqliteValue::Null)],
+            vec![None, None],
+        ));
+        encoder.finish()
+    }
+
+    fn offset(data: &[u8]) -> Option<usize> {
+        match validate_changeset(data) {
+            Ok(()) => None,
+            Err(ChangesetError::Malformed { offset }) => Some(offset),
+            Err(err) => panic!("unexpected error {err}"),
+        }
+    }
+
+    #[test]
+    fn accepts_empty_input() {
+        assert_eq!(offset(&[]), None);
+    }
+
+    #[test]
+    fn accepts_encoded_changesets_and_patchsets() {
+        assert_eq!(offset(&encoded(Format::Changeset)), None);
+        assert_eq!(offset(&encoded(Format::Patchset)), None);
+    }
+
+    #[test]
+    fn accepts_only_truncations_at_record_boundaries() {
+        for format in [Format::Changeset, Format::Patchset] {
+            let data = encoded(format);
+            let accepted: Vec<usize> = (1..data.len())
+                .filter(|&len| offset(&data[..len]).is_none())
+                .collect();
+            // The table header alone, then the header and the insert.
+            assert_eq!(accepted, [10, 28]);
+        }
+    }
+
+    #[test]
+    fn rejects_lone_table_marker() {
+        assert_eq!(offset(b"T"), Some(0));
+        assert_eq!(offset(b"P"), Some(0));
+    }
+
+    #[test]
+    fn rejects_record_before_table_header() {
+        assert_eq!(offset(&[18, 0]), Some(0));
+    }
+
+    #[test]
+    fn rejects_unknown_value_type() {
+        let mut data = b"T\x01\x01t\x00\x12\x00".to_vec();
+        data.push(6);
+        assert_eq!(offset(&data), Some(7));
+    }
+
+    #[test]
+    fn rejects_mixed_table_markers() {
+        let mut data = encoded(Format::Changeset);
+        data.extend_from_slice(b"P\x01\x01t\x00");
+        assert_eq!(offset(&data), Some(data.len() - 5));
+    }
+}
@@ -0,0 +1,97 @@
+//! Reading [`SqliteValue`]s out of `SQLite` value handles.
+
+use std::ffi::{c_int, c_void};
+
+pub use crate::core_types::SqliteValue;
+use crate::ffi::{
+    sqlite3_value, sqlite3_value_blob, sqlite3_value_bytes, sqlite3_value_double,
+    sqlite3_value_int64, sqlite3_value_text, sqlite3_value_type, SQLITE_BLOB, SQLITE_FLOAT,
+    SQLITE_INTEGER, SQLITE_TEXT,
+};
+
+impl SqliteValue {
+    /// Copy a value out of an `SQLite`-owned `sqlite3_value`.
+    ///
+    /// # Safety
+    ///
+    /// `value` must be a valid, non-null `sqlite3_value` pointer for the
+    /// duration of the call.
+    pub(crate) unsafe fn from_raw(value: *mut sqlite3_value) -> Self {
+        // SAFETY: the caller guarantees `value` is valid. For text and blobs the
+        // data pointer is fetched before the length, as SQLite recommends, and
+        // both stay valid until the value is next converted or freed.
+        unsafe {
+            match sqlite3_value_type(value) {
+                SQLITE_INTEGER => Self::Integer(sqlite3_value_int64(value)),
+                SQLITE_FLOAT => Self::Real(sqlite3_value_double(value)),
+                SQLITE_TEXT => {
+                    let data = sqlite3_value_text(value).cast::<c_void>();
+                    Self::Text(copy_bytes(data, sqlite3_value_bytes(value)))
+                }
+                SQLITE_BLOB => {
+                    let data = sqlite3_value_blob(value);
+                    Self::Blob(copy_bytes(data, sqlite3_value_bytes(value)))
+                }
+                _ => Self::Null,
+            }
+        }
+    }
+}
+
+/// Copy `len` bytes starting at `data` into an owned buffer.
+///
+/// # Safety
+///
+/// `data` must be null or point to at least `len` readable bytes.
+unsafe fn copy_bytes(data: *const c_void, len: c_int) -> Vec<u8> {
+    match usize::try_from(len) {
+        Ok(len) if len > 0 && !data.is_null() => {
+            // SAFETY: the caller guarantees `data` holds `len` readable bytes.
+            unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len) }.to_vec()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn text_decodes_valid_utf8() {
+        let value = SqliteValue::Text("héllo".as_bytes().to_vec());
+        assert_eq!(value.text(), Some(Ok("héllo")));
+        assert!(matches!(value.text_lossy(), Some(Cow::Borrowed("héllo"))));
+    }
+
+    #[test]
+    fn text_rejects_invalid_utf8() {
+        let value = SqliteValue::Text(vec![b'o', b'k', 0xc3]);
+        assert!(matches!(value.text(), Some(Err(_))));
+        assert_eq!(value.text_lossy().as_deref(), Some("ok\u{FFFD}"));
+    }
+
+    #[test]
+    fn typed_accessors_match_their_variant() {
+        assert_eq!(SqliteValue::Real(1.5).as_f64(), Some(1.5));
+        assert_eq!(SqliteValue::Integer(1).as_f64(), None);
+        assert_eq!(SqliteValue::Blob(vec![1, 2]).as_bytes(), Some(&[1, 2][..]));
+        assert_eq!(
+            SqliteValue::Text(b"ab".to_vec()).as_bytes(),
+            Some(&b"ab"[..])
+        );
+        assert_eq!(SqliteValue::Null.as_bytes(), None);
+        assert!(SqliteValue::Null.is_null());
+        assert!(!SqliteValue::Integer(0).is_null());
+    }
+
+    #[test]
+    fn text_accessors_ignore_other_variants() {
+        let blob = SqliteValue::Blob(b"bytes".to_vec());
+        assert_eq!(blob.text(), None);
+        assert_eq!(blob.text_lossy(), None);
+        assert_eq!(SqliteValue::Null.text(), None);
+    }
+}
@@ -0,0 +1,28 @@
+//! Version of the `SQLite` library the bindings are linked against.
+
+use std::ffi::CStr;
+
+use crate::ffi::{sqlite3_libversion, sqlite3_libversion_number};
+
+/// Return the version of the linked `SQLite` library.
+///
+/// The first element is the numeric version, `X * 1_000_000 + Y * 1_000 + Z`
+/// for version `X.Y.Z`, and the second the version string, such as `"3.45.1"`.
+/// Session features and extended result codes vary between releases, so this
+/// is worth logging when diagnosing version-specific behavior.
+///
+/// # Example
+///
+/// ```no_run
+/// let (number, version) = diesel_sqlite_session::sqlite_version();
+/// eprintln!("linked against SQLite {version} ({number})");
+/// ```
+#[must_use]
+pub fn sqlite_version() -> (i32, String) {
+    // SAFETY: `sqlite3_libversion_number` has no preconditions.
+    let number = unsafe { sqlite3_libversion_number() };
+    // SAFETY: `sqlite3_libversion` returns a pointer to a static,
+    // nul-terminated string owned by SQLite.
+    let version = unsafe { CStr::from_ptr(sqlite3_libversion()) };
+    (number, version.to_string_lossy().into_owned())
+}
@@ -0,0 +1,887 @@
+//! Tests for applying changesets and patchsets with `ApplyOptions`.
+
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_query;
+use diesel::sql_types::BigInt;
+use diesel_sqlite_session::{
+    ApplyError, ApplyOptions, ApplyStats, Changeset, ConflictAction, ConflictType, OpKind,
+    SqliteErrorCode, SqliteSessionExt, SqliteValue,
+};
+
+/// Helper to create an in-memory connection with an `items` table.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+/// Helper to insert rows `start..end` into `items` with the given name.
+fn insert_items(conn: &mut SqliteConnection, start: i32, end: i32, name: &str) {
+    for id in start..end {
+        sql_query(format!(
+            "INSERT INTO items (id, name) VALUES ({id}, '{name}')"
+        ))
+        .execute(conn)
+        .unwrap();
+    }
+}
+
+fn count_named(conn: &mut SqliteConnection, name: &str) -> i64 {
+    sql::<BigInt>(&format!("SELECT COUNT(*) FROM items WHERE name = '{name}'"))
+        .get_result(conn)
+        .unwrap()
+}
+
+/// Record inserts of rows `0..rows` and return the resulting changeset.
+fn changeset_inserting(rows: i32) -> Changeset {
+    changeset_inserting_range(0, rows)
+}
+
+/// Record inserts of rows `start..end` and return the resulting changeset.
+fn changeset_inserting_range(start: i32, end: i32) -> Changeset {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    insert_items(&mut source, start, end, "source");
+    session.changeset().unwrap()
+}
+
+#[test]
+fn test_max_conflicts_aborts_after_limit() {
+    let changeset = changeset_inserting(10);
+
+    // Every incoming row collides with an existing one.
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 10, "replica");
+
+    let invocations = Cell::new(0_usize);
+    let result =
+        replica.apply_changeset_with(&changeset, &ApplyOptions::new().max_conflicts(3), |_| {
+            invocations.set(invocations.get() + 1);
+            ConflictAction::Replace
+        });
+
+    assert!(matches!(
+        result,
+        Err(ApplyError::ConflictLimitExceeded { limit: 3 })
+    ));
+    assert_eq!(invocations.get(), 3);
+    // The whole apply is rolled back, including the replaced rows.
+    assert_eq!(count_named(&mut replica, "replica"), 10);
+}
+
+#[test]
+fn test_max_conflicts_allows_conflicts_up_to_limit() {
+    let changeset = changeset_inserting(10);
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 3, "replica");
+
+    let stats = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new().max_conflicts(3), |_| {
+            ConflictAction::Replace
+        })
+        .unwrap();
+
+    assert_eq!(stats.conflicts(), 3);
+    assert_eq!(count_named(&mut replica, "source"), 10);
+}
+
+#[test]
+fn test_collect_omitted_lists_each_skipped_row() {
+    let changeset = changeset_inserting(5);
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 1, 4, "replica");
+
+    let stats = replica
+        .apply_changeset_with(
+            &changeset,
+            &ApplyOptions::new().collect_omitted(true),
+            |_| ConflictAction::Omit,
+        )
+        .unwrap();
+
+    let omitted = stats.omitted();
+    assert_eq!(omitted.len(), 3);
+    assert!(omitted.iter().all(|change| change.table() == "items"
+        && change.op() == OpKind::Insert
+        && change.conflict() == ConflictType::Conflict));
+
+    let mut keys: Vec<_> = omitted
+        .iter()
+        .map(|change| change.primary_key().to_vec())
+        .collect();
+    keys.sort_by_key(|key| match key.as_slice() {
+        [SqliteValue::Integer(id)] => *id,
+        other => panic!("unexpected primary key {other:?}"),
+    });
+    assert_eq!(
+        keys,
+        [1, 2, 3].map(|id| vec![SqliteValue::Integer(id)]).to_vec()
+    );
+
+    // Omitted rows keep their replica values; the others were inserted.
+    assert_eq!(count_named(&mut replica, "replica"), 3);
+    assert_eq!(count_named(&mut replica, "source"), 2);
+}
+
+#[test]
+fn test_omitted_changes_are_not_collected_by_default() {
+    let changeset = changeset_inserting(2);
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 2, "replica");
+
+    let stats = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Omit)
+        .unwrap();
+
+    assert_eq!(stats.conflicts(), 2);
+    assert!(stats.omitted().is_empty());
+}
+
+#[test]
+fn test_no_savepoint_applies_inside_outer_transaction() {
+    let first = changeset_inserting_range(0, 3);
+    let second = changeset_inserting_range(3, 5);
+    let options = ApplyOptions::new().no_savepoint(true);
+
+    let mut replica = setup_connection();
+    replica
+        .transaction::<_, DieselError, _>(|conn| {
+            for changeset in [&first, &second] {
+                conn.apply_changeset_with(changeset, &options, |_| ConflictAction::Abort)
+                    .unwrap();
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    assert_eq!(count_named(&mut replica, "source"), 5);
+}
+
+#[test]
+fn test_no_savepoint_changes_roll_back_with_outer_transaction() {
+    let first = changeset_inserting_range(0, 3);
+    let second = changeset_inserting_range(3, 5);
+    let options = ApplyOptions::new().no_savepoint(true);
+
+    let mut replica = setup_connection();
+    let result = replica.transaction::<(), _, _>(|conn| {
+        for changeset in [&first, &second] {
+            conn.apply_changeset_with(changeset, &options, |_| ConflictAction::Abort)
+                .unwrap();
+        }
+        Err(DieselError::RollbackTransaction)
+    });
+
+    assert!(matches!(result, Err(DieselError::RollbackTransaction)));
+    assert_eq!(count_named(&mut replica, "source"), 0);
+}
+
+#[test]
+fn test_savepoint_name_rolls_back_only_the_apply() {
+    let failing = changeset_inserting_range(0, 5);
+    let succeeding = changeset_inserting_range(10, 12);
+    let options = ApplyOptions::new().savepoint_name("sync");
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 4, 5, "replica");
+    sql_query("SAVEPOINT outer").execute(&mut replica).unwrap();
+    insert_items(&mut replica, 100, 101, "local");
+
+    // Row 4 collides, so the rows inserted before it are rolled back.
+    let result = replica.apply_changeset_with(&failing, &options, |_| ConflictAction::Abort);
+    assert!(matches!(result, Err(ApplyError::ConflictAborted)));
+    assert_eq!(count_named(&mut replica, "source"), 0);
+    assert_eq!(count_named(&mut replica, "local"), 1);
+
+    replica
+        .apply_changeset_with(&succeeding, &options, |_| ConflictAction::Abort)
+        .unwrap();
+    // The caller's savepoint is still open and holds both writes.
+    sql_query("RELEASE outer").execute(&mut replica).unwrap();
+    assert_eq!(count_named(&mut replica, "source"), 2);
+    assert_eq!(count_named(&mut replica, "local"), 1);
+}
+
+#[test]
+fn test_insert_on_not_found_turns_missing_update_into_insert() {
+    let mut source = setup_connection();
+    insert_items(&mut source, 1, 2, "before");
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("UPDATE items SET name = 'after' WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = setup_connection();
+    let invocations = Cell::new(0_usize);
+    let stats = replica
+        .apply_changeset_with(
+            &changeset,
+            &ApplyOptions::new().insert_on_not_found(true),
+            |_| {
+                invocations.set(invocations.get() + 1);
+                ConflictAction::Abort
+            },
+        )
+        .unwrap();
+
+    assert_eq!(stats.conflicts(), 1);
+    assert_eq!(invocations.get(), 0);
+    assert_eq!(count_named(&mut replica, "after"), 1);
+}
+
+#[test]
+fn test_missing_update_is_lost_without_insert_on_not_found() {
+    let mut source = setup_connection();
+    insert_items(&mut source, 1, 2, "before");
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("UPDATE items SET name = 'after' WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Omit)
+        .unwrap();
+
+    assert_eq!(count_named(&mut replica, "after"), 0);
+}
+
+/// Helper to create an in-memory connection with an append-only `events` table.
+fn setup_events() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE events (id TEXT PRIMARY KEY, payload TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+/// Record inserts of events with the given ids on a fresh peer.
+fn changeset_appending(ids: &[&str]) -> Changeset {
+    let mut peer = setup_events();
+    let mut session = peer.create_session().unwrap();
+    session.attach_by_name("events").unwrap();
+    for id in ids {
+        sql_query(format!(
+            "INSERT INTO events (id, payload) VALUES ('{id}', 'event')"
+        ))
+        .execute(&mut peer)
+        .unwrap();
+    }
+    session.changeset().unwrap()
+}
+
+#[test]
+fn test_append_only_merges_disjoint_inserts() {
+    let first = changeset_appending(&[
+        "0b6c1f7e-3a51-4c9e-9d0f-2f4b8e1a7c01",
+        "0b6c1f7e-3a51-4c9e-9d0f-2f4b8e1a7c02",
+    ]);
+    let second = changeset_appending(&["5e2d9a44-81f3-4b7a-a6c2-9c1e0d3b4f03"]);
+    let options = ApplyOptions::new().append_only(true);
+
+    let mut replica = setup_events();
+    for changeset in [&first, &second] {
+        replica
+            .apply_changeset_with(changeset, &options, |_| ConflictAction::Abort)
+            .unwrap();
+    }
+
+    let count: i64 = sql::<BigInt>("SELECT COUNT(*) FROM events")
+        .get_result(&mut replica)
+        .unwrap();
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn test_append_only_rejects_duplicate_keys() {
+    let duplicate = "0b6c1f7e-3a51-4c9e-9d0f-2f4b8e1a7c01";
+    let first = changeset_appending(&[duplicate]);
+    let second = changeset_appending(&[duplicate]);
+    let options = ApplyOptions::new().append_only(true);
+
+    let mut replica = setup_events();
+    replica
+        .apply_changeset_with(&first, &options, |_| ConflictAction::Abort)
+        .unwrap();
+    let result = replica.apply_changeset_with(&second, &options, |_| ConflictAction::Replace);
+
+    match result {
+        Err(ApplyError::DuplicatePrimaryKey { table, primary_key }) => {
+            assert_eq!(table, "events");
+            assert_eq!(
+                primary_key,
+                [SqliteValue::Text(duplicate.as_bytes().to_vec())]
+            );
+        }
+        other => panic!("expected a duplicate key error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_reapplying_changeset_is_noop() {
+    let changeset = changeset_inserting(3);
+
+    let mut replica = setup_connection();
+    let first = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Omit)
+        .unwrap();
+    let second = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Omit)
+        .unwrap();
+
+    assert_eq!(first.inserted(), 3);
+    assert!(!first.is_noop());
+    assert_eq!(second.conflicts(), 3);
+    assert_eq!(second.inserted(), 0);
+    assert!(second.is_noop());
+}
+
+#[test]
+fn test_empty_input_is_reported_in_stats() {
+    let mut replica = setup_connection();
+    let stats = replica
+        .apply_changeset_with(&Changeset::default(), &ApplyOptions::new(), |_| {
+            ConflictAction::Abort
+        })
+        .unwrap();
+
+    assert_eq!(stats, ApplyStats::empty());
+    assert!(stats.is_empty_input());
+    assert!(stats.is_noop());
+    assert_eq!(stats.inserted() + stats.updated() + stats.deleted(), 0);
+    assert_eq!(stats.conflicts(), 0);
+
+    // Input whose changes are all omitted is a no-op but not empty.
+    let changeset = changeset_inserting(1);
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    let stats = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Omit)
+        .unwrap();
+    assert!(stats.is_noop());
+    assert!(!stats.is_empty_input());
+}
+
+#[test]
+fn test_per_operation_isolation_skips_failing_changes() {
+    let changeset = changeset_inserting(3);
+    let replica_with_check = || {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        sql_query(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL, CHECK (id <> 1))",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        conn
+    };
+
+    // Applied together, the constraint violation rolls everything back.
+    let mut replica = replica_with_check();
+    let result = replica.apply_changeset(&changeset, |_| ConflictAction::Abort);
+    assert!(matches!(result, Err(ApplyError::ConflictAborted)));
+    assert_eq!(count_named(&mut replica, "source"), 0);
+
+    let mut replica = replica_with_check();
+    let stats = replica
+        .apply_changeset_with(
+            &changeset,
+            &ApplyOptions::new().per_operation_isolation(true),
+            |kind| {
+                assert_eq!(kind, ConflictType::Constraint);
+                ConflictAction::Abort
+            },
+        )
+        .unwrap();
+
+    assert_eq!(count_named(&mut replica, "source"), 2);
+    assert_eq!(stats.inserted(), 2);
+    assert_eq!(stats.conflicts(), 1);
+    assert_eq!(stats.failed().len(), 1);
+    let failed = &stats.failed()[0];
+    assert_eq!(failed.table(), "items");
+    assert_eq!(failed.op(), OpKind::Insert);
+    assert_eq!(failed.primary_key(), [SqliteValue::Integer(1)]);
+    assert_eq!(failed.error(), "Conflict handler requested abort");
+}
+
+#[test]
+fn test_apply_stats_count_each_kind() {
+    let mut source = setup_connection();
+    insert_items(&mut source, 0, 3, "before");
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    insert_items(&mut source, 3, 5, "source");
+    sql_query("UPDATE items SET name = 'after' WHERE id = 0")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM items WHERE id IN (1, 2)")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 3, "before");
+    let stats = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!(
+        (stats.inserted(), stats.updated(), stats.deleted()),
+        (2, 1, 2)
+    );
+    assert!(!stats.is_noop());
+}
+
+/// Helper to create a connection with `users` and `posts` linked by a foreign key.
+fn setup_blog(enforce_foreign_keys: bool) -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    let pragma = if enforce_foreign_keys { "ON" } else { "OFF" };
+    for statement in [
+        format!("PRAGMA foreign_keys = {pragma}"),
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)".to_owned(),
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL REFERENCES users(id))"
+            .to_owned(),
+    ] {
+        sql_query(statement).execute(&mut conn).unwrap();
+    }
+    conn
+}
+
+/// Record a post and, separately, the user it belongs to.
+fn post_then_user_changesets() -> (Changeset, Changeset) {
+    let mut source = setup_blog(false);
+    let mut record = |statement: &str| {
+        let mut session = source.create_session().unwrap();
+        session.attach_all().unwrap();
+        sql_query(statement).execute(&mut source).unwrap();
+        session.changeset().unwrap()
+    };
+    let post = record("INSERT INTO posts (id, user_id) VALUES (1, 1)");
+    let user = record("INSERT INTO users (id, name) VALUES (1, 'alice')");
+    (post, user)
+}
+
+#[test]
+fn test_defer_foreign_keys_accepts_child_before_parent() {
+    let (post, user) = post_then_user_changesets();
+    let options = ApplyOptions::new().defer_foreign_keys(true);
+
+    let mut replica = setup_blog(true);
+    replica
+        .transaction::<_, DieselError, _>(|conn| {
+            for changeset in [&post, &user] {
+                conn.apply_changeset_with(changeset, &options, |_| ConflictAction::Abort)
+                    .unwrap();
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    let posts: i64 = sql::<BigInt>("SELECT COUNT(*) FROM posts")
+        .get_result(&mut replica)
+        .unwrap();
+    assert_eq!(posts, 1);
+}
+
+#[test]
+fn test_child_before_parent_is_a_foreign_key_conflict_without_deferral() {
+    let (post, _) = post_then_user_changesets();
+
+    let mut replica = setup_blog(true);
+    let result = replica.apply_changeset_with(&post, &ApplyOptions::new(), |conflict| {
+        assert_eq!(conflict, ConflictType::ForeignKey);
+        ConflictAction::Abort
+    });
+
+    assert!(matches!(result, Err(ApplyError::ConflictAborted)));
+}
+
+#[test]
+fn test_deferred_foreign_key_violation_fails_at_commit() {
+    let (post, _) = post_then_user_changesets();
+    let options = ApplyOptions::new().defer_foreign_keys(true);
+
+    let mut replica = setup_blog(true);
+    let result = replica.transaction::<_, DieselError, _>(|conn| {
+        conn.apply_changeset_with(&post, &options, |_| ConflictAction::Abort)
+            .unwrap();
+        Ok(())
+    });
+
+    assert!(result.is_err());
+    let posts: i64 = sql::<BigInt>("SELECT COUNT(*) FROM posts")
+        .get_result(&mut replica)
+        .unwrap();
+    assert_eq!(posts, 0);
+}
+
+#[test]
+fn test_on_applied_reports_each_applied_change() {
+    let changeset = changeset_inserting(3);
+    let applied = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&applied);
+    let options = ApplyOptions::new().on_applied(move |change| {
+        sink.borrow_mut().push((
+            change.table().to_owned(),
+            change.op(),
+            change.new_values().to_vec(),
+        ));
+    });
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset_with(&changeset, &options, |_| ConflictAction::Abort)
+        .unwrap();
+
+    let mut applied = applied.take();
+    applied.sort_by_key(|(_, _, values)| match values.first() {
+        Some(Some(SqliteValue::Integer(id))) => *id,
+        other => panic!("unexpected primary key {other:?}"),
+    });
+    let expected: Vec<_> = (0..3)
+        .map(|id| {
+            (
+                "items".to_owned(),
+                OpKind::Insert,
+                vec![
+                    Some(SqliteValue::Integer(id)),
+                    Some(SqliteValue::Text(b"source".to_vec())),
+                ],
+            )
+        })
+        .collect();
+    assert_eq!(applied, expected);
+}
+
+#[test]
+fn test_on_applied_skips_omitted_changes() {
+    let changeset = changeset_inserting(3);
+    let applied = Rc::new(Cell::new(0_usize));
+    let counter = Rc::clone(&applied);
+    let options = ApplyOptions::new().on_applied(move |_| counter.set(counter.get() + 1));
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 1, 2, "replica");
+    replica
+        .apply_changeset_with(&changeset, &options, |_| ConflictAction::Omit)
+        .unwrap();
+
+    assert_eq!(applied.get(), 2);
+}
+
+#[test]
+fn test_collect_affected_keys_groups_primary_keys_by_table() {
+    let changeset = changeset_inserting_range(1, 4);
+    let options = ApplyOptions::new().collect_affected_keys(true);
+    let sorted_keys = |stats: &ApplyStats| {
+        let mut keys = stats.affected_keys()["items"].clone();
+        keys.sort_by_key(|key| key[0].as_i64());
+        keys
+    };
+
+    let mut replica = setup_connection();
+    let stats = replica
+        .apply_changeset_with(&changeset, &options, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(stats.affected_keys().len(), 1);
+    assert_eq!(
+        sorted_keys(&stats),
+        [
+            [SqliteValue::Integer(1)],
+            [SqliteValue::Integer(2)],
+            [SqliteValue::Integer(3)],
+        ]
+    );
+
+    // Omitted changes did not touch the replica.
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 2, 3, "replica");
+    let stats = replica
+        .apply_changeset_with(&changeset, &options, |_| ConflictAction::Omit)
+        .unwrap();
+    assert_eq!(
+        sorted_keys(&stats),
+        [[SqliteValue::Integer(1)], [SqliteValue::Integer(3)]]
+    );
+
+    let stats = setup_connection()
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Abort)
+        .unwrap();
+    assert!(stats.affected_keys().is_empty());
+}
+
+/// Create a file-backed database with an `items` table and return its path.
+fn file_database(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "diesel-sqlite-session-{name}-{}.db",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let mut conn = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    path
+}
+
+/// Hold a write lock on the database at `path` for `hold`, returning once the
+/// lock is taken.
+fn lock_database(path: &Path, hold: Duration) -> thread::JoinHandle<()> {
+    let url = path.to_str().unwrap().to_owned();
+    let (locked, wait) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut conn = SqliteConnection::establish(&url).unwrap();
+        sql_query("BEGIN IMMEDIATE").execute(&mut conn).unwrap();
+        locked.send(()).unwrap();
+        thread::sleep(hold);
+        sql_query("COMMIT").execute(&mut conn).unwrap();
+    });
+    wait.recv().unwrap();
+    handle
+}
+
+#[test]
+fn test_busy_retry_succeeds_once_lock_is_released() {
+    let changeset = changeset_inserting(3);
+    let path = file_database("busy-retry-succeeds");
+    let mut replica = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+
+    let holder = lock_database(&path, Duration::from_millis(100));
+    let options = ApplyOptions::new().busy_retry(10, Duration::from_millis(20));
+    replica
+        .apply_changeset_with(&changeset, &options, |_| ConflictAction::Abort)
+        .unwrap();
+    holder.join().unwrap();
+
+    assert_eq!(count_named(&mut replica, "source"), 3);
+    drop(replica);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_busy_retry_gives_up_after_max_attempts() {
+    let changeset = changeset_inserting(3);
+    let path = file_database("busy-retry-gives-up");
+    let mut replica = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+
+    let holder = lock_database(&path, Duration::from_secs(2));
+    let options = ApplyOptions::new().busy_retry(3, Duration::from_millis(10));
+    let result = replica.apply_changeset_with(&changeset, &options, |_| ConflictAction::Abort);
+    assert!(matches!(
+        result,
+        Err(ApplyError::ApplyFailed(SqliteErrorCode::Busy))
+    ));
+    holder.join().unwrap();
+
+    assert_eq!(count_named(&mut replica, "source"), 0);
+    drop(replica);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_target_db_applies_into_attached_database() {
+    let changeset = changeset_inserting(3);
+    let path = file_database("target-db");
+    let mut replica = setup_connection();
+    sql_query(format!("ATTACH DATABASE '{}' AS aux", path.display()))
+        .execute(&mut replica)
+        .unwrap();
+
+    let options = ApplyOptions::new().target_db("aux");
+    replica
+        .apply_changeset_with(&changeset, &options, |_| ConflictAction::Abort)
+        .unwrap();
+
+    let in_aux: i64 = sql::<BigInt>("SELECT COUNT(*) FROM aux.items")
+        .get_result(&mut replica)
+        .unwrap();
+    assert_eq!(in_aux, 3);
+    assert_eq!(count_named(&mut replica, "source"), 0);
+    drop(replica);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_target_db_rejects_databases_without_a_file() {
+    let changeset = changeset_inserting(1);
+    let mut replica = setup_connection();
+
+    let options = ApplyOptions::new().target_db("temp");
+    let result = replica.apply_changeset_with(&changeset, &options, |_| ConflictAction::Abort);
+    assert!(matches!(
+        result,
+        Err(ApplyError::TargetDatabaseUnavailable { schema }) if schema == "temp"
+    ));
+}
+
+#[test]
+fn test_total_rows_changed_counts_every_applied_row() {
+    let mut source = setup_connection();
+    insert_items(&mut source, 0, 2, "source");
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    insert_items(&mut source, 2, 7, "source");
+    sql_query("DELETE FROM items WHERE id < 2")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 2, "source");
+    let stats = replica
+        .apply_changeset_with(&changeset, &ApplyOptions::new(), |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!((stats.inserted(), stats.deleted()), (5, 2));
+    assert_eq!(stats.total_rows_changed(), 7);
+}
+
+#[test]
+fn test_apply_changeset_verbose_returns_the_raw_result_code() {
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("INSERT INTO items (id, name) VALUES (1, NULL)")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    // The replica declares `name` NOT NULL, so the insert violates a constraint.
+    let mut replica = setup_connection();
+    let seen = Cell::new(None);
+    let result = replica.apply_changeset_verbose(&changeset, &ApplyOptions::new(), |kind| {
+        seen.set(Some(kind));
+        ConflictAction::Abort
+    });
+
+    assert_eq!(seen.get(), Some(ConflictType::Constraint));
+    let (err, rc) = result.unwrap_err();
+    assert!(matches!(err, ApplyError::ConflictAborted));
+    // SQLITE_ABORT
+    assert_eq!(rc, 4);
+    assert_eq!(count_named(&mut replica, "source"), 0);
+
+    let stats = replica
+        .apply_changeset_verbose(&changeset_inserting(2), &ApplyOptions::new(), |_| {
+            ConflictAction::Abort
+        })
+        .unwrap();
+    assert_eq!(stats.inserted(), 2);
+}
+
+#[test]
+fn test_optimize_small_matches_default_apply() {
+    let single = changeset_inserting(1);
+    let several = changeset_inserting(5);
+
+    for changeset in [&single, &several] {
+        let mut default = setup_connection();
+        let default_stats = default
+            .apply_changeset_with(changeset, &ApplyOptions::new(), |_| ConflictAction::Abort)
+            .unwrap();
+        let mut small = setup_connection();
+        let small_stats = small
+            .apply_changeset_with(changeset, &ApplyOptions::new().optimize_small(true), |_| {
+                ConflictAction::Abort
+            })
+            .unwrap();
+
+        assert_eq!(small_stats, default_stats);
+        assert_eq!(
+            count_named(&mut small, "source"),
+            count_named(&mut default, "source")
+        );
+    }
+
+    // A single aborted change still leaves the replica untouched.
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 1, "replica");
+    let result =
+        replica.apply_changeset_with(&single, &ApplyOptions::new().optimize_small(true), |_| {
+            ConflictAction::Abort
+        });
+    assert!(matches!(result, Err(ApplyError::ConflictAborted)));
+    assert_eq!(count_named(&mut replica, "replica"), 1);
+}
+
+#[test]
+fn test_optimize_small_keeps_a_named_savepoint() {
+    let single = changeset_inserting(1);
+    let options = ApplyOptions::new()
+        .optimize_small(true)
+        .savepoint_name("sync");
+
+    // Replacing row 0 deletes it before the insert is rejected, which only the
+    // savepoint undoes.
+    let mut replica = setup_connection();
+    insert_items(&mut replica, 0, 1, "replica");
+    sql_query(
+        "CREATE TRIGGER reject_source BEFORE INSERT ON items WHEN NEW.name = 'source' \
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+    )
+    .execute(&mut replica)
+    .unwrap();
+    let result = replica.apply_changeset_with(&single, &options, |conflict| {
+        if conflict == ConflictType::Conflict {
+            ConflictAction::Replace
+        } else {
+            ConflictAction::Abort
+        }
+    });
+
+    assert!(result.is_err());
+    assert_eq!(count_named(&mut replica, "replica"), 1);
+}
+
+#[test]
+fn test_merged_stats_sum_a_batch_of_applies() {
+    let first = changeset_inserting_range(0, 3);
+    // Row 2 is also in the first changeset, so it conflicts.
+    let second = changeset_inserting_range(2, 5);
+
+    let mut replica = setup_connection();
+    let batch: Vec<ApplyStats> = [first, second]
+        .iter()
+        .map(|changeset| {
+            replica
+                .apply_changeset_with(changeset, &ApplyOptions::new(), |_| ConflictAction::Omit)
+                .unwrap()
+        })
+        .collect();
+    assert_eq!((batch[0].inserted(), batch[0].conflicts()), (3, 0));
+    assert_eq!((batch[1].inserted(), batch[1].conflicts()), (2, 1));
+
+    let mut merged = batch[0].clone();
+    merged.merge(&batch[1]);
+    assert_eq!(merged.inserted(), 5);
+    assert_eq!(merged.conflicts(), 1);
+    assert_eq!(merged.total_rows_changed(), 5);
+    assert!(!merged.is_empty_input());
+
+    let total: ApplyStats = batch.into_iter().sum();
+    assert_eq!(total, merged);
+    assert_eq!(std::iter::empty().sum::<ApplyStats>(), ApplyStats::empty());
+}
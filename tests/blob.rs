@@ -0,0 +1,63 @@
+//! Tests for storing changesets in `BLOB` columns with the `blob` feature.
+#![cfg(feature = "blob")]
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel_sqlite_session::{Changeset, ConflictAction, SqliteSessionExt};
+
+diesel::table! {
+    outbox (id) {
+        id -> Integer,
+        data -> Binary,
+    }
+}
+
+diesel::table! {
+    notes (id) {
+        id -> Integer,
+        body -> Text,
+    }
+}
+
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE outbox (id INTEGER PRIMARY KEY, data BLOB NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+#[test]
+fn test_changeset_roundtrips_through_blob_column() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<notes::table>().unwrap();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'queued'), (2, 'also queued')")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.flush().unwrap();
+
+    diesel::insert_into(outbox::table)
+        .values(outbox::data.eq(&changeset))
+        .execute(&mut source)
+        .unwrap();
+    let stored: Changeset = outbox::table
+        .select(outbox::data)
+        .first(&mut source)
+        .unwrap();
+    assert_eq!(stored, changeset);
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&stored, |_| ConflictAction::Abort)
+        .unwrap();
+    let bodies: Vec<String> = notes::table
+        .select(notes::body)
+        .order(notes::id)
+        .load(&mut replica)
+        .unwrap();
+    assert_eq!(bodies, ["queued", "also queued"]);
+}
@@ -0,0 +1,204 @@
+//! Tests for synthesizing changesets from typed operations.
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use diesel_sqlite_session::{
+    diff_rows, read_changeset, Changeset, ChangesetBuilder, ChangesetError, ConflictAction,
+    SqliteSessionExt, SqliteValue,
+};
+
+/// Helper to create an in-memory connection with an `items` table.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+fn fetch_items(conn: &mut SqliteConnection) -> Vec<(i32, String)> {
+    sql::<(Integer, Text)>("SELECT id, name FROM items ORDER BY id")
+        .load(conn)
+        .unwrap()
+}
+
+fn text(value: &str) -> SqliteValue {
+    SqliteValue::Text(value.as_bytes().to_vec())
+}
+
+#[test]
+fn test_built_inserts_apply() {
+    let mut builder = ChangesetBuilder::new();
+    builder.table("items", &[true, false]);
+    builder
+        .insert("items", vec![SqliteValue::Integer(1), text("one")])
+        .unwrap()
+        .insert("items", vec![SqliteValue::Integer(2), text("two")])
+        .unwrap();
+    let changeset = builder.build();
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!(
+        fetch_items(&mut replica),
+        [(1, "one".to_owned()), (2, "two".to_owned())]
+    );
+}
+
+#[test]
+fn test_built_update_and_delete_apply() {
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO items (id, name) VALUES (1, 'before'), (2, 'gone')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut builder = ChangesetBuilder::new();
+    builder.table("items", &[true, false]);
+    builder
+        .update(
+            "items",
+            vec![Some(SqliteValue::Integer(1)), Some(text("before"))],
+            vec![None, Some(text("after"))],
+        )
+        .unwrap()
+        .delete("items", vec![SqliteValue::Integer(2), text("gone")])
+        .unwrap();
+
+    replica
+        .apply_changeset(&builder.build(), |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), [(1, "after".to_owned())]);
+}
+
+#[test]
+fn test_changeset_display_summarizes_operations() {
+    let mut builder = ChangesetBuilder::new();
+    builder
+        .table("items", &[true, false])
+        .table("tags", &[true]);
+    builder
+        .insert("items", vec![SqliteValue::Integer(1), text("one")])
+        .unwrap()
+        .insert("items", vec![SqliteValue::Integer(2), text("two")])
+        .unwrap()
+        .insert("tags", vec![text("new")])
+        .unwrap()
+        .delete("items", vec![SqliteValue::Integer(3), text("three")])
+        .unwrap();
+    let changeset = builder.build();
+
+    assert_eq!(
+        changeset.to_string(),
+        format!(
+            "Changeset{{tables: [items, tags], inserts: 3, updates: 0, deletes: 1, bytes: {}}}",
+            changeset.len()
+        )
+    );
+    assert_eq!(
+        Changeset::default().to_string(),
+        "Changeset{tables: [], inserts: 0, updates: 0, deletes: 0, bytes: 0}"
+    );
+
+    let truncated = Changeset::from_bytes(changeset[..changeset.len() - 3].to_vec());
+    assert!(truncated.to_string().ends_with(", malformed}"));
+}
+
+#[test]
+fn test_builder_rejects_invalid_operations() {
+    let mut builder = ChangesetBuilder::new();
+    assert!(matches!(
+        builder.insert("items", vec![SqliteValue::Integer(1)]),
+        Err(ChangesetError::UnknownTable(table)) if table == "items"
+    ));
+
+    builder.table("items", &[true, false]);
+    assert!(matches!(
+        builder.insert("items", vec![SqliteValue::Integer(1)]),
+        Err(ChangesetError::ColumnCountMismatch {
+            expected: 2,
+            found: 1,
+            ..
+        })
+    ));
+    assert!(matches!(
+        builder.update(
+            "items",
+            vec![None, Some(text("a"))],
+            vec![None, Some(text("b"))]
+        ),
+        Err(ChangesetError::MissingPrimaryKey(_))
+    ));
+}
+
+#[test]
+fn test_collecting_read_operations_reproduces_changeset() {
+    let mut source = setup_connection();
+    sql_query("INSERT INTO items (id, name) VALUES (1, 'before'), (2, 'gone')")
+        .execute(&mut source)
+        .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("INSERT INTO items (id, name) VALUES (3, 'new')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("UPDATE items SET name = 'after' WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM items WHERE id = 2")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let rebuilt: Changeset = read_changeset(&changeset)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(rebuilt, changeset);
+}
+
+#[test]
+fn test_diff_rows_turns_old_rows_into_new_rows() {
+    let old = vec![
+        vec![SqliteValue::Integer(1), text("kept")],
+        vec![SqliteValue::Integer(2), text("before")],
+        vec![SqliteValue::Integer(3), text("removed")],
+    ];
+    let new = vec![
+        vec![SqliteValue::Integer(1), text("kept")],
+        vec![SqliteValue::Integer(2), text("after")],
+        vec![SqliteValue::Integer(4), text("added")],
+    ];
+    let changeset = diff_rows("items", &[true, false], &old, &new).unwrap();
+    assert_eq!(read_changeset(&changeset).unwrap().count(), 3);
+
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO items (id, name) VALUES (1, 'kept'), (2, 'before'), (3, 'removed')")
+        .execute(&mut replica)
+        .unwrap();
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!(
+        fetch_items(&mut replica),
+        [
+            (1, "kept".to_owned()),
+            (2, "after".to_owned()),
+            (4, "added".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_rows_of_identical_rows_is_empty() {
+    let rows = vec![vec![SqliteValue::Integer(1), text("same")]];
+    assert!(diff_rows("items", &[true, false], &rows, &rows)
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,117 @@
+//! Tests for combining changesets with `ChangeGroup`.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Text};
+use diesel_sqlite_session::{ChangeGroup, Changeset, ConflictAction, SqliteSessionExt};
+
+/// Helper to create an in-memory connection with a `notes` table.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+/// Run `statements` on `conn` while recording them, returning the changeset.
+fn record(conn: &mut SqliteConnection, statements: &[&str]) -> Changeset {
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    for statement in statements {
+        sql_query(*statement).execute(conn).unwrap();
+    }
+    session.changeset().unwrap()
+}
+
+/// A file in the temporary directory, removed on drop.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn with_contents(name: &str, contents: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "diesel-sqlite-session-{}-{name}",
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn test_add_from_reader_merges_file_backed_changesets() {
+    let mut source = setup_connection();
+    let first = record(
+        &mut source,
+        &[
+            "INSERT INTO notes (id, body) VALUES (1, 'draft')",
+            "INSERT INTO notes (id, body) VALUES (2, 'keep')",
+        ],
+    );
+    let second = record(
+        &mut source,
+        &[
+            "UPDATE notes SET body = 'final' WHERE id = 1",
+            "INSERT INTO notes (id, body) VALUES (3, 'new')",
+        ],
+    );
+    let first = TempFile::with_contents("first.changeset", &first);
+    let second = TempFile::with_contents("second.changeset", &second);
+
+    let mut group = ChangeGroup::new().unwrap();
+    group
+        .add_from_reader(File::open(&first.0).unwrap())
+        .unwrap();
+    group
+        .add_from_reader(File::open(&second.0).unwrap())
+        .unwrap();
+    let mut merged = Vec::new();
+    group.output_to_writer(&mut merged).unwrap();
+
+    // The update of row 1 is folded into its insert.
+    assert_eq!(merged, group.output().unwrap().into_bytes());
+
+    let mut replica = setup_connection();
+    replica
+        .apply_bytes(&merged, |_| ConflictAction::Abort)
+        .unwrap();
+
+    let bodies: Vec<String> = sql::<Text>("SELECT body FROM notes ORDER BY id")
+        .load(&mut replica)
+        .unwrap();
+    assert_eq!(bodies, ["final", "keep", "new"]);
+}
+
+#[test]
+fn test_add_combines_insert_and_delete_into_nothing() {
+    let mut source = setup_connection();
+    let inserted = record(
+        &mut source,
+        &["INSERT INTO notes (id, body) VALUES (1, 'temporary')"],
+    );
+    let deleted = record(&mut source, &["DELETE FROM notes WHERE id = 1"]);
+
+    let mut group = ChangeGroup::new().unwrap();
+    group.add(&inserted).unwrap();
+    group.add(&deleted).unwrap();
+    let merged = group.output().unwrap();
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&merged, |_| ConflictAction::Abort)
+        .unwrap();
+    let count: i64 = sql::<BigInt>("SELECT COUNT(*) FROM notes")
+        .get_result(&mut replica)
+        .unwrap();
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,134 @@
+//! Tests for checking changesets against a replica schema.
+
+use std::cell::RefCell;
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel_sqlite_session::{
+    check_changeset_compatible, predict_conflicts, Changeset, ConflictAction, ConflictType,
+    Incompatibility, OpKind, PredictedConflict, SqliteSessionExt,
+};
+
+/// Helper to create an in-memory connection running the given schema.
+fn connection_with(schema: &[&str]) -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    for statement in schema {
+        sql_query(*statement).execute(&mut conn).unwrap();
+    }
+    conn
+}
+
+const ITEMS: &str = "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, price REAL)";
+const TAGS: &str = "CREATE TABLE tags (id INTEGER PRIMARY KEY, label TEXT)";
+
+/// Record one insert into each of `items` and `tags`.
+fn changeset() -> Changeset {
+    let mut source = connection_with(&[ITEMS, TAGS]);
+    let mut session = source.create_session().unwrap();
+    session.attach_all().unwrap();
+    sql_query("INSERT INTO items (id, name, price) VALUES (1, 'pen', 1.5)")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("INSERT INTO tags (id, label) VALUES (1, 'office')")
+        .execute(&mut source)
+        .unwrap();
+    session.changeset().unwrap()
+}
+
+#[test]
+fn test_compatible_replica_passes() {
+    let mut replica = connection_with(&[ITEMS, TAGS]);
+    check_changeset_compatible(&mut replica, &changeset()).unwrap();
+}
+
+#[test]
+fn test_wider_tables_are_compatible() {
+    let mut replica = connection_with(&[
+        "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, price REAL, stock INTEGER)",
+        TAGS,
+    ]);
+    check_changeset_compatible(&mut replica, &changeset()).unwrap();
+}
+
+#[test]
+fn test_incompatible_replica_reports_each_problem() {
+    let mut replica = connection_with(&["CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)"]);
+
+    let report = check_changeset_compatible(&mut replica, &changeset()).unwrap_err();
+
+    match report.issues() {
+        [Incompatibility::ColumnCountMismatch {
+            table,
+            expected,
+            found,
+        }, Incompatibility::MissingTable(missing)] => {
+            assert_eq!(table, "items");
+            assert_eq!((*expected, *found), (3, 2));
+            assert_eq!(missing, "tags");
+        }
+        other => panic!("unexpected issues {other:?}"),
+    }
+}
+
+#[test]
+fn test_predict_conflicts_on_diverged_replica() {
+    let mut source = connection_with(&[ITEMS]);
+    sql_query("INSERT INTO items (id, name, price) VALUES (2, 'ink', 3.0), (3, 'cap', 0.5)")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("INSERT INTO items (id, name, price) VALUES (1, 'pen', 1.5)")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("UPDATE items SET price = 4.0 WHERE id = 2")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM items WHERE id = 3")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    // Row 1 already exists, row 2 is missing and row 3 was edited.
+    let mut replica = connection_with(&[ITEMS]);
+    sql_query("INSERT INTO items (id, name, price) VALUES (1, 'pen', 1.5), (3, 'lid', 0.5)")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut predicted = predict_conflicts(&mut replica, &changeset).unwrap();
+    predicted.sort_by_key(|conflict| conflict.primary_key()[0].as_i64());
+    let summary: Vec<_> = predicted
+        .iter()
+        .map(|conflict| (conflict.op(), conflict.kind(), conflict.table()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (OpKind::Insert, ConflictType::Conflict, "items"),
+            (OpKind::Update, ConflictType::NotFound, "items"),
+            (OpKind::Delete, ConflictType::Data, "items"),
+        ]
+    );
+
+    // The prediction matches what SQLite reports on apply.
+    let reported = RefCell::new(Vec::new());
+    replica
+        .apply_changeset(&changeset, |kind| {
+            reported.borrow_mut().push(kind);
+            ConflictAction::Omit
+        })
+        .unwrap();
+    let mut reported = reported.into_inner();
+    reported.sort_by_key(|kind| *kind as i32);
+    let mut expected: Vec<_> = predicted.iter().map(PredictedConflict::kind).collect();
+    expected.sort_by_key(|kind| *kind as i32);
+    assert_eq!(reported, expected);
+}
+
+#[test]
+fn test_predict_conflicts_on_matching_replica_is_empty() {
+    let mut replica = connection_with(&[ITEMS, TAGS]);
+    assert!(predict_conflicts(&mut replica, &changeset())
+        .unwrap()
+        .is_empty());
+}
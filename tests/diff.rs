@@ -0,0 +1,94 @@
+//! Tests for diffing tables across attached databases.
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use diesel_sqlite_session::{changeset_between, ConflictAction, SessionError, SqliteSessionExt};
+
+const BEFORE_ROWS: &str = "(1, 'kept'), (2, 'renamed'), (3, 'deleted')";
+const AFTER_ROWS: &str = "(1, 'kept'), (2, 'new name'), (4, 'inserted')";
+
+fn execute(conn: &mut SqliteConnection, statement: &str) {
+    sql_query(statement).execute(conn).unwrap();
+}
+
+/// Helper to create a connection with `items` in `main` holding the "after"
+/// rows and in the attached `before` database holding the "before" rows.
+fn setup_snapshots() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    execute(&mut conn, "ATTACH DATABASE ':memory:' AS before");
+    for schema in ["main", "before"] {
+        execute(
+            &mut conn,
+            &format!("CREATE TABLE {schema}.items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)"),
+        );
+    }
+    execute(
+        &mut conn,
+        &format!("INSERT INTO before.items VALUES {BEFORE_ROWS}"),
+    );
+    execute(
+        &mut conn,
+        &format!("INSERT INTO main.items VALUES {AFTER_ROWS}"),
+    );
+    conn
+}
+
+fn names(conn: &mut SqliteConnection) -> Vec<String> {
+    sql::<Text>("SELECT name FROM items ORDER BY id")
+        .load(conn)
+        .unwrap()
+}
+
+#[test]
+fn test_changeset_between_transforms_one_snapshot_into_the_other() {
+    let mut conn = setup_snapshots();
+
+    let changeset = changeset_between(&mut conn, "before", "main", &["items"]).unwrap();
+
+    let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    execute(
+        &mut replica,
+        "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+    );
+    execute(
+        &mut replica,
+        &format!("INSERT INTO items VALUES {BEFORE_ROWS}"),
+    );
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!(names(&mut replica), names(&mut conn));
+}
+
+#[test]
+fn test_diff_of_identical_tables_is_empty() {
+    let mut conn = setup_snapshots();
+    execute(&mut conn, "DELETE FROM before.items");
+    execute(
+        &mut conn,
+        &format!("INSERT INTO before.items VALUES {AFTER_ROWS}"),
+    );
+
+    let changeset = changeset_between(&mut conn, "before", "main", &["items"]).unwrap();
+
+    assert!(changeset.is_empty());
+}
+
+#[test]
+fn test_diff_reports_mismatched_schemas() {
+    let mut conn = setup_snapshots();
+    execute(&mut conn, "DROP TABLE before.items");
+    execute(
+        &mut conn,
+        "CREATE TABLE before.items (id INTEGER PRIMARY KEY, name TEXT, extra TEXT)",
+    );
+
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    let result = session.diff("before", "items");
+
+    assert!(matches!(result, Err(SessionError::DiffFailed { .. })));
+}
@@ -2,9 +2,17 @@
 //!
 //! These tests verify end-to-end functionality of the session extension.
 
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void};
+use std::io::{self, Write};
+use std::ptr;
+
 use diesel::prelude::*;
 use diesel::sql_query;
-use diesel_sqlite_session::{ApplyError, ConflictAction, SessionError, SqliteSessionExt};
+use diesel_sqlite_session::{
+    apply_and_recapture, read_changeset, sqlite_version, ApplyError, Changeset, ConflictAction,
+    ConflictType, OpKind, Patchset, SessionConfig, SessionError, SqliteSessionExt, SqliteValue,
+};
 
 diesel::table! {
     items (id) {
@@ -43,6 +51,13 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    aux.notes (id) {
+        id -> Integer,
+        body -> Text,
+    }
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = items)]
 struct NewItem<'a> {
@@ -98,6 +113,15 @@ fn setup_connection() -> SqliteConnection {
     conn
 }
 
+/// Helper to read all items ordered by primary key.
+fn fetch_items(conn: &mut SqliteConnection) -> Vec<(i32, String, Option<i32>)> {
+    items::table
+        .select((items::id, items::name, items::quantity))
+        .order_by(items::id.asc())
+        .load(conn)
+        .unwrap()
+}
+
 #[test]
 fn test_full_replication_workflow() {
     // Source database
@@ -623,6 +647,77 @@ fn test_session_disable_reenable() {
     assert_eq!(count, 0);
 }
 
+#[test]
+fn test_pause_guard_suspends_tracking_until_dropped() {
+    let mut conn = setup_connection();
+
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (1, 'Tracked', 10)")
+        .execute(&mut conn)
+        .unwrap();
+    {
+        let _paused = session.pause();
+        sql_query("INSERT INTO items (id, name, quantity) VALUES (2, 'NotTracked', 20)")
+            .execute(&mut conn)
+            .unwrap();
+    }
+    assert!(session.tracking_state());
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (3, 'AlsoTracked', 30)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+
+    let ids: Vec<i32> = items::table
+        .select(items::id)
+        .order(items::id)
+        .load(&mut replica)
+        .unwrap();
+    assert_eq!(ids, vec![1, 3]);
+}
+
+#[test]
+fn test_pause_guard_keeps_disabled_session_disabled() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.set_enabled(false);
+
+    drop(session.pause());
+
+    assert!(!session.tracking_state());
+}
+
+#[test]
+fn test_try_set_enabled_reports_resulting_state() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+
+    assert!(!session.try_set_enabled(false));
+    assert!(session.try_set_enabled(true));
+    assert!(!session.try_set_enabled(false));
+}
+
+#[test]
+fn test_tracking_state_reflects_sqlite_state() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    assert!(session.tracking_state());
+
+    session.set_enabled(false);
+    assert!(!session.tracking_state());
+    // Querying does not change the state.
+    assert!(!session.tracking_state());
+
+    session.set_enabled(true);
+    assert!(session.tracking_state());
+}
+
 #[test]
 fn test_large_batch_changes() {
     let mut source = setup_connection();
@@ -756,3 +851,1372 @@ fn test_selective_table_tracking() {
     assert_eq!(tracked_count, 1);
     assert_eq!(untracked_count, 0);
 }
+
+#[test]
+fn test_attach_from_query_tracks_the_queried_table() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE untracked (id INTEGER PRIMARY KEY, val TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    let query = users::table.filter(users::id.gt(0)).select(users::username);
+    assert_eq!(session.attach_from_query(&query).unwrap(), ["users"]);
+
+    sql_query("INSERT INTO users (id, username) VALUES (1, 'alice')")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("INSERT INTO untracked (id, val) VALUES (1, 'no')")
+        .execute(&mut conn)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let tables: Vec<String> = read_changeset(&changeset)
+        .unwrap()
+        .map(|op| op.unwrap().table().to_owned())
+        .collect();
+    assert_eq!(tables, ["users"]);
+}
+
+/// Authorizer refusing every delete.
+unsafe extern "C" fn deny_deletes(
+    _context: *mut c_void,
+    action: c_int,
+    _table: *const c_char,
+    _column: *const c_char,
+    _schema: *const c_char,
+    _trigger_or_view: *const c_char,
+) -> c_int {
+    if action == libsqlite3_sys::SQLITE_DELETE {
+        libsqlite3_sys::SQLITE_DENY
+    } else {
+        libsqlite3_sys::SQLITE_OK
+    }
+}
+
+#[test]
+fn test_attach_from_query_keeps_the_connection_authorizer() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    // SAFETY: `with_raw_connection` provides a valid handle, and the
+    // authorizer takes no context.
+    unsafe {
+        conn.with_raw_connection(|db| {
+            libsqlite3_sys::sqlite3_set_authorizer(db, Some(deny_deletes), ptr::null_mut());
+        });
+    }
+
+    let mut session = conn.create_session().unwrap();
+    let query = users::table.select(users::id);
+    assert_eq!(session.attach_from_query(&query).unwrap(), ["users"]);
+    drop(session);
+
+    assert!(diesel::delete(users::table).execute(&mut conn).is_err());
+}
+
+#[test]
+fn test_apply_with_undo_restores_prior_state() {
+    let seed = [
+        NewItem {
+            id: 1,
+            name: "Keep",
+            quantity: Some(1),
+        },
+        NewItem {
+            id: 2,
+            name: "Drop",
+            quantity: Some(2),
+        },
+    ];
+
+    let mut source = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&seed)
+        .execute(&mut source)
+        .unwrap();
+    let mut replica = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&seed)
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 3,
+            name: "New",
+            quantity: None,
+        })
+        .execute(&mut source)
+        .unwrap();
+    diesel::update(items::table.filter(items::id.eq(1)))
+        .set(items::quantity.eq(10))
+        .execute(&mut source)
+        .unwrap();
+    diesel::delete(items::table.filter(items::id.eq(2)))
+        .execute(&mut source)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let before = fetch_items(&mut replica);
+
+    let undo = replica
+        .apply_with_undo(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), fetch_items(&mut source));
+
+    replica
+        .apply_changeset(&undo, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), before);
+}
+
+#[test]
+fn test_apply_with_undo_rejects_patchset_without_applying() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Item",
+            quantity: Some(1),
+        })
+        .execute(&mut source)
+        .unwrap();
+    // Patchset bytes mislabelled as a changeset are still rejected.
+    let mislabelled = Changeset::from_bytes(session.patchset().unwrap().into_bytes());
+
+    let mut replica = setup_connection();
+    let result = replica.apply_with_undo(&mislabelled, |_| ConflictAction::Abort);
+
+    assert!(matches!(result, Err(ApplyError::Changeset(_))));
+    assert!(fetch_items(&mut replica).is_empty());
+}
+
+#[test]
+fn test_apply_with_scoped_undo_skips_omitted_changes() {
+    let seed = [
+        NewItem {
+            id: 1,
+            name: "Keep",
+            quantity: Some(1),
+        },
+        NewItem {
+            id: 2,
+            name: "Drop",
+            quantity: Some(2),
+        },
+    ];
+
+    let mut source = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&seed)
+        .execute(&mut source)
+        .unwrap();
+    let mut replica = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&seed)
+        .execute(&mut replica)
+        .unwrap();
+    // The replica edited row 2 locally, so deleting it conflicts.
+    diesel::update(items::table.filter(items::id.eq(2)))
+        .set(items::quantity.eq(20))
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 3,
+            name: "New",
+            quantity: None,
+        })
+        .execute(&mut source)
+        .unwrap();
+    diesel::update(items::table.filter(items::id.eq(1)))
+        .set(items::quantity.eq(10))
+        .execute(&mut source)
+        .unwrap();
+    diesel::delete(items::table.filter(items::id.eq(2)))
+        .execute(&mut source)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let before = fetch_items(&mut replica);
+
+    let (stats, undo) = replica
+        .apply_with_scoped_undo(&changeset, |_| ConflictAction::Omit)
+        .unwrap();
+    assert_eq!(stats.conflicts(), 1);
+    assert_eq!(stats.omitted().len(), 1);
+    assert_eq!(fetch_items(&mut replica).len(), 3);
+
+    // Every change in the undo must apply cleanly: none targets the omitted row.
+    replica
+        .apply_changeset(&undo, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), before);
+}
+
+#[test]
+fn test_user_tables_lists_tables_without_internal_ones() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    // AUTOINCREMENT makes SQLite create its internal `sqlite_sequence` table.
+    sql_query("CREATE TABLE orders (id INTEGER PRIMARY KEY AUTOINCREMENT, total INTEGER)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE customers (id INTEGER PRIMARY KEY, name TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE audit (entry TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE VIEW big_orders AS SELECT * FROM orders WHERE total > 100")
+        .execute(&mut conn)
+        .unwrap();
+
+    assert_eq!(
+        conn.user_tables().unwrap(),
+        vec!["audit", "customers", "orders"]
+    );
+}
+
+#[test]
+fn test_changeset_fails_after_schema_change() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Item",
+            quantity: None,
+        })
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("ALTER TABLE items ADD COLUMN price INTEGER")
+        .execute(&mut conn)
+        .unwrap();
+
+    let result = session.changeset();
+    assert!(
+        matches!(
+            result,
+            Err(SessionError::SchemaChangedDuringSession { attached, current })
+                if current > attached
+        ),
+        "unexpected result: {result:?}"
+    );
+    assert!(matches!(
+        session.patchset(),
+        Err(SessionError::SchemaChangedDuringSession { .. })
+    ));
+}
+
+#[test]
+fn test_patchset_conflicts_never_report_data() {
+    let mut source = setup_connection();
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Item",
+            quantity: Some(1),
+        })
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+    diesel::update(items::table.filter(items::id.eq(1)))
+        .set(items::name.eq("Renamed"))
+        .execute(&mut source)
+        .unwrap();
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 2,
+            name: "Second",
+            quantity: None,
+        })
+        .execute(&mut source)
+        .unwrap();
+    let patchset = session.patchset().unwrap();
+
+    // Both rows exist on the replica with different values.
+    let mut replica = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&[
+            NewItem {
+                id: 1,
+                name: "Local",
+                quantity: Some(5),
+            },
+            NewItem {
+                id: 2,
+                name: "Local second",
+                quantity: Some(6),
+            },
+        ])
+        .execute(&mut replica)
+        .unwrap();
+
+    let seen = RefCell::new(Vec::new());
+    replica
+        .apply_patchset(&patchset, |kind| {
+            seen.borrow_mut().push(kind);
+            ConflictAction::Omit
+        })
+        .unwrap();
+
+    // The update overwrites the diverged row; only the insert conflicts.
+    assert_eq!(seen.into_inner(), [ConflictType::Conflict]);
+    assert!(!Patchset::can_report(ConflictType::Data));
+    assert_eq!(
+        fetch_items(&mut replica),
+        [
+            (1, "Renamed".to_owned(), Some(5)),
+            (2, "Local second".to_owned(), Some(6)),
+        ]
+    );
+}
+
+#[test]
+fn test_apply_and_recapture_reflects_resolved_values() {
+    let seed = NewItem {
+        id: 1,
+        name: "Item",
+        quantity: Some(1),
+    };
+    let mut source = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&seed)
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+    diesel::update(items::table.filter(items::id.eq(1)))
+        .set(items::quantity.eq(10))
+        .execute(&mut source)
+        .unwrap();
+    let incoming = session.changeset().unwrap();
+
+    // The relay's row diverged, so the update conflicts and is replaced.
+    let mut relay = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&seed)
+        .execute(&mut relay)
+        .unwrap();
+    diesel::update(items::table.filter(items::id.eq(1)))
+        .set(items::quantity.eq(5))
+        .execute(&mut relay)
+        .unwrap();
+
+    let forward = apply_and_recapture(&mut relay, &incoming, &["items"], |_| {
+        ConflictAction::Replace
+    })
+    .unwrap();
+
+    let ops: Vec<_> = read_changeset(&forward)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].old_values()[2], Some(SqliteValue::Integer(5)));
+    assert_eq!(ops[0].new_values()[2], Some(SqliteValue::Integer(10)));
+    assert_eq!(fetch_items(&mut relay), [(1, "Item".to_owned(), Some(10))]);
+}
+
+/// Writer that only counts the bytes it receives.
+#[derive(Default)]
+struct CountingWriter(usize);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer that rejects every write.
+struct FailingWriter;
+
+impl Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("writer closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_streamed_output_matches_in_memory_output() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    let new_items: Vec<NewItem> = (0..500)
+        .map(|i| NewItem {
+            id: i,
+            name: "Streamed",
+            quantity: Some(i),
+        })
+        .collect();
+    diesel::insert_into(items::table)
+        .values(&new_items)
+        .execute(&mut source)
+        .unwrap();
+
+    let mut streamed_changeset = Vec::new();
+    session
+        .changeset_to_writer(&mut streamed_changeset)
+        .unwrap();
+    assert_eq!(streamed_changeset, session.changeset().unwrap().as_bytes());
+
+    let mut streamed_patchset = Vec::new();
+    session.patchset_to_writer(&mut streamed_patchset).unwrap();
+    assert_eq!(streamed_patchset, session.patchset().unwrap().as_bytes());
+}
+
+#[test]
+fn test_streamed_output_reports_writer_errors() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Item",
+            quantity: None,
+        })
+        .execute(&mut source)
+        .unwrap();
+
+    let result = session.changeset_to_writer(FailingWriter);
+    assert!(matches!(result, Err(SessionError::Io(_))));
+}
+
+#[test]
+#[ignore = "allocates several GiB to exceed the i32 size limit of the in-memory API"]
+fn test_streamed_changeset_has_no_i32_size_ceiling() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE blobs (id INTEGER PRIMARY KEY, data BLOB)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("blobs").unwrap();
+    for id in 0..3 {
+        sql_query(format!(
+            "INSERT INTO blobs (id, data) VALUES ({id}, zeroblob(900000000))"
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    }
+
+    let mut writer = CountingWriter::default();
+    session.changeset_to_writer(&mut writer).unwrap();
+    assert!(writer.0 > usize::try_from(i32::MAX).unwrap());
+
+    // The in-memory path cannot represent a buffer this large.
+    assert!(matches!(
+        session.changeset(),
+        Err(SessionError::ChangesetFailed(_))
+    ));
+}
+
+#[test]
+fn test_changeset_len_and_changeset_into_match_changeset() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(&[
+            NewItem {
+                id: 1,
+                name: "First",
+                quantity: Some(1),
+            },
+            NewItem {
+                id: 2,
+                name: "Second",
+                quantity: None,
+            },
+        ])
+        .execute(&mut source)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let len = session.changeset_len().unwrap();
+    assert_eq!(len, changeset.len());
+
+    let mut buf = vec![0_u8; len];
+    let written = session.changeset_into(&mut buf).unwrap();
+    assert_eq!(written, len);
+    assert_eq!(buf, changeset.as_bytes());
+
+    let mut short = vec![0_u8; len - 1];
+    assert!(matches!(
+        session.changeset_into(&mut short),
+        Err(SessionError::BufferTooSmall { capacity }) if capacity == len - 1
+    ));
+}
+
+#[test]
+fn test_changeset_reserve_produces_identical_bytes() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    let names: Vec<String> = (0..2000).map(|i| format!("item-{i:0>64}")).collect();
+    let rows: Vec<_> = names
+        .iter()
+        .enumerate()
+        .map(|(id, name)| NewItem {
+            id: i32::try_from(id).unwrap(),
+            name,
+            quantity: Some(7),
+        })
+        .collect();
+    diesel::insert_into(items::table)
+        .values(&rows)
+        .execute(&mut conn)
+        .unwrap();
+
+    let plain = session.changeset().unwrap();
+    session.changeset_reserve(plain.len());
+    let reserved = session.changeset().unwrap();
+    session.changeset_reserve(0);
+
+    assert_eq!(reserved, plain);
+    assert_eq!(session.changeset().unwrap(), plain);
+}
+
+#[test]
+fn test_session_debug_shows_tables_and_state() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(&NewItem {
+            id: 1,
+            name: "Debugged",
+            quantity: None,
+        })
+        .execute(&mut conn)
+        .unwrap();
+
+    let debug = format!("{session:?}");
+    assert!(debug.contains("empty: false"), "{debug}");
+    assert!(debug.contains("enabled: true"), "{debug}");
+    assert!(debug.contains(r#"tables: ["items"]"#), "{debug}");
+}
+
+#[test]
+fn test_attachment_summary_reports_empty_attached_session() {
+    let mut conn = setup_connection();
+    sql_query("CREATE TABLE tracked (id INTEGER PRIMARY KEY, val TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    let mut session = conn.create_session().unwrap();
+    assert!(!session.attachment_summary().is_attached());
+
+    session.attach::<items::table>().unwrap();
+    session.attach::<tracked::table>().unwrap();
+
+    let summary = session.attachment_summary();
+    assert_eq!(summary.attached_tables(), 2);
+    assert!(summary.is_attached());
+    assert!(!summary.tracks_all_tables());
+    assert!(!summary.has_changes());
+}
+
+#[test]
+fn test_trackable_tables_exclude_tables_without_primary_key() {
+    let mut conn = setup_connection();
+    sql_query("CREATE TABLE audit_log (at TEXT, message TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE tags (name TEXT PRIMARY KEY) WITHOUT ROWID")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    session.attach_all().unwrap();
+    assert_eq!(session.trackable_tables().unwrap(), ["items", "tags"]);
+
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("audit_log").unwrap();
+    session.attach_by_name("missing").unwrap();
+    session.attach::<items::table>().unwrap();
+    assert_eq!(session.trackable_tables().unwrap(), ["items"]);
+}
+
+#[test]
+fn test_temp_session_tracks_temporary_tables() {
+    let mut conn = setup_connection();
+    sql_query("CREATE TEMP TABLE scratch (id INTEGER PRIMARY KEY, note TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut main_session = conn.create_session().unwrap();
+    main_session.attach_all().unwrap();
+    let mut session = conn.create_session_for("temp").unwrap();
+    session.attach_by_name("scratch").unwrap();
+
+    sql_query("INSERT INTO scratch (id, note) VALUES (1, 'temporary')")
+        .execute(&mut conn)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let ops: Vec<_> = read_changeset(&changeset)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].table(), "scratch");
+    assert!(main_session.is_empty());
+}
+
+/// Error type for closures run by `transaction_with_session`.
+#[derive(Debug)]
+#[allow(dead_code)]
+enum TxError {
+    Diesel(diesel::result::Error),
+    Session(SessionError),
+    Rejected,
+}
+
+impl From<diesel::result::Error> for TxError {
+    fn from(err: diesel::result::Error) -> Self {
+        Self::Diesel(err)
+    }
+}
+
+impl From<SessionError> for TxError {
+    fn from(err: SessionError) -> Self {
+        Self::Session(err)
+    }
+}
+
+#[test]
+fn test_transaction_with_session_captures_committed_changes() {
+    let mut conn = setup_connection();
+    let changeset = conn
+        .transaction_with_session::<items::table, _, TxError>(|conn, _session| {
+            diesel::insert_into(items::table)
+                .values(&NewItem {
+                    id: 1,
+                    name: "Committed",
+                    quantity: None,
+                })
+                .execute(conn)?;
+            Ok(())
+        })
+        .unwrap();
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(
+        fetch_items(&mut replica),
+        [(1, "Committed".to_owned(), None)]
+    );
+}
+
+#[test]
+fn test_transaction_with_session_rolls_back_on_error() {
+    let mut conn = setup_connection();
+    let result = conn.transaction_with_session::<items::table, _, TxError>(|conn, _session| {
+        diesel::insert_into(items::table)
+            .values(&NewItem {
+                id: 1,
+                name: "Discarded",
+                quantity: None,
+            })
+            .execute(conn)?;
+        Err(TxError::Rejected)
+    });
+
+    assert!(matches!(result, Err(TxError::Rejected)));
+    assert!(fetch_items(&mut conn).is_empty());
+}
+
+#[test]
+fn test_flush_returns_each_batch_once() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    let insert = |conn: &mut SqliteConnection, id: i32| {
+        diesel::insert_into(items::table)
+            .values(&NewItem {
+                id,
+                name: "Batched",
+                quantity: None,
+            })
+            .execute(conn)
+            .unwrap();
+    };
+    let ids = |changeset: &[u8]| -> Vec<i64> {
+        read_changeset(changeset)
+            .unwrap()
+            .map(|op| {
+                op.unwrap().new_values()[0]
+                    .as_ref()
+                    .unwrap()
+                    .as_i64()
+                    .unwrap()
+            })
+            .collect()
+    };
+
+    insert(&mut conn, 1);
+    let first = session.flush().unwrap();
+    assert!(session.is_empty());
+
+    insert(&mut conn, 2);
+    let second = session.flush().unwrap();
+
+    assert_eq!(ids(&first), [1]);
+    assert_eq!(ids(&second), [2]);
+    assert!(session.flush().unwrap().is_empty());
+    assert_eq!(session.attachment_summary().attached_tables(), 1);
+}
+
+#[test]
+fn test_flush_falls_back_to_snapshot_above_threshold() {
+    let mut conn = setup_connection();
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (1, 'a', 0), (2, 'b', 0), (3, 'c', 0), (4, 'd', 0), (5, 'e', 0)")
+        .execute(&mut conn)
+        .unwrap();
+    let mut session = conn.create_session().unwrap().with_snapshot_fallback(3);
+    session.attach::<items::table>().unwrap();
+    let kinds = |changeset: &[u8]| -> Vec<OpKind> {
+        read_changeset(changeset)
+            .unwrap()
+            .map(|op| op.unwrap().op())
+            .collect()
+    };
+
+    // At the threshold the flush stays incremental.
+    sql_query("UPDATE items SET quantity = 1 WHERE id <= 3")
+        .execute(&mut conn)
+        .unwrap();
+    assert_eq!(kinds(&session.flush().unwrap()), [OpKind::Update; 3]);
+
+    // One change more and the whole table is returned instead.
+    sql_query("UPDATE items SET quantity = 2 WHERE id <= 4")
+        .execute(&mut conn)
+        .unwrap();
+    let snapshot = session.flush().unwrap();
+    assert_eq!(kinds(&snapshot), [OpKind::Insert; 5]);
+    assert!(session.is_empty());
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&snapshot, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), fetch_items(&mut conn));
+}
+
+#[test]
+fn test_into_changeset_consumes_session_and_replicates() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(&NewItem {
+            id: 1,
+            name: "Final",
+            quantity: Some(7),
+        })
+        .execute(&mut source)
+        .unwrap();
+
+    let changeset = session.into_changeset().unwrap();
+
+    let mut target = setup_connection();
+    target
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(
+        fetch_items(&mut target),
+        vec![(1, "Final".to_owned(), Some(7))]
+    );
+}
+
+#[test]
+fn test_into_patchset_consumes_session_and_replicates() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(&NewItem {
+            id: 1,
+            name: "Final",
+            quantity: Some(7),
+        })
+        .execute(&mut source)
+        .unwrap();
+
+    let patchset: Patchset = session.into_patchset().unwrap();
+
+    let mut target = setup_connection();
+    target
+        .apply_patchset(&patchset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(
+        fetch_items(&mut target),
+        vec![(1, "Final".to_owned(), Some(7))]
+    );
+}
+
+#[test]
+fn test_attach_like_attaches_only_matching_tables() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    for table in ["events_2023", "events_2024", "users"] {
+        sql_query(format!(
+            "CREATE TABLE {table} (id INTEGER PRIMARY KEY, payload TEXT)"
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    }
+    sql_query("CREATE VIEW events_view AS SELECT * FROM events_2023")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    let attached = session.attach_like("events_%").unwrap();
+    assert_eq!(attached, ["events_2023", "events_2024"]);
+
+    for table in ["events_2023", "events_2024", "users"] {
+        sql_query(format!("INSERT INTO {table} (id, payload) VALUES (1, 'x')"))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let changeset = session.changeset().unwrap();
+    let mut tables: Vec<String> = read_changeset(&changeset)
+        .unwrap()
+        .map(|op| op.unwrap().table().to_owned())
+        .collect();
+    tables.sort();
+    assert_eq!(tables, ["events_2023", "events_2024"]);
+}
+
+#[test]
+fn test_attach_all_except_skips_denied_tables() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    for table in ["users", "posts", "audit"] {
+        sql_query(format!(
+            "CREATE TABLE {table} (id INTEGER PRIMARY KEY, payload TEXT)"
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    }
+
+    let mut session = conn.create_session().unwrap();
+    let attached = session.attach_all_except(&["audit"]).unwrap();
+    assert_eq!(attached, ["posts", "users"]);
+
+    for table in ["users", "posts", "audit"] {
+        sql_query(format!("INSERT INTO {table} (id, payload) VALUES (1, 'x')"))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let changeset = session.changeset().unwrap();
+    let mut tables: Vec<String> = read_changeset(&changeset)
+        .unwrap()
+        .map(|op| op.unwrap().table().to_owned())
+        .collect();
+    tables.sort();
+    assert_eq!(tables, ["posts", "users"]);
+}
+
+#[test]
+fn test_rowid_tables_are_ignored_by_default() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE t (a TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    assert!(!session.tracks_rowid_tables());
+    session.attach_by_name("t").unwrap();
+    sql_query("INSERT INTO t (a) VALUES ('x')")
+        .execute(&mut conn)
+        .unwrap();
+
+    assert!(session.trackable_tables().unwrap().is_empty());
+    assert!(session.changeset().unwrap().is_empty());
+}
+
+#[test]
+fn test_rowid_tables_replicate_by_rowid() {
+    use diesel::dsl::sql;
+    use diesel::sql_types::{BigInt, Text};
+
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    for conn in [&mut source, &mut replica] {
+        sql_query("CREATE TABLE t (a TEXT)").execute(conn).unwrap();
+    }
+
+    let mut session = source.create_session().unwrap();
+    session.track_rowid_tables(true).unwrap();
+    assert!(session.tracks_rowid_tables());
+    session.attach_by_name("t").unwrap();
+    assert_eq!(session.trackable_tables().unwrap(), ["t"]);
+    sql_query("INSERT INTO t (rowid, a) VALUES (5, 'five')")
+        .execute(&mut source)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let ops: Vec<_> = read_changeset(&changeset)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].column_count(), 2);
+    assert_eq!(ops[0].primary_key(), [true, false]);
+    assert_eq!(ops[0].new_values()[0], Some(SqliteValue::Integer(5)));
+    assert_eq!(ops[0].primary_key_values(), [SqliteValue::Integer(5)]);
+
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    let rows: Vec<(i64, String)> = sql::<(BigInt, Text)>("SELECT rowid, a FROM t")
+        .load(&mut replica)
+        .unwrap();
+    assert_eq!(rows, [(5, "five".to_owned())]);
+}
+
+#[test]
+fn test_track_rowid_tables_after_attach_fails() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    let result = session.track_rowid_tables(true);
+    assert!(matches!(result, Err(SessionError::ConfigFailed(_))));
+}
+
+#[test]
+fn test_changesets_by_table_split_the_recorded_changes() {
+    let create_tables = |conn: &mut SqliteConnection| {
+        sql_query("CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT)")
+            .execute(conn)
+            .unwrap();
+        sql_query("CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER, content TEXT)")
+            .execute(conn)
+            .unwrap();
+    };
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    create_tables(&mut source);
+
+    let mut session = source.create_session().unwrap();
+    session.attach::<users::table>().unwrap();
+    session.attach::<posts::table>().unwrap();
+    sql_query("INSERT INTO users (id, username) VALUES (1, 'alice'), (2, 'bob')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("INSERT INTO posts (id, user_id, content) VALUES (1, 1, 'hello')")
+        .execute(&mut source)
+        .unwrap();
+
+    let parts = session.changesets_by_table().unwrap();
+    assert_eq!(parts.len(), 2);
+    assert!(!session.is_empty());
+
+    for (table, changeset) in &parts {
+        assert!(read_changeset(changeset)
+            .unwrap()
+            .all(|op| op.unwrap().table() == table));
+
+        let mut replica = SqliteConnection::establish(":memory:").unwrap();
+        create_tables(&mut replica);
+        replica
+            .apply_changeset(changeset, |_| ConflictAction::Abort)
+            .unwrap();
+        let user_count: i64 = users::table.count().get_result(&mut replica).unwrap();
+        let post_count: i64 = posts::table.count().get_result(&mut replica).unwrap();
+        match table.as_str() {
+            "users" => assert_eq!((user_count, post_count), (2, 0)),
+            "posts" => assert_eq!((user_count, post_count), (0, 1)),
+            other => panic!("unexpected table {other}"),
+        }
+    }
+}
+
+#[test]
+fn test_snapshot_changeset_seeds_empty_replica() {
+    let mut source = setup_connection();
+    diesel::insert_into(items::table)
+        .values(&[
+            NewItem {
+                id: 1,
+                name: "Alpha",
+                quantity: Some(3),
+            },
+            NewItem {
+                id: 2,
+                name: "Beta",
+                quantity: None,
+            },
+        ])
+        .execute(&mut source)
+        .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+    let snapshot = session.snapshot_changeset().unwrap();
+    assert!(session.is_empty());
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&snapshot, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), fetch_items(&mut source));
+}
+
+#[test]
+fn test_snapshot_changeset_includes_rowid_tables_when_tracked() {
+    use diesel::dsl::sql;
+    use diesel::sql_types::{BigInt, Text};
+
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    for conn in [&mut source, &mut replica] {
+        sql_query("CREATE TABLE t (a TEXT)").execute(conn).unwrap();
+    }
+    sql_query("INSERT INTO t (rowid, a) VALUES (5, 'five'), (9, 'nine')")
+        .execute(&mut source)
+        .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("t").unwrap();
+    assert!(session.snapshot_changeset().unwrap().is_empty());
+
+    let mut session = source.create_session().unwrap();
+    session.track_rowid_tables(true).unwrap();
+    session.attach_by_name("t").unwrap();
+    let snapshot = session.snapshot_changeset().unwrap();
+    let ops: Vec<_> = read_changeset(&snapshot)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(ops.len(), 2);
+    assert_eq!(ops[0].primary_key(), [true, false]);
+
+    replica
+        .apply_changeset(&snapshot, |_| ConflictAction::Abort)
+        .unwrap();
+    let rows: Vec<(i64, String)> = sql::<(BigInt, Text)>("SELECT rowid, a FROM t ORDER BY rowid")
+        .load(&mut replica)
+        .unwrap();
+    assert_eq!(rows, [(5, "five".to_owned()), (9, "nine".to_owned())]);
+}
+
+#[test]
+fn test_upsert_snapshot_overwrites_stale_replica_rows() {
+    let mut source = setup_connection();
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (1, 'Alpha', 3), (2, 'Beta', NULL), (3, 'Gamma', 7)")
+        .execute(&mut source)
+        .unwrap();
+    let snapshot = source
+        .create_session()
+        .unwrap()
+        .upsert_snapshot("items")
+        .unwrap();
+
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (1, 'Stale', 0), (3, 'Gamma', 7)")
+        .execute(&mut replica)
+        .unwrap();
+    for _ in 0..2 {
+        replica
+            .apply_changeset(&snapshot, |kind| {
+                assert_eq!(kind, ConflictType::Conflict);
+                ConflictAction::Replace
+            })
+            .unwrap();
+        assert_eq!(fetch_items(&mut replica), fetch_items(&mut source));
+    }
+}
+
+#[test]
+fn test_changeset_since_mark_contains_only_later_changes() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Before",
+            quantity: None,
+        })
+        .execute(&mut conn)
+        .unwrap();
+    let mark = session.mark().unwrap();
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 2,
+            name: "After",
+            quantity: Some(1),
+        })
+        .execute(&mut conn)
+        .unwrap();
+
+    let since = session.changeset_since(&mark).unwrap();
+
+    let ops: Vec<_> = read_changeset(&since)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(
+        ops[0].primary_key_values(),
+        [diesel_sqlite_session::SqliteValue::Integer(2)]
+    );
+
+    // The session itself still holds every change.
+    assert_eq!(
+        read_changeset(&session.changeset().unwrap())
+            .unwrap()
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_changeset_since_mark_reports_net_update() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Draft",
+            quantity: None,
+        })
+        .execute(&mut conn)
+        .unwrap();
+    let mark = session.mark().unwrap();
+    diesel::update(items::table.find(1))
+        .set(items::name.eq("Final"))
+        .execute(&mut conn)
+        .unwrap();
+
+    let since = session.changeset_since(&mark).unwrap();
+
+    let mut replica = setup_connection();
+    diesel::insert_into(items::table)
+        .values(NewItem {
+            id: 1,
+            name: "Draft",
+            quantity: None,
+        })
+        .execute(&mut replica)
+        .unwrap();
+    replica
+        .apply_changeset(&since, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(fetch_items(&mut replica), fetch_items(&mut conn));
+}
+
+#[test]
+fn test_writes_through_instead_of_trigger_are_tracked_on_base_table() {
+    let mut conn = setup_connection();
+    sql_query("CREATE VIEW item_names AS SELECT id, name FROM items")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query(
+        "CREATE TRIGGER item_names_insert INSTEAD OF INSERT ON item_names BEGIN \
+         INSERT INTO items (id, name) VALUES (NEW.id, NEW.name); END",
+    )
+    .execute(&mut conn)
+    .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+    sql_query("INSERT INTO item_names (id, name) VALUES (1, 'Routed')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let ops: Vec<_> = read_changeset(&changeset)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].table(), "items");
+    assert!(ops[0].is_indirect());
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(
+        fetch_items(&mut replica),
+        vec![(1, "Routed".to_owned(), None)]
+    );
+}
+
+#[test]
+fn test_sqlite_version_matches_linked_library() {
+    let (number, version) = sqlite_version();
+    let parts: Vec<i32> = version
+        .split('.')
+        .map(|part| part.parse().unwrap())
+        .collect();
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0], 3);
+    assert_eq!(number, parts[0] * 1_000_000 + parts[1] * 1_000 + parts[2]);
+}
+
+#[test]
+fn test_changeset_chunks_concatenate_to_changeset() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+
+    let long_name = "x".repeat(4096);
+    for id in 1..=16 {
+        diesel::insert_into(items::table)
+            .values(NewItem {
+                id,
+                name: &long_name,
+                quantity: Some(id),
+            })
+            .execute(&mut source)
+            .unwrap();
+    }
+
+    let changeset = session.changeset().unwrap();
+    let chunks: Vec<Vec<u8>> = session.take_changeset_chunks().unwrap().collect();
+    assert!(chunks.len() > 1);
+    assert_eq!(chunks.concat(), changeset.as_bytes());
+}
+
+#[test]
+fn test_attach_schema_qualified_table() {
+    let mut conn = setup_connection();
+    sql_query("ATTACH DATABASE ':memory:' AS aux")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE aux.notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut main_session = conn.create_session().unwrap();
+    assert!(matches!(
+        main_session.attach::<notes::table>(),
+        Err(SessionError::SchemaMismatch { table, table_schema, session_schema })
+            if table == "notes" && table_schema == "aux" && session_schema == "main"
+    ));
+
+    let mut session = conn.create_session_for("aux").unwrap();
+    session.attach::<notes::table>().unwrap();
+    diesel::insert_into(notes::table)
+        .values((notes::id.eq(1), notes::body.eq("qualified")))
+        .execute(&mut conn)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+    let tables: Vec<String> = read_changeset(&changeset)
+        .unwrap()
+        .map(|op| op.unwrap().table().to_owned())
+        .collect();
+    assert_eq!(tables, ["notes"]);
+}
+
+#[test]
+fn test_session_config_sets_up_identical_sessions() {
+    let config = SessionConfig::new().attach_by_name("items").indirect(true);
+
+    let mut changesets = Vec::new();
+    for _ in 0..2 {
+        let mut conn = setup_connection();
+        let mut session = config.apply_to(&mut conn).unwrap();
+        assert_eq!(session.config(), config);
+        sql_query("INSERT INTO items (id, name, quantity) VALUES (1, 'Alpha', 3)")
+            .execute(&mut conn)
+            .unwrap();
+        changesets.push(session.changeset().unwrap());
+    }
+
+    assert!(!changesets[0].is_empty());
+    assert_eq!(changesets[0], changesets[1]);
+    let op = read_changeset(&changesets[0])
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(op.table(), "items");
+    assert!(op.is_indirect());
+}
+
+#[test]
+fn test_typed_sets_and_raw_bytes_apply_through_matching_methods() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<items::table>().unwrap();
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (1, 'One', 1)")
+        .execute(&mut source)
+        .unwrap();
+    let patchset = session.patchset().unwrap();
+    let changeset = session.flush().unwrap();
+    sql_query("INSERT INTO items (id, name, quantity) VALUES (2, 'Two', 2)")
+        .execute(&mut source)
+        .unwrap();
+    let raw = session.patchset().unwrap().into_bytes();
+
+    let count =
+        |conn: &mut SqliteConnection| -> i64 { items::table.count().get_result(conn).unwrap() };
+
+    let mut replica = setup_connection();
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(count(&mut replica), 1);
+
+    let mut replica = setup_connection();
+    replica
+        .apply_patchset(&patchset, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(count(&mut replica), 1);
+
+    let mut replica = setup_connection();
+    replica
+        .apply_bytes(changeset.as_bytes(), |_| ConflictAction::Abort)
+        .unwrap();
+    replica
+        .apply_bytes(&raw, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(count(&mut replica), 2);
+}
@@ -0,0 +1,375 @@
+//! Tests for reading the operations stored in changesets and patchsets.
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel_sqlite_session::{
+    invert_changeset, parse_changeset, read_changeset, read_changeset_inverted, ChangeOp,
+    ChangesetError, ChangesetIter, OpKind, SqliteSessionExt, SqliteValue,
+};
+
+/// Helper to create an in-memory connection with a `notes` table.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT, attachment BLOB)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+/// Helper to collect every operation of a changeset.
+fn collect_ops(changeset: &[u8]) -> Vec<ChangeOp> {
+    read_changeset(changeset)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn test_iterates_insert_with_typed_values() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+
+    sql_query("INSERT INTO notes (id, body, attachment) VALUES (1, 'héllo', x'00ff')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let ops = collect_ops(&changeset);
+    assert_eq!(ops.len(), 1);
+    let op = &ops[0];
+    assert_eq!(op.table(), "notes");
+    assert_eq!(op.op(), OpKind::Insert);
+    assert_eq!(op.primary_key(), [true, false, false]);
+    assert!(op.old_values().is_empty());
+
+    let new = op.new_values();
+    assert_eq!(new[0], Some(SqliteValue::Integer(1)));
+    assert_eq!(
+        new[1].as_ref().and_then(SqliteValue::text),
+        Some(Ok("héllo"))
+    );
+    assert_eq!(new[2], Some(SqliteValue::Blob(vec![0x00, 0xff])));
+}
+
+#[test]
+fn test_typed_accessors_read_changeset_values() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+
+    sql_query("INSERT INTO notes (id, body) VALUES (42, 'typed')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let ops = collect_ops(&changeset);
+    let new = ops[0].new_values();
+    assert_eq!(new[0].as_ref().and_then(SqliteValue::as_i64), Some(42));
+    assert_eq!(new[1].as_ref().and_then(SqliteValue::as_str), Some("typed"));
+    assert!(new[2].as_ref().is_some_and(SqliteValue::is_null));
+}
+
+#[test]
+fn test_invalid_utf8_text_is_reported_and_decoded_lossily() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+
+    // CAST stores the raw bytes as TEXT without validating the encoding.
+    sql_query("INSERT INTO notes (id, body) VALUES (1, CAST(x'6fff' AS TEXT))")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let ops = collect_ops(&changeset);
+    let body = ops[0].new_values()[1].clone().unwrap();
+    assert_eq!(body, SqliteValue::Text(vec![0x6f, 0xff]));
+    assert!(body.text().unwrap().is_err());
+    assert_eq!(body.text_lossy().unwrap(), "o\u{FFFD}");
+}
+
+#[test]
+fn test_iterates_update_and_delete_values() {
+    let mut conn = setup_connection();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'before'), (2, 'gone')")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query("UPDATE notes SET body = 'after' WHERE id = 1")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("DELETE FROM notes WHERE id = 2")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let ops = collect_ops(&changeset);
+    assert_eq!(ops.len(), 2);
+
+    let update = ops.iter().find(|op| op.op() == OpKind::Update).unwrap();
+    assert_eq!(update.old_values()[0], Some(SqliteValue::Integer(1)));
+    assert_eq!(
+        update.old_values()[1],
+        Some(SqliteValue::Text(b"before".to_vec()))
+    );
+    assert_eq!(
+        update.new_values()[1],
+        Some(SqliteValue::Text(b"after".to_vec()))
+    );
+    // The untouched column is not recorded by the update.
+    assert_eq!(update.new_values()[2], None);
+
+    let delete = ops.iter().find(|op| op.op() == OpKind::Delete).unwrap();
+    assert!(delete.new_values().is_empty());
+    assert_eq!(delete.old_values()[0], Some(SqliteValue::Integer(2)));
+    assert_eq!(
+        delete.old_values()[1],
+        Some(SqliteValue::Text(b"gone".to_vec()))
+    );
+}
+
+#[test]
+fn test_inverted_iteration_matches_materialized_inverse() {
+    let mut conn = setup_connection();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'before'), (2, 'gone')")
+        .execute(&mut conn)
+        .unwrap();
+
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query("UPDATE notes SET body = 'after' WHERE id = 1")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("DELETE FROM notes WHERE id = 2")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("INSERT INTO notes (id, body) VALUES (3, 'new')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let inverted: Vec<ChangeOp> = read_changeset_inverted(&changeset)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(inverted.len(), 3);
+    assert_eq!(
+        inverted,
+        collect_ops(&invert_changeset(&changeset).unwrap())
+    );
+
+    let kind_of = |id: i64| {
+        inverted
+            .iter()
+            .find(|op| {
+                let values = match op.op() {
+                    OpKind::Insert => op.new_values(),
+                    _ => op.old_values(),
+                };
+                values[0] == Some(SqliteValue::Integer(id))
+            })
+            .map(ChangeOp::op)
+    };
+    assert_eq!(kind_of(1), Some(OpKind::Update));
+    assert_eq!(kind_of(2), Some(OpKind::Insert));
+    assert_eq!(kind_of(3), Some(OpKind::Delete));
+}
+
+#[test]
+fn test_column_names_label_changeset_values() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'labelled')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let op = &collect_ops(&changeset)[0];
+    let names = ChangesetIter::column_names(&mut conn, op.table()).unwrap();
+    assert_eq!(names, ["id", "body", "attachment"]);
+    assert_eq!(names.len(), op.new_values().len());
+    assert!(ChangesetIter::column_names(&mut conn, "missing")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_empty_changeset_yields_no_operations() {
+    assert!(collect_ops(&[]).is_empty());
+}
+
+#[test]
+fn test_finish_reports_errors_met_while_iterating() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'first'), (2, 'second')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut iter = read_changeset(&changeset).unwrap();
+    assert_eq!(iter.by_ref().flatten().count(), 2);
+    iter.finish().unwrap();
+
+    // Patchsets cannot be inverted, which only shows once iteration starts.
+    let patchset = session.patchset().unwrap();
+    let mut iter = read_changeset_inverted(&patchset).unwrap();
+    assert!(iter.by_ref().any(|op| op.is_err()));
+    assert!(matches!(iter.finish(), Err(ChangesetError::IterFailed(_))));
+}
+
+#[test]
+fn test_truncated_changeset_is_rejected_before_iterating() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'first'), (2, 'second')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let truncated = &changeset[..changeset.len() - 3];
+    assert!(matches!(
+        read_changeset(truncated),
+        Err(ChangesetError::Malformed { .. })
+    ));
+    assert!(matches!(
+        read_changeset(b"T"),
+        Err(ChangesetError::Malformed { offset: 0 })
+    ));
+}
+
+#[test]
+fn test_parsed_changeset_can_be_iterated_repeatedly() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'first'), (2, 'second')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let parsed = parse_changeset(&changeset).unwrap();
+    let first_pass: Vec<&ChangeOp> = parsed.iter().collect();
+    let second_pass: Vec<&ChangeOp> = (&parsed).into_iter().collect();
+
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(first_pass, second_pass);
+    assert_eq!(parsed.clone().into_ops(), collect_ops(&changeset));
+}
+
+#[test]
+fn test_trigger_changes_are_indirect() {
+    let mut conn = setup_connection();
+    for statement in [
+        "CREATE TABLE audit (id INTEGER PRIMARY KEY, note_id INTEGER NOT NULL)",
+        "CREATE TRIGGER notes_audit AFTER INSERT ON notes \
+         BEGIN INSERT INTO audit (note_id) VALUES (new.id); END",
+    ] {
+        sql_query(statement).execute(&mut conn).unwrap();
+    }
+    let mut session = conn.create_session().unwrap();
+    session.attach_all().unwrap();
+
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'audited')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let flags: Vec<(String, bool)> = collect_ops(&changeset)
+        .iter()
+        .map(|op| (op.table().to_owned(), op.is_indirect()))
+        .collect();
+    assert_eq!(
+        flags,
+        [("notes".to_owned(), false), ("audit".to_owned(), true)]
+    );
+}
+
+#[test]
+fn test_set_indirect_marks_direct_changes() {
+    let mut conn = setup_connection();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    session.set_indirect(true);
+
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'flagged')")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    assert!(collect_ops(&changeset)[0].is_indirect());
+}
+
+#[test]
+fn test_skip_current_table_jumps_to_next_table() {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER)")
+        .execute(&mut conn)
+        .unwrap();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("users").unwrap();
+    session.attach_by_name("posts").unwrap();
+    sql_query("INSERT INTO users (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c')")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("INSERT INTO posts (id, user_id) VALUES (10, 1), (11, 2)")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut iter = read_changeset(&changeset).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().table(), "users");
+    iter.skip_current_table().unwrap();
+
+    let tables: Vec<String> = iter.map(|op| op.unwrap().table().to_owned()).collect();
+    assert_eq!(tables, ["posts", "posts"]);
+}
+
+#[test]
+fn test_current_rereads_last_yielded_update() {
+    let mut conn = setup_connection();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'before')")
+        .execute(&mut conn)
+        .unwrap();
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query("UPDATE notes SET body = 'after' WHERE id = 1")
+        .execute(&mut conn)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut iter = read_changeset(&changeset).unwrap();
+    assert_eq!(iter.current().unwrap(), None);
+
+    let yielded = iter.next().unwrap().unwrap();
+    let current = iter.current().unwrap().unwrap();
+    assert_eq!(current, yielded);
+    assert_eq!(current.op(), OpKind::Update);
+    assert!(!current.is_indirect());
+    assert_eq!(current.primary_key(), [true, false, false]);
+    assert_eq!(
+        current.old_values(),
+        [
+            Some(SqliteValue::Integer(1)),
+            Some(SqliteValue::Text(b"before".to_vec())),
+            None
+        ]
+    );
+    assert_eq!(
+        current.new_values(),
+        [None, Some(SqliteValue::Text(b"after".to_vec())), None]
+    );
+
+    assert!(iter.next().is_none());
+    assert_eq!(iter.current().unwrap(), None);
+}
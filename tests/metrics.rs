@@ -0,0 +1,53 @@
+//! Tests for the timing metrics of the `metrics` feature.
+#![cfg(feature = "metrics")]
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel_sqlite_session::{ConflictAction, SqliteSessionExt, APPLY_DURATION, CHANGESET_DURATION};
+use metrics::{SharedString, Unit};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use metrics_util::CompositeKey;
+
+const SCHEMA: &str = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)";
+
+type Entry = (CompositeKey, Option<Unit>, Option<SharedString>, DebugValue);
+
+/// Number of samples recorded in the histogram `name`.
+fn samples(snapshot: &[Entry], name: &str) -> usize {
+    snapshot
+        .iter()
+        .filter(|(key, ..)| key.key().name() == name)
+        .map(|(.., value)| match value {
+            DebugValue::Histogram(samples) => samples.len(),
+            other => panic!("{name} is not a histogram: {other:?}"),
+        })
+        .sum()
+}
+
+#[test]
+fn test_changeset_and_apply_durations_are_recorded() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    metrics::with_local_recorder(&recorder, || {
+        let mut source = SqliteConnection::establish(":memory:").unwrap();
+        sql_query(SCHEMA).execute(&mut source).unwrap();
+        let mut session = source.create_session().unwrap();
+        session.attach_by_name("users").unwrap();
+        sql_query("INSERT INTO users (id, name) VALUES (1, 'Alice')")
+            .execute(&mut source)
+            .unwrap();
+        let changeset = session.changeset().unwrap();
+
+        let mut replica = SqliteConnection::establish(":memory:").unwrap();
+        sql_query(SCHEMA).execute(&mut replica).unwrap();
+        replica
+            .apply_changeset(&changeset, |_| ConflictAction::Abort)
+            .unwrap();
+    });
+
+    // Histograms are drained by each snapshot, so take a single one.
+    let snapshot = snapshotter.snapshot().into_vec();
+    assert_eq!(samples(&snapshot, CHANGESET_DURATION), 1);
+    assert_eq!(samples(&snapshot, APPLY_DURATION), 1);
+}
@@ -1,8 +1,14 @@
 //! Property-based tests for replication invariants.
 
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
 use diesel::prelude::*;
 use diesel::sql_query;
-use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+use diesel_sqlite_session::{
+    read_changeset, Changeset, ConflictAction, Patchset, SqliteSessionExt,
+};
 
 diesel::table! {
     prop_items (id) {
@@ -144,3 +150,90 @@ fn changeset_and_patchset_replicate_the_same_final_state() {
         verify_replication_invariants(ops);
     });
 }
+
+/// Minimal xorshift generator, so the malformed inputs are reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_byte(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0.to_le_bytes()[0]
+    }
+}
+
+/// Read and apply `input` as a changeset and a patchset; returning at all is
+/// the property.
+fn apply_arbitrary(replica: &mut SqliteConnection, input: &[u8]) {
+    if let Ok(iter) = read_changeset(input) {
+        // Every operation takes at least two bytes, so this only stops an
+        // iterator that never ends.
+        assert!(iter.take(input.len() + 1).count() <= input.len());
+    }
+    let changeset = Changeset::from_bytes(input.to_vec());
+    let _ = replica.apply_changeset(&changeset, |_| ConflictAction::Omit);
+    let patchset = Patchset::from_bytes(input.to_vec());
+    let _ = replica.apply_patchset(&patchset, |_| ConflictAction::Omit);
+}
+
+#[test]
+fn malformed_input_is_rejected_without_panicking() {
+    // Run on another thread so an input that makes SQLite loop fails the
+    // test instead of hanging it.
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        apply_malformed_inputs();
+        done.send(()).unwrap();
+    });
+    match finished.recv_timeout(Duration::from_secs(60)) {
+        Ok(()) => {}
+        Err(RecvTimeoutError::Timeout) => panic!("malformed input did not return"),
+        Err(RecvTimeoutError::Disconnected) => panic!("malformed input panicked"),
+    }
+}
+
+fn apply_malformed_inputs() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach::<prop_items::table>().unwrap();
+    for operation in [
+        Operation::Put {
+            id: 1,
+            name: Some("alpha"),
+            quantity: Some(10),
+        },
+        Operation::Put {
+            id: 2,
+            name: Some("beta"),
+            quantity: None,
+        },
+        Operation::Delete { id: 1 },
+    ] {
+        apply_operation(&mut source, &operation);
+    }
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = setup_connection();
+    for len in 0..changeset.len() {
+        apply_arbitrary(&mut replica, &changeset[..len]);
+    }
+    for index in 0..changeset.len() {
+        for flip in [0x01, 0x80, 0xff] {
+            let mut corrupted = changeset.clone();
+            corrupted[index] ^= flip;
+            apply_arbitrary(&mut replica, &corrupted);
+        }
+    }
+
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    for len in [1, 2, 7, 16, 64, 512] {
+        for _ in 0..50 {
+            let mut input: Vec<u8> = (0..len).map(|_| rng.next_byte()).collect();
+            apply_arbitrary(&mut replica, &input);
+            // Keep a plausible table header so the parser gets further.
+            input[0] = b'T';
+            apply_arbitrary(&mut replica, &input);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Tests for rebasing local changesets over resolved conflicts.
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use diesel_sqlite_session::{
+    ApplyError, ApplyOptions, Changeset, ConflictAction, ConflictType, RebaseData, Rebaser,
+    SqliteSessionExt,
+};
+
+/// Helper to create an in-memory connection with one `notes` row.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("INSERT INTO notes (id, body) VALUES (1, 'original')")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+fn body_of(conn: &mut SqliteConnection) -> String {
+    sql::<Text>("SELECT body FROM notes WHERE id = 1")
+        .get_result(conn)
+        .unwrap()
+}
+
+/// Record `update` on `conn` and return the changeset.
+fn record(conn: &mut SqliteConnection, update: &str) -> Changeset {
+    let mut session = conn.create_session().unwrap();
+    session.attach_by_name("notes").unwrap();
+    sql_query(update).execute(conn).unwrap();
+    session.changeset().unwrap()
+}
+
+#[test]
+fn test_rebased_local_changes_apply_cleanly_to_remote() {
+    let mut remote = setup_connection();
+    let remote_changes = record(&mut remote, "UPDATE notes SET body = 'remote' WHERE id = 1");
+    let mut local = setup_connection();
+    let local_changes = record(&mut local, "UPDATE notes SET body = 'local' WHERE id = 1");
+
+    // The local edit wins: the remote change is omitted.
+    let (stats, resolutions) = local
+        .apply_changeset_rebasing(&remote_changes, &ApplyOptions::new(), |kind| {
+            assert_eq!(kind, ConflictType::Data);
+            ConflictAction::Omit
+        })
+        .unwrap();
+    assert_eq!(stats.conflicts(), 1);
+    assert!(!resolutions.is_empty());
+
+    // Without rebasing, the local change expects the original value.
+    let mut check = setup_connection();
+    sql_query("UPDATE notes SET body = 'remote' WHERE id = 1")
+        .execute(&mut check)
+        .unwrap();
+    let result = check.apply_changeset(&local_changes, |_| ConflictAction::Abort);
+    assert!(matches!(result, Err(ApplyError::ConflictAborted)));
+
+    let mut rebaser = Rebaser::new().unwrap();
+    rebaser
+        .configure(&RebaseData::from_bytes(resolutions.as_bytes().to_vec()))
+        .unwrap();
+    let to_send = rebaser.rebase(&local_changes).unwrap();
+
+    remote
+        .apply_changeset(&to_send, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(body_of(&mut remote), "local");
+    assert_eq!(body_of(&mut local), "local");
+}
+
+#[test]
+fn test_apply_without_conflicts_produces_empty_rebase_data() {
+    let mut remote = setup_connection();
+    let remote_changes = record(&mut remote, "UPDATE notes SET body = 'remote' WHERE id = 1");
+
+    let mut local = setup_connection();
+    let (_, rebase) = local
+        .apply_changeset_rebasing(&remote_changes, &ApplyOptions::new(), |_| {
+            ConflictAction::Abort
+        })
+        .unwrap();
+
+    assert!(rebase.is_empty());
+    assert_eq!(body_of(&mut local), "remote");
+}
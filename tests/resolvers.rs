@@ -0,0 +1,498 @@
+//! Tests for row-aware conflict resolvers.
+
+use std::sync::mpsc;
+use std::thread;
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use diesel_sqlite_session::{
+    read_changeset, ApplyError, ApplyOptions, Changeset, ChannelResolver, Conflict, ConflictAction,
+    ConflictPolicy, ConflictPrompt, ConflictType, DeadLetterResolver, OpKind, SqliteSessionExt,
+    SqliteValue, ThreeWayMerge, VersionCheck,
+};
+
+/// Helper to create an in-memory connection with a `people` table.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE people (id INTEGER PRIMARY KEY, name TEXT NOT NULL, city TEXT)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+fn name_of(conn: &mut SqliteConnection, id: i32) -> String {
+    sql::<Text>(&format!("SELECT name FROM people WHERE id = {id}"))
+        .get_result(conn)
+        .unwrap()
+}
+
+/// Record `update` against a source seeded with `seed` and return the changeset.
+fn changeset_updating(seed: &str, update: &str) -> Changeset {
+    let mut source = setup_connection();
+    sql_query(seed).execute(&mut source).unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("people").unwrap();
+    sql_query(update).execute(&mut source).unwrap();
+    session.changeset().unwrap()
+}
+
+#[test]
+fn test_resolver_closure_inspects_conflicting_row() {
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Alice')",
+        "UPDATE people SET name = 'Bob' WHERE id = 1",
+    );
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Carol')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut seen = Vec::new();
+    let mut resolver = |conflict: &Conflict<'_>| {
+        let change = conflict.change().unwrap();
+        let existing = conflict.existing_values().unwrap().unwrap();
+        seen.push((
+            conflict.kind(),
+            change.table().to_owned(),
+            existing[1].clone(),
+        ));
+        ConflictAction::Omit
+    };
+    replica
+        .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+        .unwrap();
+
+    assert_eq!(
+        seen,
+        [(
+            ConflictType::Data,
+            "people".to_owned(),
+            Some(SqliteValue::Text(b"Carol".to_vec()))
+        )]
+    );
+    assert_eq!(name_of(&mut replica, 1), "Carol");
+}
+
+#[test]
+fn test_nocase_policy_applies_case_only_differences() {
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Alice')",
+        "UPDATE people SET name = 'Bob' WHERE id = 1",
+    );
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'ALICE')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let stats = replica
+        .apply_changeset_resolving(
+            &changeset,
+            &ApplyOptions::new(),
+            &mut ConflictPolicy::nocase_text_equal_is_not_conflict(),
+        )
+        .unwrap();
+
+    assert_eq!(stats.conflicts(), 1);
+    assert_eq!(name_of(&mut replica, 1), "Bob");
+}
+
+#[test]
+fn test_nocase_policy_falls_back_on_real_differences() {
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Alice')",
+        "UPDATE people SET name = 'Bob' WHERE id = 1",
+    );
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Alicia')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let result = replica.apply_changeset_resolving(
+        &changeset,
+        &ApplyOptions::new(),
+        &mut ConflictPolicy::nocase_text_equal_is_not_conflict(),
+    );
+
+    assert!(matches!(result, Err(ApplyError::ConflictAborted)));
+    assert_eq!(name_of(&mut replica, 1), "Alicia");
+}
+
+#[test]
+fn test_apply_collecting_conflicts_returns_every_conflict() {
+    let mut source = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (4, 'Dan')")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("people").unwrap();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Ann'), (2, 'Ben'), (3, 'Cid')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("UPDATE people SET name = 'Dora' WHERE id = 4")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    // Rows 1 and 2 already exist and row 4 is missing.
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Xia'), (2, 'Yan')")
+        .execute(&mut replica)
+        .unwrap();
+    let (stats, conflicts) = replica
+        .apply_collecting_conflicts(&changeset, ConflictAction::Omit)
+        .unwrap();
+
+    let mut seen: Vec<_> = conflicts
+        .iter()
+        .map(|conflict| {
+            let change = conflict.change().unwrap();
+            (change.primary_key_values(), conflict.kind())
+        })
+        .collect();
+    seen.sort_by_key(|(pk, _)| pk[0].as_i64());
+    assert_eq!(
+        seen,
+        [
+            (vec![SqliteValue::Integer(1)], ConflictType::Conflict),
+            (vec![SqliteValue::Integer(2)], ConflictType::Conflict),
+            (vec![SqliteValue::Integer(4)], ConflictType::NotFound),
+        ]
+    );
+    assert_eq!(stats.conflicts(), 3);
+    assert_eq!(stats.inserted(), 1);
+    assert_eq!(name_of(&mut replica, 1), "Xia");
+
+    let existing = conflicts
+        .iter()
+        .find(|conflict| conflict.kind() == ConflictType::Conflict)
+        .and_then(|conflict| conflict.existing_values())
+        .unwrap();
+    assert!(matches!(
+        existing[1].as_ref().and_then(SqliteValue::as_str),
+        Some("Xia" | "Yan")
+    ));
+}
+
+#[test]
+fn test_field_level_merge_keeps_edits_to_different_columns() {
+    let setup_peer = || {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        sql_query(
+            "CREATE TABLE docs (id INTEGER PRIMARY KEY, title TEXT, body TEXT, version INTEGER)",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        sql_query("INSERT INTO docs VALUES (1, 'draft', 'empty', 1)")
+            .execute(&mut conn)
+            .unwrap();
+        conn
+    };
+
+    // Both peers bump the version, so the incoming update no longer matches.
+    let mut peer_a = setup_peer();
+    let mut session = peer_a.create_session().unwrap();
+    session.attach_by_name("docs").unwrap();
+    sql_query("UPDATE docs SET title = 'Final title', version = 2 WHERE id = 1")
+        .execute(&mut peer_a)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut peer_b = setup_peer();
+    sql_query("UPDATE docs SET body = 'Written body', version = 2 WHERE id = 1")
+        .execute(&mut peer_b)
+        .unwrap();
+
+    let stats = peer_b
+        .apply_changeset_resolving(
+            &changeset,
+            &ApplyOptions::new(),
+            &mut ConflictPolicy::field_level_merge(),
+        )
+        .unwrap();
+    assert_eq!(stats.conflicts(), 1);
+
+    let row: (String, String, i32) =
+        sql::<(Text, Text, diesel::sql_types::Integer)>("SELECT title, body, version FROM docs")
+            .get_result(&mut peer_b)
+            .unwrap();
+    assert_eq!(
+        row,
+        ("Final title".to_owned(), "Written body".to_owned(), 2)
+    );
+}
+
+#[test]
+fn test_dead_letter_resolver_collects_omitted_changes() {
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Alice'), (2, 'Bob'), (3, 'Carol')",
+        "UPDATE people SET name = name || '!'",
+    );
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Alicia'), (2, 'Bob'), (3, 'Caroline')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut resolver = DeadLetterResolver::new().unwrap();
+    let stats = replica
+        .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+        .unwrap();
+    assert_eq!(stats.conflicts(), 2);
+    assert_eq!(name_of(&mut replica, 1), "Alicia");
+    assert_eq!(name_of(&mut replica, 2), "Bob!");
+    assert_eq!(name_of(&mut replica, 3), "Caroline");
+
+    let dead_letter = resolver.into_dead_letter().unwrap();
+    let mut rejected: Vec<_> = read_changeset(&dead_letter)
+        .unwrap()
+        .map(|op| {
+            let op = op.unwrap();
+            assert_eq!(op.op(), OpKind::Update);
+            assert_eq!(op.table(), "people");
+            op.primary_key_values()
+        })
+        .collect();
+    rejected.sort_by_key(|pk| pk[0].as_i64());
+    assert_eq!(
+        rejected,
+        [[SqliteValue::Integer(1)], [SqliteValue::Integer(3)]]
+    );
+
+    // Once the conflicts are resolved, the dead letter applies cleanly.
+    sql_query("UPDATE people SET name = 'Alice' WHERE id = 1")
+        .execute(&mut replica)
+        .unwrap();
+    sql_query("UPDATE people SET name = 'Carol' WHERE id = 3")
+        .execute(&mut replica)
+        .unwrap();
+    replica
+        .apply_changeset(&dead_letter, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(name_of(&mut replica, 1), "Alice!");
+    assert_eq!(name_of(&mut replica, 3), "Carol!");
+}
+
+#[test]
+fn test_three_way_merge_uses_the_ancestor_to_pick_a_side() {
+    // The source edited row 1 and 3 before recording, so its old values no
+    // longer match the ancestor there.
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Caro')",
+        "UPDATE people SET name = CASE id WHEN 1 THEN 'Anna' WHEN 2 THEN 'Bobby' \
+         ELSE 'Caroline' END",
+    );
+    // The replica kept row 1 as in the ancestor and edited rows 2 and 3.
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Alice'), (2, 'Robert'), (3, 'Carrie')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut merge = ThreeWayMerge::new(|table: &str, pk: &[SqliteValue]| {
+        assert_eq!(table, "people");
+        let name = match pk[0].as_i64()? {
+            1 => "Alice",
+            2 => "Bob",
+            3 => "Carol",
+            _ => return None,
+        };
+        Some(vec![
+            Some(pk[0].clone()),
+            Some(SqliteValue::Text(name.as_bytes().to_vec())),
+            Some(SqliteValue::Null),
+        ])
+    })
+    .otherwise(ConflictAction::Omit);
+    let stats = replica
+        .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut merge)
+        .unwrap();
+
+    assert_eq!(stats.conflicts(), 3);
+    // Only the incoming side changed row 1, only the local side row 2.
+    assert_eq!(name_of(&mut replica, 1), "Anna");
+    assert_eq!(name_of(&mut replica, 2), "Robert");
+    // Both sides changed row 3.
+    assert_eq!(name_of(&mut replica, 3), "Carrie");
+    let unresolved = merge.unresolved();
+    assert_eq!(unresolved.len(), 1);
+    assert_eq!(
+        unresolved[0].change().unwrap().primary_key_values(),
+        [SqliteValue::Integer(3)]
+    );
+}
+
+#[test]
+fn test_channel_resolver_waits_for_the_ui_reply() {
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Alice')",
+        "UPDATE people SET name = 'Bob' WHERE id = 1",
+    );
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO people (id, name) VALUES (1, 'Carol')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let (prompt_tx, prompt_rx) = mpsc::channel();
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let ui = thread::spawn(move || {
+        prompt_rx
+            .iter()
+            .inspect(|_| reply_tx.send(ConflictAction::Replace).unwrap())
+            .collect::<Vec<ConflictPrompt>>()
+    });
+
+    let mut resolver = ChannelResolver::new(prompt_tx, reply_rx);
+    let stats = replica
+        .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+        .unwrap();
+    drop(resolver);
+    let prompts = ui.join().unwrap();
+
+    assert_eq!(stats.conflicts(), 1);
+    assert_eq!(prompts.len(), 1);
+    let prompt = &prompts[0];
+    assert_eq!(prompt.kind(), ConflictType::Data);
+    assert_eq!(prompt.table(), Some("people"));
+    assert_eq!(
+        prompt.new_values()[1],
+        Some(SqliteValue::Text(b"Bob".to_vec()))
+    );
+    assert_eq!(
+        prompt.existing_values().unwrap()[1],
+        Some(SqliteValue::Text(b"Carol".to_vec()))
+    );
+    assert_eq!(name_of(&mut replica, 1), "Bob");
+}
+
+#[test]
+fn test_version_check_rejects_stale_versions() {
+    let create = "CREATE TABLE documents (id INTEGER PRIMARY KEY, body TEXT, version INTEGER)";
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    sql_query(create).execute(&mut source).unwrap();
+    sql_query("INSERT INTO documents VALUES (1, 'draft', 1), (2, 'draft', 1)")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("documents").unwrap();
+    sql_query("UPDATE documents SET body = 'final', version = version + 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    // Row 1 diverged without a new version; row 2 has moved on to version 3.
+    let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    sql_query(create).execute(&mut replica).unwrap();
+    sql_query("INSERT INTO documents VALUES (1, 'retyped', 1), (2, 'rewritten', 3)")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut check = VersionCheck::new().table("documents", 2);
+    let stats = replica
+        .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut check)
+        .unwrap();
+
+    assert_eq!(stats.conflicts(), 2);
+    let body_of = |conn: &mut SqliteConnection, id: i32| -> String {
+        sql::<Text>(&format!("SELECT body FROM documents WHERE id = {id}"))
+            .get_result(conn)
+            .unwrap()
+    };
+    assert_eq!(body_of(&mut replica, 1), "final");
+    assert_eq!(body_of(&mut replica, 2), "rewritten");
+    let rejected = check.rejected();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(
+        rejected[0].change().unwrap().primary_key_values(),
+        [SqliteValue::Integer(2)]
+    );
+}
+
+#[test]
+fn test_version_check_rejects_stale_versions_in_patchsets() {
+    let create = "CREATE TABLE documents (id INTEGER PRIMARY KEY, body TEXT, version INTEGER)";
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    sql_query(create).execute(&mut source).unwrap();
+    sql_query("INSERT INTO documents VALUES (1, 'draft', 1), (2, 'draft', 1)")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("documents").unwrap();
+    sql_query("UPDATE documents SET body = 'final', version = version + 1")
+        .execute(&mut source)
+        .unwrap();
+    let patchset = session.patchset().unwrap();
+
+    // Row 1 is still at version 1; row 2 has moved on to version 3.
+    let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    sql_query(create).execute(&mut replica).unwrap();
+    sql_query("INSERT INTO documents VALUES (1, 'draft', 1), (2, 'rewritten', 3)")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut check = VersionCheck::new().table("documents", 2);
+    let stats = check
+        .apply(&mut replica, &patchset, &ApplyOptions::new())
+        .unwrap();
+
+    assert_eq!(stats.updated(), 1);
+    let body_of = |conn: &mut SqliteConnection, id: i32| -> String {
+        sql::<Text>(&format!("SELECT body FROM documents WHERE id = {id}"))
+            .get_result(conn)
+            .unwrap()
+    };
+    assert_eq!(body_of(&mut replica, 1), "final");
+    assert_eq!(body_of(&mut replica, 2), "rewritten");
+    let rejected = check.rejected();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].kind(), ConflictType::Data);
+    assert_eq!(
+        rejected[0].change().unwrap().primary_key_values(),
+        [SqliteValue::Integer(2)]
+    );
+    assert_eq!(
+        rejected[0].existing_values().unwrap()[2],
+        Some(SqliteValue::Integer(3))
+    );
+}
+
+#[test]
+fn test_existing_row_includes_columns_missing_from_the_changeset() {
+    let changeset = changeset_updating(
+        "INSERT INTO people (id, name) VALUES (1, 'Alice')",
+        "UPDATE people SET name = 'Bob' WHERE id = 1",
+    );
+    let mut replica = setup_connection();
+    sql_query("ALTER TABLE people ADD COLUMN notes TEXT")
+        .execute(&mut replica)
+        .unwrap();
+    sql_query("INSERT INTO people (id, name, notes) VALUES (1, 'Carol', 'keep me')")
+        .execute(&mut replica)
+        .unwrap();
+
+    let mut rows = Vec::new();
+    let mut resolver = |conflict: &Conflict<'_>| {
+        assert_eq!(conflict.existing_values().unwrap().unwrap().len(), 3);
+        let row = conflict.existing_row().unwrap().unwrap();
+        let annotated = !row[3].is_null();
+        rows.push(row);
+        if annotated {
+            ConflictAction::Omit
+        } else {
+            ConflictAction::Replace
+        }
+    };
+    replica
+        .apply_changeset_resolving(&changeset, &ApplyOptions::new(), &mut resolver)
+        .unwrap();
+
+    assert_eq!(
+        rows,
+        [vec![
+            SqliteValue::Integer(1),
+            SqliteValue::Text(b"Carol".to_vec()),
+            SqliteValue::Null,
+            SqliteValue::Text(b"keep me".to_vec()),
+        ]]
+    );
+    assert_eq!(name_of(&mut replica, 1), "Carol");
+}
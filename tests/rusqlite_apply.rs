@@ -0,0 +1,33 @@
+//! Tests for applying changesets to `rusqlite` connections.
+#![cfg(feature = "rusqlite")]
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel_sqlite_session::{apply_changeset_raw, ConflictAction, SqliteSessionExt};
+
+const SCHEMA: &str = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)";
+
+#[test]
+fn test_diesel_changeset_applies_to_rusqlite_connection() {
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    sql_query(SCHEMA).execute(&mut source).unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("users").unwrap();
+    sql_query("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob')")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let replica = rusqlite::Connection::open_in_memory().unwrap();
+    replica.execute(SCHEMA, []).unwrap();
+    apply_changeset_raw(&replica, &changeset, |_| ConflictAction::Abort).unwrap();
+
+    let names: Vec<String> = replica
+        .prepare("SELECT name FROM users ORDER BY id")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(names, ["Alice", "Bob"]);
+}
@@ -0,0 +1,172 @@
+//! Tests for applying changesets streamed from a reader.
+
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::BigInt;
+use diesel_sqlite_session::{ChangesetFrame, ConflictAction, ConflictType, SqliteSessionExt};
+
+const ROWS: i32 = 500;
+
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+    conn
+}
+
+fn insert_items(conn: &mut SqliteConnection, name: &str) {
+    for id in 0..ROWS {
+        sql_query(format!(
+            "INSERT INTO items (id, name) VALUES ({id}, '{name} {id:0>40}')"
+        ))
+        .execute(conn)
+        .unwrap();
+    }
+}
+
+fn count_named(conn: &mut SqliteConnection, name: &str) -> i64 {
+    sql::<BigInt>(&format!(
+        "SELECT COUNT(*) FROM items WHERE name LIKE '{name} %'"
+    ))
+    .get_result(conn)
+    .unwrap()
+}
+
+/// Reader that holds back the last bytes of its input until `gate` opens.
+struct GatedReader<'a> {
+    data: Vec<u8>,
+    position: usize,
+    gate_at: usize,
+    gate: Receiver<()>,
+    timed_out: &'a AtomicBool,
+}
+
+impl Read for GatedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.gate_at && self.gate.recv_timeout(Duration::from_secs(10)).is_err()
+        {
+            self.timed_out.store(true, Ordering::SeqCst);
+        }
+        let end = if self.position < self.gate_at {
+            self.gate_at
+        } else {
+            self.data.len()
+        };
+        let len = buf.len().min(end - self.position);
+        buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_conflict_events_arrive_before_the_apply_completes() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    insert_items(&mut source, "source");
+    let changeset = session.changeset().unwrap();
+
+    // Every incoming row collides with an existing one.
+    let mut replica = setup_connection();
+    insert_items(&mut replica, "replica");
+
+    let (open_gate, gate) = mpsc::channel();
+    let timed_out = AtomicBool::new(false);
+    let reader = GatedReader {
+        gate_at: changeset.len() - 16,
+        data: changeset.into_bytes(),
+        position: 0,
+        gate,
+        timed_out: &timed_out,
+    };
+
+    let events = replica
+        .apply_changeset_from_reader_iter(
+            reader,
+            |_| ConflictAction::Omit,
+            |mut events| {
+                // The rest of the input is only released once an event arrived.
+                let first = events.next().unwrap();
+                open_gate.send(()).unwrap();
+                std::iter::once(first).chain(events).collect::<Vec<_>>()
+            },
+        )
+        .unwrap();
+
+    assert!(!timed_out.load(Ordering::SeqCst));
+    assert_eq!(events.len(), usize::try_from(ROWS).unwrap());
+    assert!(events.iter().all(|event| {
+        event.kind() == ConflictType::Conflict && event.action() == ConflictAction::Omit
+    }));
+    assert_eq!(count_named(&mut replica, "replica"), i64::from(ROWS));
+}
+
+#[test]
+fn test_streamed_apply_without_consuming_events() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    insert_items(&mut source, "source");
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = setup_connection();
+    insert_items(&mut replica, "replica");
+
+    // Far more conflicts than the event buffer holds.
+    replica
+        .apply_changeset_from_reader_iter(
+            changeset.as_bytes(),
+            |_| ConflictAction::Replace,
+            |_events| {},
+        )
+        .unwrap();
+
+    assert_eq!(count_named(&mut replica, "source"), i64::from(ROWS));
+}
+
+#[test]
+fn test_framed_changesets_are_recovered_one_by_one() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    let mut stream = Vec::new();
+    for statement in [
+        "INSERT INTO items (id, name) VALUES (1, 'first row')",
+        "UPDATE items SET name = 'renamed row' WHERE id = 1",
+        "INSERT INTO items (id, name) VALUES (2, 'second row')",
+    ] {
+        sql_query(statement).execute(&mut source).unwrap();
+        ChangesetFrame::write_to(&mut stream, &session.flush().unwrap()).unwrap();
+    }
+
+    let mut replica = setup_connection();
+    let mut reader = stream.as_slice();
+    let mut frames = Vec::new();
+    while let Some(changeset) = ChangesetFrame::read_from(&mut reader).unwrap() {
+        replica
+            .apply_changeset(&changeset, |_| ConflictAction::Abort)
+            .unwrap();
+        frames.push(changeset);
+    }
+
+    assert_eq!(frames.len(), 3);
+    assert_ne!(frames[0], frames[1]);
+    assert_ne!(frames[1], frames[2]);
+    assert_eq!(count_named(&mut replica, "renamed"), 1);
+    assert_eq!(count_named(&mut replica, "second"), 1);
+
+    let mut truncated = &stream[..stream.len() - 1];
+    for _ in 0..2 {
+        ChangesetFrame::read_from(&mut truncated).unwrap().unwrap();
+    }
+    let err = ChangesetFrame::read_from(&mut truncated).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,454 @@
+//! Tests for changeset-level transformations.
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Binary, Integer, Nullable, Text};
+use diesel_sqlite_session::{
+    changeset_has_deletes, changeset_has_inserts, changeset_has_updates, changeset_op_kinds,
+    changeset_to_patchset, coalesce_changeset, filter_changeset_by_value, project_changeset,
+    read_changeset, remap_changeset_pks, reorder_changeset_columns, split_changeset, strip_deletes,
+    Changeset, ChangesetError, ConflictAction, OpKind, OpKindSet, SqliteSessionExt, SqliteValue,
+};
+
+/// Helper to create an in-memory connection with an `accounts` table.
+fn setup_connection() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    sql_query(
+        "CREATE TABLE accounts (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL, name TEXT)",
+    )
+    .execute(&mut conn)
+    .unwrap();
+    conn
+}
+
+fn count_tenant(conn: &mut SqliteConnection, tenant_id: i32) -> i64 {
+    sql::<BigInt>(&format!(
+        "SELECT COUNT(*) FROM accounts WHERE tenant_id = {tenant_id}"
+    ))
+    .get_result(conn)
+    .unwrap()
+}
+
+fn account_ids(conn: &mut SqliteConnection) -> Vec<i32> {
+    sql::<Integer>("SELECT id FROM accounts ORDER BY id")
+        .load(conn)
+        .unwrap()
+}
+
+#[test]
+fn test_filter_by_value_keeps_one_tenant() {
+    let mut source = setup_connection();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES (10, 7, 'kept'), (20, 8, 'other')",
+    )
+    .execute(&mut source)
+    .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES \
+         (1, 7, 'a'), (2, 8, 'b'), (3, 7, 'c'), (4, 9, 'd')",
+    )
+    .execute(&mut source)
+    .unwrap();
+    sql_query("DELETE FROM accounts WHERE id IN (10, 20)")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let filtered =
+        filter_changeset_by_value(&changeset, "accounts", 1, &SqliteValue::Integer(7)).unwrap();
+    assert!(filtered.len() < changeset.len());
+
+    let mut replica = setup_connection();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES (10, 7, 'kept'), (20, 8, 'other')",
+    )
+    .execute(&mut replica)
+    .unwrap();
+    replica
+        .apply_bytes(&filtered, |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!(account_ids(&mut replica), [1, 3, 20]);
+    assert_eq!(count_tenant(&mut replica, 7), 2);
+    assert_eq!(count_tenant(&mut replica, 8), 1);
+    assert_eq!(count_tenant(&mut replica, 9), 0);
+}
+
+#[test]
+fn test_filter_by_value_preserves_patchsets() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'a'), (2, 8, 'b')")
+        .execute(&mut source)
+        .unwrap();
+    let patchset = session.patchset().unwrap();
+
+    let filtered =
+        filter_changeset_by_value(&patchset, "accounts", 1, &SqliteValue::Integer(8)).unwrap();
+
+    let mut replica = setup_connection();
+    replica
+        .apply_bytes(&filtered, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(account_ids(&mut replica), [2]);
+}
+
+#[test]
+fn test_remap_pks_offsets_ids() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'a'), (2, 7, 'b'), (3, 7, 'c')",
+    )
+    .execute(&mut source)
+    .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let remapped = remap_changeset_pks(&changeset, "accounts", |id| id + 1000).unwrap();
+
+    let mut replica = setup_connection();
+    // A local row occupying id 1 no longer collides.
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 1, 'local')")
+        .execute(&mut replica)
+        .unwrap();
+    replica
+        .apply_bytes(&remapped, |_| ConflictAction::Abort)
+        .unwrap();
+    assert_eq!(account_ids(&mut replica), [1, 1001, 1002, 1003]);
+}
+
+#[test]
+fn test_remap_pks_ignores_other_tables() {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'a')")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let remapped = remap_changeset_pks(&changeset, "other", |id| id + 1000).unwrap();
+    assert_eq!(remapped, changeset.as_bytes());
+}
+
+/// Record inserts of `rows` accounts whose names are `name_len` bytes long.
+fn changeset_with_accounts(rows: i32, name_len: usize) -> Changeset {
+    let mut source = setup_connection();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    let name = "x".repeat(name_len);
+    for id in 1..=rows {
+        sql_query(format!(
+            "INSERT INTO accounts (id, tenant_id, name) VALUES ({id}, {}, '{name}')",
+            id % 3
+        ))
+        .execute(&mut source)
+        .unwrap();
+    }
+    session.changeset().unwrap()
+}
+
+#[test]
+fn test_split_changeset_chunks_apply_to_full_state() {
+    let changeset = changeset_with_accounts(1000, 300);
+    let max_bytes = 100 * 1024;
+
+    let chunks = split_changeset(&changeset, max_bytes).unwrap();
+
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= max_bytes));
+
+    let mut expected = setup_connection();
+    expected
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+    let mut replica = setup_connection();
+    for chunk in &chunks {
+        replica
+            .apply_bytes(chunk, |_| ConflictAction::Abort)
+            .unwrap();
+    }
+
+    assert_eq!(account_ids(&mut replica), account_ids(&mut expected));
+    for tenant_id in 0..3 {
+        assert_eq!(
+            count_tenant(&mut replica, tenant_id),
+            count_tenant(&mut expected, tenant_id)
+        );
+    }
+}
+
+#[test]
+fn test_split_changeset_rejects_oversized_operation() {
+    let changeset = changeset_with_accounts(1, 2048);
+
+    let result = split_changeset(&changeset, 1024);
+
+    assert!(matches!(
+        result,
+        Err(ChangesetError::OperationTooLarge { ref table, limit: 1024, .. }) if table == "accounts"
+    ));
+}
+
+#[test]
+fn test_strip_deletes_keeps_deleted_rows_in_place() {
+    let mut source = setup_connection();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'existing')")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (2, 7, 'inserted')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM accounts WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let stripped = strip_deletes(&changeset).unwrap();
+
+    let mut replica = setup_connection();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'existing')")
+        .execute(&mut replica)
+        .unwrap();
+    replica
+        .apply_bytes(&stripped, |_| ConflictAction::Abort)
+        .unwrap();
+
+    assert_eq!(account_ids(&mut replica), [1, 2]);
+}
+
+#[test]
+fn test_has_deletes_tells_destructive_changesets_apart() {
+    let mut source = setup_connection();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'existing')")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (2, 7, 'inserted')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM accounts WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+    let insert_only = strip_deletes(&changeset).unwrap();
+
+    assert!(changeset_has_deletes(&changeset).unwrap());
+    assert!(!changeset_has_deletes(&insert_only).unwrap());
+    assert!(changeset_has_inserts(&insert_only).unwrap());
+    assert!(!changeset_has_updates(&changeset).unwrap());
+}
+
+#[test]
+fn test_op_kinds_lists_distinct_operation_kinds() {
+    let mut source = setup_connection();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'first'), (2, 7, 'second')",
+    )
+    .execute(&mut source)
+    .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (3, 7, 'inserted')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("UPDATE accounts SET name = 'renamed' WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM accounts WHERE id = 2")
+        .execute(&mut source)
+        .unwrap();
+    let mixed = session.changeset().unwrap();
+
+    let kinds = changeset_op_kinds(&mixed).unwrap();
+    assert_eq!(kinds, OpKindSet::ALL);
+    assert_eq!(kinds.len(), 3);
+
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (4, 7, 'fourth')")
+        .execute(&mut source)
+        .unwrap();
+    let insert_only = session.changeset().unwrap();
+
+    let kinds = changeset_op_kinds(&insert_only).unwrap();
+    assert!(kinds.contains(OpKind::Insert));
+    assert!(!kinds.contains(OpKind::Update));
+    assert!(!kinds.contains(OpKind::Delete));
+    assert_eq!(kinds.iter().collect::<Vec<_>>(), [OpKind::Insert]);
+    assert!(changeset_op_kinds(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn test_changeset_to_patchset_is_smaller_and_applies_alike() {
+    let mut source = setup_connection();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'first'), (2, 7, 'second')",
+    )
+    .execute(&mut source)
+    .unwrap();
+    let mut replica = setup_connection();
+    sql_query(
+        "INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'first'), (2, 7, 'second')",
+    )
+    .execute(&mut replica)
+    .unwrap();
+
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("accounts").unwrap();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (3, 8, 'third')")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("UPDATE accounts SET name = 'renamed' WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("DELETE FROM accounts WHERE id = 2")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let patchset = changeset_to_patchset(&changeset).unwrap();
+    assert!(patchset.len() < changeset.len());
+    assert_eq!(changeset_to_patchset(&patchset).unwrap(), patchset);
+
+    replica
+        .apply_patchset(&patchset, |_| ConflictAction::Abort)
+        .unwrap();
+    let names = |conn: &mut SqliteConnection| -> Vec<Option<String>> {
+        sql::<Nullable<Text>>("SELECT name FROM accounts ORDER BY id")
+            .load(conn)
+            .unwrap()
+    };
+    assert_eq!(account_ids(&mut replica), [1, 3]);
+    assert_eq!(names(&mut replica), names(&mut source));
+}
+
+#[test]
+fn test_coalesce_merges_concatenated_updates_of_one_row() {
+    let mut source = setup_connection();
+    sql_query("INSERT INTO accounts (id, tenant_id, name) VALUES (1, 7, 'first')")
+        .execute(&mut source)
+        .unwrap();
+    let mut record_rename = |name: &str| {
+        let mut session = source.create_session().unwrap();
+        session.attach_by_name("accounts").unwrap();
+        sql_query(format!("UPDATE accounts SET name = '{name}' WHERE id = 1"))
+            .execute(&mut source)
+            .unwrap();
+        session.changeset().unwrap().into_bytes()
+    };
+    let concatenated = [record_rename("second"), record_rename("third")].concat();
+
+    let coalesced = coalesce_changeset(&concatenated).unwrap();
+
+    let ops: Vec<_> = read_changeset(&coalesced)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].op(), OpKind::Update);
+    assert_eq!(
+        ops[0].old_values()[2],
+        Some(SqliteValue::Text(b"first".to_vec()))
+    );
+    assert_eq!(
+        ops[0].new_values()[2],
+        Some(SqliteValue::Text(b"third".to_vec()))
+    );
+}
+
+#[test]
+fn test_project_changeset_leaves_dropped_columns_untouched() {
+    let setup = |value: &str| {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, value BLOB)")
+            .execute(&mut conn)
+            .unwrap();
+        sql_query(format!(
+            "INSERT INTO items (id, name, value) VALUES (1, 'old', {value})"
+        ))
+        .execute(&mut conn)
+        .unwrap();
+        conn
+    };
+
+    let mut source = setup("x'01'");
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("UPDATE items SET name = 'new', value = x'02' WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let projected = project_changeset(&changeset, "items", &[1]).unwrap();
+    let op = read_changeset(&projected).unwrap().next().unwrap().unwrap();
+    assert_eq!(op.old_values()[2], None);
+    assert_eq!(op.new_values()[2], None);
+
+    // The replica holds its own blob, which would conflict with the original.
+    let mut replica = setup("x'ff'");
+    replica
+        .apply_bytes(&projected, |_| ConflictAction::Abort)
+        .unwrap();
+
+    let (name, value): (String, Vec<u8>) =
+        sql::<(Text, Binary)>("SELECT name, value FROM items WHERE id = 1")
+            .get_result(&mut replica)
+            .unwrap();
+    assert_eq!(name, "new");
+    assert_eq!(value, [0xff]);
+}
+
+#[test]
+fn test_apply_changeset_mapped_follows_replica_column_order() {
+    let mut source = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, value INTEGER)")
+        .execute(&mut source)
+        .unwrap();
+    let mut session = source.create_session().unwrap();
+    session.attach_by_name("items").unwrap();
+    sql_query("INSERT INTO items (id, name, value) VALUES (1, 'one', 10), (2, 'two', 20)")
+        .execute(&mut source)
+        .unwrap();
+    sql_query("UPDATE items SET value = 11 WHERE id = 1")
+        .execute(&mut source)
+        .unwrap();
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = SqliteConnection::establish(":memory:").unwrap();
+    sql_query("CREATE TABLE items (id INTEGER PRIMARY KEY, value INTEGER, name TEXT)")
+        .execute(&mut replica)
+        .unwrap();
+    replica
+        .apply_changeset_mapped(
+            &changeset,
+            |_, column| [0, 2, 1][column],
+            |_| ConflictAction::Abort,
+        )
+        .unwrap();
+
+    let rows: Vec<(i32, String)> =
+        sql::<(Integer, Text)>("SELECT value, name FROM items ORDER BY id")
+            .load(&mut replica)
+            .unwrap();
+    assert_eq!(rows, [(11, "one".to_owned()), (20, "two".to_owned())]);
+
+    let result = reorder_changeset_columns(&changeset, |_, _| 0);
+    assert!(matches!(
+        result,
+        Err(ChangesetError::InvalidColumnMap {
+            column: 1,
+            mapped: 0,
+            ..
+        })
+    ));
+}
@@ -328,3 +328,41 @@ async fn test_enable_disable_wasm() {
     // Should have 2 rows (1 and 3, not 2)
     assert_eq!(count_rows(&mut replica), 2);
 }
+
+#[wasm_bindgen_test]
+async fn test_pause_guard_wasm() {
+    let mut conn = create_connection();
+    setup_table(&mut conn);
+
+    let mut session = conn.create_session().unwrap();
+    session.attach::<test_items::table>().unwrap();
+
+    // Insert while enabled
+    sql_query("INSERT INTO test_items (id, name, value) VALUES (1, 'tracked', 10)")
+        .execute(&mut conn)
+        .unwrap();
+
+    // Insert while paused
+    {
+        let _paused = session.pause();
+        sql_query("INSERT INTO test_items (id, name, value) VALUES (2, 'untracked', 20)")
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    // Insert after the guard is dropped
+    sql_query("INSERT INTO test_items (id, name, value) VALUES (3, 'tracked_again', 30)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let changeset = session.changeset().unwrap();
+
+    let mut replica = create_connection();
+    setup_table(&mut replica);
+    replica
+        .apply_changeset(&changeset, |_| ConflictAction::Abort)
+        .unwrap();
+
+    // Should have 2 rows (1 and 3, not 2)
+    assert_eq!(count_rows(&mut replica), 2);
+}
@@ -0,0 +1,89 @@
+//! WASM tests for sessions on OPFS-backed connections.
+//!
+//! The OPFS SAH pool VFS needs synchronous file handles, which browsers only
+//! offer to workers, so these tests run in a dedicated worker rather than on
+//! the main thread.
+
+#![cfg(target_arch = "wasm32")]
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Binary};
+use diesel_sqlite_session::{ConflictAction, SqliteSessionExt};
+use sqlite_wasm_rs::WasmOsCallback;
+use sqlite_wasm_vfs::sahpool::{install, OpfsSAHPoolCfg};
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_dedicated_worker);
+
+/// Install the OPFS SAH pool as the default VFS and clear files left by earlier runs.
+async fn install_opfs() {
+    let pool = install::<WasmOsCallback>(&OpfsSAHPoolCfg::default(), true)
+        .await
+        .expect("Failed to install the OPFS VFS");
+    pool.clear_all().expect("Failed to clear the OPFS pool");
+}
+
+/// Open a database file stored in OPFS, creating the `test_items` table.
+fn open(path: &str) -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(path).expect("Failed to open OPFS database");
+    sql_query(
+        "CREATE TABLE IF NOT EXISTS test_items (id INTEGER PRIMARY KEY, name TEXT, value INTEGER)",
+    )
+    .execute(&mut conn)
+    .expect("Failed to create table");
+    conn
+}
+
+fn count_rows(conn: &mut SqliteConnection) -> i64 {
+    sql::<BigInt>("SELECT COUNT(*) FROM test_items")
+        .get_result(conn)
+        .expect("Failed to count rows")
+}
+
+#[wasm_bindgen_test]
+async fn test_changeset_survives_reload_on_opfs() {
+    install_opfs().await;
+
+    // Record changes and persist the changeset next to the data, in OPFS.
+    {
+        let mut source = open("source.db");
+        sql_query("CREATE TABLE outbox (id INTEGER PRIMARY KEY, changeset BLOB NOT NULL)")
+            .execute(&mut source)
+            .unwrap();
+        let mut session = source.create_session().unwrap();
+        session.attach_by_name("test_items").unwrap();
+        sql_query("INSERT INTO test_items (id, name, value) VALUES (1, 'a', 10), (2, 'b', 20)")
+            .execute(&mut source)
+            .unwrap();
+        sql_query("UPDATE test_items SET value = 11 WHERE id = 1")
+            .execute(&mut source)
+            .unwrap();
+        let changeset = session.changeset().unwrap();
+        drop(session);
+        sql_query("INSERT INTO outbox (id, changeset) VALUES (1, ?)")
+            .bind::<Binary, _>(changeset)
+            .execute(&mut source)
+            .unwrap();
+    }
+
+    // After the reload, replay the stored changeset into a second OPFS database.
+    {
+        let mut source = open("source.db");
+        let changeset: Vec<u8> = sql::<Binary>("SELECT changeset FROM outbox WHERE id = 1")
+            .get_result(&mut source)
+            .unwrap();
+        let mut replica = open("replica.db");
+        replica
+            .apply_changeset(&changeset, |_| ConflictAction::Abort)
+            .unwrap();
+    }
+
+    let mut replica = open("replica.db");
+    assert_eq!(count_rows(&mut replica), 2, "Replica should keep both rows");
+    let value: i64 = sql::<BigInt>("SELECT value FROM test_items WHERE id = 1")
+        .get_result(&mut replica)
+        .unwrap();
+    assert_eq!(value, 11, "Replica should keep the updated value");
+}